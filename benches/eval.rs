@@ -0,0 +1,54 @@
+//! Benchmarks for the parser, the evaluator and the printer, run with `cargo bench`.
+
+use std::hint::black_box;
+
+use calq::expr::{parse, Evaluator, Expr};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Evaluates the statements of a line, returning the last result.
+fn eval(e: &mut Evaluator, line: &str) -> Expr {
+    let (statements, errors) = parse(e, line);
+    assert!(errors.is_empty(), "`{line}` doesn't parse");
+    statements.into_iter().map(|s| e.eval(s).unwrap()).last().unwrap()
+}
+
+fn parsing(c: &mut Criterion) {
+    let e = Evaluator::default();
+    let line = "(1 + 2*x)^3 / (4 - y) + sqrt(2) * [1, 2, 3] - f(a, b, c) / 7; 1/2 + 1/3 + 1/4";
+    c.bench_function("parse", |b| b.iter(|| parse(&e, black_box(line))));
+}
+
+fn exact_arithmetic(c: &mut Criterion) {
+    // the harmonic number H(200), whose denominator keeps growing
+    let sum = (1..=200).map(|n| format!("1/{n}")).collect::<Vec<_>>().join(" + ");
+    let mut e = Evaluator::default();
+    c.bench_function("harmonic sum", |b| b.iter(|| eval(&mut e, black_box(&sum))));
+    c.bench_function("exact power", |b| b.iter(|| eval(&mut e, black_box("(3/7)^2000 * (7/3)^1999"))));
+}
+
+fn transcendental(c: &mut Criterion) {
+    let mut e = Evaluator::default();
+    let line = "sin(1.2345) + cos(2.5) + tan(0.75) + ln(10.5) + sqrt(2.0) + asin(0.3) + acos(0.6)";
+    c.bench_function("transcendental", |b| b.iter(|| eval(&mut e, black_box(line))));
+}
+
+fn user_functions(c: &mut Criterion) {
+    let mut e = Evaluator::default();
+    eval(&mut e, "f(x) = x^2 + 1/x; g(x) = f(x) - f(x + 1)/2");
+    let line = format!("[{}]", (1..=100).map(|n| format!("g({n})")).collect::<Vec<_>>().join(", "));
+    c.bench_function("user function calls", |b| b.iter(|| eval(&mut e, black_box(&line))));
+}
+
+fn printing(c: &mut Criterion) {
+    let mut e = Evaluator::default();
+    e.settings_mut().set("exact-digits", "off").unwrap();
+    let huge = eval(&mut e, "3^50000");
+    let fraction = eval(&mut e, "(2^3000 + 1) / 3^2000");
+    c.bench_function("print huge integer", |b| b.iter(|| e.display(black_box(&huge)).to_string()));
+    c.bench_function("print huge fraction", |b| b.iter(|| e.display(black_box(&fraction)).to_string()));
+    e.settings_mut().set("render", "2d").unwrap();
+    c.bench_function("print huge fraction in 2d", |b| b.iter(|| e.display(black_box(&fraction)).to_string()));
+}
+
+criterion_group!(benches, parsing, exact_arithmetic, transcendental, user_functions, printing);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "capi")]
+    {
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out = std::env::var("OUT_DIR").unwrap();
+        println!("cargo:rerun-if-changed=src/capi.rs");
+        cbindgen::Builder::new()
+            .with_language(cbindgen::Language::C)
+            .with_include_guard("CALQ_H")
+            .with_src(format!("{dir}/src/capi.rs"))
+            .generate()
+            .expect("could not generate the C header")
+            .write_to_file(format!("{out}/calq.h"));
+    }
+}
@@ -0,0 +1,59 @@
+#ifndef CALQ_H
+#define CALQ_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * An evaluator with its variables and functions.
+ */
+typedef struct CalqSession CalqSession;
+
+/**
+ * Creates a session, which must be freed with `calq_free`.
+ */
+struct CalqSession *calq_new(void);
+
+/**
+ * Evaluates the `;` separated statements in `input`, returning the result of
+ * the last one as text to be freed with `calq_string_free`. Returns null on
+ * errors, which `calq_last_error` then describes. A panic in the evaluator
+ * is reported as an error too, leaving the session as it was.
+ *
+ * # Safety
+ *
+ * `session` must come from `calq_new` and `input` must be a valid nul-terminated string.
+ */
+char *calq_eval(struct CalqSession *session, const char *input);
+
+/**
+ * The error of the last `calq_eval`, or null if it succeeded. The string is
+ * owned by the session and valid until the next call on it.
+ *
+ * # Safety
+ *
+ * `session` must come from `calq_new`.
+ */
+const char *calq_last_error(const struct CalqSession *session);
+
+/**
+ * Frees a string returned by `calq_eval`. Does nothing for null.
+ *
+ * # Safety
+ *
+ * `s` must come from `calq_eval` and not have been freed already.
+ */
+void calq_string_free(char *s);
+
+/**
+ * Frees a session. Does nothing for null.
+ *
+ * # Safety
+ *
+ * `session` must come from `calq_new` and not have been freed already.
+ */
+void calq_free(struct CalqSession *session);
+
+#endif  /* CALQ_H */
@@ -0,0 +1,26 @@
+#!/bin/sh
+# Type-checks and lints calq for Windows from Linux, so that the `cfg(windows)`
+# code, like the console setup in `src/render.rs`, is compiled somewhere. It
+# doesn't link: building GMP for Windows needs MinGW, see the comment on `rug`
+# in Cargo.toml.
+#
+# GMP and MPFR are taken from the system (`libgmp-dev`, `libmpfr-dev` and
+# `libmpc-dev`), with headers in $GMP_INCLUDE, /usr/include by default. Extra
+# arguments go to cargo, like `--offline`.
+set -eu
+
+target=x86_64-pc-windows-gnu
+rustup target add "$target"
+
+# GMP's limbs are `long long` on 64-bit Windows, where `long` has 32 bits
+headers=$(mktemp -d)
+trap 'rm -rf "$headers"' EXIT
+cp "${GMP_INCLUDE:-/usr/include}"/gmp.h "${GMP_INCLUDE:-/usr/include}"/mpfr.h "${GMP_INCLUDE:-/usr/include}"/mpc.h "$headers"
+sed -i 's|/\* #undef _LONG_LONG_LIMB \*/|#define _LONG_LONG_LIMB 1|' "$headers/gmp.h"
+
+# gmp-mpfr-sys reads the headers rather than building GMP, and the assembly
+# of `psm`, which `stacker` needs, is left out as nothing is linked
+CARGO_FEATURE_USE_SYSTEM_LIBS=1 CARGO_FEATURE_FORCE_CROSS=1 CPATH="$headers" \
+    CC_x86_64_pc_windows_gnu=true AR_x86_64_pc_windows_gnu=true \
+    CARGO_TARGET_DIR="${CARGO_TARGET_DIR:-target}/windows-check" \
+    cargo clippy --target "$target" --all-targets "$@" -- -D warnings
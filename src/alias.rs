@@ -0,0 +1,215 @@
+//! Textual aliases defined with `:alias name = text`.
+//!
+//! In a command line, an alias for the command name is replaced (`:alias p = :set digits`
+//! makes `:p 20` run `:set digits 20`). In an expression, every identifier
+//! naming an alias is replaced by its text, and a function alias like
+//! `:alias sq = x -> x^2` is called like a function, `sq(3)`.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::bail;
+
+struct Alias {
+    /// The parameters of a function alias like `sq = x -> x^2`.
+    params: Option<Vec<String>>,
+    /// The text to replace the name with, the body of a function alias.
+    body: String,
+    /// The text as written, to list the alias with.
+    text: String,
+}
+
+#[derive(Default)]
+pub struct Aliases {
+    aliases: BTreeMap<String, Alias>,
+}
+
+impl Aliases {
+    /// Defines `name` as `text`, which is a function alias if it is like
+    /// `x -> x^2` or `(x, y) -> x*y`.
+    pub fn define(&mut self, name: String, text: String) -> color_eyre::Result<()> {
+        let (params, body) = match text.split_once("->").filter(|_| !text.starts_with(':')) {
+            Some((params, body)) => {
+                let params = params.trim();
+                let list = params.strip_prefix('(').and_then(|p| p.strip_suffix(')')).unwrap_or(params);
+                let params: Vec<String> = list.split(',').map(|p| p.trim().to_string()).collect();
+                if params.iter().any(|p| !is_ident(p)) {
+                    bail!("expected parameter names before `->`, like `x -> x^2` or `(x, y) -> x*y`");
+                }
+                (Some(params), body.trim().to_string())
+            }
+            None => (None, text.clone()),
+        };
+        self.aliases.insert(name, Alias { params, body, text });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.aliases.remove(name).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.aliases.iter().map(|(k, v)| (k.as_str(), v.text.as_str()))
+    }
+
+    /// Expands aliases in a line, this is not recursive. A function alias is
+    /// replaced by its body in brackets, with the arguments in brackets in
+    /// place of the parameters, so `sq(1 + 2)` is `((1 + 2)^2)`.
+    pub fn expand<'a>(&self, line: &'a str) -> color_eyre::Result<Cow<'a, str>> {
+        if self.aliases.is_empty() {
+            return Ok(Cow::Borrowed(line));
+        }
+
+        if let Some(command) = line.trim_start().strip_prefix(':') {
+            let name_len = command.find(char::is_whitespace).unwrap_or(command.len());
+            return Ok(match self.aliases.get(&command[..name_len]) {
+                Some(Alias { params: None, body, .. }) => {
+                    let text = body.strip_prefix(':').unwrap_or(body);
+                    Cow::Owned(format!(":{text}{}", &command[name_len..]))
+                }
+                _ => Cow::Borrowed(line),
+            });
+        }
+
+        let expanded = replace_idents(line, |name, rest| {
+            let Some(alias) = self.aliases.get(name) else {
+                return Ok(None);
+            };
+            let Some(params) = &alias.params else {
+                return Ok(Some((alias.body.clone(), 0)));
+            };
+            let Some((args, len)) = call_args(rest) else {
+                bail!("`{name}` is a function alias, call it like `{name}({})`", params.join(", "));
+            };
+            if args.len() != params.len() {
+                bail!("`{name}` takes {} arguments, found {}", params.len(), args.len());
+            }
+            let body = replace_idents(&alias.body, |ident, _| {
+                let arg = params.iter().position(|p| p == ident).map(|i| format!("({})", args[i].trim()));
+                Ok(arg.map(|arg| (arg, 0)))
+            })?;
+            Ok(Some((format!("({body})"), len)))
+        })?;
+        Ok(Cow::Owned(expanded))
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_alphabetic() || c == '_') && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// `line` with each identifier replaced by what `f` returns for it and the
+/// rest of the line after it, with how many bytes of the rest that replaces
+/// too. Identifiers in strings and right after a digit, which are literal
+/// suffixes like in `2i`, are left alone.
+fn replace_idents(
+    line: &str,
+    mut f: impl FnMut(&str, &str) -> color_eyre::Result<Option<(String, usize)>>,
+) -> color_eyre::Result<String> {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.char_indices().peekable();
+    let mut in_string = false;
+    let mut prev: Option<char> = None;
+    while let Some((start, c)) = chars.next() {
+        if in_string {
+            out.push(c);
+            if c == '\\' {
+                if let Some((_, escaped)) = chars.next() {
+                    out.push(escaped);
+                }
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+            out.push(c);
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start + c.len_utf8();
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let ident = &line[start..end];
+            let replaced = if prev.is_some_and(|p| p.is_ascii_digit()) { None } else { f(ident, &line[end..])? };
+            match replaced {
+                Some((text, len)) => {
+                    out.push_str(&text);
+                    while chars.next_if(|&(i, _)| i < end + len).is_some() {}
+                    prev = text.chars().last();
+                }
+                None => {
+                    out.push_str(ident);
+                    prev = ident.chars().last();
+                }
+            }
+            continue;
+        } else {
+            out.push(c);
+        }
+        prev = Some(c);
+    }
+    Ok(out)
+}
+
+/// The arguments of a call at the start of `rest`, like `(1, f(2))`, and
+/// its length, if it starts with one.
+fn call_args(rest: &str) -> Option<(Vec<&str>, usize)> {
+    let start = rest.len() - rest.trim_start().len();
+    if !rest[start..].starts_with('(') {
+        return None;
+    }
+    let (mut depth, mut in_string, mut escaped) = (0, false, false);
+    let mut args = vec![];
+    let mut arg_start = start + 1;
+    for (i, c) in rest.char_indices().skip_while(|&(i, _)| i < start) {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    args.push(&rest[arg_start..i]);
+                    return Some((args, i + 1));
+                }
+            }
+            ',' if depth == 1 => {
+                args.push(&rest[arg_start..i]);
+                arg_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[test]
+fn test_expand() {
+    let mut a = Aliases::default();
+    a.define("p".into(), ":set digits".into()).unwrap();
+    a.define("tau".into(), "(2*pi)".into()).unwrap();
+    a.define("i".into(), "nope".into()).unwrap();
+    let expand = |line| a.expand(line).unwrap().into_owned();
+    assert_eq!(":set digits 20", expand(":p 20"));
+    assert_eq!(":pp 20", expand(":pp 20"));
+    assert_eq!("(2*pi)/2 + taut", expand("tau/2 + taut"));
+    assert_eq!("\"tau\" + 2i", expand("\"tau\" + 2i"));
+    a.define("sq".into(), "x -> x^2".into()).unwrap();
+    a.define("hyp".into(), "(a, b) -> sqrt(sq(a) + b^2)".into()).unwrap();
+    assert_eq!("((1 + 2)^2) + 1", a.expand("sq(1 + 2) + 1").unwrap());
+    assert_eq!("(sqrt(sq((f(1, 2))) + (\"x, y\")^2))", a.expand("hyp (f(1, 2), \"x, y\")").unwrap());
+    assert!(a.expand("sq + 1").is_err());
+    assert!(a.expand("sq(1, 2)").is_err());
+    assert!(a.define("bad".into(), "2 -> x".into()).is_err());
+}
@@ -0,0 +1,171 @@
+//! `--bc` mode, which accepts the common parts of `bc` syntax by translating
+//! each line into calq's own before it is parsed, and prints results the way
+//! `bc` does.
+//!
+//! Supported are `scale=N`, `define f(x) { return expr; }` on a single line,
+//! the `s`, `c` and `l` functions of `bc -l`, `quit` and comments. Unlike in
+//! `bc`, intermediate results are exact, and `scale` only affects printing.
+
+use color_eyre::eyre::bail;
+use rug::ops::Pow;
+use rug::Integer;
+
+use crate::expr::Value;
+
+/// `bc -l` function names and the builtins they stand for.
+const FUNCTIONS: &[(&str, &str)] = &[("s", "sin"), ("c", "cos"), ("l", "ln")];
+
+#[derive(Default)]
+pub struct Bc {
+    /// Number of digits printed after the decimal point.
+    pub scale: usize,
+}
+
+impl Bc {
+    /// Translates a line of `bc` into calq, `None` if it asks to quit.
+    pub fn translate(&mut self, line: &str) -> color_eyre::Result<Option<String>> {
+        let line = strip_comments(line);
+        let line = line.trim();
+        if line == "quit" || line == "halt" {
+            return Ok(None);
+        }
+        if let Some(value) = line.strip_prefix("scale").map(str::trim_start) {
+            if value.is_empty() {
+                return Ok(Some(self.scale.to_string()));
+            }
+            if let Some(value) = value.strip_prefix('=').filter(|v| !v.starts_with('=')) {
+                let value = value.trim().trim_end_matches(';').trim_end();
+                let Ok(scale) = value.parse() else {
+                    bail!("`scale` must be set to a non-negative integer, found `{value}`");
+                };
+                self.scale = scale;
+                return Ok(Some(String::new()));
+            }
+        }
+        for unsupported in ["ibase", "obase", "if", "while", "for", "print", "auto"] {
+            if starts_with_word(line, unsupported) {
+                bail!("`{unsupported}` isn't supported in bc mode");
+            }
+        }
+        if let Some(rest) = line.strip_prefix("define").filter(|r| r.starts_with(char::is_whitespace)) {
+            return define(rest.trim()).map(Some);
+        }
+        Ok(Some(rename_functions(line)))
+    }
+
+    /// Formats a number like `bc` does, truncated to `scale` digits without a
+    /// leading `0`. Anything but a real number is left to the usual printer.
+    pub fn format(&self, v: &Value) -> Option<String> {
+        let r = match v {
+            Value::Exact(r) if *r.denom() == 1 => return Some(r.numer().to_string()),
+            Value::Exact(r) => r.clone(),
+            Value::Decimal(d) => d.to_rational()?,
+            _ => return None,
+        };
+        let negative = r.is_negative();
+        let digits = (r.abs() * Integer::from(10).pow(self.scale as u32)).trunc().numer().to_string();
+        // `bc` prints zero as `0`, however many digits it has
+        if digits == "0" {
+            return Some(digits);
+        }
+        let sign = if negative { "-" } else { "" };
+        if self.scale == 0 {
+            return Some(format!("{sign}{digits}"));
+        }
+        let digits = format!("{digits:0>width$}", width = self.scale);
+        let (int, frac) = digits.split_at(digits.len() - self.scale);
+        Some(format!("{sign}{int}.{frac}"))
+    }
+}
+
+fn starts_with_word(line: &str, word: &str) -> bool {
+    line.strip_prefix(word).is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+}
+
+/// Removes `/* ... */` and `# ...` comments.
+fn strip_comments(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    loop {
+        let block = rest.find("/*");
+        let hash = rest.find('#');
+        match block {
+            Some(block) if hash.is_none_or(|h| block < h) => {
+                out.push_str(&rest[..block]);
+                match rest[block..].find("*/") {
+                    Some(end) => rest = &rest[block + end + 2..],
+                    None => return out,
+                }
+            }
+            _ => {
+                out.push_str(&rest[..hash.unwrap_or(rest.len())]);
+                return out;
+            }
+        }
+    }
+}
+
+/// Translates `f(x, y) { return expr; }` into `f(x, y) = expr`.
+fn define(definition: &str) -> color_eyre::Result<String> {
+    let Some((head, body)) = definition.split_once('{') else {
+        bail!("expected `{{` after the parameters of `define`");
+    };
+    let Some(body) = body.trim_end().strip_suffix('}') else {
+        bail!("`define` must be on a single line ending in `}}`");
+    };
+    let statements: Vec<_> = body.split([';', '\n']).map(str::trim).filter(|s| !s.is_empty()).collect();
+    let [statement] = statements[..] else {
+        bail!("only functions made of a single `return` are supported in bc mode");
+    };
+    let Some(expr) = statement.strip_prefix("return").filter(|e| !e.trim().is_empty()) else {
+        bail!("only functions made of a single `return` are supported in bc mode");
+    };
+    Ok(format!("{} = {}", head.trim(), rename_functions(expr.trim())))
+}
+
+/// Replaces calls of the `bc -l` functions by their builtins, `s(x)` by
+/// `sin(x)`, and writes numbers like `.5`, which `bc` prints, as `0.5`.
+fn rename_functions(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if !(c.is_alphabetic() || c == '_') {
+            let fraction = c == '.' && chars.peek().is_some_and(|&(_, next)| next.is_ascii_digit());
+            if fraction && !out.ends_with(|c: char| c.is_ascii_digit()) {
+                out.push('0');
+            }
+            out.push(c);
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, c)) = chars.peek() {
+            if !(c.is_alphanumeric() || c == '_') {
+                break;
+            }
+            end = i + c.len_utf8();
+            chars.next();
+        }
+        let ident = &line[start..end];
+        let is_call = line[end..].trim_start().starts_with('(');
+        match FUNCTIONS.iter().find(|(bc, _)| *bc == ident) {
+            Some((_, builtin)) if is_call => out.push_str(builtin),
+            _ => out.push_str(ident),
+        }
+    }
+    out
+}
+
+#[test]
+fn test_translate() {
+    let mut bc = Bc::default();
+    assert_eq!(Some(String::new()), bc.translate("scale = 4 /* digits */").unwrap());
+    assert_eq!(4, bc.scale);
+    assert_eq!(Some("f(x) = x^2 + sin(1)".into()), bc.translate("define f(x) { return x^2 + s(1); }").unwrap());
+    assert_eq!(Some("ln(2) * ls".into()), bc.translate("l(2) * ls # note").unwrap());
+    assert_eq!(Some("0.5 + 1.25*(-0.5)".into()), bc.translate(".5 + 1.25*(-.5)").unwrap());
+    assert_eq!(Some("f(x) = 0.5*x".into()), bc.translate("define f(x) { return .5*x; }").unwrap());
+    assert_eq!(None, bc.translate("quit").unwrap());
+    assert_eq!(Some(".3333".into()), bc.format(&Value::Exact(rug::Rational::from((1, 3)))));
+    assert_eq!(Some("-1.5000".into()), bc.format(&Value::Exact(rug::Rational::from((-3, 2)))));
+    assert_eq!(Some("1024".into()), bc.format(&Value::Exact(rug::Rational::from(1024))));
+}
@@ -0,0 +1,125 @@
+//! The C API, enabled with the `capi` feature. Its header is checked in as
+//! `include/calq.h`, and the library is built with
+//! `cargo rustc --release --lib --features capi --crate-type cdylib`.
+//!
+//! ```c
+//! CalqSession *session = calq_new();
+//! char *result = calq_eval(session, "x = 1/3; x + 1/6");
+//! if (result) {
+//!     puts(result);
+//!     calq_string_free(result);
+//! } else {
+//!     puts(calq_last_error(session));
+//! }
+//! calq_free(session);
+//! ```
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+use color_eyre::eyre::eyre;
+
+use crate::expr::Evaluator;
+use crate::session;
+
+/// An evaluator with its variables and functions.
+pub struct CalqSession {
+    evaluator: Evaluator,
+    last_error: Option<CString>,
+}
+
+/// Creates a session, which must be freed with `calq_free`.
+#[no_mangle]
+pub extern "C" fn calq_new() -> *mut CalqSession {
+    Box::into_raw(Box::new(CalqSession { evaluator: Evaluator::default(), last_error: None }))
+}
+
+/// Evaluates the `;` separated statements in `input`, returning the result of
+/// the last one as text to be freed with `calq_string_free`. Returns null on
+/// errors, which `calq_last_error` then describes. A panic in the evaluator
+/// is reported as an error too, leaving the session as it was.
+///
+/// # Safety
+///
+/// `session` must come from `calq_new` and `input` must be a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn calq_eval(session: *mut CalqSession, input: *const c_char) -> *mut c_char {
+    let session = &mut *session;
+    let input = CStr::from_ptr(input);
+    let result = session::guarded(|| eval(&mut session.evaluator, input)).unwrap_or_else(|panic| {
+        session.evaluator.abandon();
+        Err(eyre!("internal error, please report: {panic}"))
+    });
+    match result {
+        Ok(result) => {
+            session.last_error = None;
+            result.into_raw()
+        }
+        Err(e) => {
+            // an error can't contain a nul, but better safe than sorry
+            session.last_error = CString::new(e.to_string().replace('\0', "")).ok();
+            ptr::null_mut()
+        }
+    }
+}
+
+fn eval(evaluator: &mut Evaluator, input: &CStr) -> color_eyre::Result<CString> {
+    let input = input.to_str().map_err(|_| eyre!("the input is not valid UTF-8"))?;
+    let result = session::eval(evaluator, input)?;
+    Ok(CString::new(evaluator.display(&result).to_string())?)
+}
+
+/// The error of the last `calq_eval`, or null if it succeeded. The string is
+/// owned by the session and valid until the next call on it.
+///
+/// # Safety
+///
+/// `session` must come from `calq_new`.
+#[no_mangle]
+pub unsafe extern "C" fn calq_last_error(session: *const CalqSession) -> *const c_char {
+    (*session).last_error.as_ref().map_or(ptr::null(), |e| e.as_ptr())
+}
+
+/// Frees a string returned by `calq_eval`. Does nothing for null.
+///
+/// # Safety
+///
+/// `s` must come from `calq_eval` and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn calq_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Frees a session. Does nothing for null.
+///
+/// # Safety
+///
+/// `session` must come from `calq_new` and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn calq_free(session: *mut CalqSession) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+#[test]
+fn test_eval() {
+    unsafe {
+        let session = calq_new();
+        let result = calq_eval(session, c"x = 1/3; x + 1/6".as_ptr());
+        assert_eq!(c"1/2", CStr::from_ptr(result));
+        calq_string_free(result);
+        assert!(calq_eval(session, c"1/0".as_ptr()).is_null());
+        assert_eq!(c"division by zero", CStr::from_ptr(calq_last_error(session)));
+        calq_free(session);
+    }
+}
+
+#[test]
+fn test_header() {
+    // regenerate `include/calq.h` from the generated one when this fails
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/calq.h"));
+    assert_eq!(include_str!("../include/calq.h"), generated);
+}
@@ -0,0 +1,325 @@
+//! REPL commands, which are lines starting with `:`.
+
+use std::borrow::Cow;
+
+use color_eyre::eyre::{bail, eyre};
+use rug::Float;
+
+use crate::expr::builtins::{self, docs, io};
+use crate::expr::{Expr, Value};
+use crate::render::{self, Theme};
+use crate::repl::{LastResult, Repl};
+use crate::{export, signals, transcript};
+
+pub fn run(repl: &mut Repl, line: &str) -> color_eyre::Result<()> {
+    let line = line.trim();
+    let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    match command {
+        "set" => set(repl, rest)?,
+        "copy" => copy(repl, rest)?,
+        "theme" => theme(repl, rest)?,
+        "history" => history(repl, rest),
+        "alias" => alias(repl, rest)?,
+        "unalias" => {
+            if !repl.aliases.remove(rest) {
+                bail!("there is no alias named `{rest}`");
+            }
+        }
+        "aliases" => {
+            for (name, text) in repl.aliases.iter() {
+                render::info(format_args!("{name} = {text}"));
+            }
+        }
+        "full" => full(repl)?,
+        "page" => render::page(&last_result(repl)?),
+        "help" => help(rest)?,
+        "log" => log(rest)?,
+        "workspace" => workspace(repl, rest)?,
+        "table" => table(repl, rest)?,
+        "clearcache" => repl.evaluator.clear_cache(),
+        "memstats" => memstats(repl),
+        "export" => export(repl, rest)?,
+        "write" | "append" => {
+            if rest.is_empty() {
+                bail!("expected a file name after `:{command}`");
+            }
+            io::write_text(rest, &last_result(repl)?, command == "append")?;
+        }
+        "" => bail!("expected a command after `:`"),
+        other => bail!("unknown command `:{other}`"),
+    }
+    Ok(())
+}
+
+/// `:memstats` shows how much memory the variables and functions take, and
+/// what sharing the parts they have in common saves.
+fn memstats(repl: &Repl) {
+    let stats = repl.evaluator.memory();
+    render::info(format_args!(
+        "{} expressions stored, the values of variables and bodies of functions, {} of them shared",
+        stats.expressions, stats.shared
+    ));
+    render::info(format_args!(
+        "{} nodes, {} as trees without sharing, taking {:.1} KiB",
+        stats.nodes,
+        stats.tree_nodes,
+        stats.bytes as f64 / 1024.0
+    ));
+}
+
+/// `:set` lists all settings, `:set name` shows one and `:set name value` changes it.
+fn set(repl: &mut Repl, args: &str) -> color_eyre::Result<()> {
+    let mut words = args.split_whitespace();
+    match (words.next(), words.next()) {
+        (None, _) => {
+            for (name, value) in repl.evaluator.settings().list() {
+                render::info(format_args!("{name} = {value}"));
+            }
+        }
+        (Some(name), None) => {
+            let Some((_, value)) = repl.evaluator.settings().list().into_iter().find(|(n, _)| *n == name) else {
+                bail!("unknown setting `{name}`");
+            };
+            render::info(format_args!("{name} = {value}"));
+        }
+        (Some(name), Some(value)) => repl.evaluator.settings_mut().set(name, value)?,
+    }
+    Ok(())
+}
+
+/// `:history` lists previous lines with the numbers `!n` accepts,
+/// `:history filter` only lists those containing `filter`.
+fn history(repl: &Repl, filter: &str) {
+    for (n, entry) in repl.editor.history().iter().enumerate() {
+        if entry.contains(filter) {
+            render::info(format_args!("{:>5}  {entry}", n + 1));
+        }
+    }
+}
+
+/// `:alias name = text` or `:alias name = x -> body` defines an alias, `:alias name` shows one.
+fn alias(repl: &mut Repl, args: &str) -> color_eyre::Result<()> {
+    let Some((name, text)) = args.split_once('=') else {
+        let Some((_, text)) = repl.aliases.iter().find(|(n, _)| *n == args) else {
+            bail!("there is no alias named `{args}`, use `:alias name = text` to define one");
+        };
+        render::info(format_args!("{args} = {text}"));
+        return Ok(());
+    };
+    let (name, text) = (name.trim(), text.trim());
+    let name = name.strip_prefix(':').unwrap_or(name);
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        bail!("alias names may only contain letters, digits and `_`, found `{name}`");
+    }
+    repl.aliases.define(name.into(), text.into())
+}
+
+fn last_result(repl: &Repl) -> color_eyre::Result<Cow<'_, str>> {
+    repl.last_result.as_ref().map(LastResult::text).ok_or_else(|| eyre!("there is no result yet"))
+}
+
+/// `:full` prints the whole of the last result, with all of its digits and items.
+fn full(repl: &Repl) -> color_eyre::Result<()> {
+    match &repl.last_result {
+        Some(LastResult::Digits(n)) => render::digits(repl.evaluator.settings(), n, signals::interrupted),
+        Some(LastResult::Result { result, approx, .. }) => {
+            let mut settings = repl.evaluator.settings().clone();
+            (settings.exact_digits, settings.max_output, settings.max_items) = (None, None, None);
+            render::result(&format!("{}{approx}", result.display_with(&settings).colored()));
+        }
+        _ => render::result(&last_result(repl)?),
+    }
+    Ok(())
+}
+
+/// `:copy` copies the last result, `:copy expr` evaluates and copies `expr`.
+fn copy(repl: &mut Repl, expr: &str) -> color_eyre::Result<()> {
+    let text = if expr.is_empty() {
+        last_result(repl)?.to_string()
+    } else {
+        let result = repl.eval(expr)?;
+        render::result(&repl.evaluator.display(&result).colored().to_string());
+        repl.evaluator.display(&result).to_string()
+    };
+    let clipboard = match &mut repl.clipboard {
+        Some(clipboard) => clipboard,
+        None => repl.clipboard.insert(
+            arboard::Clipboard::new().map_err(|e| eyre!("could not access the clipboard: {e}"))?,
+        ),
+    };
+    clipboard.set_text(text).map_err(|e| eyre!("could not copy to the clipboard: {e}"))?;
+    render::info("copied to clipboard");
+    Ok(())
+}
+
+/// `:theme` lists themes, `:theme name key=color ...` defines one.
+fn theme(repl: &mut Repl, args: &str) -> color_eyre::Result<()> {
+    let (name, spec) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let settings = repl.evaluator.settings_mut();
+    if name.is_empty() {
+        for (name, theme) in &settings.themes {
+            let current = if *name == settings.theme { " (current)" } else { "" };
+            render::info(format_args!("{name}: {theme}{current}"));
+        }
+        return Ok(());
+    }
+    let theme = Theme::parse(spec)?;
+    settings.themes.insert(name.into(), theme);
+    Ok(())
+}
+
+/// The functions evaluated before their arguments that don't take a single expression.
+const SPECIAL_SIGNATURES: &[(&str, &str)] = &[
+    ("rearrange", "rearrange(equation, x)"),
+    ("isolate_roots", "isolate_roots(p, x, width = none)"),
+    ("odesolve", "odesolve(f, t0, y0, t1, steps = adaptive)"),
+];
+
+/// `:help` lists the functions by category, `:help name` documents one.
+fn help(name: &str) -> color_eyre::Result<()> {
+    if name.is_empty() {
+        for (category, names) in docs::categories() {
+            render::info(format_args!("{category}: {}", names.join(", ")));
+        }
+        render::info("use `:help <function>` for details");
+        return Ok(());
+    }
+    let Some(doc) = docs::lookup(name) else {
+        bail!("no help for `{name}`, `:help` lists the functions");
+    };
+    // the functions on expressions aren't builtins, and most take a single expression
+    let special = SPECIAL_SIGNATURES.iter().find(|&&(n, _)| n == name).map(|&(_, s)| s.to_owned());
+    let signature = builtins::lookup(name).map(|b| b.signature()).or(special);
+    let signature = signature.unwrap_or_else(|| format!("{name}(expr)"));
+    render::info(signature);
+    render::info(format_args!("  {}", doc.summary));
+    if let Some(domain) = doc.domain {
+        render::info(format_args!("  domain: {domain}"));
+    }
+    if doc.angle_mode {
+        render::info("  angles are in radians or degrees, see `:set angle`");
+    }
+    for example in doc.examples {
+        render::info(format_args!("  example: {example}"));
+    }
+    Ok(())
+}
+
+/// More rows than this are probably a mistake in the step.
+const MAX_TABLE_ROWS: usize = 10_000;
+
+/// `:table f from to [step]` tabulates the function `f` of one parameter.
+/// Purely numeric functions are evaluated with `f64`s all at once where that
+/// gives the same digits, others and rows where that fails, like `1/x` at
+/// zero, are evaluated one by one.
+fn table(repl: &mut Repl, args: &str) -> color_eyre::Result<()> {
+    let words: Vec<_> = args.split_whitespace().collect();
+    let (f, bounds) = match &words[..] {
+        [f, bounds @ ..] if (2..=3).contains(&bounds.len()) => (*f, bounds),
+        _ => bail!("expected `:table f from to [step]`"),
+    };
+    // the bounds are evaluated, so that they can be like `1/2` or `pi`
+    let mut bound = |s: &str| -> color_eyre::Result<(Expr, f64)> {
+        let v = repl.eval(s)?;
+        let x = real(&v).ok_or_else(|| eyre!("`{s}` is not a real number"))?;
+        Ok((v, x))
+    };
+    let ((from, low), (_, high)) = (bound(bounds[0])?, bound(bounds[1])?);
+    let (step, size) = bounds.get(2).map_or(Ok((Expr::value(Value::one()), 1.0)), |s| bound(s))?;
+    if size <= 0.0 || high < low {
+        bail!("the step must be positive and `to` can't be less than `from`");
+    }
+    let rows = ((high - low) / size + 1e-9).floor() as usize + 1;
+    if rows > MAX_TABLE_ROWS {
+        bail!("that would be {rows} rows, more than {MAX_TABLE_ROWS}");
+    }
+    let xs = (0..rows).map(|i| {
+        let offset = Expr::product(vec![Expr::value(Value::Exact(i.into())), step.clone()]);
+        repl.evaluator.eval(Expr::sum(vec![from.clone(), offset]))
+    });
+    let xs: Vec<Expr> = xs.collect::<color_eyre::Result<_>>()?;
+    let floats: Vec<f64> = xs.iter().map(|x| real(x).unwrap_or(f64::NAN)).collect();
+    let fast = repl.evaluator.eval_batch(f, &floats);
+    let mut cells = vec![];
+    for (i, x) in xs.into_iter().enumerate() {
+        let y = match fast.as_ref().map(|ys| ys[i]).filter(|y| y.is_finite()) {
+            Some(y) => {
+                let y = Value::Decimal(Float::with_val(repl.evaluator.precision(), y));
+                repl.evaluator.display(&Expr::value(y)).to_string()
+            }
+            None => match repl.evaluator.eval(Expr::apply(Expr::symbol(f), vec![x.clone()])) {
+                Ok(y) => repl.evaluator.display(&y).to_string(),
+                Err(e) => format!("error: {e}"),
+            },
+        };
+        cells.push((repl.evaluator.display(&x).to_string(), y));
+    }
+    let width = cells.iter().map(|(x, _)| x.chars().count()).max().unwrap_or(0).max(1);
+    render::info(format_args!("{:<width$}  {f}(x)", "x"));
+    for (x, y) in cells {
+        render::info(format_args!("{x:<width$}  {y}"));
+    }
+    Ok(())
+}
+
+fn real(e: &Expr) -> Option<f64> {
+    match e.as_value()? {
+        Value::Exact(r) => Some(r.to_f64()),
+        Value::Decimal(d) => Some(d.to_f64()),
+        _ => None,
+    }
+}
+
+/// `:log file` starts appending a transcript of the session to `file`, `:log off` stops.
+fn log(path: &str) -> color_eyre::Result<()> {
+    match path {
+        "" => bail!("expected a file name or `off` after `:log`"),
+        "off" => match transcript::stop() {
+            Some(path) => render::info(format_args!("stopped logging to {path}")),
+            None => bail!("there is no log being written"),
+        },
+        path => {
+            transcript::start(path)?;
+            render::info(format_args!("logging to {path}"));
+        }
+    }
+    Ok(())
+}
+
+/// `:export typst file` or `:export markdown file` writes the calculations of
+/// the session to `file` as a document, followed by options like `timestamps`.
+fn export(repl: &Repl, args: &str) -> color_eyre::Result<()> {
+    let mut words = args.split_whitespace();
+    let (Some(format), Some(path)) = (words.next(), words.next()) else {
+        bail!("expected `:export typst file` or `:export markdown file`");
+    };
+    let options = export::Options::parse(words)?;
+    let document = match format {
+        "typst" => export::typst(&repl.evaluator, &repl.session, &options),
+        "markdown" => export::markdown(&repl.evaluator, &repl.session, &options),
+        other => bail!("unknown format `{other}`, expected `typst` or `markdown`"),
+    };
+    io::write_text(path, &document, false)?;
+    render::info(format_args!("exported the session to {path}"));
+    Ok(())
+}
+
+/// `:workspace` lists workspaces, `:workspace name` creates one, and
+/// `:workspace switch name` and `:workspace delete name` do what they say.
+fn workspace(repl: &mut Repl, args: &str) -> color_eyre::Result<()> {
+    let words: Vec<_> = args.split_whitespace().collect();
+    match words[..] {
+        [] => {
+            for name in repl.workspaces.names() {
+                let current = if name == repl.workspaces.current { " (current)" } else { "" };
+                render::info(format_args!("{name}{current}"));
+            }
+        }
+        ["switch", name] => repl.workspaces.switch(&mut repl.evaluator, name, false)?,
+        ["delete", name] => repl.workspaces.delete(name)?,
+        [name] => repl.workspaces.switch(&mut repl.evaluator, name, true)?,
+        _ => bail!("expected `:workspace name`, `:workspace switch name` or `:workspace delete name`"),
+    }
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! The config file, which is a list of REPL lines (usually `:set` and `:theme`
+//! commands) that are run before the first prompt. Lines starting with `#` are
+//! comments.
+
+use std::path::PathBuf;
+
+use crate::render;
+use crate::repl::Repl;
+
+/// `$XDG_CONFIG_HOME/calq/config`, falling back to `~/.config/calq/config`,
+/// or `%APPDATA%\calq\config` on Windows where `HOME` usually isn't set.
+pub fn path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(dir.join("calq").join("config"))
+}
+
+pub fn load(repl: &mut Repl) {
+    let Some(path) = path() else { return };
+    let Ok(contents) = std::fs::read_to_string(&path) else { return };
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Err(e) = repl.run_line(line) {
+            render::error(repl.evaluator.settings(), format_args!("{}:{}: {e}", path.display(), n + 1));
+        }
+    }
+}
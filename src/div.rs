@@ -1,39 +1,107 @@
 use std::error::Error;
 use std::fmt::Display;
 
-use rug::{Float, Rational};
+use rug::{Complex, Float, Rational};
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum DivisionError {
+    /// `x/0` where `x` is not zero.
+    ByZero,
+    /// `0/0`
+    ZeroByZero,
+    /// `∞/∞`
+    InfiniteByInfinite,
+}
 
+impl Display for DivisionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            DivisionError::ByZero => "division by zero",
+            DivisionError::ZeroByZero => "0/0 is undefined",
+            DivisionError::InfiniteByInfinite => "∞/∞ is undefined",
+        })
+    }
+}
 
-#[derive(Debug, Default)]
-pub struct DivisionByZero;
+impl Error for DivisionError {}
 
-impl Display for DivisionByZero {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.pad("disivion by zero")
+/// What dividing decimals does when it runs into a [`DivisionError`].
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum DivisionPolicy {
+    /// Report the error.
+    #[default]
+    Error,
+    /// Produce ±∞ or NaN like IEEE 754 floats do.
+    Ieee,
+}
+
+/// Classifies a decimal division, `None` if it has a regular result.
+/// Note that a zero `Float` may be negative, which doesn't make it any less zero.
+pub fn classify(a: &Float, b: &Float) -> Option<DivisionError> {
+    if b.is_zero() {
+        Some(if a.is_zero() || a.is_nan() { DivisionError::ZeroByZero } else { DivisionError::ByZero })
+    } else if a.is_infinite() && b.is_infinite() {
+        Some(DivisionError::InfiniteByInfinite)
+    } else {
+        None
     }
 }
 
-impl Error for DivisionByZero {}
+/// Like [`classify`] but for complex numbers.
+pub fn classify_complex(a: &Complex, b: &Complex) -> Option<DivisionError> {
+    let infinite = |c: &Complex| c.real().is_infinite() || c.imag().is_infinite();
+    if b.is_zero() {
+        Some(if a.is_zero() { DivisionError::ZeroByZero } else { DivisionError::ByZero })
+    } else if infinite(a) && infinite(b) {
+        Some(DivisionError::InfiniteByInfinite)
+    } else {
+        None
+    }
+}
 
 pub trait CheckedDiv<T = Self> {
     type Target;
-    fn checked_div(self, other: T) -> Result<Self::Target, DivisionByZero>;
-}
-
-macro_rules! impl_checked_div {
-    ($($t:ty = $zero: expr),*$(,)?) => {$(
-        impl CheckedDiv for $t {
-            type Target = $t;
-            fn checked_div(self, other: Self) -> Result<Self::Target, DivisionByZero> {
-                if other == $zero {
-                    Err(DivisionByZero)
-                } else {
-                    Ok(self / other)
-                }
-            }
+    fn checked_div(self, other: T) -> Result<Self::Target, DivisionError>;
+}
+
+impl CheckedDiv for Rational {
+    type Target = Rational;
+    fn checked_div(self, other: Self) -> Result<Self::Target, DivisionError> {
+        if other.is_zero() {
+            Err(if self.is_zero() { DivisionError::ZeroByZero } else { DivisionError::ByZero })
+        } else {
+            Ok(self / other)
         }
-    )*};
+    }
 }
 
-impl_checked_div!(Rational = *Rational::ZERO, Float = Float::new(1));
+impl CheckedDiv for Float {
+    type Target = Float;
+    fn checked_div(self, other: Self) -> Result<Self::Target, DivisionError> {
+        match classify(&self, &other) {
+            Some(e) => Err(e),
+            None => Ok(self / other),
+        }
+    }
+}
+
+#[test]
+fn test_rational_checked_div() {
+    let r = |n: i32| Rational::from(n);
+    assert_eq!(Ok(Rational::from((1, 2))), r(1).checked_div(r(2)));
+    assert_eq!(Err(DivisionError::ByZero), r(1).checked_div(r(0)));
+    assert_eq!(Err(DivisionError::ZeroByZero), r(0).checked_div(r(0)));
+}
+
+#[test]
+fn test_float_checked_div() {
+    let f = |x: f64| Float::with_val(53, x);
+    assert_eq!(Ok(f(0.5)), f(1.0).checked_div(f(2.0)));
+    assert_eq!(Err(DivisionError::ByZero), f(1.0).checked_div(f(0.0)));
+    assert_eq!(Err(DivisionError::ByZero), f(1.0).checked_div(f(-0.0)));
+    assert_eq!(Err(DivisionError::ZeroByZero), f(-0.0).checked_div(f(0.0)));
+    assert_eq!(Err(DivisionError::InfiniteByInfinite), f(f64::INFINITY).checked_div(f(f64::NEG_INFINITY)));
+    assert_eq!(Ok(f(0.0)), f(1.0).checked_div(f(f64::INFINITY)));
+    // a tiny divisor is not zero, whatever its precision
+    assert!(f(1.0).checked_div(Float::with_val(1, 1) >> 1000).is_ok());
+}
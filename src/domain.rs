@@ -0,0 +1,36 @@
+use std::error::Error;
+use std::fmt::Display;
+
+/// A function was called with an argument it has no (real) value for.
+#[derive(Debug)]
+pub struct DomainError {
+    /// The call as the user would write it, e.g. `sqrt(-4)`.
+    pub call: String,
+    /// Why it has no value, e.g. "negative numbers have no real square root".
+    pub reason: &'static str,
+    /// Expressions that might be what the user meant instead.
+    pub suggestions: Vec<String>,
+}
+
+impl DomainError {
+    pub fn new(func: &str, arg: impl Display, reason: &'static str) -> DomainError {
+        DomainError { call: format!("{func}({arg})"), reason, suggestions: vec![] }
+    }
+
+    pub fn suggest(mut self, suggestion: impl Into<String>) -> DomainError {
+        self.suggestions.push(suggestion.into());
+        self
+    }
+}
+
+impl Display for DomainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` is undefined: {}", self.call, self.reason)?;
+        for s in &self.suggestions {
+            write!(f, "\n  help: try `{s}`")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for DomainError {}
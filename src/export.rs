@@ -0,0 +1,152 @@
+//! `:export typst report.typ` and `:export markdown notes.md`, the calculations
+//! of the session written out as a document, to turn into a report or paste
+//! into documentation. Typst gets the inputs and results as math, Markdown the
+//! inputs as code and the results as text.
+//!
+//! Lines starting with `#` are comments, which are only kept for exporting.
+//! `comments` after the file name includes them, and `timestamps` the time
+//! each line was entered.
+
+use std::time::SystemTime;
+
+use calq::expr::{Evaluator, Expr, Node};
+use calq::transcript::timestamp;
+use color_eyre::eyre::bail;
+
+/// Something entered in the session.
+pub enum Entry {
+    /// A statement that was evaluated, and its result.
+    Step { input: Expr, result: Expr, time: SystemTime },
+    /// A line starting with `#`.
+    Comment { text: String, time: SystemTime },
+}
+
+#[derive(Default)]
+pub struct Options {
+    pub timestamps: bool,
+    pub comments: bool,
+}
+
+impl Options {
+    pub fn parse<'a>(words: impl IntoIterator<Item = &'a str>) -> color_eyre::Result<Options> {
+        let mut options = Options::default();
+        for word in words {
+            match word {
+                "timestamps" => options.timestamps = true,
+                "comments" => options.comments = true,
+                other => bail!("unknown export option `{other}`, expected `timestamps` or `comments`"),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// The entries to export, with the time each was entered.
+fn entries<'a>(entries: &'a [Entry], options: &Options) -> impl Iterator<Item = (&'a Entry, SystemTime)> {
+    let comments = options.comments;
+    entries.iter().filter_map(move |entry| match entry {
+        Entry::Step { time, .. } => Some((entry, *time)),
+        Entry::Comment { time, .. } => comments.then_some((entry, *time)),
+    })
+}
+
+/// The step as one equation, `input = result`, or just the input if the result
+/// is the same, like for `x = 2` or a function definition.
+fn equation(evaluator: &Evaluator, input: &Expr, result: &Expr) -> String {
+    let typst = evaluator.typst(input);
+    let given = match input.root().node() {
+        Node::Define(..) | Node::DefineUnit(..) => return typst,
+        &Node::Assign(_, _, value) | &Node::Unpack(_, value) => evaluator.typst(&input.root().get(value).to_expr()),
+        _ => typst.clone(),
+    };
+    let result = evaluator.typst(result);
+    if given == result { typst } else { format!("{typst} = {result}") }
+}
+
+/// Text that Typst or Markdown shows as it is, with the characters that mean
+/// something to them escaped.
+fn escape(text: &str, special: &[char]) -> String {
+    text.chars().fold(String::new(), |mut out, c| {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+        out
+    })
+}
+
+/// The entries as a Typst document, one equation after another.
+pub fn typst(evaluator: &Evaluator, session: &[Entry], options: &Options) -> String {
+    let mut out = String::from("= Calculations\n");
+    for (entry, time) in entries(session, options) {
+        out.push('\n');
+        if options.timestamps {
+            out.push_str(&format!("#text(size: 0.8em)[{}]\n", timestamp(time)));
+        }
+        match entry {
+            Entry::Step { input, result, .. } => out.push_str(&format!("$ {} $\n", equation(evaluator, input, result))),
+            Entry::Comment { text, .. } => {
+                out.push_str(&escape(text, &['\\', '#', '$', '*', '_', '`', '<', '@', '=', '-', '+', '/', '[', ']']));
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// A code span that holds `text`, even if it has backticks in it.
+fn code(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let ticks = "`".repeat(longest + 1);
+    let pad = if text.starts_with('`') || text.ends_with('`') { " " } else { "" };
+    format!("{ticks}{pad}{text}{pad}{ticks}")
+}
+
+/// The entries as Markdown, a list item for each, like ``- `f(2)` → 4``.
+pub fn markdown(evaluator: &Evaluator, session: &[Entry], options: &Options) -> String {
+    let mut out = String::from("# Calculations\n\n");
+    for (entry, time) in entries(session, options) {
+        out.push_str("- ");
+        if options.timestamps {
+            out.push_str(&format!("{} ", timestamp(time)));
+        }
+        match entry {
+            Entry::Step { input, result, .. } => {
+                out.push_str(&code(&evaluator.display(input).to_string()));
+                if !matches!(input.root().node(), Node::Define(..) | Node::DefineUnit(..)) {
+                    let result = evaluator.display(result).to_string();
+                    out.push_str(&format!(" → {}", escape(&result, &['\\', '*', '_', '`', '[', ']', '<', '>', '|'])));
+                }
+            }
+            Entry::Comment { text, .. } => out.push_str(&format!("*{}*", escape(text, &['\\', '*', '_', '`']))),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+fn test_export() {
+    let mut evaluator = Evaluator::default();
+    let time = SystemTime::UNIX_EPOCH;
+    let mut session = vec![Entry::Comment { text: "the area of a circle".into(), time }];
+    for line in ["r = 3/2", "area(r) = pi*r^2", "area(r)", "sqrt(r)"] {
+        let input = calq::expr::parse(&evaluator, line).0.remove(0);
+        let result = evaluator.eval(input.clone()).unwrap();
+        session.push(Entry::Step { input, result, time });
+    }
+    evaluator.settings_mut().set("digits", "5").unwrap();
+
+    let document = typst(&evaluator, &session, &Options::default());
+    let equations =
+        ["r = frac(3, 2)", "op(\"area\")(r) = pi dot r^(2)", "op(\"area\")(r) = 7.0686", "sqrt(r) = 1.2247"];
+    let expected: String = equations.iter().map(|eq| format!("\n$ {eq} $\n")).collect();
+    assert_eq!(format!("= Calculations\n{expected}"), document);
+
+    let options = Options::parse(["comments", "timestamps"]).unwrap();
+    let document = markdown(&evaluator, &session, &options);
+    let lines: Vec<_> = document.lines().skip(2).map(|l| l.trim_start_matches("- 1970-01-01 00:00:00Z ")).collect();
+    let expected = ["*the area of a circle*", "`r = 3/2` → 3/2", "`area(r) = pi*r^2`", "`area(r)` → 7.0686"];
+    assert_eq!(expected, lines[..4]);
+    assert_eq!("``a`b``", code("a`b"));
+}
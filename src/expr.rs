@@ -1,29 +1,136 @@
 use core::fmt;
-use std::convert::Infallible;
-use std::ops::Neg;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
 
-use chumsky::prelude::*;
+use color_eyre::eyre::bail;
 use rug::float::Round;
-use rug::ops::{CompleteRound, DivAssignRound};
-use rug::{Complete, Float, Integer, Rational};
+use rug::ops::{AddAssignRound, CompleteRound, DivAssignRound, MulAssignRound, Pow, PowAssignRound, SubAssignRound};
+use rug::{Complex, Float, Integer, Rational};
 
-use crate::div::CheckedDiv;
+use crate::div::{self, CheckedDiv, DivisionError, DivisionPolicy};
+use self::quaternion::Quaternion;
+use crate::settings::{NonFinite, Rounding, Settings};
 
+pub mod builtins;
+mod annotation;
+mod arena;
+mod batch;
+mod bounds;
+mod bytecode;
+mod cancellation;
+mod exact;
+mod fold;
+mod introspect;
+mod lexer;
+mod loops;
+mod matrix;
+mod memo;
+mod money;
+mod numeric;
+mod ode;
+mod parser;
 mod print;
+mod quaternion;
+mod rearrange;
+mod roots;
+mod store;
+mod rewrite;
+mod units;
+
+pub use annotation::Annotation;
+pub use bounds::ErrorBar;
+pub use arena::{Expr, ExprId, ExprRef, Literal, Node};
+pub use lexer::{exponent_len, radix_literal_len, sexagesimal_len, KEYWORDS, SEPARATORS};
+pub use parser::{parse, ParseErrorKind};
+pub use print::{columns, digits};
+pub use store::MemStats;
+pub use units::is_builtin as is_unit;
 // mod trig;
 
+#[derive(Clone)]
 pub enum Value {
     Exact(Rational),
     Decimal(Float),
+    Complex(Complex),
+    Quaternion(Box<Quaternion>),
+    List(Vec<Value>),
+    Tuple(Vec<Value>),
+    Str(String),
+    /// A number with a unit, like `9.81 m/s^2`.
+    Quantity(Box<units::Quantity>),
+}
+
+/// Exact results with more bits than this are refused rather than taking forever.
+const MAX_POW_BITS: u64 = 1 << 26;
+
+/// Expressions nested deeper than this are refused, as parsing, evaluating
+/// and printing them recurses and would run out of stack.
+pub const MAX_DEPTH: usize = 500;
+
+/// The names of the constants, which variables shadow with a warning.
+const CONSTANTS: [&str; 3] = ["i", "pi", "e"];
+
+/// Whether `name` is a builtin function, which user defined functions can't replace.
+fn is_builtin(name: &str) -> bool {
+    builtins::lookup(name).is_some()
+        || introspect::lookup(name).is_some()
+        || [numeric::NAME, rearrange::NAME, roots::NAME, ode::NAME].contains(&name)
+}
+
+fn check_depth(e: &Expr) -> color_eyre::Result<()> {
+    if e.nesting() > MAX_DEPTH {
+        bail!("the result is nested more than {MAX_DEPTH} deep");
+    }
+    Ok(())
+}
+
+fn exact_pow(a: Rational, n: &Integer) -> color_eyre::Result<Rational> {
+    let Some(n) = n.to_i32() else {
+        bail!("the exponent {n} is too large");
+    };
+    if a.is_zero() && n < 0 {
+        return Err(DivisionError::ByZero.into());
+    }
+    // common enough not to bother GMP with
+    if n == 1 || (n > 0 && a.is_zero()) || a == 1 {
+        return Ok(a);
+    }
+    let bits = a.numer().significant_bits().max(a.denom().significant_bits()) as u64;
+    if bits.saturating_mul(n.unsigned_abs() as u64) > MAX_POW_BITS {
+        bail!("the result of raising to the power of {n} would be too large");
+    }
+    Ok(a.pow(n))
 }
 
-fn perform_op<E>(
+/// What kind of operand a list or tuple is, whether or not its items are values.
+fn sequence_kind(e: &Expr) -> Option<&'static str> {
+    match e.root().node() {
+        Node::List(_) | Node::Value(Value::List(_)) => Some("a list"),
+        Node::Tuple(_) | Node::Value(Value::Tuple(_)) => Some("a tuple"),
+        _ => None,
+    }
+}
+
+/// Rejects arithmetic on a list or tuple with symbols in it, like it is
+/// rejected on one of numbers, rather than leaving `[a, b] + [1, 2]` as it is.
+fn check_operands(a: &Expr, b: &Expr) -> color_eyre::Result<()> {
+    if sequence_kind(a).is_none() && sequence_kind(b).is_none() {
+        return Ok(());
+    }
+    let kind = |e: &Expr| sequence_kind(e).or(e.as_value().map(Value::kind)).unwrap_or("an expression");
+    bail!("cannot perform arithmetic on {} and {}", kind(a), kind(b))
+}
+
+fn perform_op(
     a: Value,
     b: Value,
-    do_exact: fn(Rational, Rational) -> Result<Rational, E>,
-    do_decimal: fn(Float, Float, &Evaluator) -> Result<Float, E>,
+    do_exact: fn(Rational, Rational) -> color_eyre::Result<Rational>,
+    do_decimal: fn(Float, Float, &Evaluator) -> color_eyre::Result<Float>,
+    do_complex: fn(Complex, Complex, &Evaluator) -> color_eyre::Result<Complex>,
+    do_quaternion: fn(Quaternion, Quaternion) -> color_eyre::Result<Quaternion>,
     evaluator: &Evaluator,
-) -> Result<Value, E> {
+) -> color_eyre::Result<Value> {
     let conv = |x: Rational| {
         Float::with_val_round(evaluator.precision(), x, evaluator.round()).0
     };
@@ -32,228 +139,880 @@ fn perform_op<E>(
         (Value::Decimal(a), Value::Exact(b)) => do_decimal(a, conv(b), evaluator).map(Value::Decimal),
         (Value::Exact(a), Value::Decimal(b)) => do_decimal(conv(a), b, evaluator).map(Value::Decimal),
         (Value::Decimal(a), Value::Decimal(b)) => do_decimal(a, b, evaluator).map(Value::Decimal),
+        (a @ Value::Quaternion(_), b) | (a, b @ Value::Quaternion(_)) => {
+            match (evaluator.to_quaternion(a), evaluator.to_quaternion(b)) {
+                (Some(a), Some(b)) => do_quaternion(a, b).map(|q| Value::Quaternion(Box::new(q))),
+                _ => bail!("cannot perform arithmetic on a quaternion and a non-number"),
+            }
+        }
+        (a @ Value::Complex(_), b) | (a, b @ Value::Complex(_)) => {
+            match (evaluator.to_complex(a), evaluator.to_complex(b)) {
+                (Some(a), Some(b)) => do_complex(a, b, evaluator).map(Value::Complex),
+                (a, b) => bail!(
+                    "cannot perform arithmetic on {} and {}",
+                    if a.is_some() { "a complex number" } else { "a non-number" },
+                    if b.is_some() { "a complex number" } else { "a non-number" },
+                ),
+            }
+        }
+        (a, b) => bail!("cannot perform arithmetic on {} and {}", a.kind(), b.kind()),
     }
 }
 
 macro_rules! op_impl {
-    ($name:ident($op:tt)) => {
-        fn $name(self, other: Value, e: &Evaluator) -> Value {
-            match perform_op::<Infallible>(self, other, |a, b| Ok(a $op b), |a, b, _| Ok(a $op b), e) {
-                Ok(x) => x,
-                Err(e) => match e {}
+    ($name:ident($op:tt, $assign_round:ident)) => {
+        fn $name(self, other: Value, e: &Evaluator) -> color_eyre::Result<Value> {
+            if self.is_quantity() || other.is_quantity() {
+                return units::arithmetic(self, stringify!($op), other, e);
             }
+            perform_op(
+                self,
+                other,
+                |a, b| Ok(a $op b),
+                |mut a, b, e| {
+                    a.$assign_round(b, e.round());
+                    Ok(a)
+                },
+                |mut a, b, e| {
+                    a.$assign_round(b, (e.round(), e.round()));
+                    Ok(a)
+                },
+                |a, b| Ok(a $op b),
+                e,
+            )
         }
     };
 }
 
 impl Value {
-    op_impl!(add(+));
-    op_impl!(sub(-));
-    op_impl!(mul(*));
-    fn div(self, other: Value, evaluator: &Evaluator) -> Result<Value, crate::div::DivisionByZero> {
-        perform_op(self, other, CheckedDiv::checked_div, |mut a, b, e| {
+    op_impl!(add(+, add_assign_round));
+    op_impl!(sub(-, sub_assign_round));
+    op_impl!(mul(*, mul_assign_round));
+    fn div(self, other: Value, evaluator: &Evaluator) -> color_eyre::Result<Value> {
+        if self.is_quantity() || other.is_quantity() {
+            return units::arithmetic(self, "/", other, evaluator);
+        }
+        perform_op(self, other, |a, b| Ok(a.checked_div(b)?), |mut a, b, e| {
+            if e.settings.division == DivisionPolicy::Error {
+                if let Some(err) = div::classify(&a, &b) {
+                    return Err(err.into());
+                }
+            }
             a.div_assign_round(b, e.round());
             Ok(a)
+        }, |mut a, b, e| {
+            if e.settings.division == DivisionPolicy::Error {
+                if let Some(err) = div::classify_complex(&a, &b) {
+                    return Err(err.into());
+                }
+            }
+            a.div_assign_round(b, (e.round(), e.round()));
+            Ok(a)
+        }, |a, b| {
+            // right division, `a * b^-1`
+            Ok(a * b.inverse().ok_or(DivisionError::ByZero)?)
         }, evaluator)
     }
-}
 
-impl Neg for Value {
-    type Output = Value;
-    fn neg(self) -> Self::Output {
+    fn pow(self, exp: Value, e: &Evaluator) -> color_eyre::Result<Value> {
+        if self.is_quantity() || exp.is_quantity() {
+            return units::arithmetic(self, "^", exp, e);
+        }
+        let real = |v: &Value| matches!(v, Value::Exact(_) | Value::Decimal(_));
+        match (self, exp) {
+            (Value::Exact(a), Value::Exact(b)) if *b.denom() == 1 => exact_pow(a, b.numer()).map(Value::Exact),
+            (a @ Value::Quaternion(_), b) | (a, b @ Value::Quaternion(_)) => {
+                bail!("cannot raise {} to the power of {}", a.kind(), b.kind())
+            }
+            (a, b) if real(&a) && real(&b) => {
+                let (mut a, b) = (e.to_float(a), e.to_float(b));
+                // a negative base with a fractional exponent has a complex result
+                if a < 0 && !b.is_integer() {
+                    let mut a = Complex::with_val(e.precision(), a);
+                    a.pow_assign_round(b, (e.round(), e.round()));
+                    return Ok(Value::Complex(a));
+                }
+                a.pow_assign_round(b, e.round());
+                Ok(Value::Decimal(a))
+            }
+            (a, b) => {
+                let (kind_a, kind_b) = (a.kind(), b.kind());
+                match (e.to_complex(a), e.to_complex(b)) {
+                    (Some(mut a), Some(b)) => {
+                        a.pow_assign_round(b, (e.round(), e.round()));
+                        Ok(Value::Complex(a))
+                    }
+                    _ => bail!("cannot raise {kind_a} to the power of {kind_b}"),
+                }
+            }
+        }
+    }
+
+    /// Exact `0`, which doesn't allocate.
+    pub fn zero() -> Value {
+        Value::Exact(Rational::new())
+    }
+
+    /// Exact `1`.
+    pub fn one() -> Value {
+        Value::Exact(Rational::ONE.clone())
+    }
+
+    /// Describes the first NaN or infinity found in this value, if any.
+    pub fn non_finite(&self) -> Option<&'static str> {
+        let float = |f: &Float| if f.is_nan() { Some("NaN") } else if f.is_infinite() { Some("infinite") } else { None };
+        match self {
+            Value::Exact(_) | Value::Str(_) => None,
+            Value::Decimal(d) => float(d),
+            Value::Complex(c) => float(c.real()).or_else(|| float(c.imag())),
+            Value::Quaternion(q) => [&q.w, &q.x, &q.y, &q.z].into_iter().find_map(float),
+            Value::List(items) | Value::Tuple(items) => items.iter().find_map(Value::non_finite),
+            Value::Quantity(q) => q.magnitude.non_finite(),
+        }
+    }
+
+    /// Whether this is zero or empty, which counts as false, like `expr` does.
+    pub fn is_zero_or_empty(&self) -> bool {
+        match self {
+            Value::Exact(r) => r.is_zero(),
+            Value::Decimal(d) => d.is_zero(),
+            Value::Complex(c) => c.is_zero(),
+            Value::Quaternion(q) => q.is_zero(),
+            Value::List(items) | Value::Tuple(items) => items.is_empty(),
+            Value::Str(s) => s.is_empty(),
+            Value::Quantity(q) => q.magnitude.is_zero_or_empty(),
+        }
+    }
+
+    /// A short description of what kind of value this is, for error messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Exact(r) if *r.denom() == 1 => "an integer",
+            Value::Exact(_) => "a rational",
+            Value::Decimal(_) => "a decimal",
+            Value::Complex(_) => "a complex number",
+            Value::Quaternion(_) => "a quaternion",
+            Value::List(_) => "a list",
+            Value::Tuple(_) => "a tuple",
+            Value::Str(_) => "a string",
+            Value::Quantity(_) => "a quantity",
+        }
+    }
+
+    fn is_quantity(&self) -> bool {
+        matches!(self, Value::Quantity(_))
+    }
+
+    /// Returns the integer this value holds, or an error if it is not an exact integer.
+    pub fn as_integer(&self) -> color_eyre::Result<&Integer> {
         match self {
+            Value::Exact(r) if *r.denom() == 1 => Ok(r.numer()),
+            other => bail!("expected an integer, found {}", other.kind()),
+        }
+    }
+
+    pub fn as_str(&self) -> color_eyre::Result<&str> {
+        match self {
+            Value::Str(s) => Ok(s),
+            other => bail!("expected a string, found {}", other.kind()),
+        }
+    }
+}
+
+impl Value {
+    fn neg(self) -> color_eyre::Result<Value> {
+        Ok(match self {
             Value::Exact(e) => Value::Exact(-e),
             Value::Decimal(d) => Value::Decimal(-d),
-        }
+            Value::Complex(c) => Value::Complex(-c),
+            Value::Quaternion(q) => Value::Quaternion(Box::new(Quaternion {
+                w: -q.w,
+                x: -q.x,
+                y: -q.y,
+                z: -q.z,
+            })),
+            Value::List(l) => Value::List(l.into_iter().map(Value::neg).collect::<color_eyre::Result<_>>()?),
+            Value::Tuple(t) => Value::Tuple(t.into_iter().map(Value::neg).collect::<color_eyre::Result<_>>()?),
+            Value::Str(_) => bail!("cannot negate a string"),
+            Value::Quantity(mut q) => {
+                q.magnitude = q.magnitude.neg()?;
+                Value::Quantity(q)
+            }
+        })
     }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Value::Exact(r) => r.fmt(f),
-            // TODO print in scientific notation when possible
-            Value::Decimal(d) => d.fmt(f),
-        }
+        print::Printer::new(f, &Settings::default()).print_value(self)
     }
 }
 
-pub enum PrecisionMode {
-    Decent,
+/// A function defined with `f(x) = expr`, or by cases like `f(0) = 1` and
+/// `f(n) = n*f(n-1)`, which are tried in the order they were defined in.
+#[derive(Clone, Default)]
+struct Function {
+    cases: Vec<Arc<Case>>,
 }
 
-impl PrecisionMode {
-    fn precision(&self) -> u32 {
-        match self {
-            PrecisionMode::Decent => 100,
+/// One definition of a function.
+struct Case {
+    /// The names of the parameters, or the integers the arguments must be.
+    params: Vec<String>,
+    body: Expr,
+    /// Defined with `memo f(n) = expr`.
+    memo: bool,
+    /// The body compiled for calls with values, if it can be.
+    code: Option<bytecode::Code>,
+}
+
+/// The integer a parameter like the `0` in `f(0) = 1` must be, `None` for names.
+fn pattern(param: &str) -> Option<Rational> {
+    Integer::parse(param).ok().map(|n| Rational::from(Integer::from(n)))
+}
+
+impl Function {
+    /// The definition of a function that isn't defined by cases, the only kind
+    /// that is compiled or evaluated in batches.
+    fn single(&self) -> Option<&Arc<Case>> {
+        match &self.cases[..] {
+            [case] if case.params.iter().all(|p| pattern(p).is_none()) => Some(case),
+            _ => None,
         }
     }
 }
 
+impl Case {
+    fn matches(&self, args: &[Value]) -> bool {
+        self.params.iter().zip(args).all(|(param, arg)| match pattern(param) {
+            Some(n) => matches!(arg, Value::Exact(r) if *r == n),
+            None => true,
+        })
+    }
+}
+
+/// How deeply user defined functions may call each other before giving up.
+/// The stack grows as they do, see [`grow_stack`].
+const MAX_CALL_DEPTH: usize = 10_000;
+
+/// Runs `f` with at least a little more stack than evaluating a node or calling
+/// a user defined function takes, on a new segment when the current one is
+/// almost used up, so that deep recursion doesn't overflow the stack of the
+/// thread it is on.
+fn grow_stack<T>(f: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(256 * 1024, 4 * 1024 * 1024, f)
+}
+
+#[derive(Clone)]
 pub struct Evaluator {
-    precision: PrecisionMode,
+    settings: Settings,
+    /// Whether functions like `write` may touch the outside world, off for previews.
+    side_effects: bool,
+    /// Variables assigned with `x = expr`.
+    variables: BTreeMap<String, Expr>,
+    /// The annotations of variables assigned with `x: int = expr`.
+    annotations: BTreeMap<String, Annotation>,
+    /// Units defined with `unit name = value`.
+    units: BTreeMap<String, units::Definition>,
+    functions: BTreeMap<String, Function>,
+    /// The arguments of the user defined functions being called, innermost last.
+    scopes: Vec<BTreeMap<String, Expr>>,
+    /// Results of `memo` functions, see `expr/memo.rs`.
+    memos: memo::Cache,
+    /// Bits lost to cancellation by the statement being evaluated, see `expr/cancellation.rs`.
+    cancellation: Option<cancellation::Cancellation>,
+    /// The values of variables and bodies of functions, shared where they are the same.
+    store: store::Store,
+    /// Warnings about the statement evaluated last, like a constant being shadowed.
+    warnings: Vec<String>,
+    /// When evaluating gives up, for previews that mustn't hold up typing.
+    deadline: Option<Instant>,
 }
 
 impl Default for Evaluator {
     fn default() -> Self {
-        Evaluator { precision: PrecisionMode::Decent }
+        Evaluator {
+            settings: Settings::default(),
+            side_effects: true,
+            variables: BTreeMap::new(),
+            annotations: BTreeMap::new(),
+            units: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            scopes: vec![],
+            memos: BTreeMap::new(),
+            cancellation: None,
+            store: store::Store::default(),
+            warnings: vec![],
+            deadline: None,
+        }
+    }
+}
+
+impl From<Rounding> for Round {
+    fn from(rounding: Rounding) -> Round {
+        match rounding {
+            Rounding::Nearest => Round::Nearest,
+            Rounding::Zero => Round::Zero,
+            Rounding::Up => Round::Up,
+            Rounding::Down => Round::Down,
+        }
     }
 }
 
 impl Evaluator {
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        self.clear_cache();
+        &mut self.settings
+    }
+    /// The units, variables and functions defined so far, as the statements that define them.
+    pub fn definitions(&self) -> impl Iterator<Item = Expr> + '_ {
+        let variables = self.variables.iter().map(|(name, value)| {
+            Expr::assign(name.clone(), self.annotations.get(name).cloned(), value.clone())
+        });
+        let functions = self.functions.iter().flat_map(|(name, f)| {
+            f.cases.iter().map(|case| Expr::define(name.clone(), case.params.clone(), case.body.clone(), case.memo))
+        });
+        self.unit_definitions().chain(variables).chain(functions)
+    }
+    /// Warnings about the statement evaluated last, which didn't stop it.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+    /// Makes functions with side effects fail instead.
+    pub fn forbid_side_effects(&mut self) {
+        self.side_effects = false;
+    }
+    /// Makes evaluating fail once `deadline` has passed. A single builtin
+    /// call, like a huge `primorial`, still runs to its end.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+    /// Forgets the calls in progress when an evaluation stopped halfway, by a panic.
+    pub fn abandon(&mut self) {
+        self.scopes.clear();
+        self.finish_cancellation();
+    }
+    /// Called by functions with side effects before doing anything.
+    fn side_effect(&self, function: &str) -> color_eyre::Result<()> {
+        if !self.side_effects {
+            bail!("`{function}` has side effects, which aren't allowed here");
+        }
+        Ok(())
+    }
+    /// Converts a real value to a `Float`.
+    fn to_float(&self, v: Value) -> Float {
+        match v {
+            Value::Exact(r) => Float::with_val_round(self.precision(), r, self.round()).0,
+            Value::Decimal(d) => d,
+            _ => unreachable!("only called with real values"),
+        }
+    }
+    /// Converts any numeric value to a complex number, `None` for non-numbers.
+    fn to_complex(&self, v: Value) -> Option<Complex> {
+        let prec = (self.precision(), self.precision());
+        match v {
+            Value::Exact(r) => Some(Complex::with_val(prec, r)),
+            Value::Decimal(d) => Some(Complex::with_val(prec, d)),
+            Value::Complex(c) => Some(c),
+            _ => None,
+        }
+    }
+    /// Converts any numeric value to a quaternion, `None` for non-numbers.
+    fn to_quaternion(&self, v: Value) -> Option<Quaternion> {
+        let zero = || Float::new(self.precision());
+        match v {
+            Value::Quaternion(q) => Some(*q),
+            other => {
+                let (w, x) = self.to_complex(other)?.into_real_imag();
+                Some(Quaternion { w, x, y: zero(), z: zero() })
+            }
+        }
+    }
+    /// The value of a variable where it is used, see [`Annotation::read`].
+    fn variable(&self, name: &str) -> Option<Expr> {
+        let value = self.variables.get(name)?;
+        Some(match (self.annotations.get(name), value.as_value()) {
+            (Some(annotation), Some(v)) => Expr::value(annotation.read(self, v)),
+            _ => value.clone(),
+        })
+    }
+    /// Looks up a named constant, one of [`CONSTANTS`], none of which are
+    /// exact, so they are left symbolic in `strict-exact` and `symbolic` mode.
+    fn constant(&self, name: &str) -> Option<Value> {
+        if self.settings.strict_exact || self.settings.symbolic {
+            return None;
+        }
+        let prec = self.precision();
+        match name {
+            "i" => Some(Value::Complex(Complex::with_val((prec, prec), (0, 1)))),
+            "pi" => Some(Value::Decimal(Float::with_val(prec, rug::float::Constant::Pi))),
+            "e" => Some(Value::Decimal(Float::with_val(prec, 1).exp())),
+            _ => None,
+        }
+    }
     fn round(&self) -> Round {
-        Round::Nearest
+        self.settings.rounding.into()
     }
-    fn precision(&self) -> u32 {
-        self.precision.precision()
+    /// The precision of decimals, in bits.
+    pub fn precision(&self) -> u32 {
+        self.settings.precision
     }
     fn complete<C: CompleteRound<Prec = u32, Round = Round>>(&self, c: C) -> C::Completed {
         c.complete_round(self.precision(), self.round()).0
     }
+    // perform a binary operation implemented numerically.
+    fn numeric_op(
+        &mut self,
+        a: Value,
+        b: Value,
+        op: char,
+        numerical: fn(Value, Value, &mut Evaluator) -> color_eyre::Result<Value>,
+    ) -> color_eyre::Result<Value> {
+        let finite = a.non_finite().is_none() && b.non_finite().is_none();
+        let largest = self.cancellation.and_then(|_| cancellation::sum_exponent(op, &a, &b));
+        let mut v = numerical(a, b, self)?;
+        if let Some(largest) = largest {
+            self.track_cancellation(largest, &v);
+        }
+        if let Some(digits) = self.settings.money {
+            v = money::round(v, digits);
+        }
+        // `division` already decided what dividing by zero does
+        if finite && (op != '/' || self.settings.division == DivisionPolicy::Error) {
+            self.check_finite(&v, || format!("`{op}`"))?;
+        }
+        Ok(v)
+    }
+
     // evaluate a binary operation implemented numerically.
     fn eval_binop(
         &mut self,
-        a: Expr,
-        b: Expr,
+        a: ExprRef,
+        b: ExprRef,
+        op: char,
         numerical: fn(Value, Value, &mut Evaluator) -> color_eyre::Result<Value>,
-        fallback: fn(Expr, Expr) -> Expr,
+        node: fn(ExprId, ExprId) -> Node,
     ) -> color_eyre::Result<Expr> {
-        match (self.eval(a)?, self.eval(b)?) {
-            (Expr::Value(a), Expr::Value(b)) => numerical(a, b, self).map(Expr::Value),
-            (a, b) => Ok(fallback(a, b)),
+        let (a, b) = (self.eval_at(a)?, self.eval_at(b)?);
+        match (a.into_value(), b.into_value()) {
+            (Ok(a), Ok(b)) => {
+                let operands = self.may_stay_symbolic([&a, &b]).then(|| (a.clone(), b.clone()));
+                match (self.numeric_op(a, b, op, numerical)?, operands) {
+                    (v, Some((a, b))) if !v.is_exact() => Ok(Expr::binary(Expr::value(a), Expr::value(b), node)),
+                    (v, _) => Ok(Expr::value(v)),
+                }
+            }
+            (a, b) => {
+                let (a, b) = (a.map_or_else(|e| e, Expr::value), b.map_or_else(|e| e, Expr::value));
+                check_operands(&a, &b)?;
+                Ok(Expr::binary(a, b, node))
+            }
         }
     }
 
-    pub fn eval(&mut self, e: Expr) -> color_eyre::Result<Expr> {
-        Ok(match e {
-            Expr::Value(val) => Expr::Value(val),
-            Expr::Symbol(s) => Expr::Symbol(s),
-            Expr::Add(values) => {
-                let (a, b) = *values;
-                self.eval_binop(a, b, |a, b, e| Ok(a.add(b, &*e)), |a, b| Expr::Add(Box::new((a, b))))?
+    /// Evaluates a sum or product, folding operands next to each other that
+    /// are values, so that `x+1+2` becomes `x+3` but `1+x+2` stays as it is.
+    /// When constants are kept symbolic all exact numbers are folded into the
+    /// first one, so that `2*pi*3` is `6*pi`.
+    fn eval_nary(
+        &mut self,
+        items: Vec<ExprRef>,
+        op: char,
+        numerical: fn(Value, Value, &mut Evaluator) -> color_eyre::Result<Value>,
+        node: fn(Vec<Expr>) -> Expr,
+    ) -> color_eyre::Result<Expr> {
+        let mut folded: Vec<Expr> = Vec::with_capacity(items.len());
+        let symbolic = self.settings.strict_exact || self.settings.symbolic;
+        // where the exact numbers are folded into
+        let mut exact: Option<usize> = None;
+        for item in items {
+            let item = self.eval_at(item)?;
+            if let (Some(i), Some(b @ Value::Exact(_))) = (exact, item.as_value()) {
+                if let Some(a @ Value::Exact(_)) = folded[i].as_value() {
+                    folded[i] = Expr::value(self.numeric_op(a.clone(), b.clone(), op, numerical)?);
+                    continue;
+                }
+            }
+            match (folded.last().and_then(Expr::as_value), item.as_value()) {
+                (Some(a), Some(b)) => {
+                    let operands = self.may_stay_symbolic([a, b]).then(|| (a.clone(), b.clone()));
+                    let a = folded.pop().unwrap().into_value().ok().unwrap();
+                    let b = item.into_value().ok().unwrap();
+                    match (self.numeric_op(a, b, op, numerical)?, operands) {
+                        (v, Some((a, b))) if !v.is_exact() => folded.extend([Expr::value(a), Expr::value(b)]),
+                        (v, _) => folded.push(Expr::value(v)),
+                    }
+                }
+                _ => {
+                    if let Some(last) = folded.last() {
+                        check_operands(last, &item)?;
+                    }
+                    folded.push(item);
+                }
+            }
+            if symbolic && exact.is_none() && matches!(folded.last().and_then(Expr::as_value), Some(Value::Exact(_))) {
+                exact = Some(folded.len() - 1);
+            }
+        }
+        Ok(match <[Expr; 1]>::try_from(folded) {
+            Ok([e]) => e,
+            Err(folded) => node(folded),
+        })
+    }
+
+    /// Rejects a freshly computed NaN or infinity unless `nonfinite` is set to propagate them.
+    fn check_finite(&self, v: &Value, what: impl FnOnce() -> String) -> color_eyre::Result<()> {
+        match v.non_finite() {
+            Some(kind) if self.settings.nonfinite == NonFinite::Error => {
+                bail!("{} produced a result that is {kind}, `:set nonfinite propagate` to allow this", what())
             }
-            Expr::Sub(values) => {
-                let (a, b) = *values;
-                self.eval_binop(a, b, |a, b, e| Ok(a.sub(b, &*e)), |a, b| Expr::Sub(Box::new((a, b))))?
+            _ => Ok(()),
+        }
+    }
+
+    /// Evaluates all expressions, returning just the values if every one of
+    /// them evaluated to a value, and the (partially evaluated) expressions otherwise.
+    fn eval_all(&mut self, exprs: Vec<ExprRef>) -> color_eyre::Result<Result<Vec<Value>, Vec<Expr>>> {
+        let exprs = exprs.into_iter().map(|e| self.eval_at(e)).collect::<color_eyre::Result<Vec<_>>>()?;
+        if exprs.iter().all(|e| e.as_value().is_some()) {
+            Ok(Ok(exprs.into_iter().map(|e| e.into_value().ok().unwrap()).collect()))
+        } else {
+            Ok(Err(exprs))
+        }
+    }
+
+    /// Calls a user defined function, with its parameters bound to the evaluated arguments.
+    fn call(&mut self, name: &str, args: Vec<ExprRef>) -> color_eyre::Result<Expr> {
+        let function = self.functions[name].clone();
+        let params = function.cases[0].params.len();
+        if params != args.len() {
+            let s = if params == 1 { "" } else { "s" };
+            bail!("`{name}` takes {params} argument{s}, but {} were given", args.len());
+        }
+        if self.scopes.len() >= MAX_CALL_DEPTH {
+            bail!("`{name}` was called more than {MAX_CALL_DEPTH} times without returning");
+        }
+        let mut key = None;
+        let (case, args) = match self.eval_all(args)? {
+            Ok(values) if function.single().is_some_and(|case| case.code.is_some()) && self.runnable(name) => {
+                return Ok(Expr::value(self.run(name, values, self.scopes.len())?));
             }
-            Expr::Mul(values) => {
-                let (a, b) = *values;
-                self.eval_binop(a, b, |a, b, e| Ok(a.mul(b, &*e)), |a, b| Expr::Mul(Box::new((a, b))))?
+            Ok(values) => {
+                key = function.memo().then(|| memo::key(&values)).flatten();
+                if let Some(v) = key.as_ref().and_then(|key| self.remembered(name, key)) {
+                    return Ok(Expr::value(v));
+                }
+                let case = function.cases.iter().find(|case| case.matches(&values));
+                let args: Vec<_> = values.into_iter().map(Expr::value).collect();
+                match case {
+                    Some(case) => (case, args),
+                    None => bail!("no definition of `{name}` matches `{}`", Expr::apply(Expr::symbol(name), args)),
+                }
             }
-            Expr::Div(values) => {
-                let (a, b) = *values;
-                self.eval_binop(
-                    a,
-                    b,
-                    |a, b, e| a.div(b, &*e).map_err(Into::into),
-                    |a, b| Expr::Div(Box::new((a, b))),
-                )?
+            // which case applies isn't known until the arguments are values
+            Err(args) if function.single().is_none() => return Ok(Expr::apply(Expr::symbol(name), args)),
+            Err(args) => (&function.cases[0], args),
+        };
+        self.scopes.push(case.params.iter().cloned().zip(args).collect());
+        let result = self.eval_at(case.body.root());
+        self.scopes.pop();
+        if let (Some(key), Ok(result)) = (key, &result) {
+            self.remember(name, key, result);
+        }
+        result
+    }
+
+    /// `name = value`, or `name: annotation = value`.
+    fn assign(&mut self, name: &str, annotation: Option<Annotation>, value: ExprRef) -> color_eyre::Result<Expr> {
+        let value = self.eval_at(value)?;
+        check_depth(&value)?;
+        let value = self.simplify(value)?;
+        self.set_variable(name, annotation, value)
+    }
+
+    /// `q, r = value`, with as many names as the tuple or list has items.
+    fn unpack(&mut self, names: &[String], value: ExprRef) -> color_eyre::Result<Expr> {
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                bail!("`{name}` can't be assigned twice at once");
             }
-            Expr::Neg(neg) => match self.eval(*neg)? {
-                Expr::Value(v) => Expr::Value(-v),
-                other => Expr::Neg(Box::new(other)),
+        }
+        let value = self.eval_at(value)?;
+        check_depth(&value)?;
+        let value = self.simplify(value)?;
+        let items: Vec<Expr> = match value.as_value() {
+            Some(Value::Tuple(items) | Value::List(items)) => items.iter().cloned().map(Expr::value).collect(),
+            Some(v) => bail!("expected a tuple or list of {} items to assign, found {}", names.len(), v.kind()),
+            None => match value.root().node() {
+                Node::Tuple(items) | Node::List(items) => {
+                    items.iter().map(|&i| value.root().get(i).to_expr()).collect()
+                }
+                _ => bail!("expected a tuple or list of {} items to assign, found `{value}`", names.len()),
             },
-            Expr::Apply(left, args) => match self.eval(*left)? {
-                Expr::Symbol(n) if n == "sin" && args.len() == 1 => {
-                    let arg = args.into_iter().next().unwrap();
-                    match self.eval(arg)? {
-                        Expr::Value(Value::Decimal(mut d)) => Expr::Value(Value::Decimal({
-                            d.sin_round(self.round()); d
-                        })),
-                        _ => todo!(),
-                    }
+        };
+        if items.len() != names.len() {
+            bail!("{} names can't be assigned the {} items of `{value}`", names.len(), items.len());
+        }
+        let values = names.iter().zip(items).map(|(name, item)| self.set_variable(name, None, item));
+        Ok(Expr::tuple(values.collect::<color_eyre::Result<_>>()?))
+    }
+
+    /// Gives the variable `name` an evaluated `value`, checking it against its
+    /// annotation, and returns the value as reading the variable gives it.
+    fn set_variable(
+        &mut self,
+        name: &str,
+        annotation: Option<Annotation>,
+        mut value: Expr,
+    ) -> color_eyre::Result<Expr> {
+        if !self.settings.allow_shadowing && !self.variables.contains_key(name) {
+            if is_builtin(name) {
+                bail!("`{name}` is a builtin function, `:set allow-shadowing on` to use the name for a variable too");
+            }
+            if CONSTANTS.contains(&name) {
+                self.warnings.push(format!("`{name}` is a variable now, rather than the constant"));
+            }
+        }
+        // an annotation stays with the variable until it is given another one
+        if let Some(annotation) = annotation.or_else(|| self.annotations.get(name).cloned()) {
+            value = Expr::value(annotation.check(self, name, value)?);
+            self.annotations.insert(name.to_owned(), annotation);
+        }
+        if let (Some(digits), Some(v)) = (self.settings.money, value.as_value()) {
+            value = Expr::value(money::round(v.clone(), digits));
+        }
+        value = self.store.intern(value);
+        self.variables.insert(name.to_owned(), value);
+        self.clear_cache();
+        // `a: deg = 45` is in radians where it's used with `:set angle rad`
+        Ok(self.variable(name).expect("the variable was just set"))
+    }
+
+    /// `name(params) = body`, a new function or another case of one.
+    fn define(&mut self, name: &str, params: &[String], body: ExprRef, memo: bool) -> color_eyre::Result<()> {
+        if is_builtin(name) {
+            bail!("`{name}` is a builtin function and can't be redefined");
+        }
+        // compiled code doesn't look in the cache of `memo` functions
+        let general = params.iter().all(|p| pattern(p).is_none()) && !memo;
+        let code = if general { bytecode::Code::compile(params, body) } else { None };
+        let body = self.store.intern(body.to_expr());
+        let case = Arc::new(Case { params: params.to_vec(), body, memo, code });
+        self.clear_cache();
+        let cases = &mut self.functions.entry(name.to_owned()).or_default().cases;
+        // a different number of parameters starts over, and the same patterns replace that case
+        if cases.first().is_some_and(|c| c.params.len() != params.len()) {
+            cases.clear();
+        }
+        let same = |c: &Arc<Case>| c.params.iter().zip(params).all(|(a, b)| pattern(a) == pattern(b));
+        match cases.iter().position(same) {
+            Some(i) => cases[i] = case,
+            None => cases.push(case),
+        }
+        Ok(())
+    }
+
+    /// A call of `N`, a builtin or a user defined function.
+    fn eval_apply(&mut self, e: ExprRef, left: ExprId, args: &[ExprId]) -> color_eyre::Result<Expr> {
+        let args: Vec<_> = args.iter().map(|&i| e.get(i)).collect();
+        Ok(match e.get(left).node() {
+            Node::Symbol(n) if n == numeric::NAME => match args[..] {
+                [arg] => {
+                    let e = self.eval_at(arg)?;
+                    self.numeric(e)?
                 }
-                _ => todo!(),
+                [arg, digits] => self.numeric_digits(arg, digits, None)?,
+                [arg, digits, mode] => self.numeric_digits(arg, digits, Some(mode))?,
+                _ => bail!("`{n}` takes 1 to 3 arguments"),
             },
+            Node::Symbol(n) if n == rearrange::NAME => match args[..] {
+                [equation, x] => self.rearrange(equation, x)?,
+                _ => bail!("`{n}` takes 2 arguments"),
+            },
+            Node::Symbol(n) if n == roots::NAME => match args[..] {
+                [p, x] => self.isolate_roots(p, x, None)?,
+                [p, x, width] => self.isolate_roots(p, x, Some(width))?,
+                _ => bail!("`{n}` takes 2 or 3 arguments"),
+            },
+            Node::Symbol(n) if n == ode::NAME => match args[..] {
+                [f, ref rest @ ..] if (3..=4).contains(&rest.len()) => self.odesolve(f, rest)?,
+                _ => bail!("`{n}` takes 4 or 5 arguments"),
+            },
+            Node::Symbol(n) if introspect::lookup(n).is_some() => {
+                let f = introspect::lookup(n).unwrap();
+                let [arg] = args[..] else {
+                    bail!("`{n}` takes 1 argument");
+                };
+                Expr::value(f(&self.eval_at(arg)?))
+            }
+            Node::Symbol(n) if self.functions.contains_key(n) => self.call(n, args)?,
+            Node::Symbol(n) => self.call_builtin(n, args)?,
+            _ => bail!("`{}` is not a function", self.eval_at(e.get(left))?),
         })
     }
-}
 
-pub enum Expr {
-    Value(Value),
-    Symbol(String),
-    Add(Box<(Expr, Expr)>),
-    Sub(Box<(Expr, Expr)>),
-    Mul(Box<(Expr, Expr)>),
-    Div(Box<(Expr, Expr)>),
-    Neg(Box<Expr>),
-    Apply(Box<Expr>, Vec<Expr>),
-}
-
-pub fn expr_parser(e: &Evaluator) -> impl Parser<char, Expr, Error = Simple<char>> + '_ {
-    let expr = recursive(|expr| {
-        let int = text::int(10)
-            .then(just('.').ignore_then(text::digits(10).or_not()).or_not())
-            .then(just('e').ignore_then(text::int(10)).or_not())
-            .try_map(|((int, dec), exp), span| {
-                Ok(Expr::Value(if dec.is_some() || exp.is_some() {
-                    Value::Decimal({
-                        let mut s = int.to_string();
-                        if let Some(Some(dec)) = dec {
-                            s.push('.');
-                            s.push_str(&dec);
-                        }
-                        if let Some(exp) = exp {
-                            s.push('e');
-                            s.push_str(&exp);
-                        }
-                        let f = Float::parse(s).map_err(|e| Simple::custom(span, e.to_string()))?;
-                        e.complete(f)
-                    })
-                } else {
-                    Value::Exact(Integer::parse(int).map_err(|e| Simple::custom(span, e.to_string()))?.complete().into())
-                }))
-            })
-            .padded();
-
-        let atom = int
-            .or(expr.clone().delimited_by(just('('), just(')')))
-            .or(text::ident().map(Expr::Symbol))
-            .padded();
-
-        let op = |c| just(c).padded();
-
-        let func = atom
-            .clone()
-            .then(
-                expr.separated_by(just(','))
-                    .allow_trailing() // Foo is Rust-like, so allow trailing commas to appear in arg lists
-                    .delimited_by(just('('), just(')')),
-            )
-            .map(|(f, args)| Expr::Apply(Box::new(f), args));
-
-        let calls = func.or(atom);
-
-        let unary = op('-')
-            .repeated()
-            .then(calls)
-            .foldr(|_op, rhs| Expr::Neg(Box::new(rhs)));
-
-        let product = unary
-            .clone()
-            .then(
-                op('*')
-                    .to(Expr::Mul as fn(_) -> _)
-                    .or(op('/').to(Expr::Div as fn(_) -> _))
-                    .then(unary)
-                    .repeated(),
-            )
-            .foldl(|lhs, (op, rhs)| op(Box::new((lhs, rhs))));
-
-        let sum = product
-            .clone()
-            .then(
-                op('+')
-                    .to(Expr::Add as fn(_) -> _)
-                    .or(op('-').to(Expr::Sub as fn(_) -> _))
-                    .then(product)
-                    .repeated(),
-            )
-            .foldl(|lhs, (op, rhs)| op(Box::new((lhs, rhs))));
+    fn call_builtin(&mut self, name: &str, args: Vec<ExprRef>) -> color_eyre::Result<Expr> {
+        let Some(builtin) = builtins::lookup(name) else {
+            bail!("unknown function `{name}`");
+        };
+        Ok(match self.eval_all(args)? {
+            Ok(args) => {
+                let finite = args.iter().all(|a| a.non_finite().is_none());
+                let exact_args = self.may_stay_symbolic(&args).then(|| args.clone());
+                let mut v = builtin.call(self, args)?;
+                if let Some(digits) = self.settings.money {
+                    v = money::round(v, digits);
+                }
+                if finite {
+                    self.check_finite(&v, || format!("`{name}`"))?;
+                }
+                match exact_args {
+                    Some(args) if !v.is_exact() => match builtins::symbolic(name, &args) {
+                        Some(e) => e,
+                        None => Expr::apply(Expr::symbol(name), args.into_iter().map(Expr::value).collect()),
+                    },
+                    _ => Expr::value(v),
+                }
+            }
+            Err(args) => match self.symbolic_linalg(name, &args)? {
+                Some(e) => e,
+                None => Expr::apply(Expr::symbol(name), args),
+            },
+        })
+    }
+
+    /// `let name = value in body`.
+    fn eval_let(&mut self, name: &str, value: ExprRef, body: ExprRef) -> color_eyre::Result<Expr> {
+        let value = self.eval_at(value)?;
+        // the body sees the arguments of the function it is in, but not the functions it calls
+        let mut scope = self.scopes.last().cloned().unwrap_or_default();
+        scope.insert(name.to_owned(), value);
+        self.scopes.push(scope);
+        let result = self.eval_at(body);
+        self.scopes.pop();
+        result
+    }
+
+    /// `e` evaluated with `x` left a symbol, even if there is a variable `x`.
+    fn eval_in_terms_of(&mut self, e: ExprRef, x: &str) -> color_eyre::Result<Expr> {
+        let mut scope = self.scopes.last().cloned().unwrap_or_default();
+        scope.insert(x.to_owned(), Expr::symbol(x));
+        self.scopes.push(scope);
+        let result = self.eval_at(e);
+        self.scopes.pop();
+        result
+    }
+
+    pub fn eval(&mut self, e: Expr) -> color_eyre::Result<Expr> {
+        // values are already as evaluated as they get
+        match e.into_value() {
+            Ok(v) => Ok(Expr::value(v)),
+            Err(e) if self.cancellation.is_some() => self.eval_at(e.root()),
+            Err(e) => {
+                self.warnings.clear();
+                let result = self.eval_accurately(e)?;
+                check_depth(&result)?;
+                self.simplify(result)
+            }
+        }
+    }
+
+    /// The value of a number literal with the current precision, exact in
+    /// `strict-exact` and `money` mode.
+    fn literal(&self, l: &Literal) -> color_eyre::Result<Value> {
+        if l.imaginary && self.settings.strict_exact {
+            bail!("`{}i` isn't exact, which `:set strict-exact` doesn't allow", l.text);
+        }
+        if !l.imaginary && self.settings.strict_exact {
+            return Ok(Value::Exact(exact::literal(&l.text)?));
+        }
+        if let (false, Some(digits)) = (l.imaginary, self.settings.money) {
+            return Ok(money::round(Value::Exact(exact::literal(&l.text)?), digits));
+        }
+        let f = Float::parse(&l.text).expect("literals are checked when parsing");
+        Ok(if l.imaginary {
+            Value::Complex(Complex::with_val(self.precision(), (0, f)))
+        } else {
+            Value::Decimal(self.complete(f))
+        })
+    }
 
-        sum
-    });
-    expr.then_ignore(end())
+    fn eval_at(&mut self, e: ExprRef) -> color_eyre::Result<Expr> {
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            bail!("the evaluation took too long");
+        }
+        // a chain like `1-2-3-...` recurses once per operand
+        grow_stack(|| self.eval_node(e))
+    }
+
+    fn eval_node(&mut self, e: ExprRef) -> color_eyre::Result<Expr> {
+        Ok(match e.node() {
+            Node::Value(val) => Expr::value(val.clone()),
+            Node::Literal(l) => Expr::value(self.literal(l)?),
+            Node::Symbol(s) => {
+                // a function only sees its own arguments, not those of its callers
+                let bound = self.scopes.last().and_then(|scope| scope.get(s)).cloned().or_else(|| self.variable(s));
+                match bound {
+                    Some(e) => e,
+                    None => match self.constant(s) {
+                        Some(v) => Expr::value(v),
+                        None => Expr::symbol(s.clone()),
+                    },
+                }
+            }
+            Node::Assign(name, annotation, value) => self.assign(name, annotation.clone(), e.get(*value))?,
+            &Node::Unpack(ref names, value) => self.unpack(names, e.get(value))?,
+            &Node::Define(ref name, ref params, body, memo) => {
+                self.define(name, params, e.get(body), memo)?;
+                e.to_expr()
+            }
+            &Node::DefineUnit(ref name, value) => {
+                self.define_unit(name, e.get(value))?;
+                e.to_expr()
+            }
+            &Node::Let(ref name, value, body) => self.eval_let(name, e.get(value), e.get(body))?,
+            &Node::Quantity(magnitude, unit) => self.eval_quantity(e.get(magnitude), e.get(unit))?,
+            &Node::Convert(value, unit) => self.eval_convert(e.get(value), e.get(unit))?,
+            Node::Block(statements) => self.eval_block(e, statements)?,
+            &Node::For(ref var, list, body) => self.eval_for(var, e.get(list), e.get(body))?,
+            &Node::While(condition, body) => self.eval_while(e.get(condition), e.get(body))?,
+            Node::Add(items) => {
+                let items = items.iter().map(|&i| e.get(i)).collect();
+                self.eval_nary(items, '+', |a, b, e| a.add(b, &*e), Expr::sum)?
+            }
+            &Node::Sub(a, b) => self.eval_binop(e.get(a), e.get(b), '-', |a, b, e| a.sub(b, &*e), Node::Sub)?,
+            Node::Mul(items) => {
+                let items = items.iter().map(|&i| e.get(i)).collect();
+                self.eval_nary(items, '*', |a, b, e| a.mul(b, &*e), Expr::product)?
+            }
+            &Node::Div(a, b) => self.eval_binop(e.get(a), e.get(b), '/', |a, b, e| a.div(b, &*e), Node::Div)?,
+            &Node::Pow(a, b) => self.eval_binop(e.get(a), e.get(b), '^', |a, b, e| a.pow(b, &*e), Node::Pow)?,
+            &Node::Neg(neg) => match self.eval_at(e.get(neg))?.into_value() {
+                Ok(v) => Expr::value(v.neg()?),
+                // like a list of numbers, `-[a, 1]` is `[-a, -1]`
+                Err(other) => match other.root().node() {
+                    Node::List(items) => {
+                        let negated = Expr::list(items.iter().map(|&i| -other.root().get(i).to_expr()).collect());
+                        self.eval_at(negated.root())?
+                    }
+                    _ => -other,
+                },
+            },
+            Node::List(items) => match self.eval_all(items.iter().map(|&i| e.get(i)).collect())? {
+                Ok(values) => Expr::value(Value::List(values)),
+                Err(items) => Expr::list(items),
+            },
+            Node::Tuple(items) => match self.eval_all(items.iter().map(|&i| e.get(i)).collect())? {
+                Ok(values) => Expr::value(Value::Tuple(values)),
+                Err(items) => Expr::tuple(items),
+            },
+            // Function names are looked up before evaluation so that they can't be shadowed by constants.
+            &Node::Apply(left, ref args) => self.eval_apply(e, left, args)?,
+        })
+    }
 }
 
 /// An enum representing operator precedence. Useful for printing stuff.
@@ -266,7 +1025,7 @@ pub enum PrecedenceContext {
     /// Product context. Sums must be wrapped in parens
     Product,
     /// Exponentiation
-    // Pow,
+    Pow,
     /// Negation
     Neg,
     /// These operations are performed to their immediate left, so if their left
@@ -276,15 +1035,180 @@ pub enum PrecedenceContext {
 
 impl Expr {
     pub fn precedence(&self) -> PrecedenceContext {
+        self.root().precedence()
+    }
+}
+
+impl ExprRef<'_> {
+    pub fn precedence(self) -> PrecedenceContext {
         use PrecedenceContext::*;
-        match self {
-            Self::Value(_) | Self::Symbol(_) => NoPrecedence,
-            Self::Mul(_) | Self::Div(_) => Product,
-            Self::Add(_) | Self::Sub(_) => Sum,
-            Self::Neg(_) => Neg,
-            Self::Apply(..) => FunctionOrFactorial,
-            //Self::Pow(_) => Pow,
-            // Self::Factorial(_) | Self::Function(_, _) => FunctionOrFactorial,
+        match self.node() {
+            // `1+2i` needs the same care as a sum
+            Node::Value(Value::Complex(c)) if !c.real().is_zero() && !c.imag().is_zero() => Sum,
+            // `1/2` is printed as a division
+            Node::Value(Value::Exact(r)) if *r.denom() != 1 => Product,
+            // `(2 m)^2` needs parentheses like a product
+            Node::Value(Value::Quantity(_)) | Node::Quantity(..) => Product,
+            Node::Value(_) | Node::Literal(_) | Node::Symbol(_) | Node::List(_) | Node::Tuple(_) => NoPrecedence,
+            Node::Assign(..) | Node::Unpack(..) | Node::Define(..) => NoPrecedence,
+            Node::Block(_) | Node::For(..) | Node::While(..) => NoPrecedence,
+            Node::DefineUnit(..) | Node::Convert(..) => NoPrecedence,
+            Node::Mul(..) | Node::Div(..) => Product,
+            Node::Pow(..) => Pow,
+            // `let` takes in everything on its right, so it is parenthesized like a sum
+            Node::Add(..) | Node::Sub(..) | Node::Let(..) => Sum,
+            // `a == b`, printed like `let`
+            Node::Apply(..) if print::comparison(self).is_some() => Sum,
+            Node::Neg(_) => Neg,
+            Node::Apply(..) => FunctionOrFactorial,
         }
     }
 }
+
+#[test]
+fn test_cases() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    eval("f(0) = 1").unwrap();
+    eval("f(n) = n*f(n-1)").unwrap();
+    assert_eq!("120", eval("f(5)").unwrap());
+    // which case applies isn't known yet
+    assert_eq!("f(x)", eval("f(x)").unwrap());
+    // the same patterns replace a case
+    eval("f(0) = 2").unwrap();
+    assert_eq!("12", eval("f(3)").unwrap());
+    eval("g(-1) = 0").unwrap();
+    assert!(eval("g(1)").is_err());
+}
+
+#[test]
+fn test_let() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("9", eval("let t = 2 + 1 in t*t").unwrap());
+    assert_eq!("t", eval("t").unwrap());
+    assert_eq!("f(x) = 2*(let t = x+1 in t*t)", eval("f(x) = 2*(let t = x + 1 in t*t)").unwrap());
+    assert_eq!("18", eval("f(2)").unwrap());
+    // the binding is lexical, functions called from the body don't see it
+    eval("g(y) = t").unwrap();
+    assert_eq!("t", eval("let t = 5 in g(1)").unwrap());
+}
+
+#[test]
+fn test_shadowing() {
+    use crate::session::eval_text as eval;
+    let mut e = Evaluator::default();
+    assert!(eval(&mut e, "sin = 3").is_err());
+    assert!(eval(&mut e, "N(x) = x").is_err());
+    assert_eq!("3", eval(&mut e, "pi = 3").unwrap());
+    assert_eq!(1, e.warnings().len());
+    // only the first assignment shadows it
+    assert_eq!("4", eval(&mut e, "pi = pi + 1").unwrap());
+    assert!(e.warnings().is_empty());
+    e.settings_mut().set("allow-shadowing", "on").unwrap();
+    assert_eq!("(1, 2)", eval(&mut e, "e, sin = (1, 2)").unwrap());
+    assert!(e.warnings().is_empty());
+    assert_eq!("3", eval(&mut e, "sin + e").unwrap());
+}
+
+#[test]
+fn test_nonfinite() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    let error = eval("10.0^(10^20)").unwrap_err().to_string();
+    assert_eq!("`^` produced a result that is infinite, `:set nonfinite propagate` to allow this", error);
+    assert!(eval("cosh(10^20)").unwrap_err().to_string().starts_with("`cosh` produced a result that is infinite"));
+    // with `:set division ieee`, dividing by zero is the division policy's to decide
+    e.settings_mut().set("division", "ieee").unwrap();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("∞", eval("1.0/0").unwrap());
+    // only results computed from finite numbers are checked
+    assert_eq!("NaN", eval("1.0/0 - 1.0/0").unwrap());
+
+    e.settings_mut().set("nonfinite", "propagate").unwrap();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("[∞, -∞]", eval("[10.0^(10^20), -cosh(10^20)]").unwrap());
+    assert_eq!("NaN", eval("10.0^(10^20) - 10.0^(10^20)").unwrap());
+    assert_eq!("[∞, 1]", eval("[10.0^(10^20), 1]").unwrap());
+    assert!(e.settings_mut().set("nonfinite", "ignore").is_err());
+}
+
+#[test]
+fn test_rounding() {
+    let mut e = Evaluator::default();
+    e.settings_mut().set("precision", "8").unwrap();
+    let mut eval = |rounding: &str, line: &str| {
+        e.settings_mut().set("rounding", rounding).unwrap();
+        crate::session::eval_text(&mut e, line).unwrap()
+    };
+    assert_eq!(["1.0000000", "1.0078125"], ["down", "up"].map(|r| eval(r, "1.0 + 0.001")));
+    assert_eq!(["0.99609375", "1.0000000"], ["down", "up"].map(|r| eval(r, "1.0 - 0.001")));
+    assert_eq!(["1.1953125", "1.2187500"], ["down", "up"].map(|r| eval(r, "1.1 * 1.1")));
+    // on either side of √2
+    assert_eq!(["1.4140625", "1.4218750"], ["down", "up"].map(|r| eval(r, "2.0^0.5")));
+}
+
+#[test]
+fn test_pow() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("1024", eval("2^10").unwrap());
+    assert_eq!("1024", eval("2**10").unwrap());
+    // right associative
+    assert_eq!("2417851639229258349412352", eval("2^3^4").unwrap());
+    assert_eq!("1/8", eval("2^-3").unwrap());
+    assert_eq!("8/27", eval("(2/3)^3").unwrap());
+    assert!(eval("0^-1").is_err());
+    // results of more than `MAX_POW_BITS` bits are refused rather than computed
+    assert_eq!(MAX_POW_BITS, 1 << 26);
+    assert!(eval("2^(2^25)").is_ok());
+    assert!(eval("2^(2^26)").is_err());
+    assert!(eval("2^(2^40)").is_err());
+}
+
+#[test]
+fn test_assign_and_define() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("3", eval("x = 1 + 2").unwrap());
+    assert_eq!("6", eval("x = 2*x").unwrap());
+    assert_eq!("(1, 2)", eval("a, b = (1, 2)").unwrap());
+    assert!(eval("a, b = (1, 2, 3)").is_err());
+    assert!(eval("sin = 1").is_err());
+    eval("f(x) = x + y").unwrap();
+    eval("y = 10").unwrap();
+    // parameters shadow variables, and variables are looked up when called
+    assert_eq!("11", eval("f(1)").unwrap());
+    assert_eq!("6", eval("x").unwrap());
+    // a function doesn't see the parameters of its caller
+    eval("g(y) = f(0)").unwrap();
+    assert_eq!("10", eval("g(5)").unwrap());
+    assert!(eval("f(1, 2)").is_err());
+    assert!(eval("sqrt(x) = x").is_err());
+}
+
+#[test]
+fn test_call_depth() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    eval("f(0) = 1").unwrap();
+    eval("f(n) = n*f(n-1)").unwrap();
+    assert_eq!("300", eval("f(300) / f(299)").unwrap());
+    eval("memo fib(0) = 0").unwrap();
+    eval("memo fib(1) = 1").unwrap();
+    eval("memo fib(n) = fib(n-1) + fib(n-2)").unwrap();
+    assert_eq!("1", eval("fib(1000) > 4*10^208").unwrap());
+    assert_eq!("1", eval("fib(1000) < 5*10^208").unwrap());
+    // compiled functions recurse as deeply
+    eval("g(n) = g(n+1)").unwrap();
+    let error = eval("g(0)").unwrap_err().to_string();
+    assert!(error.contains(&format!("called more than {MAX_CALL_DEPTH} times")), "{error}");
+    let error = eval("f(-1)").unwrap_err().to_string();
+    assert!(error.contains(&format!("called more than {MAX_CALL_DEPTH} times")), "{error}");
+    // long chains recurse once per operand as well
+    assert_eq!("-19998", eval(&vec!["1"; 20_000].join("-")).unwrap());
+    eval(&format!("h(x) = {}", vec!["x"; 20_000].join("-"))).unwrap();
+    assert_eq!("-39996", eval("h(2)").unwrap());
+    // the evaluator is usable afterwards
+    assert_eq!("120", eval("f(5)").unwrap());
+}
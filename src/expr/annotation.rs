@@ -0,0 +1,148 @@
+//! Annotations on assignments, `n: int = 5` or `a: deg = 45`. The annotation
+//! stays with the variable, so that later assignments to it are checked too.
+//!
+//! Besides the kinds of values and the angle units of `:set angle`, any unit
+//! can be an annotation: `d: km = 5000 m` converts to `5 km`, a plain number
+//! like in `d: km = 5` is taken to be in it, and a quantity of some other
+//! dimension is an error. Currencies are units the user defines, like
+//! `unit EUR = 1` for `budget: EUR = 1500`. Only a single unit name can be an
+//! annotation, not a product like `m/s`.
+
+use std::fmt;
+
+use color_eyre::eyre::{bail, eyre};
+use rug::float::Constant;
+use rug::Float;
+
+use super::{Evaluator, Expr, Value};
+use crate::settings::AngleMode;
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum Annotation {
+    Int,
+    /// Any exact rational.
+    Exact,
+    /// A real number, exact values are converted to decimals.
+    Real,
+    /// Any number, real ones are converted to complex numbers.
+    Complex,
+    List,
+    Str,
+    /// A real number that is an angle in this unit, converted to the unit
+    /// of `:set angle` wherever the variable is used.
+    Angle(AngleMode),
+    /// A quantity in the unit with this name, see [`units`](super::units).
+    Unit(String),
+}
+
+const NAMES: &[(&str, Annotation)] = &[
+    ("int", Annotation::Int),
+    ("exact", Annotation::Exact),
+    ("real", Annotation::Real),
+    ("complex", Annotation::Complex),
+    ("list", Annotation::List),
+    ("string", Annotation::Str),
+    ("deg", Annotation::Angle(AngleMode::Degrees)),
+    ("rad", Annotation::Angle(AngleMode::Radians)),
+];
+
+impl Annotation {
+    /// One of the kinds of values, or otherwise a unit, which is looked up
+    /// when a value is assigned, as units can be defined later.
+    pub fn parse(name: &str) -> Annotation {
+        NAMES.iter().find(|(n, _)| *n == name).map_or_else(|| Annotation::Unit(name.into()), |(_, a)| a.clone())
+    }
+
+    /// The names annotations can be written as, for error messages.
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        NAMES.iter().map(|(n, _)| *n)
+    }
+
+    /// Checks a value assigned to the variable `name`, converting it if needed.
+    pub(super) fn check(&self, e: &Evaluator, name: &str, value: Expr) -> color_eyre::Result<Value> {
+        let declared = match self {
+            Annotation::Unit(unit) => format!("in `{unit}`"),
+            _ => format!("as {self}"),
+        };
+        let v = match value.into_value() {
+            Ok(v) => v,
+            Err(other) => bail!("`{name}` is declared {declared}, but `{other}` isn't a value"),
+        };
+        let fits = match (self, &v) {
+            (Annotation::Int, Value::Exact(r)) => *r.denom() == 1,
+            (Annotation::Exact, Value::Exact(_)) => true,
+            (Annotation::Real | Annotation::Angle(_), Value::Exact(_) | Value::Decimal(_)) => true,
+            (Annotation::Complex, _) => e.to_complex(v.clone()).is_some(),
+            (Annotation::List, Value::List(_)) => true,
+            (Annotation::Str, Value::Str(_)) => true,
+            (Annotation::Unit(_), Value::Exact(_) | Value::Decimal(_) | Value::Quantity(_)) => true,
+            _ => false,
+        };
+        if !fits {
+            bail!("`{name}` is declared {declared}, but {} is {}", e.display(&Expr::value(v.clone())), v.kind());
+        }
+        Ok(match self {
+            Annotation::Real => Value::Decimal(e.to_float(v)),
+            Annotation::Complex => Value::Complex(e.to_complex(v).unwrap()),
+            Annotation::Unit(unit) => match e.in_unit(v, unit) {
+                Some(v) => v.map_err(|err| eyre!("`{name}` is declared {declared}, but {err}"))?,
+                None => {
+                    let names = Annotation::names().collect::<Vec<_>>().join(", ");
+                    bail!("unknown type or unit `{unit}`, expected a unit or one of {names}");
+                }
+            },
+            _ => v,
+        })
+    }
+
+    /// The value of a variable with this annotation where it is used.
+    pub(super) fn read(&self, e: &Evaluator, v: &Value) -> Value {
+        let &Annotation::Angle(unit) = self else {
+            return v.clone();
+        };
+        if unit == e.settings.angle {
+            return v.clone();
+        }
+        let pi = Float::with_val(e.precision(), Constant::Pi);
+        let f = e.to_float(v.clone());
+        Value::Decimal(match unit {
+            AngleMode::Degrees => f * pi / 180u32,
+            AngleMode::Radians => f * 180u32 / pi,
+        })
+    }
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Annotation::Unit(unit) => f.write_str(unit),
+            _ => f.write_str(NAMES.iter().find(|(_, a)| a == self).map(|(n, _)| *n).unwrap()),
+        }
+    }
+}
+
+#[test]
+fn test_annotations() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    // an angle echoes as it reads, in the unit of `:set angle`
+    assert_eq!("3.1415927", eval("a: deg = 180").unwrap());
+    assert_eq!("3.1415927", eval("a").unwrap());
+    eval("n: int = 2").unwrap();
+    // the annotation stays with the variable
+    assert!(eval("n = 1/2").is_err());
+    assert_eq!("2", eval("n").unwrap());
+    // any unit, converted on assignment
+    assert_eq!("5000 m", eval("d: m = 5 km").unwrap());
+    assert_eq!("(3/10) m", eval("d = 30 cm").unwrap());
+    assert!(eval("d = 3 s").is_err());
+    eval("unit EUR = 1").unwrap();
+    eval("unit USD = 9/10 EUR").unwrap();
+    assert_eq!("1500 EUR", eval("budget: EUR = 1500").unwrap());
+    assert_eq!("1410 EUR", eval("budget - 100 USD").unwrap());
+    assert!(eval("x: itn = 5").is_err());
+    let definitions: Vec<_> = e.definitions().map(|d| d.to_string()).collect();
+    let units = ["unit EUR = 1", "unit USD = 9/10 EUR"];
+    let variables = ["a: deg = 180", "budget: EUR = 1500 EUR", "d: m = (3/10) m", "n: int = 2"];
+    assert_eq!([&units[..], &variables[..]].concat(), definitions);
+}
@@ -0,0 +1,371 @@
+//! Expressions are stored as a list of nodes that refer to their children by
+//! index, rather than as a tree of boxes, so that a whole expression is a
+//! single allocation and cheap to clone, walk and rebuild. Clones share the
+//! list, which is only copied when an expression is built on top of it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use super::{Annotation, Value};
+
+/// The index of a node in an [`Expr`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ExprId(u32);
+
+impl ExprId {
+    pub(super) fn new(index: usize) -> ExprId {
+        ExprId(index as u32)
+    }
+
+    /// The position of the node in the nodes of its expression.
+    pub(super) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A decimal or imaginary number as written, which only becomes a value when
+/// evaluated so that it gets the precision at that time, e.g. when it is part
+/// of a function defined before the precision was changed.
+#[derive(Clone)]
+pub struct Literal {
+    /// The digits, without the `i` of imaginary numbers.
+    pub text: String,
+    pub imaginary: bool,
+}
+
+/// A node of an expression, with its children in the same [`Expr`].
+#[derive(Clone)]
+pub enum Node {
+    Value(Value),
+    Literal(Literal),
+    Symbol(String),
+    /// A sum of two or more operands, flattened so that none of them is a sum itself.
+    Add(Vec<ExprId>),
+    Sub(ExprId, ExprId),
+    /// A product of two or more operands, flattened like sums.
+    Mul(Vec<ExprId>),
+    Div(ExprId, ExprId),
+    Pow(ExprId, ExprId),
+    Neg(ExprId),
+    Apply(ExprId, Vec<ExprId>),
+    List(Vec<ExprId>),
+    Tuple(Vec<ExprId>),
+    /// `let x = value in body`, with `x` only bound in `body`.
+    Let(String, ExprId, ExprId),
+    /// `x = expr` or `x: int = expr`, only allowed as a whole statement.
+    Assign(String, Option<Annotation>, ExprId),
+    /// `q, r = expr`, assigning the items of a tuple or list to the names in
+    /// order, only allowed as a whole statement.
+    Unpack(Vec<String>, ExprId),
+    /// `f(x, y) = expr`, only allowed as a whole statement. Parameters may also
+    /// be integers, like in `f(0) = 1`, to define a function by cases. The
+    /// flag is set for `memo f(n) = expr`, whose results are remembered.
+    Define(String, Vec<String>, ExprId, bool),
+    /// `unit furlong = 201.168 m`, only allowed as a whole statement.
+    DefineUnit(String, ExprId),
+    /// A number followed by a unit, like `9.81 m/s^2`, where the names of the
+    /// unit are units unless they are variables.
+    Quantity(ExprId, ExprId),
+    /// `value in unit`, a quantity converted to another unit.
+    Convert(ExprId, ExprId),
+    /// `{ a; b }`, statements evaluated in order, the body of a loop.
+    Block(Vec<ExprId>),
+    /// `for k in list { ... }`, with `1..10` a list of the integers from 1 to 10.
+    For(String, ExprId, ExprId),
+    /// `while condition { ... }`.
+    While(ExprId, ExprId),
+}
+
+impl Node {
+    /// The node with each child replaced by `f(child)`.
+    pub(super) fn map_ids(self, mut f: impl FnMut(ExprId) -> ExprId) -> Node {
+        match self {
+            Node::Value(_) | Node::Literal(_) | Node::Symbol(_) => self,
+            Node::Add(items) => Node::Add(items.into_iter().map(f).collect()),
+            Node::Sub(a, b) => Node::Sub(f(a), f(b)),
+            Node::Mul(items) => Node::Mul(items.into_iter().map(f).collect()),
+            Node::Div(a, b) => Node::Div(f(a), f(b)),
+            Node::Pow(a, b) => Node::Pow(f(a), f(b)),
+            Node::Neg(a) => Node::Neg(f(a)),
+            Node::Apply(callee, args) => Node::Apply(f(callee), args.into_iter().map(f).collect()),
+            Node::List(items) => Node::List(items.into_iter().map(f).collect()),
+            Node::Tuple(items) => Node::Tuple(items.into_iter().map(f).collect()),
+            Node::Assign(name, annotation, value) => Node::Assign(name, annotation, f(value)),
+            Node::Unpack(names, value) => Node::Unpack(names, f(value)),
+            Node::Define(name, params, body, memo) => Node::Define(name, params, f(body), memo),
+            Node::DefineUnit(name, value) => Node::DefineUnit(name, f(value)),
+            Node::Quantity(magnitude, unit) => Node::Quantity(f(magnitude), f(unit)),
+            Node::Convert(value, unit) => Node::Convert(f(value), f(unit)),
+            Node::Block(statements) => Node::Block(statements.into_iter().map(f).collect()),
+            Node::Let(name, value, body) => Node::Let(name, f(value), f(body)),
+            Node::For(var, list, body) => Node::For(var, f(list), f(body)),
+            Node::While(condition, body) => Node::While(f(condition), f(body)),
+        }
+    }
+
+    /// The direct children of the node.
+    pub fn children(&self) -> Vec<ExprId> {
+        match self {
+            Node::Value(_) | Node::Literal(_) | Node::Symbol(_) => vec![],
+            Node::Sub(a, b) | Node::Div(a, b) | Node::Pow(a, b) => vec![*a, *b],
+            Node::Quantity(a, b) | Node::Convert(a, b) => vec![*a, *b],
+            Node::Let(_, a, b) | Node::For(_, a, b) | Node::While(a, b) => vec![*a, *b],
+            Node::Neg(a) | Node::Assign(_, _, a) | Node::Define(_, _, a, _) | Node::DefineUnit(_, a) => vec![*a],
+            Node::Unpack(_, a) => vec![*a],
+            Node::Apply(callee, args) => std::iter::once(*callee).chain(args.iter().copied()).collect(),
+            Node::Add(items) | Node::Mul(items) | Node::List(items) | Node::Tuple(items) => items.clone(),
+            Node::Block(items) => items.clone(),
+        }
+    }
+}
+
+/// An expression. Children always come before their parents, and the root is
+/// the last node. A node may be the child of more than one other node, see
+/// `expr/store.rs`.
+#[derive(Clone)]
+pub struct Expr {
+    nodes: Arc<Vec<Node>>,
+}
+
+impl Expr {
+    pub fn value(v: Value) -> Expr {
+        Expr::from_nodes(vec![Node::Value(v)])
+    }
+
+    pub fn literal(l: Literal) -> Expr {
+        Expr::from_nodes(vec![Node::Literal(l)])
+    }
+
+    pub fn symbol(s: impl Into<String>) -> Expr {
+        Expr::from_nodes(vec![Node::Symbol(s.into())])
+    }
+
+    /// An expression of nodes that come before the nodes they refer to.
+    pub(super) fn from_nodes(nodes: Vec<Node>) -> Expr {
+        Expr { nodes: Arc::new(nodes) }
+    }
+
+    pub(super) fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// A reference to the nodes that doesn't keep them alive.
+    pub(super) fn downgrade(&self) -> Weak<Vec<Node>> {
+        Arc::downgrade(&self.nodes)
+    }
+
+    pub(super) fn upgrade(nodes: &Weak<Vec<Node>>) -> Option<Expr> {
+        nodes.upgrade().map(|nodes| Expr { nodes })
+    }
+
+
+    /// Combines expressions into one, with `node` made from their roots on top.
+    fn join(parts: Vec<Expr>, node: impl FnOnce(Vec<ExprId>) -> Node) -> Expr {
+        Expr::join_flat(parts, node, |_| None)
+    }
+
+    /// Like `join`, but a part whose root `flatten` gives operands for is
+    /// replaced by those operands, so that `(a+b)+c` becomes `a+b+c`.
+    fn join_flat(
+        parts: Vec<Expr>,
+        node: impl FnOnce(Vec<ExprId>) -> Node,
+        flatten: impl Fn(&Node) -> Option<&Vec<ExprId>>,
+    ) -> Expr {
+        // the biggest part is moved rather than copied
+        let Some(biggest) = (0..parts.len()).max_by_key(|&i| parts[i].nodes.len()) else {
+            return Expr::from_nodes(vec![node(vec![])]);
+        };
+        let mut parts: Vec<_> = parts.into_iter().map(Some).collect();
+        let mut nodes = vec![];
+        let mut roots = vec![vec![]; parts.len()];
+        let order = std::iter::once(biggest).chain((0..parts.len()).filter(|&i| i != biggest));
+        for i in order {
+            let part = parts[i].take().unwrap();
+            let offset = nodes.len() as u32;
+            let part = Arc::unwrap_or_clone(part.nodes);
+            if offset == 0 {
+                nodes = part;
+            } else {
+                nodes.extend(part.into_iter().map(|n| n.map_ids(|ExprId(id)| ExprId(id + offset))));
+            }
+            let root = nodes.last().expect("expressions have at least one node");
+            roots[i] = match flatten(root) {
+                Some(operands) => {
+                    let operands = operands.clone();
+                    nodes.pop();
+                    operands
+                }
+                None => vec![ExprId(nodes.len() as u32 - 1)],
+            };
+        }
+        nodes.push(node(roots.concat()));
+        Expr::from_nodes(nodes)
+    }
+
+    /// A sum of the operands, flattening the ones that are sums themselves.
+    pub fn sum(items: Vec<Expr>) -> Expr {
+        Expr::join_flat(items, Node::Add, |n| match n {
+            Node::Add(items) => Some(items),
+            _ => None,
+        })
+    }
+
+    /// A product of the operands, flattening the ones that are products themselves.
+    pub fn product(items: Vec<Expr>) -> Expr {
+        Expr::join_flat(items, Node::Mul, |n| match n {
+            Node::Mul(items) => Some(items),
+            _ => None,
+        })
+    }
+
+    /// A binary operation, with `node` one of `Node::Sub`, `Node::Div` and `Node::Pow`.
+    pub fn binary(a: Expr, b: Expr, node: fn(ExprId, ExprId) -> Node) -> Expr {
+        Expr::join(vec![a, b], |ids| node(ids[0], ids[1]))
+    }
+
+    pub fn apply(callee: Expr, args: Vec<Expr>) -> Expr {
+        let mut parts = vec![callee];
+        parts.extend(args);
+        Expr::join(parts, |mut ids| {
+            let callee = ids.remove(0);
+            Node::Apply(callee, ids)
+        })
+    }
+
+    pub fn list(items: Vec<Expr>) -> Expr {
+        Expr::join(items, Node::List)
+    }
+
+    pub fn tuple(items: Vec<Expr>) -> Expr {
+        Expr::join(items, Node::Tuple)
+    }
+
+    pub fn assign(name: impl Into<String>, annotation: Option<Annotation>, value: Expr) -> Expr {
+        Expr::join(vec![value], |ids| Node::Assign(name.into(), annotation, ids[0]))
+    }
+
+    pub fn unpack(names: Vec<String>, value: Expr) -> Expr {
+        Expr::join(vec![value], |ids| Node::Unpack(names, ids[0]))
+    }
+
+    pub fn define(name: impl Into<String>, params: Vec<String>, body: Expr, memo: bool) -> Expr {
+        Expr::join(vec![body], |ids| Node::Define(name.into(), params, ids[0], memo))
+    }
+
+    pub fn define_unit(name: impl Into<String>, value: Expr) -> Expr {
+        Expr::join(vec![value], |ids| Node::DefineUnit(name.into(), ids[0]))
+    }
+
+    pub fn quantity(magnitude: Expr, unit: Expr) -> Expr {
+        Expr::join(vec![magnitude, unit], |ids| Node::Quantity(ids[0], ids[1]))
+    }
+
+    pub fn convert(value: Expr, unit: Expr) -> Expr {
+        Expr::join(vec![value, unit], |ids| Node::Convert(ids[0], ids[1]))
+    }
+
+    pub fn block(statements: Vec<Expr>) -> Expr {
+        Expr::join(statements, Node::Block)
+    }
+
+    pub fn let_in(name: impl Into<String>, value: Expr, body: Expr) -> Expr {
+        Expr::join(vec![value, body], |ids| Node::Let(name.into(), ids[0], ids[1]))
+    }
+
+    pub fn for_loop(var: impl Into<String>, list: Expr, body: Expr) -> Expr {
+        Expr::join(vec![list, body], |ids| Node::For(var.into(), ids[0], ids[1]))
+    }
+
+    pub fn while_loop(condition: Expr, body: Expr) -> Expr {
+        Expr::join(vec![condition, body], |ids| Node::While(ids[0], ids[1]))
+    }
+
+    pub fn root(&self) -> ExprRef<'_> {
+        ExprRef { expr: self, id: ExprId(self.nodes.len() as u32 - 1) }
+    }
+
+    /// The value this expression is, if it is just a value.
+    pub fn as_value(&self) -> Option<&Value> {
+        match self.root().node() {
+            Node::Value(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The value this expression is, or the expression back if it isn't just a value.
+    pub fn into_value(self) -> Result<Value, Expr> {
+        if !matches!(self.nodes[..], [Node::Value(_)]) {
+            return Err(self);
+        }
+        match Arc::unwrap_or_clone(self.nodes).pop() {
+            Some(Node::Value(v)) => Ok(v),
+            _ => unreachable!("the only node is a value"),
+        }
+    }
+}
+
+impl std::ops::Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        Expr::join(vec![self], |ids| Node::Neg(ids[0]))
+    }
+}
+
+/// A node of an expression together with the expression, to get to its children.
+#[derive(Clone, Copy)]
+pub struct ExprRef<'a> {
+    expr: &'a Expr,
+    id: ExprId,
+}
+
+impl<'a> ExprRef<'a> {
+    pub fn node(self) -> &'a Node {
+        &self.expr.nodes[self.id.0 as usize]
+    }
+
+    pub(super) fn id(self) -> ExprId {
+        self.id
+    }
+
+    /// A child of this node.
+    pub fn get(self, id: ExprId) -> ExprRef<'a> {
+        ExprRef { expr: self.expr, id }
+    }
+
+    /// The subexpression starting at this node, as an expression of its own.
+    pub fn to_expr(self) -> Expr {
+        if self.id == self.expr.root().id {
+            return self.expr.clone();
+        }
+        // a node that is the child of several nodes is copied once
+        fn copy(r: ExprRef, nodes: &mut Vec<Node>, copied: &mut HashMap<u32, ExprId>) -> ExprId {
+            if let Some(&id) = copied.get(&r.id.0) {
+                return id;
+            }
+            let node = super::grow_stack(|| r.node().clone().map_ids(|child| copy(r.get(child), nodes, copied)));
+            nodes.push(node);
+            let id = ExprId(nodes.len() as u32 - 1);
+            copied.insert(r.id.0, id);
+            id
+        }
+        let mut nodes = vec![];
+        copy(self, &mut nodes, &mut HashMap::new());
+        Expr::from_nodes(nodes)
+    }
+}
+
+#[test]
+fn test_join() {
+    let sum = Expr::sum(vec![Expr::symbol("x"), Expr::value(Value::Exact(1.into()))]);
+    let product = Expr::product(vec![Expr::symbol("y"), sum]);
+    let Node::Mul(items) = product.root().node() else { panic!("expected a product") };
+    assert!(matches!(product.root().get(items[0]).node(), Node::Symbol(s) if s == "y"));
+    let sum = product.root().get(items[1]).to_expr();
+    assert_eq!("x+1", sum.to_string());
+    assert_eq!("y*(x+1)", product.to_string());
+    // nested sums are flattened, with their operands in order
+    let nested = Expr::sum(vec![Expr::symbol("w"), sum.clone(), sum]);
+    assert!(matches!(nested.root().node(), Node::Add(items) if items.len() == 5));
+    assert_eq!("w+x+1+x+1", nested.to_string());
+}
@@ -0,0 +1,236 @@
+//! A fast path for evaluating one expression for many values of a variable,
+//! e.g. to tabulate a function. The expression is compiled once to a small
+//! stack program over plain `f64`s, and every instruction is run on all of
+//! the inputs at once, which keeps the loops simple enough to vectorize.
+//!
+//! Only purely numeric expressions compile: real numbers, the variable,
+//! variables and constants that are real numbers, arithmetic and the
+//! elementary functions. Anything else is left to the evaluator, and so are
+//! expressions whose results would be exact, and everything once both the
+//! precision and the digits shown are more than an `f64` holds, where the
+//! digits of `f64`s would be wrong.
+
+use crate::settings::AngleMode;
+
+use super::{Evaluator, ExprRef, Node, Value};
+
+#[derive(Clone, Copy)]
+enum Op {
+    Const(f64),
+    Var,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Neg,
+    Call(fn(f64) -> f64),
+}
+
+pub struct Program {
+    ops: Vec<Op>,
+}
+
+impl Program {
+    /// Compiles `e` as a function of `var`, `None` if it isn't purely numeric.
+    pub fn compile(evaluator: &Evaluator, e: ExprRef, var: &str) -> Option<Program> {
+        let mut ops = vec![];
+        compile(evaluator, e, var, &mut ops)?;
+        Some(Program { ops })
+    }
+
+    /// The value of the program for each of `xs`.
+    pub fn run(&self, xs: &[f64]) -> Vec<f64> {
+        let mut stack: Vec<Vec<f64>> = vec![];
+        for op in &self.ops {
+            let column = match *op {
+                Op::Const(c) => vec![c; xs.len()],
+                Op::Var => xs.to_vec(),
+                Op::Neg => map(stack.pop().unwrap(), |x| -x),
+                Op::Call(f) => map(stack.pop().unwrap(), f),
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => {
+                    let b = stack.pop().unwrap();
+                    let mut a = stack.pop().unwrap();
+                    let f: fn(f64, f64) -> f64 = match op {
+                        Op::Add => |a, b| a + b,
+                        Op::Sub => |a, b| a - b,
+                        Op::Mul => |a, b| a * b,
+                        Op::Div => |a, b| a / b,
+                        _ => f64::powf,
+                    };
+                    a.iter_mut().zip(&b).for_each(|(a, &b)| *a = f(*a, b));
+                    a
+                }
+            };
+            stack.push(column);
+        }
+        stack.pop().expect("a program leaves its result on the stack")
+    }
+}
+
+fn map(mut column: Vec<f64>, f: fn(f64) -> f64) -> Vec<f64> {
+    column.iter_mut().for_each(|x| *x = f(*x));
+    column
+}
+
+fn real(v: &Value) -> Option<f64> {
+    match v {
+        Value::Exact(r) => Some(r.to_f64()),
+        Value::Decimal(d) => Some(d.to_f64()),
+        _ => None,
+    }
+}
+
+/// `atan(1/x)`, a right angle at 0 like the builtin.
+fn acot(x: f64) -> f64 {
+    if x == 0.0 { std::f64::consts::FRAC_PI_2 } else { x.recip().atan() }
+}
+
+/// The `f64` version of a builtin, in the angle unit of the settings.
+fn function(name: &str, angle: AngleMode) -> Option<fn(f64) -> f64> {
+    let degrees = angle == AngleMode::Degrees;
+    Some(match name {
+        "sin" if degrees => |x| x.to_radians().sin(),
+        "cos" if degrees => |x| x.to_radians().cos(),
+        "tan" if degrees => |x| x.to_radians().tan(),
+        "asin" if degrees => |x| x.asin().to_degrees(),
+        "acos" if degrees => |x| x.acos().to_degrees(),
+        "sec" if degrees => |x| x.to_radians().cos().recip(),
+        "csc" if degrees => |x| x.to_radians().sin().recip(),
+        "cot" if degrees => |x| x.to_radians().tan().recip(),
+        "atan" if degrees => |x| x.atan().to_degrees(),
+        "asec" if degrees => |x| x.recip().acos().to_degrees(),
+        "acsc" if degrees => |x| x.recip().asin().to_degrees(),
+        "acot" if degrees => |x| acot(x).to_degrees(),
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "asin" => f64::asin,
+        "acos" => f64::acos,
+        "sec" => |x| x.cos().recip(),
+        "csc" => |x| x.sin().recip(),
+        "cot" => |x| x.tan().recip(),
+        "atan" => f64::atan,
+        "asec" => |x| x.recip().acos(),
+        "acsc" => |x| x.recip().asin(),
+        "acot" => acot,
+        "sinh" => f64::sinh,
+        "cosh" => f64::cosh,
+        "tanh" => f64::tanh,
+        "asinh" => f64::asinh,
+        "acosh" => f64::acosh,
+        "atanh" => f64::atanh,
+        "sqrt" => f64::sqrt,
+        "ln" => f64::ln,
+        "abs" => f64::abs,
+        _ => return None,
+    })
+}
+
+/// Whether `e` has a decimal in it, without which the result would be exact.
+fn has_decimal(evaluator: &Evaluator, e: ExprRef) -> bool {
+    super::grow_stack(|| has_decimal_node(evaluator, e))
+}
+
+fn has_decimal_node(evaluator: &Evaluator, e: ExprRef) -> bool {
+    let decimal = |v: Option<&Value>| matches!(v, Some(Value::Decimal(_)));
+    match e.node() {
+        Node::Value(v) => decimal(Some(v)),
+        Node::Literal(l) => l.text.contains('.'),
+        Node::Symbol(s) => match evaluator.variable(s) {
+            Some(bound) => decimal(bound.as_value()),
+            None => decimal(evaluator.constant(s).as_ref()),
+        },
+        node => node.children().into_iter().any(|child| has_decimal(evaluator, e.get(child))),
+    }
+}
+
+fn compile(evaluator: &Evaluator, e: ExprRef, var: &str, ops: &mut Vec<Op>) -> Option<()> {
+    super::grow_stack(|| compile_node(evaluator, e, var, ops))
+}
+
+fn compile_node(evaluator: &Evaluator, e: ExprRef, var: &str, ops: &mut Vec<Op>) -> Option<()> {
+    let mut binary = |a, b, op| {
+        compile(evaluator, e.get(a), var, ops)?;
+        compile(evaluator, e.get(b), var, ops)?;
+        ops.push(op);
+        Some(())
+    };
+    match e.node() {
+        Node::Value(v) => ops.push(Op::Const(real(v)?)),
+        Node::Literal(l) if !l.imaginary => ops.push(Op::Const(l.text.parse().ok()?)),
+        Node::Symbol(s) if s == var => ops.push(Op::Var),
+        Node::Symbol(s) => {
+            let value = match evaluator.variable(s) {
+                Some(bound) => real(bound.as_value()?)?,
+                None => real(&evaluator.constant(s)?)?,
+            };
+            ops.push(Op::Const(value));
+        }
+        &Node::Sub(a, b) => binary(a, b, Op::Sub)?,
+        &Node::Div(a, b) => binary(a, b, Op::Div)?,
+        &Node::Pow(a, b) => binary(a, b, Op::Pow)?,
+        Node::Add(items) | Node::Mul(items) => {
+            let op = if let Node::Add(_) = e.node() { Op::Add } else { Op::Mul };
+            for (n, &item) in items.iter().enumerate() {
+                compile(evaluator, e.get(item), var, ops)?;
+                if n != 0 {
+                    ops.push(op);
+                }
+            }
+        }
+        &Node::Neg(a) => {
+            compile(evaluator, e.get(a), var, ops)?;
+            ops.push(Op::Neg);
+        }
+        Node::Apply(callee, args) => {
+            let (Node::Symbol(name), &[arg]) = (e.get(*callee).node(), &args[..]) else {
+                return None;
+            };
+            let f = function(name, evaluator.settings.angle)?;
+            compile(evaluator, e.get(arg), var, ops)?;
+            ops.push(Op::Call(f));
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+impl Evaluator {
+    /// Evaluates the user defined function `f` of one parameter for each of
+    /// `xs` with plain `f64`s, `None` if its body isn't purely numeric with
+    /// a decimal in it, or if more than 53 bits and 15 digits are asked for.
+    pub fn eval_batch(&self, f: &str, xs: &[f64]) -> Option<Vec<f64>> {
+        if self.settings.strict_exact || self.settings.money.is_some() {
+            return None;
+        }
+        if self.precision() > f64::MANTISSA_DIGITS && self.settings.round_digits > f64::DIGITS as usize {
+            return None;
+        }
+        let function = self.functions.get(f)?.single()?;
+        let [param] = &function.params[..] else {
+            return None;
+        };
+        if !has_decimal(self, function.body.root()) {
+            return None;
+        }
+        Some(Program::compile(self, function.body.root(), param)?.run(xs))
+    }
+}
+
+#[test]
+fn test_eval_batch() {
+    let mut e = Evaluator::default();
+    for line in ["a = 3", "f(x) = a*x^2 - 1/x + sqrt(4) + 1.5", "g(x) = [x]", "h(x) = x + y", "k(x) = x/3"] {
+        crate::session::eval(&mut e, line).unwrap();
+    }
+    assert_eq!(Some(vec![5.5, 15.0, 30.166666666666668]), e.eval_batch("f", &[1.0, 2.0, 3.0]));
+    assert_eq!(None, e.eval_batch("g", &[1.0]));
+    assert_eq!(None, e.eval_batch("h", &[1.0]));
+    // exact, or more precise than `f64`s
+    assert_eq!(None, e.eval_batch("k", &[1.0]));
+    e.settings_mut().set("digits", "16").unwrap();
+    assert_eq!(None, e.eval_batch("f", &[1.0]));
+    e.settings_mut().set("precision", "53").unwrap();
+    assert!(e.eval_batch("f", &[1.0]).is_some());
+}
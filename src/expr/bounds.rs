@@ -0,0 +1,74 @@
+//! `:set error-bars on`, which evaluates every statement with a decimal result
+//! twice more, once with every operation rounded down and once up, and shows
+//! how far apart the two results are. It is cheap compared to interval
+//! arithmetic and isn't a proof, but when the two disagree in the digits
+//! shown, the `precision` isn't enough for the statement.
+
+use rug::Float;
+
+use super::{Evaluator, Expr, Value};
+use crate::settings::Rounding;
+
+/// How far apart rounding down and up put a result.
+pub struct ErrorBar {
+    pub low: Float,
+    pub high: Float,
+}
+
+impl ErrorBar {
+    pub fn width(&self) -> Float {
+        Float::with_val(self.low.prec().max(self.high.prec()), &self.high - &self.low)
+    }
+
+    /// How many significant digits the two results have in common, roughly.
+    pub fn digits(&self) -> u32 {
+        let width = self.width();
+        if width.is_zero() {
+            return (f64::from(self.low.prec()) * std::f64::consts::LOG10_2) as u32;
+        }
+        // tiny results would be 0 as an `f64`
+        let (low, high) = (self.low.clone().abs(), self.high.clone().abs());
+        let magnitude = if low > high { low } else { high };
+        (magnitude / width).log10().to_f64().max(0.0) as u32
+    }
+}
+
+impl Evaluator {
+    /// Evaluates `statement` again with the rounding down and then up, `None`
+    /// unless both give a real decimal. Nothing changes in `self`.
+    pub fn error_bar(&self, statement: &Expr) -> Option<ErrorBar> {
+        let bound = |rounding| {
+            let mut e = self.clone();
+            e.forbid_side_effects();
+            e.settings.rounding = rounding;
+            match e.eval(statement.clone()).ok()?.into_value() {
+                Ok(Value::Decimal(f)) if f.is_finite() => Some(f),
+                _ => None,
+            }
+        };
+        let (down, up) = (bound(Rounding::Down)?, bound(Rounding::Up)?);
+        // an operation rounded down can still make the result larger, like in `1/x`
+        Some(if down <= up { ErrorBar { low: down, high: up } } else { ErrorBar { low: up, high: down } })
+    }
+}
+
+#[test]
+fn test_error_bar() {
+    let e = Evaluator::default();
+    let bar = |line: &str| e.error_bar(&super::parse(&e, line).0.remove(0));
+    let third = bar("1/3 + 0.0").unwrap();
+    assert!(third.low < third.high);
+    assert_eq!(29, third.digits());
+    for line in ["1.0 + 0.001", "1.1 * 1.1", "2.0^0.5"] {
+        let bar = bar(line).unwrap();
+        assert!(bar.low < bar.high, "{line}");
+        assert_eq!(29, bar.digits(), "{line}");
+    }
+    let mut e = Evaluator::default();
+    e.settings_mut().set("precision", "8").unwrap();
+    let sum = e.error_bar(&super::parse(&e, "1.0 + 0.001").0.remove(0)).unwrap();
+    assert_eq!((1.0, 1.0078125), (sum.low.to_f64(), sum.high.to_f64()));
+    // what is left after cancelling out is only rounding errors
+    assert_eq!(0, bar("(1e8 + 0.1) - 1e8 - 0.1").unwrap().digits());
+    assert!(bar("1/3").is_none());
+}
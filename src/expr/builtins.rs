@@ -0,0 +1,214 @@
+//! Functions that can be called by name, e.g. `sin(x)` or `primes(1, 100)`.
+
+use color_eyre::eyre::bail;
+
+use super::{Evaluator, Value};
+
+mod codec;
+mod compare;
+mod complex;
+pub mod docs;
+mod elementary;
+mod format;
+mod hyperbolic;
+pub mod io;
+mod linalg;
+mod modular;
+mod ntheory;
+mod predicates;
+mod quat;
+mod special;
+mod trig;
+
+pub use special::symbolic;
+
+pub struct Param {
+    pub name: &'static str,
+    /// What the parameter defaults to when left out, for documentation only.
+    pub default: Option<&'static str>,
+}
+
+pub struct Builtin {
+    pub name: &'static str,
+    /// Optional parameters come after the required ones.
+    pub params: &'static [Param],
+    pub func: fn(&mut Evaluator, Vec<Value>) -> color_eyre::Result<Value>,
+}
+
+impl Builtin {
+    pub fn min_args(&self) -> usize {
+        self.params.iter().filter(|p| p.default.is_none()).count()
+    }
+
+    pub fn max_args(&self) -> usize {
+        self.params.len()
+    }
+
+    /// How the function is called, e.g. `norm(v, p = 2)`.
+    pub fn signature(&self) -> String {
+        let params: Vec<_> = self
+            .params
+            .iter()
+            .map(|p| match p.default {
+                Some(default) => format!("{} = {default}", p.name),
+                None => p.name.to_string(),
+            })
+            .collect();
+        format!("{}({})", self.name, params.join(", "))
+    }
+
+    pub fn call(&self, evaluator: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+        let (min, max) = (self.min_args(), self.max_args());
+        if args.len() < min || args.len() > max {
+            let expected = if min == max {
+                min.to_string()
+            } else {
+                format!("{min} to {max}")
+            };
+            bail!("`{}` takes {expected} arguments, but {} were given", self.name, args.len());
+        }
+        (self.func)(evaluator, args)
+    }
+}
+
+macro_rules! builtins {
+    ($($name:literal ($($param:ident $(= $default:tt)?),*) => $func:expr),*$(,)?) => {
+        &[$(
+            Builtin {
+                name: $name,
+                params: &[$(Param {
+                    name: stringify!($param),
+                    default: builtins!(@default $($default)?),
+                }),*],
+                func: $func,
+            }
+        ),*]
+    };
+    (@default) => { None };
+    (@default $default:tt) => { Some(stringify!($default)) };
+}
+
+static BUILTINS: &[Builtin] = builtins! {
+    "sin"(x) => trig::sin,
+    "cos"(x) => trig::cos,
+    "tan"(x) => trig::tan,
+    "sec"(x) => trig::sec,
+    "csc"(x) => trig::csc,
+    "cot"(x) => trig::cot,
+    "sqrt"(x) => elementary::sqrt,
+    "ln"(x) => elementary::ln,
+    "asin"(x) => elementary::asin,
+    "acos"(x) => elementary::acos,
+    "atan"(x) => trig::atan,
+    "atan2"(y, x) => trig::atan2,
+    "topolar"(x, y) => trig::topolar,
+    "torect"(r, theta) => trig::torect,
+    "asec"(x) => elementary::asec,
+    "acsc"(x) => elementary::acsc,
+    "acot"(x) => trig::acot,
+    "sinh"(x) => hyperbolic::sinh,
+    "cosh"(x) => hyperbolic::cosh,
+    "tanh"(x) => hyperbolic::tanh,
+    "asinh"(x) => hyperbolic::asinh,
+    "acosh"(x) => hyperbolic::acosh,
+    "atanh"(x) => hyperbolic::atanh,
+    "abs"(x) => elementary::abs,
+    "hypot"(x, y, z = 0) => elementary::hypot,
+    "erf"(x) => special::erf,
+    "erfc"(x) => special::erfc,
+    "gamma"(x) => special::gamma,
+    "lgamma"(x) => special::lgamma,
+    "beta"(a, b) => special::beta,
+    "zeta"(s) => special::zeta,
+    "primes"(from, to) => ntheory::primes,
+    "prime"(n) => ntheory::prime,
+    "primorial"(n) => ntheory::primorial,
+    "divmod"(a, b) => ntheory::divmod,
+    "quot"(a, b) => ntheory::quot,
+    "rem"(a, b) => ntheory::rem,
+    "mod"(a, b) => ntheory::modulo,
+    "range"(from, to) => ntheory::range,
+    "powmod"(base, exp, modulus) => modular::powmod,
+    "invmod"(a, modulus) => modular::invmod,
+    "crt"(congruences) => modular::crt,
+    "tohex"(n) => codec::tohex,
+    "fromhex"(s) => codec::fromhex,
+    "tobase64"(n) => codec::tobase64,
+    "frombase64"(s) => codec::frombase64,
+    "xor"(a, b) => codec::xor,
+    "format"(x, digits) => format::format,
+    "dot"(u, v) => linalg::dot,
+    "cross"(u, v) => linalg::cross,
+    "norm"(v, p = 2) => linalg::norm,
+    "angle_between"(u, v) => linalg::angle_between,
+    "det"(m) => linalg::det,
+    "linsolve"(m, b) => linalg::linsolve,
+    "lu"(m) => linalg::lu,
+    "qr"(m) => linalg::qr,
+    "eigenvalues"(m) => linalg::eigenvalues,
+    "re"(z) => complex::re,
+    "im"(z) => complex::im,
+    "arg"(z) => complex::arg,
+    "conj"(z) => complex::conj,
+    "polar"(r, theta) => complex::polar,
+    "quat"(w, x, y, z) => quat::quat,
+    "normalize"(v) => quat::normalize,
+    "rotate"(v, q) => quat::rotate,
+    "write"(value, path, mode = "w") => io::write,
+    "readcsv"(path, col = all) => io::readcsv,
+    "readlist"(path) => io::readlist,
+    "env"(name) => io::env,
+    "lt"(a, b) => compare::lt,
+    "le"(a, b) => compare::le,
+    "gt"(a, b) => compare::gt,
+    "ge"(a, b) => compare::ge,
+    "eq"(a, b) => compare::eq,
+    "ne"(a, b) => compare::ne,
+    "isinteger"(x) => predicates::isinteger,
+    "isrational"(x) => predicates::isrational,
+    "iseven"(n) => predicates::iseven,
+    "isodd"(n) => predicates::isodd,
+    "isprime"(n) => predicates::isprime,
+    "isreal"(x) => predicates::isreal,
+};
+
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|b| b.name == name)
+}
+
+/// Splits the arguments into an array, the arity has already been checked by `Builtin::call`.
+fn take<const N: usize>(args: Vec<Value>) -> [Value; N] {
+    match args.try_into() {
+        Ok(args) => args,
+        Err(_) => unreachable!("arity is checked before calling"),
+    }
+}
+
+/// 1 for true and 0 for false, what comparisons and predicates return.
+fn truth(holds: bool) -> Value {
+    Value::Exact((holds as u32).into())
+}
+
+/// Converts a numeric argument to a `Float` at the evaluator's precision.
+fn to_float(e: &Evaluator, v: Value) -> color_eyre::Result<rug::Float> {
+    match v {
+        Value::Decimal(d) => Ok(d),
+        Value::Exact(r) => Ok(rug::Float::with_val_round(e.precision(), r, e.round()).0),
+        Value::Complex(_) => bail!("expected a real number, found a complex number"),
+        other => bail!("expected a number, found {}", other.kind()),
+    }
+}
+
+/// Square root that stays exact for rationals whose numerator and denominator
+/// are both perfect squares.
+fn sqrt(e: &Evaluator, v: Value) -> color_eyre::Result<Value> {
+    if let Value::Exact(r) = &v {
+        if r.numer().is_perfect_square() && r.denom().is_perfect_square() {
+            let (n, d) = r.clone().into_numer_denom();
+            return Ok(Value::Exact((n.sqrt(), d.sqrt()).into()));
+        }
+    }
+    let mut f = to_float(e, v)?;
+    f.sqrt_round(e.round());
+    Ok(Value::Decimal(f))
+}
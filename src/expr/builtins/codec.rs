@@ -0,0 +1,132 @@
+//! Big integers as bits: converting them to and from hex and base64 strings,
+//! and bitwise operations. Hex and base64 are the digits or bytes of integers
+//! ≥ 0, neither encodes a sign.
+
+use color_eyre::eyre::{bail, eyre};
+use rug::integer::Order;
+use rug::Integer;
+
+use super::{take, Evaluator, Value};
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes base64, with or without the `=` padding, but if it is there it
+/// must make the length a multiple of 4.
+fn base64_decode(s: &str) -> color_eyre::Result<Vec<u8>> {
+    let padded = s.trim();
+    let s = padded.trim_end_matches('=');
+    let padding = padded.len() - s.len();
+    if padding > 2 || (padding > 0 && !padded.len().is_multiple_of(4)) || s.len() % 4 == 1 {
+        bail!("`{padded}` is not valid base64, its length or `=` padding is wrong");
+    }
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in s.chars() {
+        let Some(v) = BASE64_ALPHABET.iter().position(|&a| a as char == c) else {
+            bail!("invalid base64 character `{c}`");
+        };
+        acc = acc << 6 | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    // the bits left over only fill up the last character
+    if acc & ((1 << bits) - 1) != 0 {
+        bail!("`{padded}` is not valid base64, its last character has bits past the end set");
+    }
+    Ok(out)
+}
+
+fn non_negative(n: &Value) -> color_eyre::Result<&Integer> {
+    let n = n.as_integer()?;
+    if n.is_negative() {
+        bail!("cannot encode the negative number {n}");
+    }
+    Ok(n)
+}
+
+pub fn tohex(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [n] = take(args);
+    Ok(Value::Str(non_negative(&n)?.to_string_radix(16)))
+}
+
+pub fn fromhex(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [s] = take(args);
+    let s = s.as_str()?.trim();
+    let digits = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s).replace('_', "");
+    // `from_str_radix` would take a sign
+    if !digits.starts_with(|c: char| c.is_ascii_hexdigit()) {
+        bail!("`{s}` is not a valid hex number");
+    }
+    let n = Integer::from_str_radix(&digits, 16).map_err(|_| eyre!("`{s}` is not a valid hex number"))?;
+    Ok(Value::Exact(n.into()))
+}
+
+/// Encodes the big-endian bytes of a non-negative integer.
+pub fn tobase64(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [n] = take(args);
+    let mut bytes = non_negative(&n)?.to_digits::<u8>(Order::Msf);
+    if bytes.is_empty() {
+        bytes.push(0);
+    }
+    Ok(Value::Str(base64_encode(&bytes)))
+}
+
+pub fn frombase64(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [s] = take(args);
+    let bytes = base64_decode(s.as_str()?)?;
+    Ok(Value::Exact(Integer::from_digits(&bytes, Order::Msf).into()))
+}
+
+/// Bitwise exclusive or, negative numbers behave as infinitely sign-extended two's complement.
+pub fn xor(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b] = take(args);
+    Ok(Value::Exact(Integer::from(a.as_integer()? ^ b.as_integer()?).into()))
+}
+
+#[test]
+fn test_base64() {
+    assert_eq!("TWFu", base64_encode(b"Man"));
+    assert_eq!("TWE=", base64_encode(b"Ma"));
+    assert_eq!("TQ==", base64_encode(b"M"));
+    assert_eq!(b"Ma".to_vec(), base64_decode("TWE=").unwrap());
+    assert_eq!(b"Man".to_vec(), base64_decode("TWFu").unwrap());
+    // the padding may be left out, but not be wrong
+    assert_eq!(b"Ma".to_vec(), base64_decode("TWE").unwrap());
+    for bad in ["TWE==", "TQ=", "TWFuT", "TWFu====", "T===", "TWF="] {
+        assert!(base64_decode(bad).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn test_codecs() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("[\"ff\", 255, 255]", eval("[tohex(255), fromhex(\"0xff\"), fromhex(\"F_F\")]").unwrap());
+    assert_eq!("[\"QQ==\", 65, \"AA==\"]", eval("[tobase64(65), frombase64(\"QQ==\"), tobase64(0)]").unwrap());
+    // neither encodes a sign
+    assert_eq!("cannot encode the negative number -255", eval("tohex(-255)").unwrap_err().to_string());
+    assert_eq!("cannot encode the negative number -65", eval("tobase64(-65)").unwrap_err().to_string());
+    assert_eq!("`-ff` is not a valid hex number", eval("fromhex(\"-ff\")").unwrap_err().to_string());
+    assert!(eval("fromhex(\"0x-ff\")").is_err());
+    let error = eval("frombase64(\"QQ=\")").unwrap_err().to_string();
+    assert_eq!("`QQ=` is not valid base64, its length or `=` padding is wrong", error);
+}
@@ -0,0 +1,60 @@
+//! Comparisons, which `a < b`, `a == b` and the like are parsed into, with 1
+//! for true and 0 for false, for the conditions of `while` loops.
+
+use std::cmp::Ordering;
+
+use color_eyre::eyre::bail;
+
+use super::super::units;
+use super::{take, to_float, truth, Evaluator, Value};
+
+fn order(e: &Evaluator, a: Value, b: Value) -> color_eyre::Result<Ordering> {
+    let (a, b) = if a.is_quantity() || b.is_quantity() { units::comparable(a, b, e)? } else { (a, b) };
+    if let (Value::Exact(a), Value::Exact(b)) = (&a, &b) {
+        return Ok(a.cmp(b));
+    }
+    let (a, b) = (to_float(e, a)?, to_float(e, b)?);
+    match a.partial_cmp(&b) {
+        Some(ordering) => Ok(ordering),
+        None => bail!("NaN can't be compared"),
+    }
+}
+
+pub fn lt(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b] = take(args);
+    Ok(truth(order(e, a, b)?.is_lt()))
+}
+
+pub fn le(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b] = take(args);
+    Ok(truth(order(e, a, b)?.is_le()))
+}
+
+pub fn gt(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b] = take(args);
+    Ok(truth(order(e, a, b)?.is_gt()))
+}
+
+pub fn ge(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b] = take(args);
+    Ok(truth(order(e, a, b)?.is_ge()))
+}
+
+/// Whether two values are equal, strings included.
+fn equal(e: &Evaluator, a: Value, b: Value) -> color_eyre::Result<bool> {
+    match (a, b) {
+        (Value::Str(a), Value::Str(b)) => Ok(a == b),
+        (Value::Str(_), _) | (_, Value::Str(_)) => Ok(false),
+        (a, b) => Ok(order(e, a, b)?.is_eq()),
+    }
+}
+
+pub fn eq(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b] = take(args);
+    Ok(truth(equal(e, a, b)?))
+}
+
+pub fn ne(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b] = take(args);
+    Ok(truth(!equal(e, a, b)?))
+}
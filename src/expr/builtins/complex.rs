@@ -0,0 +1,90 @@
+//! Functions on complex numbers. Real numbers are accepted everywhere and
+//! treated as having a zero imaginary part.
+
+use color_eyre::eyre::bail;
+use rug::{Complex, Float};
+
+use super::{take, to_float, Evaluator, Value};
+
+fn complex(e: &Evaluator, v: Value) -> color_eyre::Result<Complex> {
+    let kind = v.kind();
+    match e.to_complex(v) {
+        Some(c) => Ok(c),
+        None => bail!("expected a number, found {kind}"),
+    }
+}
+
+pub fn re(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [z] = take(args);
+    Ok(match z {
+        Value::Complex(c) => Value::Decimal(c.into_real_imag().0),
+        real => {
+            complex(e, real.clone())?;
+            real
+        }
+    })
+}
+
+pub fn im(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [z] = take(args);
+    Ok(match z {
+        Value::Complex(c) => Value::Decimal(c.into_real_imag().1),
+        real => {
+            complex(e, real)?;
+            Value::zero()
+        }
+    })
+}
+
+/// The angle of `z` from the positive real axis, in `(-pi, pi]`.
+pub fn arg(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [z] = take(args);
+    let z = complex(e, z)?;
+    Ok(Value::Decimal(Float::with_val(e.precision(), z.arg_ref())))
+}
+
+pub fn conj(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [z] = take(args);
+    Ok(match z {
+        Value::Complex(c) => Value::Complex(c.conj()),
+        Value::Quaternion(q) => Value::Quaternion(Box::new(q.conj())),
+        real => {
+            complex(e, real.clone())?;
+            real
+        }
+    })
+}
+
+/// The complex number with absolute value `r` and argument `theta`.
+pub fn polar(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [r, theta] = take(args);
+    let (r, theta) = (to_float(e, r)?, to_float(e, theta)?);
+    let (sin, cos) = theta.sin_cos(Float::new(e.precision()));
+    Ok(Value::Complex(Complex::with_val(e.precision(), (cos * &r, sin * r))))
+}
+
+#[test]
+fn test_complex() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("[3.0000000, 4.0000000]", eval("[re(3+4i), im(3+4i)]").unwrap());
+    // real numbers have no imaginary part
+    assert_eq!("[5, 0]", eval("[re(5), im(5)]").unwrap());
+    assert_eq!("[3.1415927, 1.5707963]", eval("[arg(-1), arg(1i)]").unwrap());
+    assert_eq!("3.0000000-4.0000000i", eval("conj(3+4i)").unwrap());
+    assert_eq!("2", eval("conj(2)").unwrap());
+    assert_eq!("2.0000000", eval("polar(2, 0)").unwrap());
+    assert_eq!("1", eval("abs(polar(1, pi/2) - 1i) < 10^-20").unwrap());
+    assert_eq!("expected a number, found a string", eval("re(\"a\")").unwrap_err().to_string());
+
+    let settings = e.settings_mut();
+    settings.set("complex-form", "polar").unwrap();
+    assert_eq!("polar", settings.list().iter().find(|(name, _)| *name == "complex-form").unwrap().1);
+    assert!(settings.set("complexform", "polar").is_err());
+    assert!(settings.set("complex-form", "round").is_err());
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("polar(5.0000000, 0.92729522)", eval("3+4i").unwrap());
+    assert_eq!("polar(2.0000000, 3.1415927)", eval("polar(2, pi)").unwrap());
+    // real results are printed as usual
+    assert_eq!("5.0000000", eval("abs(3+4i)").unwrap());
+}
@@ -0,0 +1,172 @@
+//! Documentation for `:help`, kept next to the function table so that new
+//! builtins are documented in the same change (`test_all_documented` checks).
+
+pub struct Doc {
+    pub name: &'static str,
+    pub category: &'static str,
+    pub summary: &'static str,
+    /// Which arguments are accepted, if not every number.
+    pub domain: Option<&'static str>,
+    /// Whether angles are taken or returned in the unit set with `:set angle`.
+    pub angle_mode: bool,
+    pub examples: &'static [&'static str],
+}
+
+macro_rules! docs {
+    ($($category:literal { $($name:literal $(@$angle:ident)? $(domain $domain:literal)? : $summary:literal [$($example:literal),*])* })*) => {
+        &[$($(
+            Doc {
+                name: $name,
+                category: $category,
+                summary: $summary,
+                domain: docs!(@domain $($domain)?),
+                angle_mode: docs!(@angle $($angle)?),
+                examples: &[$($example),*],
+            },
+        )*)*]
+    };
+    (@domain) => { None };
+    (@domain $domain:literal) => { Some($domain) };
+    (@angle) => { false };
+    (@angle angle) => { true };
+}
+
+static DOCS: &[Doc] = docs! {
+    "Elementary" {
+        "sqrt" domain "x ≥ 0, or complex": "The square root, exact when the argument is the square of a rational." ["sqrt(9/4)", "sqrt(-4 + 0i)"]
+        "ln" domain "x > 0, or complex": "The natural logarithm." ["ln(2)", "ln(-1 + 0i)"]
+        "hypot": "The length `sqrt(x^2 + y^2 + z^2)`, exact where it is rational and otherwise rounded only once." ["hypot(3, 4)", "hypot(1, 2, 2)", "hypot(1e300, 1e300)"]
+        "abs": "The absolute value of a number, the modulus of a complex number or the norm of a quaternion." ["abs(-3)", "abs(3 + 4i)"]
+    }
+    "Trigonometry" {
+        "sin" @angle: "The sine. In degrees, angles with a rational sine (0°, 30°, 90°, ...) give exact results." ["sin(1)", "sin(pi/6)"]
+        "cos" @angle: "The cosine. In degrees, angles with a rational cosine (0°, 60°, 90°, ...) give exact results." ["cos(1)", "cos(pi/3)"]
+        "tan" @angle domain "x not an odd multiple of 90°": "The tangent. In degrees, multiples of 45° give exact results." ["tan(1)", "tan(pi/4)"]
+        "asin" @angle domain "-1 ≤ x ≤ 1, or complex": "The inverse sine." ["asin(1/2)"]
+        "acos" @angle domain "-1 ≤ x ≤ 1, or complex": "The inverse cosine." ["acos(1/2)"]
+        "atan" @angle: "The inverse tangent." ["atan(1)"]
+        "atan2" @angle: "The angle of the point `(x, y)`, in (-180°, 180°], the inverse tangent of `y/x` in the right quadrant." ["atan2(1, -1)", "atan2(-1, 0)"]
+        "topolar" @angle: "The polar coordinates `[r, theta]` of the point `(x, y)`, with `r` from `hypot` and `theta` from `atan2`." ["topolar(1, 1)", "topolar(-3, 0)"]
+        "torect" @angle: "The rectangular coordinates `[x, y]` of the point at distance `r` and angle `theta`." ["torect(2, pi/3)"]
+        "sec" @angle domain "x not an odd multiple of 90°": "The secant, `1/cos(x)`, exact in degrees where the cosine is." ["sec(1)", "sec(pi/3)"]
+        "csc" @angle domain "x not a multiple of 180°": "The cosecant, `1/sin(x)`, exact in degrees where the sine is." ["csc(1)", "csc(pi/6)"]
+        "cot" @angle domain "x not a multiple of 180°": "The cotangent, `1/tan(x)`, exact in degrees where the tangent is." ["cot(1)", "cot(pi/4)"]
+        "asec" @angle domain "x ≤ -1 or x ≥ 1, or complex": "The inverse secant, `acos(1/x)`." ["asec(2)"]
+        "acsc" @angle domain "x ≤ -1 or x ≥ 1, or complex": "The inverse cosecant, `asin(1/x)`." ["acsc(2)"]
+        "acot" @angle: "The inverse cotangent, `atan(1/x)`, a right angle at 0." ["acot(1)", "acot(0)"]
+    }
+    "Hyperbolic functions" {
+        "sinh": "The hyperbolic sine." ["sinh(1)"]
+        "cosh": "The hyperbolic cosine." ["cosh(1)"]
+        "tanh": "The hyperbolic tangent." ["tanh(1)"]
+        "asinh": "The inverse hyperbolic sine." ["asinh(1)"]
+        "acosh" domain "x ≥ 1, or complex": "The inverse hyperbolic cosine, the one that isn't negative." ["acosh(2)"]
+        "atanh" domain "-1 < x < 1, or complex": "The inverse hyperbolic tangent." ["atanh(1/2)"]
+    }
+    "Special functions" {
+        "erf": "The error function, `2/sqrt(pi)` times the integral of `e^(-t^2)` from 0 to `x`." ["erf(1)"]
+        "erfc": "The complementary error function `1 - erf(x)`, accurate where that is tiny." ["erfc(10)"]
+        "gamma" domain "x not 0 or a negative integer": "The gamma function, exact for positive integers, where `gamma(n)` is `(n-1)!`, and a multiple of `sqrt(pi)` for halves in `symbolic` mode." ["gamma(5)", "gamma(1/2)"]
+        "lgamma" domain "x not 0 or a negative integer": "The logarithm of the absolute value of `gamma(x)`, which stays small where that overflows." ["lgamma(1000)"]
+        "beta" domain "a and b not 0 or negative integers": "The beta function `gamma(a)*gamma(b)/gamma(a+b)`, exact for positive integers and halves." ["beta(2, 5)", "beta(1/2, 1/2)"]
+        "zeta" domain "s ≠ 1": "The Riemann zeta function, exact at the negative integers, and a multiple of a power of `pi` at even ones in `symbolic` mode." ["zeta(2)", "zeta(-1)"]
+    }
+    "Number theory" {
+        "primes" domain "integers": "The primes between `from` and `to`, inclusive." ["primes(1, 30)"]
+        "prime" domain "n ≥ 1": "The `n`th prime." ["prime(100)"]
+        "primorial" domain "n ≥ 0": "The product of the primes up to `n`." ["primorial(10)"]
+        "divmod" domain "b ≠ 0": "The quotient rounded down and the remainder, as `(q, r)` with `a = q*b + r`, which can be assigned to two names at once. The remainder has the sign of `b`, like `mod`." ["divmod(17, 5)", "q, r = divmod(-7, 2)"]
+        "quot" domain "b ≠ 0": "`a/b` rounded toward zero, like integer division in C and Rust." ["quot(-7, 2)"]
+        "rem" domain "b ≠ 0": "The remainder `a - quot(a, b)*b`, which has the sign of `a`, like `%` in C and Rust." ["rem(-7, 2)"]
+        "mod" domain "b ≠ 0": "The remainder `a - floor(a/b)*b`, which has the sign of `b`, like `%` in Python." ["mod(-7, 2)", "mod(7.5, 2)"]
+        "range" domain "integers": "The integers from `from` to `to`, which `for k in 1..10 { ... }` loops over." ["range(1, 5)"]
+        "powmod" domain "integers, modulus ≥ 1": "`base` to the power `exp`, modulo `modulus`." ["powmod(2, 100, 7)"]
+        "invmod" domain "integers coprime to the modulus": "The inverse of `a` modulo `modulus`." ["invmod(3, 7)"]
+        "crt" domain "a list of (residue, modulus) pairs": "Solves simultaneous congruences with the Chinese remainder theorem." ["crt([(2, 3), (3, 5)])"]
+    }
+    "Encoding" {
+        "tohex" domain "integers ≥ 0": "An integer in hexadecimal." ["tohex(255)"]
+        "fromhex" domain "strings of hexadecimal digits": "Reads a hexadecimal integer, without a sign." ["fromhex(\"ff\")"]
+        "tobase64" domain "integers ≥ 0": "The big-endian bytes of an integer in base64." ["tobase64(65)"]
+        "frombase64" domain "base64 strings": "Reads base64 as the big-endian bytes of an integer." ["frombase64(\"QQ==\")"]
+        "format" domain "digits ≥ 1": "The text of `x` printed with `digits` significant digits, without changing the `digits` setting for other results." ["format(pi, 20)", "format(1/3, 3)"]
+        "xor" domain "integers": "The bitwise exclusive or, in two's complement for negative numbers. `:set caret xor` makes `a ^ b` mean this." ["xor(12, 10)"]
+    }
+    "Linear algebra" {
+        "dot" domain "vectors of the same length": "The dot product, of vectors with symbols in them too." ["dot([1, 2], [3, 4])", "dot([a, b], [c, d])"]
+        "cross" domain "3D vectors": "The cross product." ["cross([1, 0, 0], [0, 1, 0])"]
+        "norm" domain "vectors, p ≥ 1 or \"inf\"": "The `p`-norm of a vector." ["norm([3, 4])", "norm([3, 4], 1)"]
+        "angle_between" domain "nonzero vectors": "The angle between two vectors, in radians." ["angle_between([1, 0], [0, 1])"]
+        "det" domain "square matrices": "The determinant, computed exactly for exact matrices and as an expression for matrices with symbols." ["det([[1, 2], [3, 4]])", "det([[a, b], [c, d]])"]
+        "linsolve" domain "exact matrices and vectors": "The solution `x` of `m x = b`, by fraction-free elimination without rounding. Where there are many, `(p, [v1, v2, ...])`, a solution `p` and vectors any multiples of which can be added to it." ["linsolve([[2, 1], [1, 3]], [3, 5])", "linsolve([[1, 1]], [2])"]
+        "lu" domain "square matrices": "The LU decomposition with partial pivoting, as `(L, U, P)`." ["lu([[1, 2], [3, 4]])"]
+        "qr" domain "matrices": "The QR decomposition, as `(Q, R)`." ["qr([[1, 2], [3, 4]])"]
+        "eigenvalues" domain "square matrices with real eigenvalues": "The eigenvalues." ["eigenvalues([[2, 0], [0, 3]])"]
+    }
+    "Complex numbers" {
+        "re": "The real part." ["re(1 + 2i)"]
+        "im": "The imaginary part." ["im(1 + 2i)"]
+        "arg": "The angle from the positive real axis, in radians." ["arg(1i)"]
+        "conj": "The complex conjugate." ["conj(1 + 2i)"]
+        "polar" domain "real r and theta": "The complex number with absolute value `r` and argument `theta` in radians." ["polar(2, pi/2)"]
+    }
+    "Quaternions" {
+        "quat" domain "real numbers": "The quaternion w + xi + yj + zk." ["quat(1, 0, 0, 0)"]
+        "normalize" domain "nonzero quaternions and vectors": "Scales to unit length." ["normalize([3, 4])"]
+        "rotate" domain "a 3D vector and a nonzero quaternion": "Rotates `v` by `q`, which is normalized first." ["rotate([1, 0, 0], quat(1, 0, 0, 1))"]
+    }
+    "Files and environment" {
+        "write" domain "mode \"w\" or \"a\"": "Writes a value to a file as it would be printed and returns it. Mode \"a\" appends to the file." ["write(primes(1, 100), \"primes.txt\")", "write(2/3, \"log.txt\", \"a\")"]
+        "readcsv" domain "col ≥ 1": "Reads column `col` of a CSV file as a list, or all columns as a list of rows. Integers and fractions are read exactly, a header row is skipped." ["readcsv(\"data.csv\", 2)"]
+        "readlist": "Reads whitespace separated numbers from a file as a list." ["readlist(\"nums.txt\")"]
+        "env": "An environment variable, read as a number when it is one and as a string otherwise." ["env(\"COLUMNS\") / 2"]
+    }
+    "Comparisons" {
+        "lt": "1 if `a < b` and 0 otherwise, what `a < b` means, for the condition of `while a < b { ... }`." ["lt(1, 2)", "1 < 2"]
+        "le": "1 if `a ≤ b` and 0 otherwise, what `a <= b` means." ["2 <= 2"]
+        "gt": "1 if `a > b` and 0 otherwise, what `a > b` means." ["3 > 2"]
+        "ge": "1 if `a ≥ b` and 0 otherwise, what `a >= b` means." ["2 >= 3"]
+        "eq": "1 if the numbers or strings are equal and 0 otherwise, what `a == b` means." ["1/2 == 0.5"]
+        "ne": "0 if the numbers or strings are equal and 1 otherwise, what `a != b` means." ["\"a\" != \"b\""]
+    }
+    "Kinds of numbers" {
+        "isinteger": "1 if `x` is an integer and 0 otherwise, decimals like `4.0` included." ["isinteger(4.0)", "isinteger(7/2)"]
+        "isrational": "1 if `x` is an exact number and 0 for decimals, which are approximations, unless they are integers." ["isrational(1/3)", "isrational(0.5)"]
+        "iseven": "1 if `n` is an even integer and 0 otherwise." ["iseven(10)"]
+        "isodd": "1 if `n` is an odd integer and 0 otherwise." ["isodd(-3)"]
+        "isprime": "1 if `n` is a prime and 0 otherwise, non-integers included." ["isprime(97)", "isprime(2^61 - 1)"]
+        "isreal": "1 if `x` is a real number and 0 otherwise. Complex numbers and quaternions count when their imaginary parts are 0, quantities when their magnitude is real." ["isreal(sqrt(-1 + 0i))", "isreal(2 m)"]
+    }
+    "Expressions" {
+        "depth": "The number of nodes on the longest path through the expression tree." ["depth(x * (y + 1))"]
+        "nodecount": "The number of nodes in the expression tree." ["nodecount(x * (y + 1))"]
+        "variables": "The symbols left in the expression, sorted." ["variables(x * y + pi)"]
+        "rearrange": "The equation solved for `x`, which may appear once under operations that can be undone, or more often if the equation is linear in it. The equation can be written with `=` too." ["rearrange(v == u + a*t, t)", "rearrange(E = m*c^2, c)"]
+        "isolate_roots": "Intervals `[a, b]` with rational ends that each hold one real root of a polynomial with exact coefficients, found with Sturm sequences. `isolate_roots(p, x, width)` narrows them down to at most `width` wide." ["isolate_roots(x^3 - 2*x, x)", "isolate_roots(x^5 - x - 1, x, 1/10^6)"]
+        "odesolve": "The solution of `y' = f(t, y)` with `y(t0) = y0` at `t1`, in adaptive steps at the session precision. With a number of `steps`, the rows `[t, y]` of that many equal RK4 steps. `y0` may be a list for a system of equations." ["f(t, y) = -2*t*y; odesolve(f, 0, 1, 1)", "odesolve(f, 0, 1, 1, 10)"]
+        "N": "The expression with constants like `pi` and functions like `sqrt` computed as decimals, for `:set symbolic on`. `N(x, digits)` computes it to that many digits, whatever the precision is, and `N(x, digits, mode)` rounds `up`, `down`, toward `zero` or to the `nearest` whatever the rounding setting is." ["N(2*pi)", "N(pi, 50)", "N(pi, 5, up)"]
+    }
+};
+
+pub fn lookup(name: &str) -> Option<&'static Doc> {
+    DOCS.iter().find(|d| d.name == name)
+}
+
+/// The categories in order, each with the names of its functions.
+pub fn categories() -> Vec<(&'static str, Vec<&'static str>)> {
+    let mut categories: Vec<(&str, Vec<&str>)> = vec![];
+    for doc in DOCS {
+        match categories.last_mut() {
+            Some((category, names)) if *category == doc.category => names.push(doc.name),
+            _ => categories.push((doc.category, vec![doc.name])),
+        }
+    }
+    categories
+}
+
+#[test]
+fn test_all_documented() {
+    for builtin in super::BUILTINS {
+        assert!(lookup(builtin.name).is_some(), "`{}` has no documentation", builtin.name);
+    }
+}
@@ -0,0 +1,215 @@
+//! Elementary functions whose real domain is restricted. Complex arguments
+//! are always accepted, real ones outside the domain are a [`DomainError`].
+//! `hypot` is here too, as the square root that is always real.
+
+use color_eyre::eyre::{bail, eyre};
+use rug::float::Round;
+use rug::{Float, Integer, Rational};
+
+use crate::domain::DomainError;
+
+use super::trig::from_radians;
+use super::{take, to_float, Evaluator, Value};
+
+pub fn sqrt(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if let Value::Complex(mut c) = x {
+        c.sqrt_round((e.round(), e.round()));
+        return Ok(Value::Complex(c));
+    }
+    if to_float(e, x.clone())? < 0 {
+        return Err(DomainError::new("sqrt", &x, "negative numbers have no real square root")
+            .suggest(format!("sqrt({x} + 0i)"))
+            .suggest(format!("sqrt(abs({x}))"))
+            .into());
+    }
+    super::sqrt(e, x)
+}
+
+pub fn ln(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    match x {
+        Value::Complex(mut c) => {
+            c.ln_round((e.round(), e.round()));
+            Ok(Value::Complex(c))
+        }
+        Value::Exact(r) if r == 1 => Ok(Value::zero()),
+        x => {
+            let mut f = to_float(e, x.clone())?;
+            if f.is_zero() {
+                return Err(DomainError::new("ln", &x, "the logarithm of zero is -∞").into());
+            }
+            if f < 0 {
+                return Err(DomainError::new("ln", &x, "negative numbers have no real logarithm")
+                    .suggest(format!("ln({x} + 0i)"))
+                    .suggest(format!("ln(abs({x}))"))
+                    .into());
+            }
+            f.ln_round(e.round());
+            Ok(Value::Decimal(f))
+        }
+    }
+}
+
+pub fn asin(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    arc("asin", e, args, Float::asin_round, rug::Complex::asin_round)
+}
+
+pub fn acos(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    arc("acos", e, args, Float::acos_round, rug::Complex::acos_round)
+}
+
+pub fn asec(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    reciprocal_arc("asec", e, args, Float::acos_round, rug::Complex::acos_round)
+}
+
+pub fn acsc(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    reciprocal_arc("acsc", e, args, Float::asin_round, rug::Complex::asin_round)
+}
+
+/// `asin` and `acos`, which are only real on `[-1, 1]`.
+fn arc(
+    name: &str,
+    e: &mut Evaluator,
+    args: Vec<Value>,
+    real: fn(&mut Float, rug::float::Round) -> std::cmp::Ordering,
+    complex: fn(&mut rug::Complex, (rug::float::Round, rug::float::Round)) -> (std::cmp::Ordering, std::cmp::Ordering),
+) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if let Value::Complex(mut c) = x {
+        complex(&mut c, (e.round(), e.round()));
+        return Ok(Value::Complex(c));
+    }
+    let mut f = to_float(e, x.clone())?;
+    if f.clone().abs() > 1 {
+        return Err(DomainError::new(name, &x, "the argument must be between -1 and 1 to have a real result")
+            .suggest(format!("{name}({x} + 0i)"))
+            .into());
+    }
+    real(&mut f, e.round());
+    Ok(Value::Decimal(from_radians(e, f)))
+}
+
+/// `asec` and `acsc`, `acos` and `asin` of the reciprocal, which are only real
+/// outside of `(-1, 1)`.
+fn reciprocal_arc(
+    name: &str,
+    e: &mut Evaluator,
+    args: Vec<Value>,
+    real: fn(&mut Float, rug::float::Round) -> std::cmp::Ordering,
+    complex: fn(&mut rug::Complex, (rug::float::Round, rug::float::Round)) -> (std::cmp::Ordering, std::cmp::Ordering),
+) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if matches!(x, Value::Exact(_) | Value::Decimal(_) | Value::Complex(_)) && x.is_zero_or_empty() {
+        return Err(DomainError::new(name, &x, "the reciprocal of 0 is infinite").into());
+    }
+    if let Value::Complex(c) = x {
+        let mut c = c.recip();
+        complex(&mut c, (e.round(), e.round()));
+        return Ok(Value::Complex(c));
+    }
+    let mut f = to_float(e, x.clone())?;
+    if f.clone().abs() < 1 {
+        return Err(DomainError::new(name, &x, "the argument must be at most -1 or at least 1 to have a real result")
+            .suggest(format!("{name}({x} + 0i)"))
+            .into());
+    }
+    f.recip_mut();
+    real(&mut f, e.round());
+    Ok(Value::Decimal(from_radians(e, f)))
+}
+
+/// `sqrt(x^2 + y^2)`, or `sqrt(x^2 + y^2 + z^2)`, without the squares
+/// overflowing. It is exact where the sum of the squares is the square of a
+/// rational, and otherwise rounded once from the exact sum.
+pub fn hypot(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let mut sum = Rational::new();
+    let mut exact = true;
+    for x in args {
+        let x = match x {
+            Value::Exact(r) => r,
+            Value::Decimal(d) => {
+                exact = false;
+                d.to_rational().ok_or_else(|| eyre!("`hypot` expected finite numbers, found {d}"))?
+            }
+            other => bail!("`hypot` expected real numbers, found {}", other.kind()),
+        };
+        sum += x.square();
+    }
+    if exact && sum.numer().is_perfect_square() && sum.denom().is_perfect_square() {
+        return super::sqrt(e, Value::Exact(sum));
+    }
+    Ok(Value::Decimal(sqrt_rounded(sum, e.precision(), e.round())))
+}
+
+/// The square root of `q ≥ 0`, rounded once. The integer square root is taken
+/// with two more bits than are kept and a last bit that is set if anything is
+/// left over, which rounds the same as the exact root.
+fn sqrt_rounded(q: Rational, precision: u32, round: Round) -> Float {
+    if q.is_zero() {
+        return Float::new(precision);
+    }
+    let (n, d) = q.into_numer_denom();
+    // `q * 4^k` has more than `2 * (precision + 2)` bits before the point
+    let bits = i64::from(n.significant_bits()) - i64::from(d.significant_bits());
+    let k = i64::from(precision) + 3 - bits.div_euclid(2);
+    let (scaled, rem) = match k >= 0 {
+        true => (n << (2 * k) as u32).div_rem_floor(d),
+        false => n.div_rem_floor(d << (-2 * k) as u32),
+    };
+    let (root, left) = scaled.sqrt_rem(Integer::new());
+    let sticky = u32::from(!rem.is_zero() || !left.is_zero());
+    let f = Float::with_val_round(precision, (root << 1u32) + sticky, round).0;
+    f >> (k + 1) as i32
+}
+
+pub fn abs(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    Ok(match x {
+        Value::Exact(r) => Value::Exact(r.abs()),
+        Value::Decimal(d) => Value::Decimal(d.abs()),
+        Value::Complex(c) => Value::Decimal(Float::with_val(e.precision(), c.abs_ref())),
+        Value::Quaternion(q) => Value::Decimal(q.norm()),
+        other => bail!("expected a number, found {}", other.kind()),
+    })
+}
+
+#[test]
+fn test_sqrt_rounded() {
+    for (q, precision) in [((2, 1), 53), ((1, 3), 64), ((10, 1), 7), ((1, 3 << 20), 200)] {
+        let q = Rational::from(q);
+        let expected = Float::with_val(precision, &q).sqrt();
+        assert_eq!(expected, sqrt_rounded(q.clone(), precision, Round::Nearest));
+        let up = sqrt_rounded(q.clone(), precision, Round::Up);
+        assert!(up.to_rational().unwrap().square() > q);
+    }
+    assert_eq!(Float::with_val(10, 3), sqrt_rounded(Rational::from(9), 10, Round::Down));
+}
+
+#[test]
+fn test_domain_errors() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    let cases = [
+        ("sqrt(-4)", "negative numbers have no real square root", &["sqrt(-4 + 0i)", "sqrt(abs(-4))"][..]),
+        ("ln(-1)", "negative numbers have no real logarithm", &["ln(-1 + 0i)", "ln(abs(-1))"]),
+        ("ln(0)", "the logarithm of zero is -∞", &[]),
+        ("asin(2)", "the argument must be between -1 and 1 to have a real result", &["asin(2 + 0i)"]),
+        ("acos(-3/2)", "the argument must be between -1 and 1 to have a real result", &["acos(-3/2 + 0i)"]),
+        ("asec(0)", "the reciprocal of 0 is infinite", &[]),
+        ("acsc(1/2)", "the argument must be at most -1 or at least 1 to have a real result", &["acsc(1/2 + 0i)"]),
+    ];
+    for (call, reason, suggestions) in cases {
+        let error = eval(call).unwrap_err();
+        let domain = error.downcast_ref::<DomainError>().unwrap();
+        assert_eq!((call, reason), (domain.call.as_str(), domain.reason));
+        assert_eq!(suggestions, domain.suggestions);
+        let help: String = suggestions.iter().map(|s| format!("\n  help: try `{s}`")).collect();
+        assert_eq!(format!("`{call}` is undefined: {reason}{help}"), error.to_string());
+        // what is suggested has a value
+        for suggestion in suggestions {
+            eval(suggestion).unwrap();
+        }
+    }
+    assert_eq!("[2.0000000i, 2, 3.1415927i]", eval("[sqrt(-4 + 0i), sqrt(abs(-4)), ln(-1 + 0i)]").unwrap());
+}
@@ -0,0 +1,61 @@
+//! `format(x, digits)`, the text of a result printed with `digits`
+//! significant digits, for when one result needs more or fewer than the
+//! `digits` setting gives all of them.
+
+use color_eyre::eyre::eyre;
+use rug::{Complex, Float};
+
+use super::{take, Evaluator, Value};
+use crate::expr::quaternion::Quaternion;
+use crate::expr::Expr;
+
+/// The most digits `format` prints, about what the highest precision holds.
+const MAX_DIGITS: usize = 300_000;
+
+/// Bits beyond those of the digits shown, so that rounding to them rounds the value and not an approximation.
+const GUARD_BITS: u32 = 16;
+
+pub fn format(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x, digits] = take(args);
+    let digits = digits.as_integer()?.to_usize().filter(|d| (1..=MAX_DIGITS).contains(d));
+    let digits = digits.ok_or_else(|| eyre!("`format` prints from 1 to {MAX_DIGITS} digits"))?;
+    let mut settings = e.settings().clone();
+    settings.round_digits = digits;
+    settings.round_trip = false;
+    settings.precision = settings.precision.max((digits as f64 * std::f64::consts::LOG2_10) as u32 + GUARD_BITS);
+    let x = with_precision(x, settings.precision);
+    Ok(Value::Str(Expr::value(x).print_with(&settings)))
+}
+
+/// The decimals in a value, and its fractions, as decimals with `bits`, which
+/// the printer shows with the `digits` setting rather than with what their own precision holds.
+fn with_precision(v: Value, bits: u32) -> Value {
+    let float = |f: &Float| Float::with_val(bits, f);
+    match v {
+        Value::Exact(r) if *r.denom() != 1 => Value::Decimal(Float::with_val(bits, r)),
+        Value::Decimal(f) => Value::Decimal(float(&f)),
+        Value::Complex(c) => Value::Complex(Complex::with_val(bits, c)),
+        Value::Quaternion(q) => {
+            Value::Quaternion(Box::new(Quaternion { w: float(&q.w), x: float(&q.x), y: float(&q.y), z: float(&q.z) }))
+        }
+        Value::List(items) => Value::List(items.into_iter().map(|v| with_precision(v, bits)).collect()),
+        Value::Tuple(items) => Value::Tuple(items.into_iter().map(|v| with_precision(v, bits)).collect()),
+        Value::Quantity(mut q) => {
+            q.magnitude = with_precision(q.magnitude, bits);
+            Value::Quantity(q)
+        }
+        v @ (Value::Exact(_) | Value::Str(_)) => v,
+    }
+}
+
+#[test]
+fn test_format() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("\"0.333\"", eval("format(1/3, 3)").unwrap());
+    assert_eq!("\"3.14159265358979323846\"", eval("format(pi, 21)").unwrap());
+    assert_eq!("\"[12, 1.41]\"", eval("format([12, sqrt(2)], 3)").unwrap());
+    // the setting stays as it was
+    assert_eq!("1.4142136", eval("sqrt(2)").unwrap());
+    assert!(eval("format(2, 0)").is_err());
+}
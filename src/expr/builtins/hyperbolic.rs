@@ -0,0 +1,97 @@
+//! Hyperbolic functions and their inverses. They don't take angles, so they
+//! don't depend on `:set angle`. Complex arguments are accepted by all of
+//! them, and real ones outside the domain of `acosh` or `atanh` are a
+//! [`DomainError`] like for `asin`.
+
+use rug::{Complex, Float};
+
+use crate::domain::DomainError;
+
+use super::{take, to_float, Evaluator, Value};
+
+type RealFn = fn(&mut Float, rug::float::Round) -> std::cmp::Ordering;
+type ComplexFn = fn(&mut Complex, (rug::float::Round, rug::float::Round)) -> (std::cmp::Ordering, std::cmp::Ordering);
+
+pub fn sinh(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    odd(e, args, Float::sinh_round, Complex::sinh_round)
+}
+
+pub fn cosh(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if matches!(&x, Value::Exact(r) if r.is_zero()) {
+        return Ok(Value::one());
+    }
+    apply(e, x, Float::cosh_round, Complex::cosh_round)
+}
+
+pub fn tanh(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    odd(e, args, Float::tanh_round, Complex::tanh_round)
+}
+
+pub fn asinh(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    odd(e, args, Float::asinh_round, Complex::asinh_round)
+}
+
+pub fn acosh(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    match &x {
+        Value::Exact(r) if *r == 1 => return Ok(Value::zero()),
+        Value::Complex(_) => {}
+        x if to_float(e, x.clone())? < 1 => {
+            return Err(DomainError::new("acosh", x, "the argument must be at least 1 to have a real result")
+                .suggest(format!("acosh({x} + 0i)"))
+                .into());
+        }
+        _ => {}
+    }
+    apply(e, x, Float::acosh_round, Complex::acosh_round)
+}
+
+pub fn atanh(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if !matches!(x, Value::Complex(_)) {
+        let abs = to_float(e, x.clone())?.abs();
+        if abs == 1 {
+            return Err(DomainError::new("atanh", &x, "the inverse hyperbolic tangent of ±1 is infinite").into());
+        }
+        if abs > 1 {
+            return Err(DomainError::new("atanh", &x, "the argument must be between -1 and 1 to have a real result")
+                .suggest(format!("atanh({x} + 0i)"))
+                .into());
+        }
+    }
+    odd(e, vec![x], Float::atanh_round, Complex::atanh_round)
+}
+
+/// A function that is 0 at 0, which stays exact.
+fn odd(e: &mut Evaluator, args: Vec<Value>, real: RealFn, complex: ComplexFn) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if matches!(&x, Value::Exact(r) if r.is_zero()) {
+        return Ok(Value::zero());
+    }
+    apply(e, x, real, complex)
+}
+
+fn apply(e: &mut Evaluator, x: Value, real: RealFn, complex: ComplexFn) -> color_eyre::Result<Value> {
+    if let Value::Complex(mut c) = x {
+        complex(&mut c, (e.round(), e.round()));
+        return Ok(Value::Complex(c));
+    }
+    let mut f = to_float(e, x)?;
+    real(&mut f, e.round());
+    Ok(Value::Decimal(f))
+}
+
+#[test]
+fn test_hyperbolic() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("1.1752012", eval("sinh(1)").unwrap());
+    assert_eq!("1", eval("cosh(0)").unwrap());
+    assert_eq!("1.0000000", eval("tanh(asinh(sinh(1)))/tanh(1)").unwrap());
+    assert_eq!("0", eval("acosh(1)").unwrap());
+    assert_eq!("0.54930614", eval("atanh(1/2)").unwrap());
+    assert!(eval("acosh(1/2)").is_err());
+    assert!(eval("atanh(1)").is_err());
+    assert!(eval("atanh(2 + 0i)").is_ok());
+}
@@ -0,0 +1,164 @@
+//! Functions that read from and write to the outside world: files and the environment.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use color_eyre::eyre::{bail, eyre};
+use rug::{Float, Integer, Rational};
+
+use super::{take, Evaluator, Value};
+use crate::expr::Expr;
+
+/// Writes `text` and a newline to `path`, appending instead of replacing the file if `append` is set.
+pub fn write_text(path: &str, text: &str, append: bool) -> color_eyre::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map_err(|e| eyre!("could not open `{path}`: {e}"))?;
+    writeln!(file, "{text}").map_err(|e| eyre!("could not write to `{path}`: {e}"))
+}
+
+/// Writes a value the way it is displayed, and returns it.
+pub fn write(e: &mut Evaluator, mut args: Vec<Value>) -> color_eyre::Result<Value> {
+    let mode = if args.len() == 3 { args.pop() } else { None };
+    let [value, path] = take(args);
+    let append = match &mode {
+        None => false,
+        Some(mode) => match mode.as_str()? {
+            "w" => false,
+            "a" => true,
+            other => bail!("`mode` must be \"w\" to replace the file or \"a\" to append, found {other:?}"),
+        },
+    };
+    e.side_effect("write")?;
+    let text = e.display(&Expr::value(value.clone())).to_string();
+    write_text(path.as_str()?, &text, append)?;
+    Ok(value)
+}
+
+fn read(path: &str) -> color_eyre::Result<String> {
+    fs::read_to_string(path).map_err(|e| eyre!("could not read `{path}`: {e}"))
+}
+
+/// Reads a number the way it would be written in calq: integers and
+/// fractions like `2/3` are exact, anything with a point or exponent is a decimal.
+fn parse_number(e: &Evaluator, s: &str) -> Option<Value> {
+    let s = s.trim();
+    if let Ok(n) = Integer::parse(s) {
+        return Some(Value::Exact(Integer::from(n).into()));
+    }
+    if let Some((n, d)) = s.split_once('/') {
+        let (n, d) = (Integer::from(Integer::parse(n.trim()).ok()?), Integer::from(Integer::parse(d.trim()).ok()?));
+        return (d != 0).then(|| Value::Exact(Rational::from((n, d))));
+    }
+    Float::parse(s).ok().map(|f| Value::Decimal(e.complete(f)))
+}
+
+/// Splits a CSV line into fields, with `"` quoting fields that contain commas.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// Reads a column of a CSV file, counting from 1, or every column as a list of rows.
+/// A first row that isn't numeric is taken to be a header and skipped.
+pub fn readcsv(e: &mut Evaluator, mut args: Vec<Value>) -> color_eyre::Result<Value> {
+    let col = if args.len() == 2 { args.pop() } else { None };
+    let [path] = take(args);
+    let col = match col {
+        None => None,
+        Some(col) => {
+            let col = col.as_integer()?;
+            match col.to_usize().filter(|&c| c >= 1) {
+                Some(c) => Some(c - 1),
+                None => bail!("columns are counted from 1, found {col}"),
+            }
+        }
+    };
+    let text = read(path.as_str()?)?;
+    let mut rows = vec![];
+    let mut header = true;
+    for (n, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let fields = csv_fields(line);
+        let fields = match col {
+            Some(c) => match fields.get(c) {
+                Some(field) => std::slice::from_ref(field),
+                None => bail!("line {}: there is no column {}", n + 1, c + 1),
+            },
+            None => &fields[..],
+        };
+        let values: Option<Vec<_>> = fields.iter().map(|f| parse_number(e, f)).collect();
+        let first = std::mem::replace(&mut header, false);
+        match values {
+            Some(values) => rows.push(match col {
+                Some(_) => values.into_iter().next().unwrap(),
+                None => Value::List(values),
+            }),
+            None if first => {}
+            None => {
+                let bad = fields.iter().find(|f| parse_number(e, f).is_none()).unwrap();
+                bail!("line {}: `{}` is not a number", n + 1, bad.trim());
+            }
+        }
+    }
+    Ok(Value::List(rows))
+}
+
+/// Reads whitespace separated numbers.
+pub fn readlist(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [path] = take(args);
+    let text = read(path.as_str()?)?;
+    let values = text
+        .split_whitespace()
+        .map(|s| parse_number(e, s).ok_or_else(|| eyre!("`{s}` is not a number")))
+        .collect::<color_eyre::Result<_>>()?;
+    Ok(Value::List(values))
+}
+
+/// An environment variable, as a number if it is one and as a string otherwise.
+pub fn env(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [name] = take(args);
+    let name = name.as_str()?;
+    let value = std::env::var(name).map_err(|err| eyre!("could not read `{name}`: {err}"))?;
+    Ok(parse_number(e, &value).unwrap_or(Value::Str(value)))
+}
+
+#[test]
+fn test_csv_fields() {
+    assert_eq!(vec!["1", "a, b", "say \"hi\"", ""], csv_fields(r#"1,"a, b","say ""hi""","#));
+}
+
+#[test]
+fn test_write() {
+    let path = std::env::temp_dir().join(format!("calq-write-{}.txt", std::process::id()));
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, &line.replace("PATH", &format!("{path:?}")));
+    // the value is written the way it is displayed and given back
+    assert_eq!("1/3", eval("write(1/3, PATH)").unwrap());
+    assert_eq!("[2, 3]", eval("write([2, 3], PATH, \"a\")").unwrap());
+    assert_eq!("1/3\n[2, 3]\n", fs::read_to_string(&path).unwrap());
+    assert_eq!("4", eval("write(4, PATH, \"w\")").unwrap());
+    assert_eq!("4\n", fs::read_to_string(&path).unwrap());
+    let error = eval("write(5, PATH, \"x\")").unwrap_err().to_string();
+    assert_eq!("`mode` must be \"w\" to replace the file or \"a\" to append, found \"x\"", error);
+    e.forbid_side_effects();
+    assert!(crate::session::eval_text(&mut e, &format!("write(6, {path:?})")).is_err());
+    assert_eq!("4\n", fs::read_to_string(&path).unwrap());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,555 @@
+//! Linear algebra on vectors, represented as lists of numbers.
+
+use color_eyre::eyre::bail;
+use rug::ops::Pow;
+use rug::{Float, Integer, Rational};
+
+use super::{sqrt, take, to_float, Evaluator, Value};
+use crate::expr::Expr;
+
+/// Returns the components of a vector, checking that they are all numbers.
+fn vector(v: &Value) -> color_eyre::Result<&[Value]> {
+    let Value::List(items) = v else {
+        bail!("expected a vector, found {}", v.kind());
+    };
+    if let Some(item) = items.iter().find(|v| !matches!(v, Value::Exact(_) | Value::Decimal(_))) {
+        bail!("vector components must be numbers, found {}", item.kind());
+    }
+    Ok(items)
+}
+
+fn same_len<'a>(u: &'a Value, v: &'a Value) -> color_eyre::Result<(&'a [Value], &'a [Value])> {
+    let (u, v) = (vector(u)?, vector(v)?);
+    if u.len() != v.len() {
+        bail!("vectors have different lengths, {} and {}", u.len(), v.len());
+    }
+    Ok((u, v))
+}
+
+fn abs(v: Value) -> Value {
+    match v {
+        Value::Exact(r) => Value::Exact(r.abs()),
+        Value::Decimal(d) => Value::Decimal(d.abs()),
+        other => other,
+    }
+}
+
+fn inner(e: &Evaluator, u: &[Value], v: &[Value]) -> color_eyre::Result<Value> {
+    let mut sum = Value::zero();
+    for (a, b) in u.iter().zip(v) {
+        sum = sum.add(a.clone().mul(b.clone(), e)?, e)?;
+    }
+    Ok(sum)
+}
+
+fn euclidean(e: &Evaluator, u: &[Value]) -> color_eyre::Result<Value> {
+    sqrt(e, inner(e, u, u)?)
+}
+
+pub fn dot(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [u, v] = take(args);
+    let (u, v) = same_len(&u, &v)?;
+    inner(e, u, v)
+}
+
+/// Cross product of 3D vectors, 2D vectors give the scalar z component.
+pub fn cross(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [u, v] = take(args);
+    let (u, v) = same_len(&u, &v)?;
+    let det = |a: usize, b: usize| -> color_eyre::Result<Value> {
+        u[a].clone().mul(v[b].clone(), e)?.sub(u[b].clone().mul(v[a].clone(), e)?, e)
+    };
+    match u.len() {
+        2 => det(0, 1),
+        3 => Ok(Value::List(vec![det(1, 2)?, det(2, 0)?, det(0, 1)?])),
+        n => bail!("the cross product is only defined for 2D and 3D vectors, found {n} components"),
+    }
+}
+
+/// The `p`-norm of a vector, `p` defaults to 2 and may be `"inf"` for the maximum norm.
+pub fn norm(e: &mut Evaluator, mut args: Vec<Value>) -> color_eyre::Result<Value> {
+    let p = if args.len() == 2 { args.pop() } else { None };
+    let [v] = take(args);
+    let v = vector(&v)?;
+
+    let p = match p {
+        None => return euclidean(e, v),
+        Some(Value::Str(s)) if s == "inf" => {
+            return Ok(v
+                .iter()
+                .cloned()
+                .map(abs)
+                .max_by(|a, b| match (a, b) {
+                    (Value::Exact(a), Value::Exact(b)) => a.cmp(b),
+                    _ => to_float(e, a.clone()).unwrap().total_cmp(&to_float(e, b.clone()).unwrap()),
+                })
+                .unwrap_or(Value::zero()));
+        }
+        Some(p) => p,
+    };
+
+    match &p {
+        Value::Exact(r) if *r == 1 => {
+            let mut sum = Value::zero();
+            for x in v {
+                sum = sum.add(abs(x.clone()), e)?;
+            }
+            return Ok(sum);
+        }
+        Value::Exact(r) if *r == 2 => return euclidean(e, v),
+        _ => {}
+    }
+
+    let p = match to_float(e, p.clone())? {
+        f if f < 1 => bail!("the norm is only defined for p >= 1, found {}", e.display(&Expr::value(p))),
+        f => f,
+    };
+    let mut sum = Float::new(e.precision());
+    for x in v {
+        let x = to_float(e, abs(x.clone()))?;
+        sum += x.pow(&p);
+    }
+    let mut recip = Float::with_val(e.precision(), 1);
+    recip /= &p;
+    Ok(Value::Decimal(sum.pow(recip)))
+}
+
+/// The angle between two vectors, in radians.
+pub fn angle_between(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [u, v] = take(args);
+    let (u, v) = same_len(&u, &v)?;
+    let lengths = euclidean(e, u)?.mul(euclidean(e, v)?, e)?;
+    let lengths = to_float(e, lengths)?;
+    if lengths.is_zero() {
+        bail!("the angle with a zero vector is undefined");
+    }
+    let mut cos = to_float(e, inner(e, u, v)?)?;
+    cos /= lengths;
+    // rounding can push this slightly outside of [-1, 1]
+    cos.clamp_mut(&-1, &1);
+    cos.acos_round(e.round());
+    Ok(Value::Decimal(cos))
+}
+
+type Matrix = Vec<Vec<Float>>;
+
+/// Converts a list of rows into a matrix of `Float`s at the working precision.
+fn matrix(e: &Evaluator, m: Value) -> color_eyre::Result<Matrix> {
+    let Value::List(rows) = m else {
+        bail!("expected a matrix, found {}", m.kind());
+    };
+    let mut out = Vec::with_capacity(rows.len());
+    for row in rows {
+        let row = vector(&row)?.iter().map(|x| to_float(e, x.clone())).collect::<color_eyre::Result<Vec<_>>>()?;
+        if out.first().is_some_and(|first: &Vec<_>| first.len() != row.len()) {
+            bail!("matrix rows have different lengths");
+        }
+        out.push(row);
+    }
+    if out.is_empty() || out[0].is_empty() {
+        bail!("matrix must not be empty");
+    }
+    Ok(out)
+}
+
+fn square_matrix(e: &Evaluator, m: Value) -> color_eyre::Result<Matrix> {
+    let m = matrix(e, m)?;
+    if m.len() != m[0].len() {
+        bail!("expected a square matrix, found a {}x{} matrix", m.len(), m[0].len());
+    }
+    Ok(m)
+}
+
+fn to_value(m: Matrix) -> Value {
+    Value::List(m.into_iter().map(|row| Value::List(row.into_iter().map(Value::Decimal).collect())).collect())
+}
+
+fn identity(prec: u32, n: usize) -> Matrix {
+    (0..n).map(|i| (0..n).map(|j| Float::with_val(prec, (i == j) as u32)).collect()).collect()
+}
+
+/// Anything smaller than this (relative to the entries around it) is treated as zero.
+fn tolerance(e: &Evaluator) -> Float {
+    Float::with_val(e.precision(), 1) >> (e.precision() as i32 - 8)
+}
+
+/// The numbers of a vector, which have to be exact.
+fn exact_vector(v: &Value) -> color_eyre::Result<Vec<Rational>> {
+    vector(v)?
+        .iter()
+        .map(|x| match x {
+            Value::Exact(r) => Ok(r.clone()),
+            _ => bail!("`linsolve` solves exactly, so the numbers must be exact, like 1/10 instead of 0.1"),
+        })
+        .collect()
+}
+
+/// The row as integers with the same ratios, by multiplying with its denominators.
+fn integers(row: Vec<Rational>) -> Vec<Integer> {
+    let lcm = row.iter().fold(Integer::from(1), |lcm, r| lcm.lcm(r.denom()));
+    row.into_iter().map(|r| (r * &lcm).into_numer_denom().0).collect()
+}
+
+/// The solution of `m x = b` for the echelon form `m` with these pivot columns,
+/// with the free variables set to `free` and `b` its last column unless `homogeneous`.
+fn back_substitute(m: &[Vec<Integer>], pivots: &[usize], free: &[Rational], homogeneous: bool) -> Vec<Rational> {
+    let n = free.len();
+    let mut x = free.to_vec();
+    for (row, &c) in m.iter().zip(pivots).rev() {
+        let mut rhs = if homogeneous { Rational::new() } else { Rational::from(&row[n]) };
+        for j in c + 1..n {
+            rhs -= Rational::from(&x[j] * &row[j]);
+        }
+        x[c] = rhs / &row[c];
+    }
+    x
+}
+
+/// Solves `m x = b` exactly with Bareiss' fraction-free elimination, which
+/// keeps the entries integers that are minors of `[m | b]`, rather than
+/// fractions that grow with every step. An underdetermined system gives a
+/// solution and a basis of the solutions of `m x = 0` as `(p, [v1, ...])`.
+pub fn linsolve(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [m, b] = take(args);
+    let Value::List(rows) = &m else {
+        bail!("expected a matrix, found {}", m.kind());
+    };
+    let b = exact_vector(&b)?;
+    if b.len() != rows.len() {
+        bail!("expected a number in `b` for each of the {} rows, found {}", rows.len(), b.len());
+    }
+    let mut m = Vec::with_capacity(rows.len());
+    for (row, b) in rows.iter().zip(b) {
+        let mut row = exact_vector(row)?;
+        if m.first().is_some_and(|first: &Vec<_>| first.len() != row.len() + 1) {
+            bail!("matrix rows have different lengths");
+        }
+        row.push(b);
+        m.push(integers(row));
+    }
+    let n = match m.first() {
+        Some(row) if row.len() > 1 => row.len() - 1,
+        _ => bail!("matrix must not be empty"),
+    };
+
+    let mut pivots = vec![];
+    let mut previous = Integer::from(1);
+    for c in 0..n {
+        let r = pivots.len();
+        let Some(pivot) = (r..m.len()).find(|&i| !m[i][c].is_zero()) else {
+            continue;
+        };
+        m.swap(r, pivot);
+        let (top, bottom) = m.split_at_mut(r + 1);
+        let pivot = &top[r];
+        for row in bottom {
+            for j in c + 1..=n {
+                // exact by Sylvester's identity
+                let minor = Integer::from(&pivot[c] * &row[j]) - Integer::from(&row[c] * &pivot[j]);
+                row[j] = minor.div_exact(&previous);
+            }
+            row[c] = Integer::new();
+        }
+        previous = pivot[c].clone();
+        pivots.push(c);
+    }
+    // the rows left over reduced to `0 = c`
+    if m[pivots.len()..].iter().any(|row| !row[n].is_zero()) {
+        bail!("the equations are inconsistent, so there is no solution");
+    }
+
+    let m = &m[..pivots.len()];
+    let zero = vec![Rational::new(); n];
+    let solution = Value::List(back_substitute(m, &pivots, &zero, false).into_iter().map(Value::Exact).collect());
+    let free: Vec<_> = (0..n).filter(|c| !pivots.contains(c)).collect();
+    if free.is_empty() {
+        return Ok(solution);
+    }
+    let basis = free.iter().map(|&f| {
+        let mut x = zero.clone();
+        x[f] = Rational::from(1);
+        Value::List(back_substitute(m, &pivots, &x, true).into_iter().map(Value::Exact).collect())
+    });
+    Ok(Value::Tuple(vec![solution, Value::List(basis.collect())]))
+}
+
+/// LU decomposition with partial pivoting, returns `(L, U, P)` such that `PA = LU`.
+pub fn lu(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a] = take(args);
+    let mut u = square_matrix(e, a)?;
+    let n = u.len();
+    let prec = e.precision();
+    let mut l = identity(prec, n);
+    let mut p = identity(prec, n);
+
+    for k in 0..n {
+        let pivot = (k..n).max_by(|&i, &j| u[i][k].clone().abs().total_cmp(&u[j][k].clone().abs())).unwrap();
+        if pivot != k {
+            u.swap(k, pivot);
+            p.swap(k, pivot);
+            let (top, bottom) = l.split_at_mut(pivot);
+            top[k][..k].swap_with_slice(&mut bottom[0][..k]);
+        }
+        if u[k][k].is_zero() {
+            continue;
+        }
+        for (i, l_row) in l.iter_mut().enumerate().skip(k + 1) {
+            let (top, bottom) = u.split_at_mut(i);
+            let factor = Float::with_val(prec, &bottom[0][k] / &top[k][k]);
+            for (x, p) in bottom[0][k..].iter_mut().zip(&top[k][k..]) {
+                *x -= Float::with_val(prec, &factor * p);
+            }
+            l_row[k] = factor;
+        }
+    }
+    Ok(Value::Tuple(vec![to_value(l), to_value(u), to_value(p)]))
+}
+
+/// Householder QR decomposition of an `m x n` matrix, `Q` is `m x m` and `R` is `m x n`.
+fn householder_qr(prec: u32, mut r: Matrix) -> (Matrix, Matrix) {
+    let (m, n) = (r.len(), r[0].len());
+    let mut q = identity(prec, m);
+    for k in 0..n.min(m.saturating_sub(1)) {
+        let norm = Float::with_val(prec, Float::sum(r[k..].iter().map(|row| Float::with_val(prec, row[k].square_ref())).collect::<Vec<_>>().iter())).sqrt();
+        if norm.is_zero() {
+            continue;
+        }
+        let alpha = if r[k][k].is_sign_negative() { norm } else { -norm };
+        let mut v: Vec<Float> = r[k..].iter().map(|row| row[k].clone()).collect();
+        v[0] -= &alpha;
+        let vnorm = Float::with_val(prec, Float::sum(v.iter().map(|x| Float::with_val(prec, x.square_ref())).collect::<Vec<_>>().iter())).sqrt();
+        if vnorm.is_zero() {
+            continue;
+        }
+        for x in &mut v {
+            *x /= &vnorm;
+        }
+
+        // R = H R, where H = I - 2vv^T acts on rows k..
+        for j in 0..n {
+            let proj = Float::with_val(prec, Float::dot(v.iter().zip(r[k..].iter().map(|row| &row[j])))) * 2u32;
+            for (i, vi) in v.iter().enumerate() {
+                r[k + i][j] -= Float::with_val(prec, vi * &proj);
+            }
+        }
+        // Q = Q H
+        for row in &mut q {
+            let proj = Float::with_val(prec, Float::dot(v.iter().zip(&row[k..]))) * 2u32;
+            for (i, vi) in v.iter().enumerate() {
+                row[k + i] -= Float::with_val(prec, vi * &proj);
+            }
+        }
+    }
+    (q, r)
+}
+
+/// QR decomposition, returns `(Q, R)` such that `A = QR`.
+pub fn qr(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a] = take(args);
+    let a = matrix(e, a)?;
+    let (q, r) = householder_qr(e.precision(), a);
+    Ok(Value::Tuple(vec![to_value(q), to_value(r)]))
+}
+
+fn mat_mul(prec: u32, a: &Matrix, b: &Matrix) -> Matrix {
+    (0..a.len())
+        .map(|i| (0..b[0].len()).map(|j| Float::with_val(prec, Float::dot(a[i].iter().zip(b.iter().map(|row| &row[j]))))).collect())
+        .collect()
+}
+
+/// Eigenvalues of the 2x2 matrix `[[a, b], [c, d]]`, if they are real.
+fn eigenvalues_2x2(prec: u32, a: &Float, b: &Float, c: &Float, d: &Float) -> Option<(Float, Float)> {
+    let half_tr = Float::with_val(prec, a + d) / 2u32;
+    let half_diff = Float::with_val(prec, a - d) / 2u32;
+    let disc = Float::with_val(prec, half_diff.square_ref()) + Float::with_val(prec, b * c);
+    if disc.is_sign_negative() && !disc.is_zero() {
+        return None;
+    }
+    let root = disc.sqrt();
+    Some((Float::with_val(prec, &half_tr + &root), half_tr - root))
+}
+
+/// Real eigenvalues using the shifted QR algorithm, sorted in descending order.
+pub fn eigenvalues(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    const MAX_ITERATIONS: usize = 1000;
+
+    let [a] = take(args);
+    let mut a = square_matrix(e, a)?;
+    let prec = e.precision();
+    let tol = tolerance(e);
+    let mut n = a.len();
+    let mut out = Vec::with_capacity(n);
+
+    let small = |a: &Matrix, i: usize| {
+        let scale = Float::with_val(prec, a[i][i].abs_ref()) + Float::with_val(prec, a[i - 1][i - 1].abs_ref());
+        let scale = if scale.is_zero() { Float::with_val(prec, 1) } else { scale };
+        Float::with_val(prec, a[i][i - 1].abs_ref()) <= Float::with_val(prec, &tol * &scale)
+    };
+
+    let mut iterations = 0;
+    while n > 0 {
+        if n == 1 || small(&a, n - 1) {
+            out.push(a[n - 1][n - 1].clone());
+            n -= 1;
+            iterations = 0;
+            continue;
+        }
+        if n == 2 || small(&a, n - 2) {
+            let (x, y) = (&a[n - 2], &a[n - 1]);
+            if let Some((l1, l2)) = eigenvalues_2x2(prec, &x[n - 2], &x[n - 1], &y[n - 2], &y[n - 1]) {
+                out.extend([l1, l2]);
+                n -= 2;
+                iterations = 0;
+                continue;
+            } else if n == 2 || iterations > MAX_ITERATIONS / 2 {
+                bail!("matrix has complex eigenvalues, which are not supported");
+            }
+        }
+        if iterations > MAX_ITERATIONS {
+            bail!("eigenvalue iteration did not converge");
+        }
+        iterations += 1;
+
+        // Wilkinson shift: the eigenvalue of the trailing 2x2 block closer to the last diagonal entry.
+        let (x, y) = (&a[n - 2], &a[n - 1]);
+        let d = &y[n - 1];
+        let shift = match eigenvalues_2x2(prec, &x[n - 2], &x[n - 1], &y[n - 2], d) {
+            Some((l1, l2)) => {
+                if Float::with_val(prec, &l1 - d).abs() < Float::with_val(prec, &l2 - d).abs() { l1 } else { l2 }
+            }
+            None => d.clone(),
+        };
+
+        let mut active: Matrix = a[..n].iter().map(|row| row[..n].to_vec()).collect();
+        for (i, row) in active.iter_mut().enumerate() {
+            row[i] -= &shift;
+        }
+        let (q, r) = householder_qr(prec, active);
+        let mut next = mat_mul(prec, &r, &q);
+        for (i, row) in next.iter_mut().enumerate() {
+            row[i] += &shift;
+        }
+        for (row, next) in a.iter_mut().zip(next) {
+            row[..n].clone_from_slice(&next);
+        }
+    }
+
+    out.sort_by(|a, b| b.total_cmp(a));
+    Ok(Value::List(out.into_iter().map(Value::Decimal).collect()))
+}
+
+/// Determinant by Gaussian elimination, exact when all entries are exact.
+pub fn det(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a] = take(args);
+    let Value::List(rows) = &a else {
+        bail!("expected a matrix, found {}", a.kind());
+    };
+    let mut m = rows.iter().map(|row| vector(row).map(<[Value]>::to_vec)).collect::<color_eyre::Result<Vec<_>>>()?;
+    let n = m.len();
+    if n == 0 || m.iter().any(|row| row.len() != n) {
+        bail!("expected a non-empty square matrix");
+    }
+
+    let is_zero = |v: &Value| match v {
+        Value::Exact(r) => r.is_zero(),
+        Value::Decimal(d) => d.is_zero(),
+        _ => false,
+    };
+    let mut det = Value::one();
+    for k in 0..n {
+        let Some(pivot) = (k..n).find(|&i| !is_zero(&m[i][k])) else {
+            return Ok(Value::zero());
+        };
+        if pivot != k {
+            m.swap(k, pivot);
+            det = det.neg()?;
+        }
+        det = det.mul(m[k][k].clone(), e)?;
+        for i in k + 1..n {
+            let (top, bottom) = m.split_at_mut(i);
+            let factor = bottom[0][k].clone().div(top[k][k].clone(), e)?;
+            for (x, p) in bottom[0][k..].iter_mut().zip(&top[k][k..]) {
+                *x = x.clone().sub(factor.clone().mul(p.clone(), e)?, e)?;
+            }
+        }
+    }
+    Ok(det)
+}
+
+#[test]
+fn test_linsolve() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("[4/5, 7/5]", eval("linsolve([[2, 1], [1, 3]], [3, 5])").unwrap());
+    assert_eq!("[1/2, -1/3, 1]", eval("linsolve([[1/2, 0, 1], [0, 3, 0], [1, 0, 0]], [5/4, -1, 1/2])").unwrap());
+    // the first two equations are the same, so one variable is free
+    assert_eq!("([1, 0, 1], [[-2, 1, 0]])", eval("linsolve([[1, 2, 0], [2, 4, 0], [0, 0, 1]], [1, 2, 1])").unwrap());
+    assert!(eval("linsolve([[1, 1], [1, 1]], [1, 2])").is_err());
+    assert!(eval("linsolve([[0.5, 1], [1, 1]], [1, 2])").is_err());
+    let error = eval("norm([3, 4], 0.5)").unwrap_err().to_string();
+    assert_eq!("the norm is only defined for p >= 1, found 0.50000000", error);
+    let error = eval("norm([3, 4], 1/2)").unwrap_err().to_string();
+    assert_eq!("the norm is only defined for p >= 1, found 1/2", error);
+}
+
+#[test]
+fn test_decompositions() {
+    let mut e = Evaluator::default();
+    let prec = e.precision();
+    let mut eval = |line: &str| crate::session::eval(&mut e, line).map(|r| r.as_value().unwrap().clone());
+    let parts = |v: Value| match v {
+        Value::Tuple(parts) => parts.into_iter().map(|m| matrix(&Evaluator::default(), m).unwrap()).collect::<Vec<_>>(),
+        v => panic!("expected a tuple, found {}", v.kind()),
+    };
+    let close = |a: &Matrix, b: &[&[f64]]| {
+        let row_close = |x: &Vec<Float>, y: &[f64]| {
+            x.len() == y.len() && x.iter().zip(y).all(|(x, y)| (x.to_f64() - y).abs() < 1e-20)
+        };
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| row_close(x, y))
+    };
+    let transpose = |m: &Matrix| -> Matrix {
+        (0..m[0].len()).map(|j| m.iter().map(|row| row[j].clone()).collect()).collect()
+    };
+
+    // `PA = LU` with the rows swapped for the larger pivot, singular matrices included
+    let lu_parts = parts(eval("lu([[1, 2], [3, 4]])").unwrap());
+    let (l, u, p) = (&lu_parts[0], &lu_parts[1], &lu_parts[2]);
+    assert!(close(l, &[&[1.0, 0.0], &[1.0 / 3.0, 1.0]]) && close(p, &[&[0.0, 1.0], &[1.0, 0.0]]));
+    assert!(close(&mat_mul(prec, l, u), &[&[3.0, 4.0], &[1.0, 2.0]]));
+    let lu_parts = parts(eval("lu([[1, 2], [2, 4]])").unwrap());
+    assert!(close(&lu_parts[1], &[&[2.0, 4.0], &[0.0, 0.0]]));
+    let error = eval("lu([[1, 2, 3], [4, 5, 6]])").err().unwrap().to_string();
+    assert_eq!("expected a square matrix, found a 2x3 matrix", error);
+
+    // `A = QR` with `Q` orthogonal, for tall and singular matrices too
+    for (a, rows) in [
+        ("[[3, 0], [4, 5]]", &[&[3.0, 0.0][..], &[4.0, 5.0]][..]),
+        ("[[1, 2], [3, 4], [5, 6]]", &[&[1.0, 2.0], &[3.0, 4.0], &[5.0, 6.0]]),
+        ("[[1, 2], [2, 4]]", &[&[1.0, 2.0], &[2.0, 4.0]]),
+    ] {
+        let qr_parts = parts(eval(&format!("qr({a})")).unwrap());
+        let (q, r) = (&qr_parts[0], &qr_parts[1]);
+        assert!(close(&mat_mul(prec, q, r), rows), "{a}");
+        let n = q.len();
+        let identity: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| f64::from(u8::from(i == j))).collect()).collect();
+        let identity: Vec<&[f64]> = identity.iter().map(Vec::as_slice).collect();
+        assert!(close(&mat_mul(prec, &transpose(q), q), &identity), "{a}");
+        assert!(r.iter().enumerate().all(|(i, row)| row[..i.min(row.len())].iter().all(|x| x.to_f64().abs() < 1e-20)));
+    }
+
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    let tridiagonal = eval("eigenvalues([[2, -1, 0], [-1, 2, -1], [0, -1, 2]])").unwrap();
+    assert_eq!("[3.4142136, 2.0000000, 0.58578644]", tridiagonal);
+    assert_eq!("[4.7320508, 3.0000000, 1.2679492]", eval("eigenvalues([[4, 1, 0], [1, 3, 1], [0, 1, 2]])").unwrap());
+    assert_eq!("[5.0000000, 0]", eval("eigenvalues([[1, 2], [2, 4]])").unwrap());
+    let error = eval("eigenvalues([[0, -1], [1, 0]])").unwrap_err().to_string();
+    assert_eq!("matrix has complex eigenvalues, which are not supported", error);
+    assert!(eval("eigenvalues([[1, 2, 3], [4, 5, 6]])").is_err());
+
+    // exact for exact entries, with a row swap for the zero pivot
+    assert_eq!("-2", eval("det([[1, 2], [3, 4]])").unwrap());
+    assert_eq!("-1", eval("det([[0, 1], [1, 0]])").unwrap());
+    assert_eq!("0", eval("det([[1, 2, 3], [4, 5, 6], [7, 8, 9]])").unwrap());
+    assert_eq!("-0.50000000", eval("det([[0.5, 1], [1, 1]])").unwrap());
+    assert_eq!("expected a non-empty square matrix", eval("det([[1, 2, 3], [4, 5, 6]])").unwrap_err().to_string());
+}
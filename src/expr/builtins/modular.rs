@@ -0,0 +1,87 @@
+//! Modular arithmetic.
+
+use color_eyre::eyre::bail;
+use rug::{Complete, Integer};
+
+use super::{take, Evaluator, Value};
+
+fn modulus(m: &Value) -> color_eyre::Result<Integer> {
+    let m = m.as_integer()?;
+    if m.is_zero() {
+        bail!("modulus must not be zero");
+    }
+    Ok(m.clone().abs())
+}
+
+/// `a^b mod m`, where a negative `b` uses the inverse of `a`.
+pub fn powmod(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b, m] = take(args);
+    let m = modulus(&m)?;
+    let (a, b) = (a.as_integer()?, b.as_integer()?);
+    match a.pow_mod_ref(b, &m) {
+        Some(r) => Ok(Value::Exact(Integer::from(r).into())),
+        None => bail!("{a} has no inverse modulo {m}, so it cannot be raised to the negative power {b}"),
+    }
+}
+
+/// The inverse of `a` modulo `m`.
+pub fn invmod(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, m] = take(args);
+    let m = modulus(&m)?;
+    let a = a.as_integer()?;
+    match a.invert_ref(&m) {
+        Some(r) => Ok(Value::Exact(Integer::from(r).into())),
+        None => bail!("{a} has no inverse modulo {m}"),
+    }
+}
+
+/// Combines `x = r1 (mod m1)` and `x = r2 (mod m2)` into a single congruence,
+/// the moduli need not be coprime.
+fn crt_combine((r1, m1): (Integer, Integer), (r2, m2): (Integer, Integer)) -> Option<(Integer, Integer)> {
+    let g = m1.gcd_ref(&m2).complete();
+    let diff = (&r2 - &r1).complete();
+    if !diff.is_divisible(&g) {
+        return None;
+    }
+    let m2g = (&m2 / &g).complete();
+    // solve m1 * k = r2 - r1 (mod m2)
+    let inv = (&m1 / &g).complete().invert(&m2g).ok()?;
+    let k = (diff / &g * inv).modulo(&m2g);
+    let lcm = (&m1 * &m2g).complete();
+    let r = (r1 + m1 * k).modulo(&lcm);
+    Some((r, lcm))
+}
+
+/// Chinese remainder theorem over a list of `(residue, modulus)` pairs.
+pub fn crt(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [congruences] = take(args);
+    let Value::List(congruences) = congruences else {
+        bail!("expected a list of (residue, modulus) pairs, found {}", congruences.kind());
+    };
+
+    let mut acc = (Integer::ZERO, Integer::from(1));
+    for c in &congruences {
+        let (Value::Tuple(pair) | Value::List(pair)) = c else {
+            bail!("expected a (residue, modulus) pair, found {}", c.kind());
+        };
+        let [r, m] = &pair[..] else {
+            bail!("expected a (residue, modulus) pair, found {} elements", pair.len());
+        };
+        let m = modulus(m)?;
+        let r = r.as_integer()?.clone().modulo(&m);
+        let Some(next) = crt_combine(acc, (r.clone(), m.clone())) else {
+            bail!("the congruences are inconsistent at x = {r} (mod {m})");
+        };
+        acc = next;
+    }
+    Ok(Value::Exact(acc.0.into()))
+}
+
+#[test]
+fn test_crt_combine() {
+    let c = |r: i32, m: i32| (Integer::from(r), Integer::from(m));
+    assert_eq!(Some(c(23, 105)), crt_combine(crt_combine(c(2, 3), c(3, 5)).unwrap(), c(2, 7)));
+    // non-coprime moduli
+    assert_eq!(Some(c(10, 12)), crt_combine(c(4, 6), c(2, 4)));
+    assert_eq!(None, crt_combine(c(1, 4), c(2, 6)));
+}
@@ -0,0 +1,198 @@
+//! Number theory: primes and friends.
+
+use color_eyre::eyre::bail;
+use rug::integer::IsPrime;
+use rug::{Complete, Float, Integer, Rational};
+
+use super::{take, to_float, Evaluator, Value};
+use crate::div::DivisionError;
+
+/// Ranges whose upper end is below this are sieved, anything larger uses
+/// rug's primality testing.
+const SIEVE_LIMIT: u64 = 1 << 24;
+
+/// We refuse to produce lists larger than this many candidates.
+const MAX_RANGE: u64 = 1 << 26;
+
+/// Sieve of Eratosthenes for all primes in `lo..=hi`.
+fn sieve(lo: u64, hi: u64) -> Vec<u64> {
+    let hi = hi as usize;
+    let mut composite = vec![false; hi + 1];
+    let mut primes = Vec::new();
+    for n in 2..=hi {
+        if composite[n] {
+            continue;
+        }
+        if n as u64 >= lo {
+            primes.push(n as u64);
+        }
+        for m in (n * n..=hi).step_by(n) {
+            composite[m] = true;
+        }
+    }
+    primes
+}
+
+pub(super) fn is_prime(n: &Integer) -> bool {
+    n.is_probably_prime(30) != IsPrime::No
+}
+
+/// The integers from `from` to `to`, which `for k in 1..10` loops over.
+pub fn range(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b] = take(args);
+    let (a, b) = (a.as_integer()?, b.as_integer()?);
+    if b < a {
+        return Ok(Value::List(Vec::new()));
+    }
+    if (b - a).complete() > MAX_RANGE {
+        bail!("range is too large, at most {MAX_RANGE} numbers can be listed");
+    }
+    let mut out = Vec::new();
+    let mut n = a.clone();
+    while n <= *b {
+        out.push(Value::Exact(n.clone().into()));
+        n += 1;
+    }
+    Ok(Value::List(out))
+}
+
+pub fn primes(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b] = take(args);
+    let (a, b) = (a.as_integer()?, b.as_integer()?);
+    // there are no primes below 2
+    let two = Integer::from(2);
+    let a = if *a < 2 { &two } else { a };
+    if b < a {
+        return Ok(Value::List(Vec::new()));
+    }
+    if (b - a).complete() > MAX_RANGE {
+        bail!("range is too large, at most {MAX_RANGE} numbers can be searched");
+    }
+
+    if *b < SIEVE_LIMIT {
+        let lo = a.to_u64().expect("at least 2");
+        let hi = b.to_u64().expect("at least 2");
+        return Ok(Value::List(sieve(lo, hi).into_iter().map(|p| Value::Exact(p.into())).collect()));
+    }
+
+    let mut out = Vec::new();
+    let mut p = if is_prime(a) { a.clone() } else { a.clone().next_prime() };
+    while p <= *b {
+        let next = p.clone().next_prime();
+        out.push(Value::Exact(p.into()));
+        p = next;
+    }
+    Ok(Value::List(out))
+}
+
+/// The `n`th prime, counting from `prime(1) = 2`.
+pub fn prime(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [n] = take(args);
+    let n = n.as_integer()?;
+    let Some(n) = n.to_u64().filter(|&n| n >= 1) else {
+        bail!("`prime` expects a positive index, found {n}");
+    };
+
+    // Rosser's theorem: the nth prime is below n(ln n + ln ln n) for n >= 6.
+    let bound = if n < 6 {
+        13
+    } else {
+        let n = n as f64;
+        (n * (n.ln() + n.ln().ln())).ceil() as u64
+    };
+
+    if bound < SIEVE_LIMIT {
+        let p = sieve(2, bound)[n as usize - 1];
+        return Ok(Value::Exact(p.into()));
+    }
+
+    if n > MAX_RANGE {
+        bail!("index is too large, at most the {MAX_RANGE}th prime can be computed");
+    }
+    let mut p = Integer::from(2);
+    for _ in 1..n {
+        p.next_prime_mut();
+    }
+    Ok(Value::Exact(p.into()))
+}
+
+/// The product of all primes less than or equal to `n`.
+pub fn primorial(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [n] = take(args);
+    let n = n.as_integer()?;
+    let Some(n) = n.to_u32() else {
+        bail!("`primorial` expects a non-negative integer that fits in 32 bits, found {n}");
+    };
+    Ok(Value::Exact(Integer::primorial(n).complete().into()))
+}
+
+/// The quotient `q` of `a/b` rounded to an integer, down if `floor` and
+/// toward zero otherwise, and the remainder `a - q*b`. Rounded down, the
+/// remainder has the sign of `b`, toward zero that of `a`.
+fn divide(e: &Evaluator, args: Vec<Value>, floor: bool) -> color_eyre::Result<(Value, Value)> {
+    let [a, b] = take(args);
+    if b.is_zero_or_empty() {
+        return Err(DivisionError::ByZero.into());
+    }
+    if let (Value::Exact(a), Value::Exact(b)) = (&a, &b) {
+        let q = Rational::from(a / b);
+        let q = if floor { q.floor() } else { q.trunc() };
+        let r = a - Rational::from(&q * b);
+        return Ok((Value::Exact(q), Value::Exact(r)));
+    }
+    let (a, b) = (to_float(e, a)?, to_float(e, b)?);
+    let q = Float::with_val(e.precision(), &a / &b);
+    let q = if floor { q.floor() } else { q.trunc() };
+    let r = Float::with_val(e.precision(), &a - Float::with_val(e.precision(), &q * &b));
+    Ok((Value::Decimal(q), Value::Decimal(r)))
+}
+
+/// `(q, r)` with `q = floor(a/b)` and `r = a - q*b`, like in Python.
+pub fn divmod(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let (q, r) = divide(e, args, true)?;
+    Ok(Value::Tuple(vec![q, r]))
+}
+
+/// `a/b` rounded toward zero, like `/` on integers in C and Rust.
+pub fn quot(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    Ok(divide(e, args, false)?.0)
+}
+
+/// What is left of `a` after `quot(a, b)*b`, with the sign of `a` like `%` in C and Rust.
+pub fn rem(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    Ok(divide(e, args, false)?.1)
+}
+
+/// What is left of `a` after `floor(a/b)*b`, with the sign of `b` like `%` in Python.
+pub fn modulo(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    Ok(divide(e, args, true)?.1)
+}
+
+#[test]
+fn test_divmod() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("(-4, 1)", eval("divmod(-7, 2)").unwrap());
+    assert_eq!("(1, 1/6)", eval("divmod(1/2, 1/3)").unwrap());
+    assert_eq!("(3, 2)", eval("q, r = divmod(17, 5)").unwrap());
+    assert_eq!("17", eval("q*5 + r").unwrap());
+    assert_eq!("(y, 1)", eval("(a, b) = [y, 1]").unwrap());
+    assert_eq!("y+1", eval("a + b").unwrap());
+    assert!(eval("a, b = (1, 2, 3)").is_err());
+    assert!(eval("a, a = (1, 2)").is_err());
+    assert!(eval("divmod(1, 0)").is_err());
+    let signs = "[quot(-7, 2), rem(-7, 2), mod(-7, 2), quot(7, -2), rem(7, -2), mod(7, -2)]";
+    assert_eq!("[-3, -1, 1, -3, 1, -1]", eval(signs).unwrap());
+    assert_eq!("[1.5000000, 1.5000000]", eval("[rem(5.5, 2), mod(-0.5, 2)]").unwrap());
+}
+
+#[test]
+fn test_sieve() {
+    assert_eq!(vec![2, 3, 5, 7], sieve(0, 10));
+    assert_eq!(vec![11, 13, 17, 19], sieve(10, 20));
+    let mut e = Evaluator::default();
+    let mut primes = |a: i32, b: i32| primes(&mut e, vec![Value::Exact(a.into()), Value::Exact(b.into())]).unwrap();
+    assert!(matches!(primes(-10, -5), Value::List(l) if l.is_empty()));
+    assert!(matches!(primes(-10, 1), Value::List(l) if l.is_empty()));
+    assert!(matches!(primes(-10, 5), Value::List(l) if l.len() == 3));
+}
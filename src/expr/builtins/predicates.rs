@@ -0,0 +1,77 @@
+//! Questions about what kind of number a value is, with 1 for yes and 0 for
+//! no like comparisons, for the cases of piecewise functions and for
+//! conditions. Anything that isn't a number of the kind asked about is a no,
+//! rather than an error.
+//!
+//! Decimals are approximations, so they are never rational, unless they have
+//! an integer value: an integer is rational, and `isinteger(3.0)` is 1.
+
+use rug::Integer;
+
+use super::ntheory::is_prime;
+use super::{take, truth, Evaluator, Value};
+
+/// The integer `x` is, decimals with an integer value included.
+fn integer(x: &Value) -> Option<Integer> {
+    match x {
+        Value::Exact(r) if *r.denom() == 1 => Some(r.numer().clone()),
+        Value::Decimal(f) if f.is_integer() => f.to_integer(),
+        _ => None,
+    }
+}
+
+pub fn isinteger(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    Ok(truth(integer(&x).is_some()))
+}
+
+pub fn isrational(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    Ok(truth(matches!(x, Value::Exact(_)) || integer(&x).is_some()))
+}
+
+pub fn iseven(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [n] = take(args);
+    Ok(truth(integer(&n).is_some_and(|n| n.is_even())))
+}
+
+pub fn isodd(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [n] = take(args);
+    Ok(truth(integer(&n).is_some_and(|n| n.is_odd())))
+}
+
+pub fn isprime(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [n] = take(args);
+    Ok(truth(integer(&n).is_some_and(|n| n > 1 && is_prime(&n))))
+}
+
+/// Complex numbers and quaternions count when their imaginary parts are 0,
+/// and quantities when their magnitude is real.
+fn real(x: &Value) -> bool {
+    match x {
+        Value::Exact(_) => true,
+        Value::Decimal(f) => !f.is_nan(),
+        Value::Complex(c) => c.imag().is_zero() && !c.real().is_nan(),
+        Value::Quaternion(q) => q.x.is_zero() && q.y.is_zero() && q.z.is_zero() && !q.w.is_nan(),
+        Value::Quantity(q) => real(&q.magnitude),
+        Value::List(_) | Value::Tuple(_) | Value::Str(_) => false,
+    }
+}
+
+pub fn isreal(_: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    Ok(truth(real(&x)))
+}
+
+#[test]
+fn test_predicates() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("[1, 1, 0, 0]", eval("[isinteger(4), isinteger(4.0), isinteger(1/2), isinteger(\"4\")]").unwrap());
+    assert_eq!("[1, 1, 0]", eval("[isrational(1/3), isrational(2), isrational(0.5)]").unwrap());
+    // every integer is rational
+    assert_eq!("[1, 1]", eval("[isinteger(3.0), isrational(3.0)]").unwrap());
+    assert_eq!("[1, 0, 1, 0, 0]", eval("[iseven(-2), iseven(3), isodd(3), isodd(3/2), iseven(0.5)]").unwrap());
+    assert_eq!("[1, 0, 0, 0]", eval("[isprime(97), isprime(91), isprime(1), isprime(-3)]").unwrap());
+    assert_eq!("[1, 0, 1, 0]", eval("[isreal(pi), isreal(2i), isreal(2 + 0i), isreal([1])]").unwrap());
+}
@@ -0,0 +1,59 @@
+//! Quaternion construction and rotations.
+
+use color_eyre::eyre::{bail, eyre};
+
+use super::{take, to_float, Evaluator, Value};
+use crate::expr::quaternion::Quaternion;
+
+pub fn quat(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [w, x, y, z] = take(args);
+    Ok(Value::Quaternion(Box::new(Quaternion {
+        w: to_float(e, w)?,
+        x: to_float(e, x)?,
+        y: to_float(e, y)?,
+        z: to_float(e, z)?,
+    })))
+}
+
+/// Scales a quaternion or a vector to unit length.
+pub fn normalize(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [v] = take(args);
+    match v {
+        Value::Quaternion(q) => {
+            let q = q.normalize().ok_or_else(|| eyre!("cannot normalize a zero quaternion"))?;
+            Ok(Value::Quaternion(Box::new(q)))
+        }
+        Value::List(_) => {
+            let len = super::linalg::norm(e, vec![v.clone()])?;
+            if to_float(e, len.clone())?.is_zero() {
+                bail!("cannot normalize a zero vector");
+            }
+            let Value::List(items) = v else { unreachable!() };
+            let items = items.into_iter().map(|x| x.div(len.clone(), e)).collect::<color_eyre::Result<_>>()?;
+            Ok(Value::List(items))
+        }
+        other => bail!("expected a quaternion or a vector, found {}", other.kind()),
+    }
+}
+
+/// Rotates the 3D vector `v` by the quaternion `q`, which is normalized first.
+pub fn rotate(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [v, q] = take(args);
+    let Value::Quaternion(q) = q else {
+        bail!("expected a quaternion, found {}", q.kind());
+    };
+    let q = q.normalize().ok_or_else(|| eyre!("cannot rotate by a zero quaternion"))?;
+    let components = match v {
+        Value::List(items) if items.len() == 3 => items,
+        other => bail!("expected a 3D vector, found {}", other.kind()),
+    };
+    let [x, y, z] = take(components);
+    let v = Quaternion {
+        w: rug::Float::new(e.precision()),
+        x: to_float(e, x)?,
+        y: to_float(e, y)?,
+        z: to_float(e, z)?,
+    };
+    let r = q.clone() * v * q.conj();
+    Ok(Value::List(vec![Value::Decimal(r.x), Value::Decimal(r.y), Value::Decimal(r.z)]))
+}
@@ -0,0 +1,271 @@
+//! Special functions, the error function and the gamma function and its
+//! relatives, computed by MPFR at the session precision. Where the result is
+//! rational for an exact argument, like `gamma(5) = 24` or `zeta(-1) = -1/12`,
+//! it is exact.
+//!
+//! In `symbolic` mode the values that are a rational multiple of a power of
+//! `pi` are left that way too, like `zeta(2) = pi^2/6` or `gamma(1/2) = sqrt(pi)`,
+//! see [`symbolic`].
+
+use rug::{Float, Integer, Rational};
+
+use crate::domain::DomainError;
+use crate::expr::{Expr, Node};
+
+use super::{take, to_float, Evaluator, Value};
+
+/// `gamma` of integers up to this is an exact factorial, above it a decimal.
+const MAX_EXACT_GAMMA: u32 = 1 << 16;
+
+/// Bernoulli numbers take quadratic time, so `zeta` is only exact for
+/// integers up to this size.
+const MAX_BERNOULLI: u32 = 256;
+
+/// The integer `x` is, if it is an exact one.
+fn integer(x: &Value) -> Option<&Integer> {
+    match x {
+        Value::Exact(r) if r.is_integer() => Some(r.numer()),
+        _ => None,
+    }
+}
+
+/// `(n - 1)!`, for a positive `n` small enough.
+fn exact_gamma(n: &Integer) -> Option<Integer> {
+    n.to_u32().filter(|n| (1..=MAX_EXACT_GAMMA).contains(n)).map(|n| Integer::from(Integer::factorial(n - 1)))
+}
+
+/// The Bernoulli number `B(n)`, by the Akiyama-Tanigawa algorithm.
+fn bernoulli(n: u32) -> Rational {
+    let mut a: Vec<Rational> = Vec::with_capacity(n as usize + 1);
+    for m in 0..=n {
+        a.push(Rational::from((1, m + 1)));
+        for j in (1..=m as usize).rev() {
+            a[j - 1] = Rational::from(&a[j - 1] - &a[j]) * j as u32;
+        }
+    }
+    a.swap_remove(0)
+}
+
+/// `k` for a half-integer `k + 1/2`.
+fn half_integer(x: &Value) -> Option<i64> {
+    match x {
+        Value::Exact(r) if *r.denom() == 2 => Some(Integer::from(r.numer() - 1u32).to_i64()? / 2),
+        _ => None,
+    }
+}
+
+/// `gamma(k + 1/2) / sqrt(pi)`, which is rational.
+fn gamma_half(k: i64) -> Option<Rational> {
+    let m = u32::try_from(k.unsigned_abs()).ok().filter(|&m| m <= MAX_EXACT_GAMMA)?;
+    let (factorial, double) = (Integer::from(Integer::factorial(m)), Integer::from(Integer::factorial(2 * m)));
+    let power = Integer::from(Integer::u_pow_u(4, m));
+    Some(match k >= 0 {
+        true => Rational::from((double, power * factorial)),
+        false if m % 2 == 0 => Rational::from((power * factorial, double)),
+        false => -Rational::from((power * factorial, double)),
+    })
+}
+
+fn pole(name: &str, x: impl std::fmt::Display) -> color_eyre::Result<Value> {
+    Err(DomainError::new(name, x, "the gamma function is infinite at 0 and the negative integers").into())
+}
+
+pub fn erf(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if x.is_exact() && x.is_zero_or_empty() {
+        return Ok(Value::zero());
+    }
+    let mut f = to_float(e, x)?;
+    f.erf_round(e.round());
+    Ok(Value::Decimal(f))
+}
+
+pub fn erfc(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if x.is_exact() && x.is_zero_or_empty() {
+        return Ok(Value::one());
+    }
+    let mut f = to_float(e, x)?;
+    f.erfc_round(e.round());
+    Ok(Value::Decimal(f))
+}
+
+pub fn gamma(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if let Some(n) = integer(&x) {
+        if *n <= 0 {
+            return pole("gamma", &x);
+        }
+        if let Some(factorial) = exact_gamma(n) {
+            return Ok(Value::Exact(factorial.into()));
+        }
+    }
+    let mut f = to_float(e, x.clone())?;
+    if f.is_integer() && f <= 0 {
+        return pole("gamma", &x);
+    }
+    f.gamma_round(e.round());
+    Ok(Value::Decimal(f))
+}
+
+/// The logarithm of the absolute value of `gamma`, which doesn't overflow
+/// where `gamma` does.
+pub fn lgamma(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if integer(&x).is_some_and(|n| *n == 1 || *n == 2) {
+        return Ok(Value::zero());
+    }
+    let mut f = to_float(e, x.clone())?;
+    if f.is_integer() && f <= 0 {
+        return pole("lgamma", &x);
+    }
+    f.ln_abs_gamma_round(e.round());
+    Ok(Value::Decimal(f))
+}
+
+/// `gamma(a)*gamma(b)/gamma(a + b)`, from the logarithms of the gammas so
+/// that large arguments don't overflow in between.
+pub fn beta(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [a, b] = take(args);
+    let call = format!("{a}, {b}");
+    if let (Some(m), Some(n)) = (integer(&a), integer(&b)) {
+        if *m <= 0 || *n <= 0 {
+            return pole("beta", &call);
+        }
+        if let (Some(m), Some(n), Some(sum)) = (exact_gamma(m), exact_gamma(n), exact_gamma(&Integer::from(m + n))) {
+            return Ok(Value::Exact(Rational::from((m * n, sum))));
+        }
+    }
+    // the `sqrt(pi)`s of a half-integer and the half-integer sum cancel
+    for (n, k) in [(&a, &b), (&b, &a)] {
+        let (Some(n), Some(k)) = (integer(n), half_integer(k)) else { continue };
+        if *n <= 0 {
+            return pole("beta", &call);
+        }
+        let sum = n.to_i64().and_then(|n| gamma_half(k.checked_add(n)?));
+        if let (Some(n), Some(k), Some(sum)) = (exact_gamma(n), gamma_half(k), sum) {
+            return Ok(Value::Exact(Rational::from(n) * k / sum));
+        }
+    }
+    // `gamma(a + b)` is infinite
+    if let (Some(a), Some(b)) = (half_integer(&a), half_integer(&b)) {
+        if a.saturating_add(b) < 0 {
+            return Ok(Value::zero());
+        }
+    }
+    let (a, b) = (to_float(e, a)?, to_float(e, b)?);
+    if [&a, &b].iter().any(|x| x.is_integer() && **x <= 0) {
+        return pole("beta", &call);
+    }
+    // the logarithms are large where the result is tiny or huge, so they need more bits
+    let precision = e.precision() + 64;
+    let sum = Float::with_val(precision, &a + &b);
+    if sum.is_integer() && sum <= 0 {
+        return Ok(Value::zero());
+    }
+    let ln_gamma = |x: Float| Float::with_val(precision, x).ln_abs_gamma();
+    let ((a, a_sign), (b, b_sign), (sum, sum_sign)) = (ln_gamma(a), ln_gamma(b), ln_gamma(sum));
+    let mut f = Float::with_val(precision, a + b - sum).exp();
+    if (a_sign as i8) * (b_sign as i8) != sum_sign as i8 {
+        f = -f;
+    }
+    Ok(Value::Decimal(Float::with_val_round(e.precision(), f, e.round()).0))
+}
+
+/// The Riemann zeta function, exact at 0 and the negative even integers.
+pub fn zeta(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [s] = take(args);
+    if let Some(n) = integer(&s) {
+        if n.is_zero() {
+            return Ok(Value::Exact((-1, 2).into()));
+        }
+        if *n < 0 && n.is_even() {
+            return Ok(Value::zero());
+        }
+        // `-B(n + 1)/(n + 1)` at a negative `-n`
+        if let Some(n) = n.to_i32().filter(|&n| n < 0).map(i32::unsigned_abs).filter(|&n| n < MAX_BERNOULLI) {
+            return Ok(Value::Exact(-bernoulli(n + 1) / (n + 1)));
+        }
+    }
+    let mut f = to_float(e, s.clone())?;
+    if f == 1 {
+        return Err(DomainError::new("zeta", &s, "the zeta function has a pole at 1").into());
+    }
+    f.zeta_round(e.round());
+    Ok(Value::Decimal(f))
+}
+
+/// `r*t` written the way it would be typed, like `pi^2/6` or `-2*sqrt(pi)`.
+fn times(r: Rational, t: Expr) -> Expr {
+    let (p, q) = r.into_numer_denom();
+    let t = match p.to_i32() {
+        Some(1) => t,
+        Some(-1) => -t,
+        _ => Expr::product(vec![Expr::value(Value::Exact(p.into())), t]),
+    };
+    match q == 1 {
+        true => t,
+        false => Expr::binary(t, Expr::value(Value::Exact(q.into())), Node::Div),
+    }
+}
+
+/// The value of a builtin in terms of `pi` where it is known to be a rational
+/// multiple of a power of it, for `symbolic` mode, where exact arguments that
+/// have no exact result are otherwise left as a call.
+pub fn symbolic(name: &str, args: &[Value]) -> Option<Expr> {
+    let pi = || Expr::symbol("pi");
+    match (name, args) {
+        ("zeta", [s]) => {
+            let n = integer(s)?.to_u32().filter(|&n| n > 0 && n % 2 == 0 && n <= MAX_BERNOULLI)?;
+            // `|B(n)| (2 pi)^n / (2 n!)` for an even `n`
+            let factorial = Integer::from(Integer::factorial(n));
+            let r = bernoulli(n).abs() * Integer::from(Integer::u_pow_u(2, n)) / (factorial * 2u32);
+            Some(times(r, Expr::binary(pi(), Expr::value(Value::Exact(n.into())), Node::Pow)))
+        }
+        ("gamma", [x]) => Some(times(gamma_half(half_integer(x)?)?, Expr::apply(Expr::symbol("sqrt"), vec![pi()]))),
+        ("beta", [a, b]) => {
+            let (a, b) = (half_integer(a)?, half_integer(b)?);
+            // `gamma(a + b)` is `(a + b - 1)!`, which isn't 0 as `a + b` is positive
+            let sum = exact_gamma(&Integer::from(a.checked_add(b)?.checked_add(1)?))?;
+            Some(times(gamma_half(a)? * gamma_half(b)? / sum, pi()))
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn test_special() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("0.84270079", eval("erf(1)").unwrap());
+    assert_eq!("1.0000000", eval("erf(1/2) + erfc(1/2)").unwrap());
+    assert_eq!("24", eval("gamma(5)").unwrap());
+    assert_eq!("1.7724539", eval("gamma(1/2)").unwrap());
+    assert!(eval("gamma(-2)").is_err());
+    assert_eq!("857.93367", eval("lgamma(200)").unwrap());
+    assert_eq!("1/30", eval("beta(2, 5)").unwrap());
+    assert_eq!("3.1415927", eval("beta(1/2, 1/2)").unwrap());
+    assert_eq!("-3.1415927", eval("beta(-1/2, 3/2)").unwrap());
+    assert_eq!("0", eval("beta(-1/2, 1/2)").unwrap());
+    assert_eq!("1.6449341", eval("zeta(2)").unwrap());
+    assert_eq!("-1/2", eval("zeta(0)").unwrap());
+    assert!(eval("zeta(1)").is_err());
+    assert_eq!("-1/12", eval("zeta(-1)").unwrap());
+    assert_eq!("4/3", eval("beta(1/2, 2)").unwrap());
+}
+
+#[test]
+fn test_symbolic() {
+    let mut e = Evaluator::default();
+    e.settings_mut().symbolic = true;
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("pi^2/6", eval("zeta(2)").unwrap());
+    assert_eq!("pi^4/90", eval("zeta(4)").unwrap());
+    assert_eq!("zeta(3)", eval("zeta(3)").unwrap());
+    assert_eq!("sqrt(pi)", eval("gamma(1/2)").unwrap());
+    assert_eq!("-2*sqrt(pi)", eval("gamma(-1/2)").unwrap());
+    assert_eq!("3*sqrt(pi)/4", eval("gamma(5/2)").unwrap());
+    assert_eq!("pi", eval("beta(1/2, 1/2)").unwrap());
+    assert_eq!("24", eval("gamma(5)").unwrap());
+    assert_eq!("1.6449341", eval("zeta(2.0)").unwrap());
+}
@@ -0,0 +1,246 @@
+//! Trigonometric functions, which take and return angles in the unit chosen
+//! with `:set angle`. In degrees, angles whose sine, cosine or tangent is
+//! rational give exact results, and so do their reciprocals, the secant,
+//! cosecant and cotangent.
+
+use std::cmp::Ordering;
+
+use rug::float::Constant;
+use rug::{Float, Rational};
+
+use crate::domain::DomainError;
+use crate::settings::AngleMode;
+
+use super::{take, to_float, Evaluator, Value};
+
+pub fn sin(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if let Some(r) = exact_degrees(e, &x).and_then(|d| exact_sin(&d)) {
+        return Ok(Value::Exact(r));
+    }
+    let mut d = to_radians(e, x)?;
+    d.sin_round(e.round());
+    Ok(Value::Decimal(d))
+}
+
+pub fn cos(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if let Some(r) = exact_degrees(e, &x).and_then(|d| exact_sin(&(d + 90u32))) {
+        return Ok(Value::Exact(r));
+    }
+    let mut d = to_radians(e, x)?;
+    d.cos_round(e.round());
+    Ok(Value::Decimal(d))
+}
+
+pub fn tan(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if let Some(d) = exact_degrees(e, &x).filter(|d| *d.denom() == 1) {
+        match d.numer().mod_u(180) {
+            0 => return Ok(Value::zero()),
+            45 => return Ok(Value::one()),
+            135 => return Ok(Value::Exact((-1).into())),
+            90 => return Err(DomainError::new("tan", &x, "the tangent of an odd multiple of 90° is infinite").into()),
+            _ => {}
+        }
+    }
+    let mut d = to_radians(e, x)?;
+    d.tan_round(e.round());
+    Ok(Value::Decimal(d))
+}
+
+pub fn sec(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if let Some(cos) = exact_degrees(e, &x).and_then(|d| exact_sin(&(d + 90u32))) {
+        return reciprocal("sec", &x, cos, "the secant of an odd multiple of 90° is infinite");
+    }
+    let mut d = to_radians(e, x)?;
+    d.sec_round(e.round());
+    Ok(Value::Decimal(d))
+}
+
+pub fn csc(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if let Some(sin) = exact_degrees(e, &x).and_then(|d| exact_sin(&d)) {
+        return reciprocal("csc", &x, sin, "the cosecant of a multiple of 180° is infinite");
+    }
+    let mut d = to_radians(e, x.clone())?;
+    if d.is_zero() {
+        return Err(DomainError::new("csc", &x, "the cosecant of 0 is infinite").into());
+    }
+    d.csc_round(e.round());
+    Ok(Value::Decimal(d))
+}
+
+pub fn cot(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if let Some(d) = exact_degrees(e, &x).filter(|d| *d.denom() == 1) {
+        match d.numer().mod_u(180) {
+            0 => return Err(DomainError::new("cot", &x, "the cotangent of a multiple of 180° is infinite").into()),
+            45 => return Ok(Value::one()),
+            90 => return Ok(Value::zero()),
+            135 => return Ok(Value::Exact((-1).into())),
+            _ => {}
+        }
+    }
+    let mut d = to_radians(e, x.clone())?;
+    if d.is_zero() {
+        return Err(DomainError::new("cot", &x, "the cotangent of 0 is infinite").into());
+    }
+    d.cot_round(e.round());
+    Ok(Value::Decimal(d))
+}
+
+pub fn atan(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    if let Value::Complex(mut c) = x {
+        c.atan_round((e.round(), e.round()));
+        return Ok(Value::Complex(c));
+    }
+    let mut f = to_float(e, x)?;
+    f.atan_round(e.round());
+    Ok(Value::Decimal(from_radians(e, f)))
+}
+
+/// The inverse cotangent, `atan(1/x)`, with `acot(0)` a right angle so that
+/// it is continuous through 0 from the right.
+pub fn acot(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x] = take(args);
+    let right_angle = |e: &Evaluator| {
+        Value::Decimal(from_radians(e, Float::with_val(e.precision(), Constant::Pi) / 2u32))
+    };
+    match x {
+        Value::Complex(c) if c.is_zero() => Ok(right_angle(e)),
+        Value::Complex(mut c) => {
+            c.recip_mut();
+            c.atan_round((e.round(), e.round()));
+            Ok(Value::Complex(c))
+        }
+        x => {
+            let mut f = to_float(e, x)?;
+            if f.is_zero() {
+                return Ok(right_angle(e));
+            }
+            f.recip_mut();
+            f.atan_round(e.round());
+            Ok(Value::Decimal(from_radians(e, f)))
+        }
+    }
+}
+
+/// The angle of the point `(x, y)` from the positive `x` axis, in `(-180°, 180°]`.
+/// Unlike `atan(y/x)` it tells all four quadrants apart and is fine where `x` is 0.
+pub fn atan2(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [y, x] = take(args);
+    if let (Value::Exact(y), Value::Exact(x)) = (&y, &x) {
+        match exact_atan2(y, x) {
+            Some(d) if d == 0 || e.settings.angle == AngleMode::Degrees => return Ok(Value::Exact(d.into())),
+            _ => {}
+        }
+    }
+    let mut y = to_float(e, y)?;
+    y.atan2_round(&to_float(e, x)?, e.round());
+    Ok(Value::Decimal(from_radians(e, y)))
+}
+
+/// The polar coordinates `[r, theta]` of the point `(x, y)`.
+pub fn topolar(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [x, y] = take(args);
+    let r = super::elementary::hypot(e, vec![x.clone(), y.clone()])?;
+    Ok(Value::List(vec![r, atan2(e, vec![y, x])?]))
+}
+
+/// The rectangular coordinates `[x, y]` of the point at distance `r` and angle `theta`,
+/// exact where the sine and cosine of `theta` are.
+pub fn torect(e: &mut Evaluator, args: Vec<Value>) -> color_eyre::Result<Value> {
+    let [r, theta] = take(args);
+    to_float(e, r.clone())?;
+    let (cos, sin) = (cos(e, vec![theta.clone()])?, sin(e, vec![theta])?);
+    Ok(Value::List(vec![r.clone().mul(cos, e)?, r.mul(sin, e)?]))
+}
+
+/// `atan2(y, x)` in degrees, where it is a multiple of 45°. The angle of the
+/// origin is taken to be 0, like the `arg` of 0.
+fn exact_atan2(y: &Rational, x: &Rational) -> Option<i32> {
+    Some(match (y.cmp0(), x.cmp0()) {
+        (Ordering::Equal, Ordering::Less) => 180,
+        (Ordering::Equal, _) => 0,
+        (sign, Ordering::Equal) => 90 * sign as i32,
+        (sign, Ordering::Greater) if y.cmp_abs(x).is_eq() => 45 * sign as i32,
+        (sign, Ordering::Less) if y.cmp_abs(x).is_eq() => 135 * sign as i32,
+        _ => return None,
+    })
+}
+
+/// `1/r` for the exact sine or cosine `r` that the cosecant or secant of `x` divides by.
+fn reciprocal(name: &str, x: &Value, r: Rational, infinite: &'static str) -> color_eyre::Result<Value> {
+    if r.is_zero() {
+        return Err(DomainError::new(name, x, infinite).into());
+    }
+    Ok(Value::Exact(r.recip()))
+}
+
+/// The exact angle in degrees, if `x` is exact and we are in degrees mode.
+fn exact_degrees(e: &Evaluator, x: &Value) -> Option<Rational> {
+    match x {
+        Value::Exact(r) if e.settings.angle == AngleMode::Degrees => Some(r.clone()),
+        _ => None,
+    }
+}
+
+/// The sine of the angles in degrees where it is rational, multiples of 30°
+/// that aren't also odd multiples of 45°.
+fn exact_sin(deg: &Rational) -> Option<Rational> {
+    if *deg.denom() != 1 {
+        return None;
+    }
+    Some(match deg.numer().mod_u(360) {
+        0 | 180 => Rational::new(),
+        30 | 150 => Rational::from((1, 2)),
+        90 => Rational::from(1),
+        210 | 330 => Rational::from((-1, 2)),
+        270 => Rational::from(-1),
+        _ => return None,
+    })
+}
+
+/// Converts an angle argument to radians.
+pub(super) fn to_radians(e: &Evaluator, x: Value) -> color_eyre::Result<Float> {
+    let f = to_float(e, x)?;
+    Ok(match e.settings.angle {
+        AngleMode::Radians => f,
+        AngleMode::Degrees => f * Float::with_val(e.precision(), Constant::Pi) / 180u32,
+    })
+}
+
+/// Converts an angle in radians to the current angle unit.
+pub(super) fn from_radians(e: &Evaluator, f: Float) -> Float {
+    match e.settings.angle {
+        AngleMode::Radians => f,
+        AngleMode::Degrees => f * 180u32 / Float::with_val(e.precision(), Constant::Pi),
+    }
+}
+
+#[test]
+fn test_exact_sin() {
+    let sin = |d: i32| exact_sin(&Rational::from(d));
+    assert_eq!(Some(Rational::from((1, 2))), sin(30));
+    assert_eq!(Some(Rational::from((-1, 2))), sin(-30));
+    assert_eq!(Some(Rational::from(-1)), sin(630));
+    assert_eq!(None, sin(45));
+    assert_eq!(None, exact_sin(&Rational::from((61, 2))));
+    let atan2 = |y: i32, x: i32| exact_atan2(&Rational::from(y), &Rational::from(x));
+    assert_eq!((Some(-135), Some(90), Some(180)), (atan2(-2, -2), atan2(3, 0), atan2(0, -1)));
+    assert_eq!(None, atan2(1, 2));
+}
+
+#[test]
+fn test_coordinates() {
+    let mut e = Evaluator::default();
+    e.settings_mut().angle = AngleMode::Degrees;
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("[5, 53.130102]", eval("topolar(3, 4)").unwrap());
+    assert_eq!("[3.0000000, 4.0000000]", eval("torect(5, atan2(4, 3))").unwrap());
+    assert_eq!("[2, -90]", eval("topolar(0, -2)").unwrap());
+    assert_eq!("[-1, 0]", eval("torect(1, 180)").unwrap());
+}
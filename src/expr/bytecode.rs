@@ -0,0 +1,237 @@
+//! User defined functions are compiled to a list of stack instructions when
+//! they are defined, so that calling them doesn't walk and rebuild their body.
+//!
+//! Compiled code only works with values. A call only runs it once it is known
+//! that every variable it and the functions it calls read is a value, otherwise
+//! the body is evaluated as an expression as before, which may leave it symbolic.
+
+use std::collections::BTreeSet;
+
+use color_eyre::eyre::bail;
+
+use super::builtins::{self, Builtin};
+use super::{
+    grow_stack, introspect, numeric, Evaluator, ExprId, ExprRef, Function, Literal, Node, Value, MAX_CALL_DEPTH,
+};
+
+#[derive(Clone)]
+enum Instr {
+    Value(Value),
+    Literal(Literal),
+    /// The argument for the parameter at this index.
+    Param(usize),
+    /// A variable or constant, known to be a value before running.
+    Global(String),
+    Add(usize),
+    Sub,
+    Mul(usize),
+    Div,
+    Pow,
+    Neg,
+    List(usize),
+    Tuple(usize),
+    Builtin(&'static Builtin, usize),
+    /// A user defined function, with the number of arguments.
+    Call(String, usize),
+}
+
+/// The compiled body of a user defined function.
+pub struct Code {
+    instrs: Vec<Instr>,
+    /// The variables the body reads, and the user defined functions it calls.
+    globals: BTreeSet<String>,
+    calls: BTreeSet<String>,
+}
+
+impl Code {
+    /// Compiles a function body, `None` if it uses something only expressions
+    /// can do, like the introspection functions or calling a non-name.
+    pub fn compile(params: &[String], body: ExprRef) -> Option<Code> {
+        let mut code = Code { instrs: vec![], globals: BTreeSet::new(), calls: BTreeSet::new() };
+        code.emit(params, body)?;
+        Some(code)
+    }
+
+    fn emit(&mut self, params: &[String], e: ExprRef) -> Option<()> {
+        grow_stack(|| self.emit_node(params, e))
+    }
+
+    fn emit_node(&mut self, params: &[String], e: ExprRef) -> Option<()> {
+        let all = |code: &mut Code, items: &[ExprId]| {
+            items.iter().try_for_each(|&i| code.emit(params, e.get(i)))
+        };
+        let instr = match e.node() {
+            Node::Value(v) => Instr::Value(v.clone()),
+            Node::Literal(l) => Instr::Literal(l.clone()),
+            Node::Symbol(s) => match params.iter().position(|p| p == s) {
+                Some(i) => Instr::Param(i),
+                None => {
+                    self.globals.insert(s.clone());
+                    Instr::Global(s.clone())
+                }
+            },
+            Node::Add(items) => {
+                all(self, items)?;
+                Instr::Add(items.len())
+            }
+            Node::Mul(items) => {
+                all(self, items)?;
+                Instr::Mul(items.len())
+            }
+            &Node::Sub(a, b) | &Node::Div(a, b) | &Node::Pow(a, b) => {
+                all(self, &[a, b])?;
+                match e.node() {
+                    Node::Sub(..) => Instr::Sub,
+                    Node::Div(..) => Instr::Div,
+                    _ => Instr::Pow,
+                }
+            }
+            &Node::Neg(a) => {
+                all(self, &[a])?;
+                Instr::Neg
+            }
+            Node::List(items) => {
+                all(self, items)?;
+                Instr::List(items.len())
+            }
+            Node::Tuple(items) => {
+                all(self, items)?;
+                Instr::Tuple(items.len())
+            }
+            Node::Apply(callee, args) => {
+                let Node::Symbol(name) = e.get(*callee).node() else {
+                    return None;
+                };
+                if introspect::lookup(name).is_some() || name == numeric::NAME {
+                    return None;
+                }
+                all(self, args)?;
+                match builtins::lookup(name) {
+                    Some(builtin) => Instr::Builtin(builtin, args.len()),
+                    None => {
+                        self.calls.insert(name.clone());
+                        Instr::Call(name.clone(), args.len())
+                    }
+                }
+            }
+            Node::Let(..) | Node::Assign(..) | Node::Unpack(..) | Node::Define(..) => return None,
+            Node::Block(_) | Node::For(..) | Node::While(..) => return None,
+            // units are looked up when evaluating
+            Node::DefineUnit(..) | Node::Quantity(..) | Node::Convert(..) => return None,
+        };
+        self.instrs.push(instr);
+        Some(())
+    }
+}
+
+impl Evaluator {
+    /// Whether calling `name` can run compiled code all the way down: it and
+    /// every function it may call are compiled and only read variables that are values.
+    /// Never in `strict-exact` or `symbolic` mode, where results that aren't exact are left symbolic.
+    pub(super) fn runnable(&self, name: &str) -> bool {
+        if self.settings.strict_exact || self.settings.symbolic {
+            return false;
+        }
+        let mut seen = BTreeSet::new();
+        let mut todo = vec![name];
+        while let Some(name) = todo.pop() {
+            if !seen.insert(name) {
+                continue;
+            }
+            // calling an undefined function is an error either way
+            let Some(code) = self.functions.get(name).and_then(Function::single).and_then(|f| f.code.as_ref()) else {
+                return !self.functions.contains_key(name);
+            };
+            let value = |g: &String| match self.variables.get(g) {
+                Some(e) => e.as_value().is_some(),
+                None => self.constant(g).is_some(),
+            };
+            if !code.globals.iter().all(value) {
+                return false;
+            }
+            todo.extend(code.calls.iter().map(String::as_str));
+        }
+        true
+    }
+
+    /// Runs the compiled body of `name`, which [`Evaluator::runnable`] allowed.
+    pub(super) fn run(&mut self, name: &str, args: Vec<Value>, depth: usize) -> color_eyre::Result<Value> {
+        let Some(function) = self.functions.get(name).and_then(Function::single).cloned() else {
+            bail!("unknown function `{name}`");
+        };
+        if function.params.len() != args.len() {
+            let s = if function.params.len() == 1 { "" } else { "s" };
+            bail!("`{name}` takes {} argument{s}, but {} were given", function.params.len(), args.len());
+        }
+        if depth >= MAX_CALL_DEPTH {
+            bail!("`{name}` was called more than {MAX_CALL_DEPTH} times without returning");
+        }
+        let code = function.code.as_ref().expect("only compiled functions are run");
+        let mut stack: Vec<Value> = vec![];
+        let pop = |stack: &mut Vec<Value>, n: usize| stack.split_off(stack.len() - n);
+        for instr in &code.instrs {
+            let v = match instr {
+                Instr::Value(v) => v.clone(),
+                Instr::Literal(l) => self.literal(l)?,
+                Instr::Param(i) => args[*i].clone(),
+                Instr::Global(g) => match self.variable(g) {
+                    Some(e) => e.into_value().ok().expect("checked by `runnable`"),
+                    None => self.constant(g).expect("checked by `runnable`"),
+                },
+                Instr::Add(n) | Instr::Mul(n) => {
+                    let (op, numerical): (char, fn(Value, Value, &mut Evaluator) -> _) = match instr {
+                        Instr::Add(_) => ('+', |a, b, e| a.add(b, &*e)),
+                        _ => ('*', |a, b, e| a.mul(b, &*e)),
+                    };
+                    let mut items = pop(&mut stack, *n).into_iter();
+                    let first = items.next().expect("sums and products have operands");
+                    items.try_fold(first, |a, b| self.numeric_op(a, b, op, numerical))?
+                }
+                Instr::Sub | Instr::Div | Instr::Pow => {
+                    let (op, numerical): (char, fn(Value, Value, &mut Evaluator) -> _) = match instr {
+                        Instr::Sub => ('-', |a, b, e| a.sub(b, &*e)),
+                        Instr::Div => ('/', |a, b, e| a.div(b, &*e)),
+                        _ => ('^', |a, b, e| a.pow(b, &*e)),
+                    };
+                    let [a, b]: [Value; 2] = pop(&mut stack, 2).try_into().ok().unwrap();
+                    self.numeric_op(a, b, op, numerical)?
+                }
+                Instr::Neg => stack.pop().unwrap().neg()?,
+                Instr::List(n) => Value::List(pop(&mut stack, *n)),
+                Instr::Tuple(n) => Value::Tuple(pop(&mut stack, *n)),
+                Instr::Builtin(builtin, n) => {
+                    let args = pop(&mut stack, *n);
+                    let finite = args.iter().all(|a| a.non_finite().is_none());
+                    let v = builtin.call(self, args)?;
+                    if finite {
+                        self.check_finite(&v, || format!("`{}`", builtin.name))?;
+                    }
+                    v
+                }
+                Instr::Call(f, n) => {
+                    let args = pop(&mut stack, *n);
+                    grow_stack(|| self.run(f, args, depth + 1))?
+                }
+            };
+            stack.push(v);
+        }
+        Ok(stack.pop().expect("the body leaves its value on the stack"))
+    }
+}
+
+#[test]
+fn test_compiled_calls() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    eval("f(x) = x^2 + 1/x").unwrap();
+    eval("g(x, y) = [f(x) - y, sqrt(y)]").unwrap();
+    assert_eq!("[1/2, 2]", eval("g(2, 4)").unwrap());
+    // a variable that isn't a value makes the call symbolic, like before
+    eval("h(x) = a*f(x)").unwrap();
+    assert_eq!("a*2", eval("h(1)").unwrap());
+    eval("a = 3").unwrap();
+    assert_eq!("6", eval("h(1)").unwrap());
+    assert_eq!("division by zero", eval("h(0)").unwrap_err().to_string());
+    eval("r(x) = r(x)").unwrap();
+    assert!(eval("r(1)").unwrap_err().to_string().contains("without returning"));
+}
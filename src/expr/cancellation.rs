@@ -0,0 +1,156 @@
+//! Sums of decimals that cancel out, like `exp(1e-20) - 1`, where the operands
+//! have most of their digits in common and what is left of them after
+//! subtracting has only a few correct ones. The bits lost this way are counted
+//! while a statement is evaluated, and if too few are left for the digits that
+//! are shown, the statement is evaluated again with more precision, up to a few
+//! times. The result is rounded back to the usual precision.
+
+use rug::float::Round;
+use rug::{Complex, Float};
+
+use super::quaternion::Quaternion;
+use super::{Evaluator, Expr, Node, Value};
+use crate::settings::MAX_PRECISION;
+
+/// How many times a statement is evaluated again, each time with twice the precision.
+const MAX_RETRIES: u32 = 4;
+
+/// Bits beyond those of the digits shown that should still be correct.
+const GUARD_BITS: u32 = 16;
+
+/// Bookkeeping while a statement is evaluated.
+#[derive(Clone, Copy)]
+pub(super) struct Cancellation {
+    /// The most bits any sum lost so far.
+    lost: u32,
+    /// The `precision` setting and whether side effects were allowed before
+    /// evaluating the statement, to go back to afterwards.
+    precision: u32,
+    side_effects: bool,
+}
+
+/// The exponent of a value that can take part in a sum of decimals.
+fn exponent(v: &Value) -> Option<i32> {
+    match v {
+        Value::Decimal(f) => f.get_exp(),
+        Value::Exact(r) => Float::with_val(64, r).get_exp(),
+        _ => None,
+    }
+}
+
+/// The exponent of the larger operand of an operation that may cancel out,
+/// adding or subtracting decimals that aren't zero.
+pub(super) fn sum_exponent(op: char, a: &Value, b: &Value) -> Option<i32> {
+    let decimals = matches!(a, Value::Decimal(_)) || matches!(b, Value::Decimal(_));
+    (matches!(op, '+' | '-') && decimals).then(|| exponent(a).max(exponent(b))).flatten()
+}
+
+/// The decimals with `from` bits in a value rounded to `to` bits, leaving
+/// alone those computed with a precision of their own, like by `N(pi, 50)`.
+fn with_precision(v: Value, from: u32, to: u32, round: Round) -> Value {
+    let float = |f: &Float| if f.prec() == from { Float::with_val_round(to, f, round).0 } else { f.clone() };
+    let items = |items: Vec<Value>| items.into_iter().map(|v| with_precision(v, from, to, round)).collect();
+    match v {
+        Value::Decimal(f) => Value::Decimal(float(&f)),
+        Value::Complex(c) if c.prec() == (from, from) => {
+            Value::Complex(Complex::with_val_round(to, c, (round, round)).0)
+        }
+        Value::Quaternion(q) => {
+            Value::Quaternion(Box::new(Quaternion { w: float(&q.w), x: float(&q.x), y: float(&q.y), z: float(&q.z) }))
+        }
+        Value::List(v) => Value::List(items(v)),
+        Value::Tuple(v) => Value::Tuple(items(v)),
+        Value::Quantity(mut q) => {
+            q.magnitude = with_precision(q.magnitude, from, to, round);
+            Value::Quantity(q)
+        }
+        v @ (Value::Exact(_) | Value::Complex(_) | Value::Str(_)) => v,
+    }
+}
+
+impl Evaluator {
+    /// Counts the bits lost by a sum whose larger operand had the exponent `largest`.
+    pub(super) fn track_cancellation(&mut self, largest: i32, sum: &Value) {
+        let (Some(c), Value::Decimal(sum)) = (&mut self.cancellation, sum) else {
+            return;
+        };
+        let lost = match sum.get_exp() {
+            Some(exp) => largest.saturating_sub(exp).max(0) as u32,
+            // neither operand was zero, so everything cancelled out
+            None if sum.is_zero() => sum.prec(),
+            None => 0,
+        };
+        c.lost = c.lost.max(lost);
+    }
+
+    /// Evaluates a statement, and again with more precision while its sums cancel out too much.
+    pub(super) fn eval_accurately(&mut self, e: Expr) -> color_eyre::Result<Expr> {
+        let precision = self.settings.precision;
+        let wanted = ((self.settings.round_digits as f64 * std::f64::consts::LOG2_10) as u32 + GUARD_BITS).min(precision);
+        self.cancellation = Some(Cancellation { lost: 0, precision, side_effects: self.side_effects });
+        let mut result = self.eval_at(e.root());
+        let mut retried = false;
+        for _ in 0..MAX_RETRIES {
+            let lost = self.cancellation.map_or(0, |c| c.lost);
+            let enough = self.settings.precision.saturating_sub(lost) >= wanted;
+            if enough || self.settings.precision >= MAX_PRECISION || !result.as_ref().is_ok_and(|r| r.as_value().is_some()) {
+                break;
+            }
+            self.settings.precision = (self.settings.precision * 2).min(MAX_PRECISION);
+            // whatever the first evaluation did to the outside world shouldn't happen twice
+            self.side_effects = false;
+            if let Some(c) = &mut self.cancellation {
+                c.lost = 0;
+            }
+            match self.eval_at(e.root()) {
+                Ok(retry) if retry.as_value().is_some() => (result, retried) = (Ok(retry), true),
+                _ => break,
+            }
+        }
+        let retried_with = self.settings.precision;
+        self.finish_cancellation();
+        let result = result?;
+        if !retried {
+            return Ok(result);
+        }
+        let value = with_precision(result.into_value().ok().unwrap(), retried_with, precision, self.round());
+        match (e.root().node(), &value) {
+            (Node::Assign(name, ..), _) => {
+                self.variables.insert(name.clone(), Expr::value(value.clone()));
+            }
+            (Node::Unpack(names, _), Value::Tuple(items)) => {
+                for (name, item) in names.iter().zip(items) {
+                    self.variables.insert(name.clone(), Expr::value(item.clone()));
+                }
+            }
+            _ => {}
+        }
+        Ok(Expr::value(value))
+    }
+
+    /// How much more precision the statement is evaluated with than usual, for
+    /// the precision of its own that `N(expr, digits)` uses.
+    pub(super) fn extra_precision(&self) -> u32 {
+        self.cancellation.map_or(0, |c| self.settings.precision.saturating_sub(c.precision))
+    }
+
+    /// Goes back to the precision and side effects from before the statement.
+    pub(super) fn finish_cancellation(&mut self) {
+        if let Some(c) = self.cancellation.take() {
+            (self.settings.precision, self.side_effects) = (c.precision, c.side_effects);
+        }
+    }
+}
+
+#[test]
+fn test_cancellation() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("1.0000000e-20", eval("e^(1e-20) - 1").unwrap());
+    assert_eq!("1.0000000e-40", eval("x = (1 + 1e-40) - 1").unwrap());
+    assert_eq!("1.0000000e-40", eval("x").unwrap());
+    assert_eq!("5.0000000e-41", eval("x/2").unwrap());
+    assert_eq!("0.50000000", eval("1.5 - 1").unwrap());
+    assert_eq!("5.0000000e-21", eval("sqrt(1e40 + 1.0) - 1e20").unwrap());
+    assert_eq!("1.0000000000000000000e-40", eval("N((1 + 1e-40) - 1, 20)").unwrap());
+}
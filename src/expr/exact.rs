@@ -0,0 +1,59 @@
+//! `:set strict-exact on`, where nothing is computed with decimals. Decimal
+//! literals are read as the fractions they stand for, and whatever would have
+//! an inexact result, like `sqrt(2)`, `2^(1/2)` or the constant `pi`, is left
+//! as it is instead.
+//!
+//! `:set symbolic on` leaves the same things symbolic, but only if they are
+//! computed from exact numbers, decimals are still computed with as usual.
+
+use rug::{Integer, Rational};
+
+use super::{exact_pow, Evaluator, Value};
+
+impl Value {
+    /// Whether the value has no decimals in it.
+    pub fn is_exact(&self) -> bool {
+        match self {
+            Value::Exact(_) | Value::Str(_) => true,
+            Value::List(items) | Value::Tuple(items) => items.iter().all(Value::is_exact),
+            Value::Decimal(_) | Value::Complex(_) | Value::Quaternion(_) => false,
+            Value::Quantity(q) => q.magnitude.is_exact(),
+        }
+    }
+}
+
+impl Evaluator {
+    /// Whether an operation on `operands` is left symbolic if its result isn't exact.
+    pub(super) fn may_stay_symbolic<'a>(&self, operands: impl IntoIterator<Item = &'a Value>) -> bool {
+        self.settings.strict_exact || (self.settings.symbolic && operands.into_iter().all(Value::is_exact))
+    }
+}
+
+/// A decimal literal as the fraction it stands for, `1.25e1` as `25/2`.
+pub(super) fn literal(text: &str) -> color_eyre::Result<Rational> {
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>()?),
+        None => (text, 0),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = Integer::from(Integer::parse(format!("{int}{frac}"))?);
+    let scale = exact_pow(Rational::from(10), &Integer::from(exponent - frac.len() as i64))?;
+    Ok(Rational::from(digits) * scale)
+}
+
+#[test]
+fn test_strict_exact() {
+    let mut e = super::Evaluator::default();
+    e.settings_mut().set("strict-exact", "on").unwrap();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("3/10", eval("0.1 + 0.2").unwrap());
+    assert_eq!("25/2", eval("1.25e1").unwrap());
+    assert_eq!("2*sqrt(2)", eval("2*sqrt(2)").unwrap());
+    assert_eq!("3", eval("sqrt(9)").unwrap());
+    assert_eq!("2^(1/2)+1", eval("2^(1/2) + 1").unwrap());
+    assert_eq!("pi/2", eval("pi/2").unwrap());
+    // compiled functions would compute with decimals
+    eval("f(x) = sqrt(x) + 0.5").unwrap();
+    assert_eq!("sqrt(2)+1/2", eval("f(2)").unwrap());
+    assert!(eval("2i").is_err());
+}
@@ -0,0 +1,125 @@
+//! Folding of constant subexpressions when parsing, so that a function like
+//! `f(x) = 2*3*x` is stored as `6*x` and doesn't multiply `2*3` on every call.
+//!
+//! Only exact arithmetic is folded: its result doesn't depend on the settings
+//! and it can't have side effects. The exception is `money` mode, which rounds
+//! the result of every operation, so nothing is folded in it and the rounding
+//! is left for evaluation, with the settings of that time. Symbols are never
+//! touched since they may be bound to something else by the time the
+//! expression is evaluated, and operations that fail, like `1/0`, are left
+//! for evaluation to report.
+
+use super::{Evaluator, Expr, ExprId, ExprRef, Node, Value};
+
+impl Expr {
+    /// The expression with its constant exact parts reduced to values.
+    pub fn fold(&self, e: &Evaluator) -> Expr {
+        if e.settings.money.is_some() {
+            return self.clone();
+        }
+        let mut folder = Evaluator {
+            settings: e.settings.clone(),
+            side_effects: false,
+            ..Evaluator::default()
+        };
+        fold(self.root(), &mut folder)
+    }
+}
+
+fn is_exact(e: &Expr) -> bool {
+    matches!(e.as_value(), Some(Value::Exact(_)))
+}
+
+/// Evaluates an operation on exact values, keeping it as it is if that fails
+/// or doesn't give an exact result.
+fn reduce(e: Expr, folder: &mut Evaluator) -> Expr {
+    match folder.eval(e.clone()) {
+        Ok(result) if is_exact(&result) => result,
+        _ => e,
+    }
+}
+
+fn fold_all(e: ExprRef, items: &[ExprId], folder: &mut Evaluator) -> Vec<Expr> {
+    items.iter().map(|&i| fold(e.get(i), folder)).collect()
+}
+
+fn fold(e: ExprRef, folder: &mut Evaluator) -> Expr {
+    super::grow_stack(|| fold_node(e, folder))
+}
+
+fn fold_node(e: ExprRef, folder: &mut Evaluator) -> Expr {
+    match e.node() {
+        Node::Value(_) | Node::Literal(_) | Node::Symbol(_) => e.to_expr(),
+        Node::Add(items) | Node::Mul(items) => {
+            let node = if let Node::Add(_) = e.node() { Expr::sum } else { Expr::product };
+            let mut folded: Vec<Expr> = vec![];
+            for item in fold_all(e, items, folder) {
+                match folded.pop() {
+                    // only neighbours, like evaluation does
+                    Some(prev) if is_exact(&prev) && is_exact(&item) => folded.push(reduce(node(vec![prev, item]), folder)),
+                    prev => folded.extend(prev.into_iter().chain([item])),
+                }
+            }
+            match <[Expr; 1]>::try_from(folded) {
+                Ok([item]) => item,
+                Err(folded) => node(folded),
+            }
+        }
+        &(Node::Sub(a, b) | Node::Div(a, b) | Node::Pow(a, b)) => {
+            let (a, b) = (fold(e.get(a), folder), fold(e.get(b), folder));
+            let exact = is_exact(&a) && is_exact(&b);
+            let node = match e.node() {
+                Node::Sub(..) => Node::Sub,
+                Node::Div(..) => Node::Div,
+                _ => Node::Pow,
+            };
+            let e = Expr::binary(a, b, node);
+            if exact { reduce(e, folder) } else { e }
+        }
+        &Node::Neg(a) => {
+            let a = fold(e.get(a), folder);
+            let exact = is_exact(&a);
+            if exact { reduce(-a, folder) } else { -a }
+        }
+        // the callee is kept so that `f(2*3)` is still a call of `f`
+        Node::Apply(callee, args) => Expr::apply(e.get(*callee).to_expr(), fold_all(e, args, folder)),
+        Node::List(items) => Expr::list(fold_all(e, items, folder)),
+        Node::Tuple(items) => Expr::tuple(fold_all(e, items, folder)),
+        Node::Let(name, value, body) => {
+            Expr::let_in(name.clone(), fold(e.get(*value), folder), fold(e.get(*body), folder))
+        }
+        Node::Assign(name, annotation, value) => {
+            Expr::assign(name.clone(), annotation.clone(), fold(e.get(*value), folder))
+        }
+        Node::Unpack(names, value) => Expr::unpack(names.clone(), fold(e.get(*value), folder)),
+        Node::Define(name, params, body, memo) => {
+            Expr::define(name.clone(), params.clone(), fold(e.get(*body), folder), *memo)
+        }
+        Node::DefineUnit(name, value) => Expr::define_unit(name.clone(), fold(e.get(*value), folder)),
+        // the unit is looked up when evaluating, a name in it may become a variable
+        &Node::Quantity(magnitude, unit) => Expr::quantity(fold(e.get(magnitude), folder), e.get(unit).to_expr()),
+        &Node::Convert(value, unit) => Expr::convert(fold(e.get(value), folder), e.get(unit).to_expr()),
+        // a loop runs its statements over and over, there is nothing to fold ahead of time
+        Node::Block(_) | Node::For(..) | Node::While(..) => e.to_expr(),
+    }
+}
+
+#[test]
+fn test_fold() {
+    let e = Evaluator::default();
+    let fold = |source| super::parse(&e, source).0.remove(0).fold(&e).to_string();
+    assert_eq!("6*x*20", fold("2*3*x*4*5"));
+    assert_eq!("f(x) = x^4+1/2", fold("f(x) = x^(2*2) + 1/2"));
+    // left for evaluation to report or to compute with the current precision
+    assert_eq!("x+1/0", fold("x + 1/0"));
+    assert_eq!("2^(1/2)*x", fold("2^(1/2)*x"));
+    assert_eq!("2.5*3i*x", fold("2.5*3i*x"));
+    assert_eq!("sqrt(8)+pi", fold("sqrt(2*4) + pi"));
+    // money mode rounds when evaluating, not when the function is defined
+    let mut e = Evaluator::default();
+    e.settings_mut().set("money", "2").unwrap();
+    let eval = |e: &mut Evaluator, line| crate::session::eval(e, line).unwrap().to_string();
+    assert_eq!("m(x) = x+1/3", eval(&mut e, "m(x) = x + 1/3"));
+    e.settings_mut().set("money", "off").unwrap();
+    assert_eq!("4/3", eval(&mut e, "m(1)"));
+}
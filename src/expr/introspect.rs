@@ -0,0 +1,134 @@
+//! Functions on the structure of an expression rather than its value, e.g.
+//! `depth(x * (y + 1))`. Their argument is evaluated as far as possible but
+//! doesn't need to evaluate to a value, so they can't be regular builtins.
+
+use std::collections::BTreeSet;
+
+use super::{Expr, ExprId, ExprRef, Node, Value};
+
+/// Returns the function called `name`, if there is one.
+pub fn lookup(name: &str) -> Option<fn(&Expr) -> Value> {
+    Some(match name {
+        "depth" => |e| Value::Exact(e.depth().into()),
+        "nodecount" => |e| Value::Exact(e.node_count().into()),
+        "variables" => |e| Value::List(e.variables().into_iter().map(|s| Value::Str(s.into())).collect()),
+        _ => return None,
+    })
+}
+
+/// The direct subexpressions of a node, with list and tuple values counted as nodes as well.
+enum Children<'a> {
+    Exprs(Vec<ExprRef<'a>>),
+    Values(&'a [Value]),
+}
+
+fn children(e: ExprRef<'_>) -> Children<'_> {
+    let ids = match e.node() {
+        Node::Value(Value::List(items) | Value::Tuple(items)) => return Children::Values(items),
+        // the function name is not a subexpression
+        Node::Apply(_, args) => args.clone(),
+        node => node.children(),
+    };
+    Children::Exprs(ids.into_iter().map(|id| e.get(id)).collect())
+}
+
+fn value_depth(v: &Value) -> usize {
+    match v {
+        Value::List(items) | Value::Tuple(items) => 1 + items.iter().map(value_depth).max().unwrap_or(0),
+        _ => 1,
+    }
+}
+
+fn value_node_count(v: &Value) -> usize {
+    match v {
+        Value::List(items) | Value::Tuple(items) => 1 + items.iter().map(value_node_count).sum::<usize>(),
+        _ => 1,
+    }
+}
+
+impl Expr {
+    /// The number of nodes on the longest path from the root to a leaf, `1` for a number or symbol.
+    pub fn depth(&self) -> usize {
+        // children come before their parents, so this needs no recursion, which
+        // an expression too deep to evaluate would run out of stack for
+        let mut depths: Vec<usize> = Vec::with_capacity(self.nodes().len());
+        for node in self.nodes() {
+            let below = match node {
+                Node::Value(v) => value_depth(v) - 1,
+                // the function name is not a subexpression
+                Node::Apply(_, args) => args.iter().map(|a| depths[a.index()]).max().unwrap_or(0),
+                node => node.children().iter().map(|c| depths[c.index()]).max().unwrap_or(0),
+            };
+            depths.push(below + 1);
+        }
+        depths.last().copied().unwrap_or(0)
+    }
+
+    /// Like [`Expr::depth`], but a chain of `-` and `/` like `1-2-3-4` counts
+    /// as one level, as it is written without brackets.
+    pub(super) fn nesting(&self) -> usize {
+        let mut depths: Vec<usize> = Vec::with_capacity(self.nodes().len());
+        let chain = |id: ExprId| matches!(self.nodes()[id.index()], Node::Sub(..) | Node::Div(..));
+        for node in self.nodes() {
+            let depth = match *node {
+                Node::Sub(a, b) | Node::Div(a, b) if chain(a) => depths[a.index()].max(depths[b.index()] + 1),
+                Node::Value(ref v) => value_depth(v),
+                Node::Apply(_, ref args) => args.iter().map(|a| depths[a.index()]).max().unwrap_or(0) + 1,
+                ref node => node.children().iter().map(|c| depths[c.index()]).max().unwrap_or(0) + 1,
+            };
+            depths.push(depth);
+        }
+        depths.last().copied().unwrap_or(0)
+    }
+
+    /// The number of nodes in the expression tree.
+    pub fn node_count(&self) -> usize {
+        self.root().node_count()
+    }
+
+    /// The symbols that are left in the expression, which are the ones without a value.
+    pub fn variables(&self) -> BTreeSet<&str> {
+        let mut vars = BTreeSet::new();
+        self.root().collect_variables(&mut vars);
+        vars
+    }
+}
+
+impl<'a> ExprRef<'a> {
+    fn node_count(self) -> usize {
+        1 + match children(self) {
+            Children::Exprs(es) => es.into_iter().map(ExprRef::node_count).sum::<usize>(),
+            Children::Values(vs) => vs.iter().map(value_node_count).sum::<usize>(),
+        }
+    }
+
+    fn collect_variables(self, vars: &mut BTreeSet<&'a str>) {
+        match self.node() {
+            Node::Symbol(s) => {
+                vars.insert(s);
+            }
+            _ => {
+                if let Children::Exprs(es) = children(self) {
+                    es.into_iter().for_each(|e| e.collect_variables(vars));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_introspect() {
+    let mut e = super::Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("[1, 1, 3]", eval("[depth(5), depth(x), depth(x * (y + 1))]").unwrap());
+    assert_eq!("[1, 5, 2]", eval("[nodecount(x), nodecount(x * (y + 1)), nodecount(sin(x))]").unwrap());
+    // lists count as nodes, with their items below them
+    assert_eq!("[3, 5]", eval("[depth([1, [2, 3]]), nodecount([1, [2, 3]])]").unwrap());
+    // function names aren't variables, and each variable is listed once
+    assert_eq!("[\"a\", \"b\", \"x\"]", eval("variables(sin(x) + a*b + x)").unwrap());
+    assert_eq!("[]", eval("variables(5)").unwrap());
+    // the argument is evaluated first, so variables with a value are gone
+    eval("y = 3").unwrap();
+    assert_eq!("[\"x\"]", eval("variables(x * (y + 1))").unwrap());
+    assert_eq!("[2, 3]", eval("[depth(x * (y + 1)), nodecount(x * (y + 1))]").unwrap());
+}
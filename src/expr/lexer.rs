@@ -0,0 +1,248 @@
+//! Splits a line into tokens, the first stage of parsing.
+
+use std::fmt;
+use std::ops::Range;
+
+use super::parser::ParseError;
+
+pub type Span = Range<usize>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Token {
+    /// A number literal with underscores removed, e.g. `1.5e3`
+    Number(String),
+    /// A number literal followed by `i`, without the `i`
+    Imaginary(String),
+    /// An angle like `12°34'56"` or a time like `1h23m45s`
+    Sexagesimal(String),
+    /// A string literal with escapes already replaced
+    Str(String),
+    Ident(String),
+    /// Operators and punctuation, e.g. `(` or `**`
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => f.write_str(n),
+            Token::Imaginary(n) => write!(f, "{n}i"),
+            Token::Sexagesimal(s) => f.write_str(s),
+            Token::Str(s) => write!(f, "{s:?}"),
+            Token::Ident(s) => f.write_str(s),
+            Token::Op(op) => f.write_str(op),
+        }
+    }
+}
+
+/// Longer operators come first so that `**` isn't read as two `*`s.
+const OPS: &[&str] = &[
+    "**", "<=", ">=", "==", "!=", "..", "+", "-", "*", "/", "^", "%", "<", ">", "=", "(", ")", "[", "]", "{", "}", ",",
+    ":", ";",
+];
+
+/// Words that are operators rather than names, like `of` in `15% of 240`.
+pub const KEYWORDS: &[&str] = &["of"];
+
+/// Names that are never a factor, like `in` in `let t = 2 in t^2`, so that
+/// implicit multiplication doesn't turn `2 in` into `2*in`.
+pub const SEPARATORS: &[&str] = &["in"];
+
+/// The length of the exponent marker at the start of `s`, `e` or `E` with an
+/// optional sign, if digits follow it. Otherwise the `e` starts a name, so that
+/// `2e3` and `2e-3` are numbers but `2e`, `2e-x` and `2ex` are `2` followed by
+/// `e` or `ex`, e.g. the constant `e` once implicit multiplication turns `2e` into `2*e`.
+pub fn exponent_len(s: &str) -> Option<usize> {
+    let rest = s.strip_prefix(['e', 'E'])?;
+    let digits = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+    digits.starts_with(|c: char| c.is_ascii_digit()).then(|| s.len() - digits.len())
+}
+
+/// The length of the hexadecimal, binary or octal literal at the start of `s`,
+/// like `0xff`, which calq doesn't read. Implied multiplication would make it
+/// `0*xff`, which is quietly zero.
+pub fn radix_literal_len(s: &str) -> Option<usize> {
+    let rest = s.strip_prefix('0')?.strip_prefix(['x', 'X', 'b', 'B', 'o', 'O'])?;
+    let len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+    (len > 0).then(|| s.len() - rest.len() + len)
+}
+
+/// The length of the sexagesimal literal at the start of `s`, like `12°34'56"`
+/// or `1h23m45s`, with the parts in that order but any of them left out. A time
+/// needs two parts, since `1h` is just an hour.
+pub fn sexagesimal_len(s: &str) -> Option<usize> {
+    let number = |s: &str| s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let first = s[number(s)..].chars().next()?;
+    let markers = if ['h', 'm', 's'].contains(&first) { ['h', 'm', 's'] } else { ['°', '\'', '"'] };
+    let (mut len, mut parts) = (0, 0);
+    for marker in markers {
+        let digits = number(&s[len..]);
+        if digits > 0 && s[len + digits..].starts_with(marker) {
+            len += digits + marker.len_utf8();
+            parts += 1;
+        }
+    }
+    let time = markers[0] == 'h';
+    if parts == 0 || (time && (parts < 2 || s[len..].starts_with(is_ident_char))) {
+        return None;
+    }
+    Some(len)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Lexer<'_> {
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Takes characters while `f` holds, returning them.
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &str {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.bump();
+        }
+        &self.src[start..self.pos]
+    }
+
+    /// Digits with optional underscores between them, `1_000`.
+    fn digits(&mut self, s: &mut String) {
+        s.extend(self.take_while(|c| c.is_ascii_digit() || c == '_').chars().filter(|&c| c != '_'));
+    }
+
+    fn number(&mut self) -> Token {
+        if let Some(len) = sexagesimal_len(self.rest()) {
+            let literal = self.rest()[..len].to_string();
+            self.pos += len;
+            return Token::Sexagesimal(literal);
+        }
+        let mut s = String::new();
+        self.digits(&mut s);
+        // `.5`, as printed without a leading zero, is `0.5`
+        if s.is_empty() {
+            s.push('0');
+        }
+        // `1..10` is a range rather than `1.` followed by `.10`
+        if self.peek() == Some('.') && !self.rest().starts_with("..") {
+            self.bump();
+            s.push('.');
+            self.digits(&mut s);
+        }
+        if let Some(len) = exponent_len(self.rest()) {
+            s.push_str(&self.rest()[..len]);
+            self.pos += len;
+            self.digits(&mut s);
+        }
+        // `2i` is imaginary, but `2in` is `2` followed by `in`
+        if self.rest().starts_with('i') && !self.rest()[1..].starts_with(is_ident_char) {
+            self.bump();
+            return Token::Imaginary(s);
+        }
+        Token::Number(s)
+    }
+
+    fn string(&mut self, start: usize) -> Result<Token, ParseError> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(Token::Str(s)),
+                Some('\\') => {
+                    let escape_start = self.pos - 1;
+                    s.push(match self.bump() {
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        _ => return Err(ParseError::new(escape_start..self.pos, "unknown escape sequence")),
+                    });
+                }
+                Some(c) => s.push(c),
+                None => return Err(ParseError::new(start..self.pos, "unterminated string")),
+            }
+        }
+    }
+}
+
+pub fn lex(src: &str) -> Result<Vec<(Token, Span)>, ParseError> {
+    let mut lexer = Lexer { src, pos: 0 };
+    let mut tokens = vec![];
+    loop {
+        lexer.take_while(char::is_whitespace);
+        let start = lexer.pos;
+        let Some(c) = lexer.peek() else {
+            return Ok(tokens);
+        };
+        if let Some(len) = radix_literal_len(lexer.rest()) {
+            let literal = &lexer.rest()[..len];
+            let message = match literal.as_bytes()[1] {
+                b'x' | b'X' => format!("hexadecimal literals aren't supported, write `fromhex(\"{}\")`", &literal[2..]),
+                b'b' | b'B' => "binary literals aren't supported".into(),
+                _ => "octal literals aren't supported".into(),
+            };
+            return Err(ParseError::new(start..start + len, message));
+        }
+        let token = if c.is_ascii_digit() || c == '.' && lexer.rest()[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            lexer.number()
+        } else if c == '"' {
+            lexer.bump();
+            lexer.string(start)?
+        } else if c.is_alphabetic() || c == '_' {
+            let word = lexer.take_while(is_ident_char);
+            match KEYWORDS.iter().find(|k| **k == word) {
+                Some(keyword) => Token::Op(keyword),
+                None => Token::Ident(word.into()),
+            }
+        } else if let Some(op) = OPS.iter().find(|op| lexer.rest().starts_with(**op)) {
+            lexer.pos += op.len();
+            Token::Op(op)
+        } else {
+            lexer.bump();
+            return Err(ParseError::new(start..lexer.pos, format!("unexpected character `{c}`")));
+        };
+        tokens.push((token, start..lexer.pos));
+    }
+}
+
+#[test]
+fn test_lex() {
+    let tokens = |s| lex(s).unwrap().into_iter().map(|(t, _)| t).collect::<Vec<_>>();
+    assert_eq!(
+        vec![Token::Number("1000.5".into()), Token::Op("**"), Token::Imaginary("2".into()), Token::Ident("in".into())],
+        tokens("1_000.5**2i in")
+    );
+    assert_eq!(vec![Token::Str("a\"b".into()), Token::Op(",")], tokens(r#""a\"b","#));
+    assert_eq!(vec![Token::Number("2e-3".into()), Token::Number("2E+3".into())], tokens("2e-3 2E+3"));
+    let (e, ex) = (Token::Ident("e".into()), Token::Ident("ex".into()));
+    assert_eq!(vec![Token::Number("2".into()), e.clone(), Token::Op("-"), Token::Ident("x".into())], tokens("2e-x"));
+    assert_eq!(vec![Token::Number("2".into()), ex, Token::Ident("e3".into())], tokens("2ex e3"));
+    assert_eq!(vec![Token::Imaginary("2e3".into()), e], tokens("2e3i e"));
+    assert_eq!(vec![Token::Number("15".into()), Token::Op("%"), Token::Op("of"), Token::Ident("often".into())], tokens("15% of often"));
+    assert_eq!(2..3, lex("1 $").unwrap_err().span);
+    assert_eq!(vec![Token::Number("0.5".into()), Token::Op(".."), Token::Number("2".into())], tokens(".5..2"));
+    let hex = lex("1 + 0xff").unwrap_err();
+    assert_eq!((4..8, "hexadecimal literals aren't supported, write `fromhex(\"ff\")`"), (hex.span, &*hex.message));
+    assert_eq!("binary literals aren't supported", lex("0b101").unwrap_err().message);
+    assert_eq!(vec![Token::Number("10".into()), Token::Ident("xff".into())], tokens("10xff"));
+    let sexagesimal = |s: &str| Token::Sexagesimal(s.into());
+    assert_eq!(
+        vec![sexagesimal("12°34'56.5\""), sexagesimal("1h23m"), Token::Number("1".into()), Token::Ident("h".into())],
+        tokens("12°34'56.5\" 1h23m 1h")
+    );
+}
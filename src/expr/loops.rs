@@ -0,0 +1,119 @@
+//! `{ a; b }` blocks and the loops that run them, `for k in 1..10 { ... }` and
+//! `while n > 1 { ... }`, for scripts that compute step by step instead of
+//! with recursive functions.
+//!
+//! The variable of a `for` loop only exists inside it, assignments in the
+//! body change the variables outside as usual. A range like `1..10^9` is
+//! counted through rather than listed first. Loops have the side effect of
+//! assigning variables over and over, so previews don't run them.
+
+use color_eyre::eyre::bail;
+use rug::Integer;
+
+use super::{Evaluator, Expr, ExprId, ExprRef, Node, Value};
+
+/// How many times a `while` loop runs before it's taken to never stop.
+const MAX_ITERATIONS: usize = 1 << 24;
+
+/// What a block or loop that runs nothing evaluates to.
+fn nothing() -> Expr {
+    Expr::value(Value::List(vec![]))
+}
+
+/// Whether the condition of a `while` loop holds, for a nonzero number.
+fn holds(condition: Expr) -> color_eyre::Result<bool> {
+    match condition.into_value() {
+        Ok(Value::Exact(r)) => Ok(!r.is_zero()),
+        Ok(Value::Decimal(d)) => Ok(!d.is_zero()),
+        _ => bail!("the condition of a `while` loop must be a number, like `n > 1`"),
+    }
+}
+
+impl Evaluator {
+    /// The statements of a block one after another, and the value of the last.
+    pub(super) fn eval_block(&mut self, e: ExprRef, statements: &[ExprId]) -> color_eyre::Result<Expr> {
+        statements.iter().try_fold(nothing(), |_, &s| self.eval_at(e.get(s)))
+    }
+
+    pub(super) fn eval_for(&mut self, var: &str, list: ExprRef, body: ExprRef) -> color_eyre::Result<Expr> {
+        self.side_effect("for")?;
+        let range = self.range(list)?;
+        let items = match range {
+            Some(_) => vec![],
+            None => match self.eval_at(list)?.into_value() {
+                Ok(Value::List(items)) => items,
+                _ => bail!("`for` loops over a list, like `1..10` or `[1, 2, 3]`"),
+            },
+        };
+        // the loop variable hides a variable of the same name until the loop is done
+        let scope = self.scopes.last().cloned().unwrap_or_default();
+        self.scopes.push(scope);
+        let run = |this: &mut Self, item: Value| {
+            this.scopes.last_mut().unwrap().insert(var.to_owned(), Expr::value(item));
+            this.eval_at(body)
+        };
+        let result = match range {
+            Some((mut n, to)) => {
+                let mut last = Ok(nothing());
+                while n <= to && last.is_ok() {
+                    last = run(self, Value::Exact(n.clone().into()));
+                    n += 1;
+                }
+                last
+            }
+            None => items.into_iter().try_fold(nothing(), |_, item| run(self, item)),
+        };
+        self.scopes.pop();
+        result
+    }
+
+    /// The bounds of `from..to`, which is parsed as `range(from, to)`, if `list` is one.
+    fn range(&mut self, list: ExprRef) -> color_eyre::Result<Option<(Integer, Integer)>> {
+        let Node::Apply(callee, args) = list.node() else {
+            return Ok(None);
+        };
+        let (Node::Symbol(name), &[from, to]) = (list.get(*callee).node(), &args[..]) else {
+            return Ok(None);
+        };
+        if name != "range" {
+            return Ok(None);
+        }
+        let mut bound = |e| match self.eval_at(list.get(e))?.into_value() {
+            Ok(v) => Ok(v.as_integer()?.clone()),
+            Err(e) => bail!("the bounds of a range must be integers, found `{e}`"),
+        };
+        Ok(Some((bound(from)?, bound(to)?)))
+    }
+
+    pub(super) fn eval_while(&mut self, condition: ExprRef, body: ExprRef) -> color_eyre::Result<Expr> {
+        self.side_effect("while")?;
+        let mut last = nothing();
+        for _ in 0..MAX_ITERATIONS {
+            let condition = self.eval_at(condition)?;
+            if !holds(condition)? {
+                return Ok(last);
+            }
+            last = self.eval_at(body)?;
+        }
+        bail!("the `while` loop didn't stop after {MAX_ITERATIONS} iterations");
+    }
+}
+
+#[test]
+fn test_loops() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    eval("total = 0").unwrap();
+    assert_eq!("55", eval("for k in 1..10 { total = total + k }").unwrap());
+    assert_eq!("55", eval("total").unwrap());
+    // the loop variable doesn't outlive the loop
+    assert_eq!("k", eval("k").unwrap());
+    eval("n = 1").unwrap();
+    assert_eq!("128", eval("while n < 100 { n = 2*n }").unwrap());
+    assert_eq!("[]", eval("while n < 100 { n = 2*n }").unwrap());
+    assert!(eval("for k in 3 { k }").is_err());
+    assert_eq!("[]", eval("for k in 5..1 { k }").unwrap());
+    // ranges are counted through rather than listed, so only the body stops this one
+    assert_eq!("division by zero", eval("for k in 1..10^12 { k/0 }").unwrap_err().to_string());
+    assert!(eval("for k in 1..1/2 { k }").is_err());
+}
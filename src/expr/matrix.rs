@@ -0,0 +1,135 @@
+//! The linear algebra builtins on vectors and matrices with symbols in them,
+//! `det([[a, b], [c, d]])` is `a*d-b*c`. The entries are combined into an
+//! expression that is evaluated like any other, so that the numbers among them
+//! are still computed with.
+
+use color_eyre::eyre::bail;
+
+use super::{Evaluator, Expr, ExprRef, Node, Value};
+
+/// Laplace expansion takes `n!` products, which is only reasonable for small matrices.
+const MAX_SYMBOLIC_DET: usize = 6;
+
+/// The items of a list, whether or not all of them are values.
+fn items(e: ExprRef) -> Option<Vec<Expr>> {
+    match e.node() {
+        Node::List(items) => Some(items.iter().map(|&i| e.get(i).to_expr()).collect()),
+        Node::Value(Value::List(items)) => Some(items.iter().cloned().map(Expr::value).collect()),
+        _ => None,
+    }
+}
+
+fn rows(m: &Expr) -> Option<Vec<Vec<Expr>>> {
+    items(m.root())?.iter().map(|row| items(row.root())).collect()
+}
+
+fn is_zero(e: &Expr) -> bool {
+    matches!(e.as_value(), Some(Value::Exact(r)) if r.is_zero())
+}
+
+/// `a - b + c`, from the terms and whether each is subtracted.
+fn signed_sum(terms: Vec<(bool, Expr)>) -> Expr {
+    let mut terms = terms.into_iter();
+    let Some((negative, first)) = terms.next() else {
+        return Expr::value(Value::zero());
+    };
+    let first = if negative { -first } else { first };
+    terms.fold(first, |sum, (negative, term)| match negative {
+        true => Expr::binary(sum, term, Node::Sub),
+        false => Expr::sum(vec![sum, term]),
+    })
+}
+
+/// The determinant by expanding along the first row, leaving out the terms that are zero.
+fn det(m: &[Vec<Expr>]) -> Expr {
+    if let [row] = m {
+        return row[0].clone();
+    }
+    let mut terms = vec![];
+    for (j, entry) in m[0].iter().enumerate().filter(|(_, entry)| !is_zero(entry)) {
+        let minor: Vec<Vec<Expr>> = m[1..]
+            .iter()
+            .map(|row| row.iter().enumerate().filter(|&(k, _)| k != j).map(|(_, x)| x.clone()).collect())
+            .collect();
+        let minor = det(&minor);
+        if !is_zero(&minor) {
+            terms.push((j % 2 == 1, Expr::product(vec![entry.clone(), minor])));
+        }
+    }
+    signed_sum(terms)
+}
+
+fn same_len(u: &Expr, v: &Expr) -> color_eyre::Result<Option<(Vec<Expr>, Vec<Expr>)>> {
+    let (Some(u), Some(v)) = (items(u.root()), items(v.root())) else {
+        return Ok(None);
+    };
+    if u.len() != v.len() {
+        bail!("vectors have different lengths, {} and {}", u.len(), v.len());
+    }
+    Ok(Some((u, v)))
+}
+
+impl Evaluator {
+    /// Computes a linear algebra builtin whose arguments have symbols in them,
+    /// if it is one that can be and they are lists.
+    pub(super) fn symbolic_linalg(&mut self, name: &str, args: &[Expr]) -> color_eyre::Result<Option<Expr>> {
+        let e = match (name, args) {
+            ("det", [m]) => {
+                let Some(m) = rows(m) else {
+                    return Ok(None);
+                };
+                let n = m.len();
+                if n == 0 || m.iter().any(|row| row.len() != n) {
+                    bail!("expected a non-empty square matrix");
+                }
+                if n > MAX_SYMBOLIC_DET {
+                    bail!("determinants of matrices with symbols are at most {MAX_SYMBOLIC_DET}x{MAX_SYMBOLIC_DET}");
+                }
+                det(&m)
+            }
+            ("dot", [u, v]) => {
+                let Some((u, v)) = same_len(u, v)? else {
+                    return Ok(None);
+                };
+                let terms = u.into_iter().zip(v).filter(|(a, b)| !is_zero(a) && !is_zero(b));
+                signed_sum(terms.map(|(a, b)| (false, Expr::product(vec![a, b]))).collect())
+            }
+            ("cross", [u, v]) => {
+                let Some((u, v)) = same_len(u, v)? else {
+                    return Ok(None);
+                };
+                let det = |a: usize, b: usize| {
+                    det(&[vec![u[a].clone(), u[b].clone()], vec![v[a].clone(), v[b].clone()]])
+                };
+                match u.len() {
+                    2 => det(0, 1),
+                    3 => Expr::list(vec![det(1, 2), det(2, 0), det(0, 1)]),
+                    n => bail!("the cross product is only defined for 2D and 3D vectors, found {n} components"),
+                }
+            }
+            _ => return Ok(None),
+        };
+        self.eval_at(e.root()).map(Some)
+    }
+}
+
+#[test]
+fn test_symbolic_linalg() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("a*d-b*c", eval("det([[a, b], [c, d]])").unwrap());
+    assert_eq!("a*c+b*d", eval("dot([a, b], [c, d])").unwrap());
+    assert_eq!("[0, 0, a*y-b*x]", eval("cross([a, b, 0], [x, y, 0])").unwrap());
+    // numbers among the entries are still computed with
+    assert_eq!("x*4-6", eval("det([[x, 2], [3, 4]])").unwrap());
+    assert_eq!("det(m)", eval("det(m)").unwrap());
+    assert!(eval("det([[a, b]])").is_err());
+    // arithmetic on lists is an error with symbols in them too, and negation works on both
+    let lines = ["[1, 2] + [3, 4]", "[a, b] + [1, 2]", "2*[[1, 2], [3, 4]]", "2*[[a, b], [c, d]]", "[a, b]^2", "x*[a]"];
+    for line in lines {
+        let err = eval(line).unwrap_err().to_string();
+        assert!(err.starts_with("cannot perform arithmetic on "), "{line}: {err}");
+    }
+    assert_eq!("cannot perform arithmetic on a list and an expression", eval("[a, b] - x").unwrap_err().to_string());
+    assert_eq!("[-a, -1]", eval("-[a, 1]").unwrap());
+}
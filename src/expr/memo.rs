@@ -0,0 +1,66 @@
+//! `memo f(n) = expr`, whose results are remembered, so that recursive
+//! definitions like `memo fib(n) = fib(n-1) + fib(n-2)` take linear time.
+//!
+//! Only calls with exact arguments that have exact results are remembered,
+//! since those don't depend on the precision. Assigning a variable, defining a
+//! function or changing a setting may change what a function computes, so they
+//! forget all results, and so does `:clearcache`.
+
+use std::collections::BTreeMap;
+
+use rug::Rational;
+
+use super::{Evaluator, Expr, Function, Value};
+
+/// The remembered results of each function, by their arguments.
+pub(super) type Cache = BTreeMap<String, BTreeMap<Vec<Rational>, Value>>;
+
+impl Function {
+    /// Whether any case of the function was defined with `memo`.
+    pub(super) fn memo(&self) -> bool {
+        self.cases.iter().any(|case| case.memo)
+    }
+}
+
+/// The arguments of a call as they're remembered, if they're all exact numbers.
+pub(super) fn key(args: &[Value]) -> Option<Vec<Rational>> {
+    args.iter()
+        .map(|arg| match arg {
+            Value::Exact(r) => Some(r.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+impl Evaluator {
+    /// Forgets the results of all `memo` functions.
+    pub fn clear_cache(&mut self) {
+        self.memos.clear();
+    }
+
+    pub(super) fn remembered(&self, name: &str, key: &[Rational]) -> Option<Value> {
+        self.memos.get(name)?.get(key).cloned()
+    }
+
+    pub(super) fn remember(&mut self, name: &str, key: Vec<Rational>, result: &Expr) {
+        if let Some(v) = result.as_value().filter(|v| v.is_exact()) {
+            self.memos.entry(name.into()).or_default().insert(key, v.clone());
+        }
+    }
+}
+
+#[test]
+fn test_memo() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    eval("fib(0) = 0").unwrap();
+    eval("fib(1) = 1").unwrap();
+    assert_eq!("memo fib(n) = fib(n-1)+fib(n-2)", eval("memo fib(n) = fib(n-1) + fib(n-2)").unwrap());
+    // without remembering results this would take ages
+    assert_eq!("354224848179261915075", eval("fib(100)").unwrap());
+    eval("memo g(x) = a*x").unwrap();
+    eval("a = 2").unwrap();
+    assert_eq!("6", eval("g(3)").unwrap());
+    eval("a = 3").unwrap();
+    assert_eq!("9", eval("g(3)").unwrap());
+}
@@ -0,0 +1,78 @@
+//! `:set money 2`, fixed-point arithmetic for amounts of money. Decimal
+//! literals are exact, and they, the result of every operation and function
+//! and every value assigned are rounded to that many fraction digits with ties
+//! going to the even digit, like accounting software does. Exact values are
+//! shown with exactly that many digits, `12.50`.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use rug::{Integer, Rational};
+
+use super::Value;
+
+fn scale(digits: u32) -> Integer {
+    Integer::from(Integer::u_pow_u(10, digits))
+}
+
+/// `r` times `10^digits`, rounded to an integer with ties to even.
+fn scaled(r: &Rational, digits: u32) -> Integer {
+    let scaled = Rational::from(r * scale(digits));
+    let (fract, mut n) = scaled.fract_floor(Integer::new());
+    match fract.cmp(&Rational::from((1, 2))) {
+        Ordering::Greater => n += 1,
+        Ordering::Equal if n.is_odd() => n += 1,
+        _ => {}
+    }
+    n
+}
+
+/// Rounds the real numbers in a value to exact ones with `digits` fraction digits.
+pub fn round(v: Value, digits: u32) -> Value {
+    match v {
+        Value::Exact(r) => Value::Exact(Rational::from((scaled(&r, digits), scale(digits)))),
+        Value::Decimal(d) => match d.to_rational() {
+            Some(r) => Value::Exact(Rational::from((scaled(&r, digits), scale(digits)))),
+            None => Value::Decimal(d),
+        },
+        Value::List(items) => Value::List(items.into_iter().map(|v| round(v, digits)).collect()),
+        Value::Tuple(items) => Value::Tuple(items.into_iter().map(|v| round(v, digits)).collect()),
+        v => v,
+    }
+}
+
+/// Writes an exact number with `digits` fraction digits, `-0.05` or `3.00`.
+pub fn write(w: &mut impl fmt::Write, r: &Rational, digits: u32) -> fmt::Result {
+    let n = scaled(r, digits);
+    let sign = if n.is_negative() { "-" } else { "" };
+    let digits = digits as usize;
+    let s = format!("{:0>width$}", n.abs().to_string(), width = digits + 1);
+    let (int, fract) = s.split_at(s.len() - digits);
+    match fract {
+        "" => write!(w, "{sign}{int}"),
+        fract => write!(w, "{sign}{int}.{fract}"),
+    }
+}
+
+#[test]
+fn test_money() {
+    let mut e = super::Evaluator::default();
+    e.settings_mut().set("money", "2").unwrap();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("0.30", eval("0.1 + 0.2").unwrap());
+    // ties go to the even cent
+    assert_eq!("0.12", eval("0.125 * 1").unwrap());
+    assert_eq!("0.14", eval("0.135 * 1").unwrap());
+    assert_eq!("-0.12", eval("-0.125 * 1").unwrap());
+    // every operation is rounded, not just the result
+    assert_eq!("0.99", eval("100/3/100 * 3").unwrap());
+    assert_eq!("[3.00, 0.05]", eval("[3, 1/20]").unwrap());
+    // and so are literals, assigned values and the results of functions
+    assert_eq!("0.00", eval("x = 0.005").unwrap());
+    assert_eq!("0.00", eval("x*2").unwrap());
+    assert_eq!("0.02", eval("y = 0.015").unwrap());
+    assert_eq!("1.41", eval("sqrt(2)").unwrap());
+    assert_eq!("2.82", eval("sqrt(2) * 2").unwrap());
+    assert_eq!("3.14", eval("z = pi").unwrap());
+    assert_eq!("3.14", eval("z").unwrap());
+}
@@ -0,0 +1,155 @@
+//! `N(expr)`, which computes what `:set symbolic on` or `:set strict-exact on`
+//! left symbolic, like `sqrt(2)` or `2*pi`, as decimals. Exact results become
+//! decimals too, `N(1/3)` is `0.33333333`.
+//!
+//! `N(expr, digits)` computes it to that many significant digits, whatever the
+//! `precision` setting is. The expression is evaluated with a few more digits
+//! than asked for, so that rounding errors don't reach the ones shown, and the
+//! result is rounded to `digits` digits and shown with all of them.
+//!
+//! `N(expr, digits, mode)` also rounds the way `mode` says, `nearest`, `zero`,
+//! `up` or `down`, whatever the `rounding` setting is. Every operation is
+//! rounded that way and so are the digits shown.
+
+use color_eyre::eyre::{bail, eyre};
+use rug::float::Round;
+use rug::{Complex, Float};
+
+use super::quaternion::Quaternion;
+use super::{Evaluator, Expr, ExprRef, Node, Value};
+use crate::settings::{Rounding, MAX_PRECISION};
+
+pub const NAME: &str = "N";
+
+/// How many more digits than asked for `N(expr, digits)` computes with.
+const GUARD_DIGITS: u32 = 10;
+
+/// Enough bits to tell apart all numbers with `digits` significant digits.
+fn bits(digits: u32) -> u32 {
+    (digits as f64 * std::f64::consts::LOG2_10).ceil() as u32 + 1
+}
+
+impl Evaluator {
+    /// Evaluates `e` again with constants and inexact functions computed as decimals.
+    pub fn numeric(&mut self, e: Expr) -> color_eyre::Result<Expr> {
+        let result = self.numerically(None, |this| this.eval(e))?;
+        Ok(match result.into_value() {
+            Ok(v) => Expr::value(decimal(v, self.precision(), self.round())),
+            Err(e) => e,
+        })
+    }
+
+    /// `N(e, digits)` or `N(e, digits, mode)`, with `digits` not evaluated yet.
+    pub(super) fn numeric_digits(
+        &mut self,
+        e: ExprRef,
+        digits: ExprRef,
+        mode: Option<ExprRef>,
+    ) -> color_eyre::Result<Expr> {
+        let max = (MAX_PRECISION as f64 * std::f64::consts::LOG10_2) as u32 - GUARD_DIGITS;
+        let digits = self.eval_at(digits)?.into_value().map_err(|e| eyre!("expected a number of digits, found `{e}`"))?;
+        let digits = digits.as_integer()?.to_u32().filter(|d| (1..=max).contains(d));
+        let digits = digits.ok_or_else(|| eyre!("`{NAME}` computes from 1 to {max} digits"))?;
+        let rounding = match mode {
+            Some(mode) => self.rounding_mode(mode)?,
+            None => self.settings.rounding,
+        };
+        let precision = bits(digits + GUARD_DIGITS) + self.extra_precision();
+        let saved = std::mem::replace(&mut self.settings.rounding, rounding);
+        let result = self.numerically(Some(precision.min(MAX_PRECISION)), |this| this.eval_at(e));
+        self.settings.rounding = saved;
+        Ok(match result?.into_value() {
+            Ok(v) => Expr::value(rounded(decimal(v, precision, rounding.into()), digits, rounding.into())),
+            Err(e) => e,
+        })
+    }
+
+    /// The rounding mode named by `mode`, a name like `up` or a string like `"up"`.
+    fn rounding_mode(&mut self, mode: ExprRef) -> color_eyre::Result<Rounding> {
+        let name = match mode.node() {
+            Node::Symbol(name) => name.clone(),
+            _ => match self.eval_at(mode)?.into_value() {
+                Ok(Value::Str(name)) => name,
+                Ok(v) => bail!("expected a rounding mode, found `{v}`"),
+                Err(e) => bail!("expected a rounding mode, found `{e}`"),
+            },
+        };
+        Rounding::parse(&name)
+            .ok_or_else(|| eyre!("`{NAME}` rounds to `nearest`, `zero`, `up` or `down`, found `{name}`"))
+    }
+
+    /// Runs `f` with `symbolic` and `strict-exact` off, and with `precision` if given.
+    pub(super) fn numerically<T>(&mut self, precision: Option<u32>, f: impl FnOnce(&mut Self) -> T) -> T {
+        let saved = (self.settings.symbolic, self.settings.strict_exact, self.settings.precision);
+        (self.settings.symbolic, self.settings.strict_exact) = (false, false);
+        self.settings.precision = precision.unwrap_or(saved.2);
+        let result = f(self);
+        (self.settings.symbolic, self.settings.strict_exact, self.settings.precision) = saved;
+        result
+    }
+}
+
+/// The value with its exact numbers as decimals with `precision` bits.
+fn decimal(v: Value, precision: u32, round: Round) -> Value {
+    match v {
+        Value::Exact(r) => Value::Decimal(Float::with_val_round(precision, r, round).0),
+        Value::List(items) => Value::List(items.into_iter().map(|v| decimal(v, precision, round)).collect()),
+        Value::Tuple(items) => Value::Tuple(items.into_iter().map(|v| decimal(v, precision, round)).collect()),
+        Value::Quantity(mut q) => {
+            q.magnitude = decimal(q.magnitude, precision, round);
+            Value::Quantity(q)
+        }
+        v => v,
+    }
+}
+
+/// The decimals in a value rounded to `digits` significant digits.
+fn rounded(v: Value, digits: u32, mode: Round) -> Value {
+    let round = |f: &Float| {
+        let bits = bits(digits);
+        if !f.is_finite() {
+            return Float::with_val(bits, f);
+        }
+        let text = f.to_string_radix_round(10, Some(digits as usize), mode);
+        Float::with_val(bits, Float::parse(text).expect("rug parses the numbers it writes"))
+    };
+    match v {
+        Value::Decimal(f) => Value::Decimal(round(&f)),
+        Value::Complex(c) => Value::Complex(Complex::with_val(bits(digits), (round(c.real()), round(c.imag())))),
+        Value::Quaternion(q) => {
+            Value::Quaternion(Box::new(Quaternion { w: round(&q.w), x: round(&q.x), y: round(&q.y), z: round(&q.z) }))
+        }
+        Value::List(items) => Value::List(items.into_iter().map(|v| rounded(v, digits, mode)).collect()),
+        Value::Tuple(items) => Value::Tuple(items.into_iter().map(|v| rounded(v, digits, mode)).collect()),
+        Value::Quantity(mut q) => {
+            q.magnitude = rounded(q.magnitude, digits, mode);
+            Value::Quantity(q)
+        }
+        v @ (Value::Exact(_) | Value::Str(_)) => v,
+    }
+}
+
+#[test]
+fn test_symbolic() {
+    let mut e = Evaluator::default();
+    e.settings_mut().set("symbolic", "on").unwrap();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    eval("r = 3").unwrap();
+    assert_eq!("6*pi", eval("2*pi*r").unwrap());
+    assert_eq!("sqrt(2)+1", eval("sqrt(2) + 1").unwrap());
+    // decimals are computed with as usual
+    assert_eq!("1.4142136", eval("sqrt(2.0)").unwrap());
+    assert_eq!("18.849556", eval("N(2*pi*r)").unwrap());
+    eval("f(x) = x*pi").unwrap();
+    assert_eq!("2*pi", eval("f(2)").unwrap());
+    assert_eq!("3.1415926535897932384626433832795028841971693993751", eval("N(pi, 50)").unwrap());
+    assert_eq!("1.4142135623730950488", eval("N(sqrt(2.0), 20)").unwrap());
+    assert!(eval("N(pi, 0)").is_err());
+    assert_eq!("3.1416", eval("N(pi, 5, up)").unwrap());
+    assert_eq!("3.1415", eval("N(pi, 5, \"toward-zero\")").unwrap());
+    assert_eq!("-0.33334", eval("N(-1/3, 5, down)").unwrap());
+    assert_eq!("0.33333", eval("N(1/3, 5)").unwrap());
+    assert_eq!("0.33333333", eval("N(1/3)").unwrap());
+    assert_eq!("[2.0000000, 0.50000000]", eval("N([2, 1/2])").unwrap());
+    assert!(eval("N(pi, 5, sideways)").is_err());
+}
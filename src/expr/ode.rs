@@ -0,0 +1,338 @@
+//! `odesolve(f, t0, y0, t1)`, which solves `y' = f(t, y)` with `y(t0) = y0`
+//! numerically and gives `y(t1)`. It takes adaptive steps small enough that the
+//! error of each step stays below the digits that are printed, with all
+//! arithmetic done at the session precision. Up to the 15 digits of an `f64`
+//! those are Runge-Kutta-Fehlberg 4(5) steps, which would take far too many
+//! steps for more digits, so beyond that each step is extrapolated from
+//! midpoint rules with more and more substeps (Gragg-Bulirsch-Stoer), which
+//! raises the order instead. Digits the precision doesn't hold are not
+//! aimed for, the error is kept to about a thousand times the rounding error.
+//!
+//! `odesolve(f, t0, y0, t1, steps)` takes that many equal classic RK4 steps
+//! instead and gives the rows `[t, y]` of every step, for plotting or `:table`
+//! like output. `y0` may be a list for a system of equations, in which case
+//! `f` is called with a list and gives one back, which is also how a second
+//! order equation is solved, as a system of `y` and `y'`.
+
+use std::cmp::Ordering;
+
+use color_eyre::eyre::{bail, eyre};
+use rug::ops::Pow;
+use rug::Float;
+
+use super::{builtins, Evaluator, Expr, ExprRef, Node, Value};
+
+pub const NAME: &str = "odesolve";
+
+/// How many steps are taken before the equation is taken to be too stiff to solve.
+const MAX_STEPS: usize = 1 << 16;
+
+/// The most midpoint rules a step is extrapolated from, each with two more
+/// substeps than the one before.
+const MAX_COLUMNS: usize = 80;
+
+/// The Fehlberg coefficients of the intermediate stages, `(numerator, denominator)`.
+const STAGES: [(Fraction, &[Fraction]); 5] = [
+    ((1, 4), &[(1, 4)]),
+    ((3, 8), &[(3, 32), (9, 32)]),
+    ((12, 13), &[(1932, 2197), (-7200, 2197), (7296, 2197)]),
+    ((1, 1), &[(439, 216), (-8, 1), (3680, 513), (-845, 4104)]),
+    ((1, 2), &[(-8, 27), (2, 1), (-3544, 2565), (1859, 4104), (-11, 40)]),
+];
+/// The weights of the fifth order solution, which is the one kept.
+const FIFTH: [Fraction; 6] = [(16, 135), (0, 1), (6656, 12825), (28561, 56430), (-9, 50), (2, 55)];
+/// The fifth order weights less the fourth order ones, for the error of a step.
+const ERROR: [Fraction; 6] = [(1, 360), (0, 1), (-128, 4275), (-2197, 75240), (1, 50), (2, 55)];
+
+type Fraction = (i32, i32);
+
+/// `y` or a list of `y`s, one for each equation.
+type State = Vec<Float>;
+
+impl Evaluator {
+    /// `odesolve(f, t0, y0, t1[, steps])`, with `f` not evaluated.
+    pub(super) fn odesolve(&mut self, f: ExprRef, args: &[ExprRef]) -> color_eyre::Result<Expr> {
+        let Node::Symbol(f) = f.node() else {
+            bail!("`{NAME}` takes the name of a function `f(t, y)`, not `{}`", f.to_expr());
+        };
+        if !self.functions.contains_key(f) && builtins::lookup(f).is_none() {
+            bail!("`{f}` isn't a function, define one like `f(t, y) = -y`");
+        }
+        let mut values = vec![];
+        for &arg in args {
+            let v = self.eval_at(arg)?;
+            values.push(v.into_value().map_err(|e| eyre!("`{NAME}` expected a number, found `{e}`"))?);
+        }
+        // the equation is solved in decimals like `N` computes, even in `symbolic` mode
+        self.numerically(None, |this| match values.as_slice() {
+            [t0, y0, t1] => this.solve_adaptive(f, t0, y0, t1),
+            [t0, y0, t1, steps] => this.solve_steps(f, t0, y0, t1, steps),
+            _ => unreachable!("arity is checked by the caller"),
+        })
+    }
+
+    fn solve_adaptive(&mut self, f: &str, t0: &Value, y0: &Value, t1: &Value) -> color_eyre::Result<Expr> {
+        let (mut t, t1) = (self.real(t0, "t0")?, self.real(t1, "t1")?);
+        let (mut y, list) = self.state(y0.clone(), None, "y0")?;
+        let digits = self.settings.round_digits;
+        let precision = self.precision();
+        let printed = Float::with_val(precision, 10).pow(-(digits as i32) - 2);
+        let rounding = Float::with_val(precision, 1) >> precision.saturating_sub(10);
+        let tolerance = printed.max(&rounding);
+        let extrapolate = digits > f64::DIGITS as usize;
+        let columns = (digits / 3 + 4).min(MAX_COLUMNS);
+        let mut h: Float = Float::with_val(precision, &t1 - &t) / if extrapolate { 4 } else { 100 };
+        for _ in 0..MAX_STEPS {
+            if t == t1 {
+                return Ok(Expr::value(value(y, list)));
+            }
+            let remaining = Float::with_val(precision, &t1 - &t);
+            if h.cmp_abs(&remaining).is_some_and(Ordering::is_gt) {
+                h = remaining;
+            }
+            if extrapolate {
+                match self.extrapolated(f, &t, &y, &h, list, columns, &tolerance)? {
+                    Some((next, used)) => {
+                        t += &h;
+                        y = next;
+                        // converging early means the step can be longer
+                        if used < columns / 2 {
+                            h *= 2;
+                        }
+                    }
+                    None => h /= 4,
+                }
+                continue;
+            }
+            let (next, error) = self.fehlberg(f, &t, &y, &h, list)?;
+            let worst = self.relative_error(&error, &y, &tolerance);
+            if !worst.is_finite() {
+                bail!("the solution of `{NAME}` isn't finite after t = {}", Value::Decimal(t));
+            }
+            if worst <= 1.0 {
+                t += &h;
+                y = next;
+            }
+            // the usual safety factor, and not more than 5 times larger or smaller at once
+            h *= (0.9 * worst.powf(-0.2)).clamp(0.2, 5.0);
+        }
+        bail!("`{NAME}` took more than {MAX_STEPS} steps, the equation may be too stiff for it");
+    }
+
+    /// The largest error relative to the size of `y`, where that is more than
+    /// 1, as a multiple of `tolerance`.
+    fn relative_error(&self, error: &State, y: &State, tolerance: &Float) -> f64 {
+        let mut worst = 0f64;
+        for (e, y) in error.iter().zip(y) {
+            let scale = Float::with_val(self.precision(), y.abs_ref()).max(&Float::with_val(53, 1));
+            let relative = Float::with_val(self.precision(), e.abs_ref()) / scale / tolerance;
+            worst = worst.max(relative.to_f64());
+        }
+        worst
+    }
+
+    /// A step from `(t, y)` extrapolated to a step size of zero from midpoint
+    /// rules with 2, 4, 6, ... substeps, and how many it took for the last two
+    /// extrapolations to agree to `tolerance`, `None` if they don't with
+    /// `columns` of them.
+    #[allow(clippy::too_many_arguments)]
+    fn extrapolated(
+        &mut self,
+        f: &str,
+        t: &Float,
+        y: &State,
+        h: &Float,
+        list: bool,
+        columns: usize,
+        tolerance: &Float,
+    ) -> color_eyre::Result<Option<(State, usize)>> {
+        let mut previous: Vec<State> = vec![];
+        for k in 1..=columns {
+            let mut row = vec![self.midpoint(f, t, y, h, 2 * k, list)?];
+            // Neville's scheme in `h^2`, the error of the midpoint rule being even in `h`
+            for j in 1..k {
+                // `1/((k/(k - j))^2 - 1)`
+                let (k, l) = (k as u32, (k - j) as u32);
+                let (numer, denom) = (l * l, k * k - l * l);
+                let last = &row[j - 1];
+                let next = last
+                    .iter()
+                    .zip(&previous[j - 1])
+                    .map(|(a, b)| Float::with_val(self.precision(), a - b) * numer / denom + a)
+                    .collect();
+                row.push(next);
+            }
+            if k > 1 {
+                let error: State =
+                    row[k - 1].iter().zip(&row[k - 2]).map(|(a, b)| Float::with_val(self.precision(), a - b)).collect();
+                if !error.iter().all(Float::is_finite) {
+                    bail!("the solution of `{NAME}` isn't finite after t = {}", Value::Decimal(t.clone()));
+                }
+                if self.relative_error(&error, y, tolerance) <= 1.0 {
+                    return Ok(Some((row.pop().expect("a row has k items"), k)));
+                }
+            }
+            previous = row;
+        }
+        Ok(None)
+    }
+
+    /// `y` after `h` in `n` substeps of the modified midpoint rule, with
+    /// Gragg's smoothing at the end.
+    fn midpoint(
+        &mut self,
+        f: &str,
+        t: &Float,
+        y: &State,
+        h: &Float,
+        n: usize,
+        list: bool,
+    ) -> color_eyre::Result<State> {
+        let step = Float::with_val(self.precision(), h / n as u32);
+        let mut before = y.clone();
+        let mut now = combine(y, &step, &[(1, &self.derivative(f, t, y, list)?)]);
+        for m in 1..n {
+            let at = Float::with_val(self.precision(), &step * m as u32) + t;
+            let next = combine(&before, &step, &[(2, &self.derivative(f, &at, &now, list)?)]);
+            before = std::mem::replace(&mut now, next);
+        }
+        let end = Float::with_val(self.precision(), t + h);
+        let last = combine(&before, &step, &[(1, &self.derivative(f, &end, &now, list)?)]);
+        Ok(now.iter().zip(&last).map(|(a, b)| Float::with_val(self.precision(), a + b) / 2).collect())
+    }
+
+    fn solve_steps(&mut self, f: &str, t0: &Value, y0: &Value, t1: &Value, steps: &Value) -> color_eyre::Result<Expr> {
+        let (t0, t1) = (self.real(t0, "t0")?, self.real(t1, "t1")?);
+        let (mut y, list) = self.state(y0.clone(), None, "y0")?;
+        let steps = steps.as_integer().ok().and_then(|n| n.to_usize()).filter(|n| (1..=MAX_STEPS).contains(n));
+        let steps = steps.ok_or_else(|| eyre!("`{NAME}` takes from 1 to {MAX_STEPS} steps"))?;
+        let h = Float::with_val(self.precision(), &t1 - &t0) / steps as u32;
+        let row = |t: Float, y: &State| {
+            Value::List(std::iter::once(t).chain(y.iter().cloned()).map(Value::Decimal).collect())
+        };
+        // the last row is at `t1` exactly, not after rounding errors of adding up steps
+        let at = |k: usize| match k == steps {
+            true => t1.clone(),
+            false => Float::with_val(h.prec(), &h * k as u32) + &t0,
+        };
+        let mut rows = vec![row(t0.clone(), &y)];
+        for k in 0..steps {
+            y = self.rk4(f, &at(k), &y, &h, list)?;
+            rows.push(row(at(k + 1), &y));
+        }
+        Ok(Expr::value(Value::List(rows)))
+    }
+
+    /// One classic Runge-Kutta step from `(t, y)`.
+    fn rk4(&mut self, f: &str, t: &Float, y: &State, h: &Float, list: bool) -> color_eyre::Result<State> {
+        let half = Float::with_val(self.precision(), h / 2);
+        let mid = Float::with_val(self.precision(), t + &half);
+        let k1 = self.derivative(f, t, y, list)?;
+        let k2 = self.derivative(f, &mid, &combine(y, &half, &[(1, &k1)]), list)?;
+        let k3 = self.derivative(f, &mid, &combine(y, &half, &[(1, &k2)]), list)?;
+        let end = Float::with_val(self.precision(), t + h);
+        let k4 = self.derivative(f, &end, &combine(y, h, &[(1, &k3)]), list)?;
+        let sixth = Float::with_val(self.precision(), h / 6);
+        Ok(combine(y, &sixth, &[(1, &k1), (2, &k2), (2, &k3), (1, &k4)]))
+    }
+
+    /// One Runge-Kutta-Fehlberg step from `(t, y)`, and an estimate of its error.
+    fn fehlberg(&mut self, f: &str, t: &Float, y: &State, h: &Float, list: bool) -> color_eyre::Result<(State, State)> {
+        let mut k = vec![self.derivative(f, t, y, list)?];
+        for (c, a) in STAGES {
+            let t = Float::with_val(self.precision(), h * fraction(self.precision(), c)) + t;
+            let y = weighted(y, h, a, &k);
+            k.push(self.derivative(f, &t, &y, list)?);
+        }
+        let zero = vec![Float::new(self.precision()); y.len()];
+        Ok((weighted(y, h, &FIFTH, &k), weighted(&zero, h, &ERROR, &k)))
+    }
+
+    /// `f(t, y)`, which has to give as many numbers as there are in `y`.
+    fn derivative(&mut self, f: &str, t: &Float, y: &State, list: bool) -> color_eyre::Result<State> {
+        let args = vec![Expr::value(Value::Decimal(t.clone())), Expr::value(value(y.clone(), list))];
+        let call = Expr::apply(Expr::symbol(f), args);
+        let result = self.eval_at(call.root())?;
+        let result = result.into_value().map_err(|e| eyre!("`{f}` should give a number, found `{e}`"))?;
+        Ok(self.state(result, Some(y.len()), f)?.0)
+    }
+
+    /// The numbers in a number or a list of them, and whether it was a list.
+    fn state(&self, v: Value, len: Option<usize>, what: &str) -> color_eyre::Result<(State, bool)> {
+        let (items, list) = match v {
+            Value::List(items) => (items, true),
+            v => (vec![v], false),
+        };
+        if len.is_some_and(|len| len != items.len()) {
+            bail!("`{what}` should give {} numbers, one for each in `y0`", len.unwrap());
+        }
+        Ok((items.iter().map(|v| self.real(v, what)).collect::<Result<_, _>>()?, list))
+    }
+
+    fn real(&self, v: &Value, what: &str) -> color_eyre::Result<Float> {
+        match v {
+            Value::Exact(_) | Value::Decimal(_) => Ok(Float::with_val(self.precision(), self.to_float(v.clone()))),
+            other => bail!("`{NAME}` expected a real number for `{what}`, found {}", other.kind()),
+        }
+    }
+}
+
+fn fraction(precision: u32, (n, d): Fraction) -> Float {
+    Float::with_val(precision, n) / d
+}
+
+/// `y + h*(a[0]*k[0] + a[1]*k[1] + ...)` with the Fehlberg weights `a`.
+fn weighted(y: &State, h: &Float, a: &[Fraction], k: &[State]) -> State {
+    let mut result = y.clone();
+    for (&a, k) in a.iter().zip(k) {
+        let a = Float::with_val(h.prec(), h * fraction(h.prec(), a));
+        for (r, k) in result.iter_mut().zip(k) {
+            *r += Float::with_val(h.prec(), &a * k);
+        }
+    }
+    result
+}
+
+/// `y + h*(a*k + ...)` with small integer weights.
+fn combine(y: &State, h: &Float, terms: &[(u32, &State)]) -> State {
+    let mut result = y.clone();
+    for &(a, k) in terms {
+        for (r, k) in result.iter_mut().zip(k) {
+            *r += Float::with_val(h.prec(), h * k) * a;
+        }
+    }
+    result
+}
+
+fn value(y: State, list: bool) -> Value {
+    match list {
+        true => Value::List(y.into_iter().map(Value::Decimal).collect()),
+        false => Value::Decimal(y.into_iter().next().expect("a state has at least one number")),
+    }
+}
+
+#[test]
+fn test_odesolve() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    eval("f(t, y) = y").unwrap();
+    assert_eq!("2.7182818", eval("odesolve(f, 0, 1, 1)").unwrap());
+    let table = "[[0, 1.0000000], [0.50000000, 1.6484375], [1.0000000, 2.7173462]]";
+    assert_eq!(table, eval("odesolve(f, 0, 1, 1, 2)").unwrap());
+    // y'' = -y as a system, from y = 0 and y' = 1 to a quarter turn
+    eval("g(t, y) = [dot([0, 1], y), dot([-1, 0], y)]").unwrap();
+    assert!(eval("odesolve(g, 0, [0, 1], pi/2)").unwrap().starts_with("[1.0000000, "));
+    assert!(eval("odesolve(h, 0, 1, 1)").is_err());
+    assert!(eval("odesolve(g, 0, 1, 1)").is_err());
+    assert!(eval("odesolve(f, 0, 1, 1, 0)").is_err());
+
+    // more digits than RKF45 could reach in a reasonable number of steps
+    let mut e = Evaluator::default();
+    e.settings_mut().set("precision", "130").unwrap();
+    e.settings_mut().set("digits", "30").unwrap();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    eval("f(t, y) = y").unwrap();
+    assert_eq!("2.71828182845904523536028747135", eval("odesolve(f, 0, 1, 1)").unwrap());
+    eval("g(t, y) = [dot([0, 1], y), dot([-1, 0], y)]").unwrap();
+    assert!(eval("odesolve(g, 0, [0, 1], pi/2)").unwrap().starts_with("[1.00000000000000000000000000000, "));
+}
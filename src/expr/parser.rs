@@ -0,0 +1,516 @@
+//! Turns the tokens from the lexer into an [`Expr`].
+
+use std::fmt;
+
+use chumsky::error::{RichPattern, RichReason};
+use chumsky::input::{Input, MappedInput, Stream, ValueInput};
+use chumsky::prelude::*;
+use rug::{Complete, Float, Integer};
+
+use super::lexer::{self, Span, Token, SEPARATORS};
+use super::{rearrange, units, Annotation, Evaluator, Expr, Literal, Node, Value};
+use crate::settings::CaretOp;
+
+type Extra<'src> = extra::Err<Rich<'src, Token>>;
+
+/// The tokens of a line, owned by the input so that one parser works for every line.
+type Tokens = MappedInput<
+    'static,
+    Token,
+    SimpleSpan,
+    Stream<std::vec::IntoIter<(Token, SimpleSpan)>>,
+    fn((Token, SimpleSpan)) -> (Token, SimpleSpan),
+>;
+
+type StatementsParser = Boxed<'static, 'static, Tokens, Vec<Option<Expr>>, Extra<'static>>;
+
+thread_local! {
+    static POW_PARSER: StatementsParser = statements_parser(CaretOp::Pow).boxed();
+    static XOR_PARSER: StatementsParser = statements_parser(CaretOp::Xor).boxed();
+}
+
+/// A syntax error and where in the line it is.
+#[derive(Debug)]
+pub struct ParseError {
+    pub span: Span,
+    pub message: String,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    Syntax,
+    /// The line is longer or nested deeper than `:set max-input` and
+    /// `:set max-depth` allow, which says nothing about whether it is valid.
+    TooComplex,
+}
+
+impl ParseError {
+    pub fn new(span: Span, message: impl Into<String>) -> ParseError {
+        ParseError { span, message: message.into(), kind: ParseErrorKind::Syntax }
+    }
+
+    fn too_complex(span: Span, message: impl fmt::Display) -> ParseError {
+        ParseError { span, message: format!("input too complex: {message}"), kind: ParseErrorKind::TooComplex }
+    }
+
+    /// The message followed by the line with the error marked. Lines that
+    /// are too complex are likely too long to repeat, so they aren't.
+    pub fn render(&self, line: &str) -> String {
+        if self.kind == ParseErrorKind::TooComplex {
+            return self.message.clone();
+        }
+        let start = line[..self.span.start.min(line.len())].chars().count();
+        let len = line.get(self.span.clone()).map_or(1, |s| s.chars().count().max(1));
+        format!("{}\n  {line}\n  {}{}", self.message, " ".repeat(start), "^".repeat(len))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+fn describe(p: &RichPattern<'_, Token>) -> String {
+    match p {
+        RichPattern::Token(t) => format!("`{}`", &**t),
+        RichPattern::Label(l) => l.to_string(),
+        RichPattern::Identifier(i) => format!("`{i}`"),
+        RichPattern::Any => "anything".into(),
+        RichPattern::EndOfInput => "end of input".into(),
+        _ => "something else".into(),
+    }
+}
+
+impl From<Rich<'_, Token>> for ParseError {
+    fn from(e: Rich<'_, Token>) -> ParseError {
+        let message = match e.reason() {
+            RichReason::Custom(msg) => msg.clone(),
+            RichReason::ExpectedFound { .. } => {
+                let found = match e.found() {
+                    Some(t) => format!("unexpected token `{t}`"),
+                    None => "unexpected end of input".into(),
+                };
+                let mut expected: Vec<_> = e.expected().map(describe).collect();
+                expected.sort();
+                expected.dedup();
+                match &expected[..] {
+                    [] => found,
+                    [one] => format!("{found}, expected {one}"),
+                    many => format!("{found}, expected one of {}", many.join(", ")),
+                }
+            }
+        };
+        ParseError::new(e.span().into_range(), message)
+    }
+}
+
+/// The first token that nests more than `max` deep, in brackets or in a
+/// chain of powers like `2^2^2`, which the parser recurses into.
+fn too_deep(tokens: &[(Token, Span)], max: usize) -> Option<Span> {
+    // the powers in the current chain, for each bracket that is open
+    let mut chains = vec![0];
+    let mut depth = 0;
+    let mut after_operand = false;
+    for (token, span) in tokens {
+        match *token {
+            Token::Op("(" | "[" | "{") => {
+                chains.push(0);
+                depth += 1;
+            }
+            Token::Op(")" | "]" | "}") if chains.len() > 1 => depth -= 1 + chains.pop().unwrap(),
+            Token::Op("^" | "**") => {
+                *chains.last_mut().unwrap() += 1;
+                depth += 1;
+            }
+            // `2^-2^-2` is still one chain, but `2^2-2` isn't
+            Token::Op("-") if !after_operand => {}
+            Token::Op(_) => depth -= std::mem::take(chains.last_mut().unwrap()),
+            _ => {}
+        }
+        after_operand = !matches!(token, Token::Op(op) if !matches!(*op, ")" | "]" | "}"));
+        if depth > max {
+            return Some(span.clone());
+        }
+    }
+    None
+}
+
+/// Lexes and parses a line of `;` separated statements. A statement with a
+/// syntax error is left out and its error reported, so that the others can still be evaluated.
+/// With the `fold` setting, constant parts of the statements are already reduced.
+///
+/// Lines over the `max-input` and `max-depth` limits are refused with a
+/// [`ParseErrorKind::TooComplex`] error before any of it is parsed.
+pub fn parse(e: &Evaluator, line: &str) -> (Vec<Expr>, Vec<ParseError>) {
+    let max_depth = e.settings().max_depth;
+    if let Some(max) = e.settings().max_input.filter(|&max| line.len() > max) {
+        let message = format!("the line is {} bytes long, at most {max} are allowed", line.len());
+        return (vec![], vec![ParseError::too_complex(max..line.len(), message)]);
+    }
+    let tokens = match lexer::lex(line) {
+        Ok(tokens) => tokens,
+        Err(err) => return (vec![], vec![err]),
+    };
+    if let Some(span) = too_deep(&tokens, max_depth) {
+        let message = format!("brackets and powers can be nested at most {max_depth} deep");
+        return (vec![], vec![ParseError::too_complex(span, message)]);
+    }
+    let tokens: Vec<_> = tokens.into_iter().map(|(t, span)| (t, SimpleSpan::from(span))).collect();
+    let eoi = SimpleSpan::from(line.len()..line.len());
+    let tokens: Tokens = Stream::from_iter(tokens).map(eoi, |t| t);
+    let parse = |parser: &StatementsParser| {
+        let (statements, errors) = parser.parse(tokens).into_output_errors();
+        (statements, errors.into_iter().map(ParseError::from).collect())
+    };
+    let (statements, mut errors): (_, Vec<_>) = match e.settings().caret {
+        CaretOp::Pow => POW_PARSER.with(parse),
+        CaretOp::Xor => XOR_PARSER.with(parse),
+    };
+    // like `-(-(-...))`, which nests without brackets, though `1-2-3-...` is a chain rather than nesting
+    let (statements, deep): (Vec<_>, Vec<_>) =
+        statements.into_iter().flatten().flatten().partition(|s| s.nesting() <= max_depth);
+    if !deep.is_empty() {
+        let message = format!("the expression is nested more than {max_depth} deep");
+        errors.push(ParseError::too_complex(0..line.len(), message));
+    }
+    let statements = statements.into_iter();
+    (if e.settings().fold { statements.map(|s| s.fold(e)).collect() } else { statements.collect() }, errors)
+}
+
+/// Statements, `None` for the ones that had to be skipped because of an error.
+fn statements_parser<'src, I>(caret: CaretOp) -> impl Parser<'src, I, Vec<Option<Expr>>, Extra<'src>>
+where
+    I: ValueInput<'src, Token = Token, Span = SimpleSpan>,
+{
+    let semicolon = just(Token::Op(";"));
+    let op = |op: &'static str| just(Token::Op(op));
+    let ident = select! { Token::Ident(s) => s };
+    // names that start a statement, but are names anywhere else
+    let keyword = |word: &'static str| select! { Token::Ident(s) if s == word => () };
+    let expr = expr_parser(caret);
+    // `3 furlong in m`, allowed wherever a whole statement or the value of one is
+    let converted = expr.clone().then(keyword("in").ignore_then(unit_parser(caret)).or_not()).map(|(value, unit)| {
+        match unit {
+            Some(unit) => Expr::convert(value, unit),
+            None => value,
+        }
+    });
+    // `0` in `f(0) = 1`, a case of `f` for just that argument
+    let pattern = op("-")
+        .or_not()
+        .then(select! { Token::Number(n) if n.bytes().all(|b| b.is_ascii_digit()) => n })
+        .map(|(minus, n)| if minus.is_some() { format!("-{n}") } else { n });
+    // `f(x, y) = expr`, or `memo f(x, y) = expr`
+    let memo = keyword("memo").or_not().map(|memo| memo.is_some());
+    let define = memo
+        .then(ident)
+        .then(ident.or(pattern).separated_by(op(",")).allow_trailing().collect().delimited_by(op("("), op(")")))
+        .then_ignore(op("="))
+        .then(converted.clone())
+        .map(|(((memo, name), params), body)| Expr::define(name, params, body, memo));
+    // `unit furlong = 201.168 m`
+    let define_unit = keyword("unit")
+        .ignore_then(ident)
+        .then_ignore(op("="))
+        .then(expr.clone())
+        .map(|(name, value)| Expr::define_unit(name, value));
+    // `: int` in `x: int = expr`
+    let annotation = op(":").ignore_then(ident.map(|name| Annotation::parse(&name)));
+    // `x = expr`
+    let assign = ident
+        .then(annotation.or_not())
+        .then_ignore(op("="))
+        .then(converted.clone())
+        .map(|((name, annotation), value)| Expr::assign(name, annotation, value));
+    // `q, r = expr` or `(q, r) = expr`
+    let names = ident.separated_by(op(",")).at_least(2).collect::<Vec<_>>();
+    let unpack = names
+        .clone()
+        .delimited_by(op("("), op(")"))
+        .or(names)
+        .then_ignore(op("="))
+        .then(converted.clone())
+        .map(|(names, value)| Expr::unpack(names, value));
+    let statement = recursive(|statement| {
+        let block = statement
+            .separated_by(semicolon.clone())
+            .allow_trailing()
+            .collect()
+            .delimited_by(op("{"), op("}"))
+            .map(Expr::block);
+        // `for k in 1..10 { ... }`, where the range is the builtin `range(1, 10)`
+        let range = expr.clone().then(op("..").ignore_then(expr.clone()).or_not()).map(|(from, to)| match to {
+            Some(to) => Expr::apply(Expr::symbol("range"), vec![from, to]),
+            None => from,
+        });
+        let for_loop = keyword("for")
+            .ignore_then(ident)
+            .then_ignore(keyword("in"))
+            .then(range)
+            .then(block.clone())
+            .map(|((var, list), body)| Expr::for_loop(var, list, body));
+        // `while k < 10 { ... }`
+        let while_loop = keyword("while").ignore_then(expr.clone()).then(block).map(|(condition, body)| {
+            Expr::while_loop(condition, body)
+        });
+        let definition = define.clone().or(define_unit.clone()).or(assign.clone()).or(unpack.clone());
+        for_loop.or(while_loop).or(definition).or(converted.clone())
+    });
+    let statement = statement
+        .map(Some)
+        // the whole statement must be used, `4 5` is an error rather than `4`
+        .then_ignore(semicolon.clone().ignored().or(end()).rewind())
+        .recover_with(via_parser(any().and_is(semicolon.clone().not()).repeated().at_least(1).map(|()| None)));
+    statement.separated_by(semicolon).allow_trailing().collect().then_ignore(end())
+}
+
+/// A unit, like `m/s^2`: names raised to integer powers, multiplied and divided.
+fn unit_parser<'src, I>(caret: CaretOp) -> impl Parser<'src, I, Expr, Extra<'src>> + Clone
+where
+    I: ValueInput<'src, Token = Token, Span = SimpleSpan>,
+{
+    let op = |op: &'static str| just(Token::Op(op));
+    let pow_op = match caret {
+        CaretOp::Pow => op("^").or(op("**")).boxed(),
+        CaretOp::Xor => op("**").boxed(),
+    };
+    // not the `in` of `let`, nor a function that is called
+    let name = select! { Token::Ident(s) if !SEPARATORS.contains(&s.as_str()) => s }.then_ignore(op("(").not());
+    let power = op("-")
+        .or_not()
+        .then(select! { Token::Number(n) if n.bytes().all(|b| b.is_ascii_digit()) => n })
+        .try_map(|(minus, n), span| {
+            let n = Integer::parse(&n).map_err(|err| Rich::custom(span, err.to_string()))?.complete();
+            Ok(Value::Exact(if minus.is_some() { -n } else { n }.into()))
+        });
+    let factor = name.then(pow_op.ignore_then(power).or_not()).map(|(name, power)| match power {
+        Some(power) => Expr::binary(Expr::symbol(name), Expr::value(power), Node::Pow),
+        None => Expr::symbol(name),
+    });
+    factor.clone().foldl(op("*").to(true).or(op("/").to(false)).then(factor).repeated(), |a, (times, b)| {
+        if times { Expr::product(vec![a, b]) } else { Expr::binary(a, b, Node::Div) }
+    })
+}
+
+pub fn expr_parser<'src, I>(caret: CaretOp) -> impl Parser<'src, I, Expr, Extra<'src>> + Clone
+where
+    I: ValueInput<'src, Token = Token, Span = SimpleSpan>,
+{
+    recursive(|expr| {
+        let number = select! {
+            Token::Number(n) => (n, false),
+            Token::Imaginary(n) => (n, true),
+        }
+        // integers are exact, anything that depends on the precision is converted when evaluating
+        .try_map(|(n, imaginary), span| {
+            if imaginary || n.contains(['.', 'e', 'E']) {
+                let text = n.trim_end_matches('.').to_string();
+                Float::parse(&text).map_err(|err| Rich::custom(span, err.to_string()))?;
+                Ok(Expr::literal(Literal { text, imaginary }))
+            } else {
+                let n = Integer::parse(&n).map_err(|err| Rich::custom(span, err.to_string()))?.complete();
+                Ok(Expr::value(Value::Exact(n.into())))
+            }
+        });
+
+        let sexagesimal = select! { Token::Sexagesimal(s) => s }.try_map(|s, span| {
+            units::sexagesimal(&s).map(Expr::value).map_err(|err| Rich::custom(span, err.to_string()))
+        });
+
+        let string = select! { Token::Str(s) => Expr::value(Value::Str(s)) };
+        let op = |op: &'static str| just(Token::Op(op));
+
+        let list = expr
+            .clone()
+            .separated_by(op(","))
+            .allow_trailing()
+            .collect()
+            .delimited_by(op("["), op("]"))
+            .map(Expr::list);
+
+        // `(a)` is just grouping, while `(a, b)` is a tuple.
+        let parens = expr
+            .clone()
+            .separated_by(op(","))
+            .at_least(1)
+            .collect::<Vec<_>>()
+            .delimited_by(op("("), op(")"))
+            .map(|mut items| if items.len() == 1 { items.pop().unwrap() } else { Expr::tuple(items) });
+
+        // `let t = x + 1 in t*t`, where `t` is only bound in the body
+        let keyword = |word: &'static str| select! { Token::Ident(s) if s == word => () };
+        let let_in = keyword("let")
+            .ignore_then(select! { Token::Ident(s) => s })
+            .then_ignore(op("="))
+            .then(expr.clone())
+            .then_ignore(keyword("in"))
+            .then(expr.clone())
+            .map(|((name, value), body)| Expr::let_in(name, value, body));
+
+        let atom = number
+            .or(sexagesimal)
+            .or(string)
+            .or(parens)
+            .or(list)
+            .or(let_in)
+            .or(select! { Token::Ident(s) => Expr::symbol(s) })
+            .labelled("a value");
+
+        // the atom is only parsed once whether or not it is called, trying a call
+        // first and then the atom alone would take exponential time in `((((1))))`
+        let func = atom
+            .then(
+                expr.clone()
+                    .separated_by(op(","))
+                    .allow_trailing() // Foo is Rust-like, so allow trailing commas to appear in arg lists
+                    .collect()
+                    .delimited_by(op("("), op(")"))
+                    .or_not(),
+            )
+            .map(|(f, args)| match args {
+                Some(args) => Expr::apply(f, args),
+                None => f,
+            });
+
+        // `rearrange(v = u + a*t, t)` may write the equation with `=`, where
+        // it can't be an assignment
+        let equation = expr.clone().then_ignore(op("=")).then(expr.clone());
+        let rearrange = select! { Token::Ident(s) if s == rearrange::NAME => Expr::symbol(s) }
+            .then(
+                equation
+                    .then(op(",").ignore_then(expr).repeated().collect::<Vec<_>>())
+                    .delimited_by(op("("), op(")")),
+            )
+            .map(|(f, ((a, b), rest))| {
+                let equation = Expr::apply(Expr::symbol("eq"), vec![a, b]);
+                Expr::apply(f, std::iter::once(equation).chain(rest).collect())
+            });
+        let func = rearrange.or(func);
+
+        // `15%` is `15/100`
+        let calls = func.foldl(op("%").repeated(), |x, _| {
+            Expr::binary(x, Expr::value(Value::Exact(100.into())), Node::Div)
+        });
+
+        // powers are right associative and bind tighter than negation on their left,
+        // `-2^2` is `-(2^2)`, but allow one on their right, `2^-1`.
+        let pow_op = match caret {
+            CaretOp::Pow => op("^").or(op("**")).boxed(),
+            CaretOp::Xor => op("**").boxed(),
+        };
+        let unit = unit_parser(caret);
+        let unary = recursive(|unary| {
+            let power = calls
+                .clone()
+                .then(pow_op.ignore_then(unary).or_not())
+                .map(|(base, exp)| match exp {
+                    Some(exp) => Expr::binary(base, exp, Node::Pow),
+                    None => base,
+                })
+                // `9.81 m/s^2`, a factor followed by a unit
+                .then(unit.or_not())
+                .map(|(magnitude, unit)| match unit {
+                    Some(unit) => Expr::quantity(magnitude, unit),
+                    None => magnitude,
+                });
+            op("-").repeated().foldr(power, |_op, rhs| -rhs)
+        });
+
+        // sums and products are flattened as they are built, `a*b*c` is one product of three
+        type BinOp = fn(Expr, Expr) -> Expr;
+        let product = unary.clone().foldl(
+            op("*")
+                .to((|a, b| Expr::product(vec![a, b])) as BinOp)
+                .or(op("/").to((|a, b| Expr::binary(a, b, Node::Div)) as BinOp))
+                .then(unary)
+                .repeated(),
+            |lhs, (op, rhs)| op(lhs, rhs),
+        );
+
+        // `3/4 of x` and `20% of 50% of 1000` are products, binding looser than
+        // `*` and `/` so that the whole fraction is taken of the rest
+        let of = product.clone().then(op("of").ignore_then(product).repeated().collect::<Vec<_>>()).map(
+            |(first, rest)| match rest.is_empty() {
+                true => first,
+                false => Expr::product(std::iter::once(first).chain(rest).collect()),
+            },
+        );
+
+        let sum = of.clone().foldl(
+            op("+")
+                .to((|a, b| Expr::sum(vec![a, b])) as BinOp)
+                .or(op("-").to((|a, b| Expr::binary(a, b, Node::Sub)) as BinOp))
+                .then(of)
+                .repeated(),
+            |lhs, (op, rhs)| op(lhs, rhs),
+        );
+
+        let sum = match caret {
+            CaretOp::Pow => sum.boxed(),
+            // `a ^ b` is the builtin `xor(a, b)`, so that printing it doesn't depend on the setting
+            CaretOp::Xor => sum
+                .clone()
+                .foldl(op("^").ignore_then(sum).repeated(), |a, b| Expr::apply(Expr::symbol("xor"), vec![a, b]))
+                .boxed(),
+        };
+
+        // `a < b` is the builtin `lt(a, b)`, and so on, which don't chain
+        let comparison = choice((
+            op("<").to("lt"),
+            op("<=").to("le"),
+            op(">").to("gt"),
+            op(">=").to("ge"),
+            op("==").to("eq"),
+            op("!=").to("ne"),
+        ));
+        sum.clone().then(comparison.then(sum).or_not()).map(|(a, comparison)| match comparison {
+            Some((name, b)) => Expr::apply(Expr::symbol(name), vec![a, b]),
+            None => a,
+        })
+    })
+}
+
+#[test]
+fn test_recovery() {
+    let e = Evaluator::default();
+    let (statements, errors) = parse(&e, "2+3; 4*; (5; 6");
+    assert_eq!(2, statements.len());
+    assert_eq!(vec![7..8, 11..12], errors.iter().map(|e| e.span.clone()).collect::<Vec<_>>());
+    let mut e = Evaluator::default();
+    e.settings_mut().set("fold", "off").unwrap();
+    let (statements, _) = parse(&e, "1 + 20% of 50%^2 of x/4");
+    assert_eq!("1+20/100*(50/100)^2*x/4", statements[0].to_string());
+    e.settings_mut().set("caret", "xor").unwrap();
+    let (statements, _) = parse(&e, "x ^ y**2 + 1 ^ z");
+    assert_eq!("xor(xor(x,y**2+1),z)", statements[0].to_source(CaretOp::Xor));
+}
+
+#[test]
+fn test_too_deep() {
+    use super::MAX_DEPTH;
+    let e = Evaluator::default();
+    let deep = format!("{}1{}", "(".repeat(MAX_DEPTH + 1), ")".repeat(MAX_DEPTH + 1));
+    assert_eq!(vec![MAX_DEPTH..MAX_DEPTH + 1], parse(&e, &deep).1.iter().map(|e| e.span.clone()).collect::<Vec<_>>());
+    // the powers of a sum don't nest
+    assert!(parse(&e, &vec!["x^2"; MAX_DEPTH + 1].join("+")).1.is_empty());
+    assert!(parse(&e, &vec!["2^2^2"; 260].join("-")).1.is_empty());
+    assert!(parse(&e, &vec!["2^(x)^2"; 260].join("-")).1.is_empty());
+    assert!(!parse(&e, &format!("{}2", "2^-".repeat(MAX_DEPTH + 1))).1.is_empty());
+    // chains of `-` and `/` are as flat as sums, however long
+    assert!(parse(&e, &format!("1{}", "-1".repeat(MAX_DEPTH))).1.is_empty());
+    assert!(parse(&e, &format!("1{}", "/x-1".repeat(MAX_DEPTH))).1.is_empty());
+    let (statements, errors) = parse(&e, &format!("{}1; 2", "-".repeat(MAX_DEPTH + 1)));
+    assert_eq!((1, 1), (statements.len(), errors.len()));
+    assert_eq!(ParseErrorKind::TooComplex, errors[0].kind);
+    let mut e = Evaluator::default();
+    e.settings_mut().set("max-depth", "3").unwrap();
+    e.settings_mut().set("max-input", "20").unwrap();
+    assert!(parse(&e, "((1))^2^2").1.is_empty());
+    let message = "input too complex: brackets and powers can be nested at most 3 deep";
+    assert_eq!(message, parse(&e, "((1^2^2))").1[0].message);
+    let (statements, errors) = parse(&e, &"1+".repeat(20));
+    assert_eq!((0, 20..40, ParseErrorKind::TooComplex), (statements.len(), errors[0].span.clone(), errors[0].kind));
+    assert!(e.settings_mut().set("max-depth", "501").is_err());
+}
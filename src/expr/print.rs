@@ -1,6 +1,16 @@
 use std::fmt::{self, Write};
 
-use super::{Expr, PrecedenceContext, Value};
+use rug::float::Round;
+use rug::{Float, Rational};
+
+use super::{money, Evaluator, Expr, ExprRef, Node, PrecedenceContext, Value};
+use crate::render::{Color, Theme};
+use crate::settings::{CaretOp, ComplexForm, RenderMode, Settings};
+
+pub mod columns;
+pub mod digits;
+mod layout;
+mod typst;
 
 /* pub fn print_expr_to_string(x: &Expr) -> String {
     let mut p = Printer::new_string();
@@ -10,17 +20,121 @@ use super::{Expr, PrecedenceContext, Value};
 
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        Printer {
-            writer: f,
-            round_digits: 8,
+        Printer::new(f, &Settings::default()).print(self)
+    }
+}
+
+impl Expr {
+    /// Prints the expression so that parsing it with `caret` gives back the
+    /// same value, exact values in full and decimals with all of their digits.
+    pub fn to_source(&self, caret: CaretOp) -> String {
+        let settings = Settings {
+            round_trip: true,
+            complex_form: ComplexForm::Rectangular,
+            exact_digits: None,
+            caret,
+            ..Settings::default()
+        };
+        self.print_with(&settings)
+    }
+
+    /// Prints the expression with `settings` rather than the evaluator's.
+    pub(super) fn print_with(&self, settings: &Settings) -> String {
+        let mut p = Printer::new(String::new(), settings);
+        p.print(self).expect("String format does not have errors");
+        p.writer
+    }
+}
+
+/// Displays an expression using the evaluator's settings.
+pub struct Displayed<'a> {
+    expr: &'a Expr,
+    settings: &'a Settings,
+    colored: bool,
+}
+
+impl Displayed<'_> {
+    /// Colors the output with the current theme, if colors are turned on.
+    pub fn colored(self) -> Self {
+        Displayed { colored: true, ..self }
+    }
+}
+
+impl fmt::Display for Displayed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.settings.render == RenderMode::TwoD {
+            let layout = layout::Layout::new(self.settings);
+            let block = layout.expr(self.expr);
+            let approx = match self.expr.as_value() {
+                Some(Value::Exact(r)) if self.settings.show_approx && self.settings.money.is_none() && *r.denom() != 1 => {
+                    layout.approx(r)
+                }
+                _ => None,
+            };
+            return match approx {
+                Some(approx) => write!(f, "{}", layout.with_approx(block, approx)),
+                None => write!(f, "{block}"),
+            };
+        }
+        let mut printer = Printer::new(f, self.settings);
+        if self.colored {
+            printer.theme = self.settings.theme().copied();
+        }
+        printer.print(self.expr)?;
+        match self.expr.as_value() {
+            Some(Value::Exact(r))
+                if self.settings.show_approx
+                    && self.settings.money.is_none()
+                    && *r.denom() != 1
+                    && !printer.is_approximated(r) =>
+            {
+                printer.writer.write_str(" ≈ ")?;
+                printer.print_approx(r)
+            }
+            _ => Ok(()),
         }
-        .print(self)
     }
 }
 
-pub struct Printer<W: Write> {
+impl Evaluator {
+    pub fn display<'a>(&'a self, expr: &'a Expr) -> Displayed<'a> {
+        expr.display_with(&self.settings)
+    }
+}
+
+impl Expr {
+    /// Displays the expression with other settings than the evaluator's.
+    pub fn display_with<'a>(&'a self, settings: &'a Settings) -> Displayed<'a> {
+        Displayed { expr: self, settings, colored: false }
+    }
+}
+
+pub struct Printer<'a, W: Write> {
     writer: W,
-    round_digits: usize,
+    settings: &'a Settings,
+    /// Colors to print with, `None` for plain output.
+    theme: Option<Theme>,
+}
+
+/// The operator of a comparison like `eq(a, b)`, which is printed as `a == b`
+/// since that is what it is parsed from.
+pub(super) fn comparison(x: ExprRef) -> Option<&'static str> {
+    let Node::Apply(f, args) = x.node() else {
+        return None;
+    };
+    let Node::Symbol(f) = x.get(*f).node() else {
+        return None;
+    };
+    let op = match f.as_str() {
+        "lt" => "<",
+        "le" => "<=",
+        "gt" => ">",
+        "ge" => ">=",
+        "eq" => "==",
+        "ne" => "!=",
+        _ => return None,
+    };
+    (args.len() == 2).then_some(op)
 }
 
 /*
@@ -61,9 +175,53 @@ fn can_combine_with_prev(x: &Expr) -> bool {
 }
 */
 
-impl<W: Write> Printer<W> {
+/// Roughly how many decimal digits the larger of the numerator and denominator has.
+fn decimal_digits(r: &Rational) -> usize {
+    let bits = r.numer().significant_bits().max(r.denom().significant_bits());
+    (bits as f64 * std::f64::consts::LOG10_2).ceil() as usize
+}
+
+/// The fewest significant digits that `dec` can be printed with so that reading
+/// them at its precision gives back exactly `dec`.
+fn round_trip_digits(dec: &Float, round: Round) -> usize {
+    if !dec.is_normal() {
+        return 1;
+    }
+    let reads_back = |digits| {
+        let text = dec.to_string_radix(10, Some(digits));
+        Float::parse(text).is_ok_and(|f| Float::with_val_round(dec.prec(), f, round).0 == *dec)
+    };
+    // this many digits always read back, fewer do if the bits end in zeros
+    let mut high = (dec.prec() as f64 * std::f64::consts::LOG10_2).ceil() as usize + 1;
+    let mut low = 1;
+    while low < high {
+        let mid = (low + high) / 2;
+        if reads_back(mid) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    high
+}
+
+impl<W: Write> Write for Printer<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.writer.write_str(s)
+    }
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    pub fn new(writer: W, settings: &'a Settings) -> Self {
+        Printer { writer, settings, theme: None }
+    }
+
     pub fn print(&mut self, x: &Expr) -> fmt::Result {
-        self.print_with_precedence(x, PrecedenceContext::NoPrecedence)
+        self.print_with_precedence(x.root(), PrecedenceContext::NoPrecedence)
+    }
+
+    fn painted(&mut self, color: Color, f: impl FnOnce(&mut Self) -> fmt::Result) -> fmt::Result {
+        color.paint(self, |this| f(this))
     }
 
     pub fn maybe_enter_parens(
@@ -167,101 +325,367 @@ impl<W: Write> Printer<W> {
         )
     } */
 
-    pub fn print_with_precedence(&mut self, x: &Expr, p: PrecedenceContext) -> fmt::Result {
+    pub fn print_with_precedence(&mut self, x: ExprRef, p: PrecedenceContext) -> fmt::Result {
+        // a chain like `1-2-3-...` recurses once per operand
+        super::grow_stack(|| self.print_node(x, p))
+    }
+
+    fn print_node(&mut self, x: ExprRef, p: PrecedenceContext) -> fmt::Result {
         let new_ctxt = x.precedence();
-        match x {
+        match x.node() {
             /*Expr::Factorial(x) => {
                 self.print_with_precedence(x, new_ctxt)?;
                 write!(self.writer, "!")?;
             }*/
-            Expr::Value(x) => match x {
-                Value::Decimal(dec) => {
-                    let (sign, mut string, exp) = dec.to_sign_string_exp(10, Some(self.round_digits));
-                    let sign = if sign {
-                        ""
-                    } else {
-                        "-"
-                    };
-                    let exp = exp.map(|x| x - 1);
-                    let (prefix, string, suffix, suffix2) = match exp {
-                        Some(exp @ ..=-4) => {
-                            let rest = string.split_off(1);
-                            (string, ".".into(), rest, format!("e{exp}"))
-                        }
-                        Some(exp @ -5..=-1) => {
-                            (format!("0.{}", "0".repeat((-exp - 1) as usize)), string, String::new(), String::new())
-                        }
-                        Some(0) => {
-                            let rest = string.split_off(1);
-                            (string, ".".into(), rest, String::new())
-                        }
-                        // TODO fix below, that doesn't work!
-                        Some(exp @ 1..) => {
-                            (String::new(), string, "0".repeat(exp as usize), String::new())
-                        }
-                        None => (String::new(), string, String::new(), String::new()),
-                    };
-                    
-                    write!(self.writer, "{sign}{prefix}{string}{suffix}{suffix2}")?;
-                }
-                Value::Exact(e) => {
-                    write!(self.writer, "{e}")?;
-                }
-            },
-            Expr::Symbol(x) => {
-                self.writer.write_str(x)?;
+            Node::Value(x) => {
+                let color = self.theme.map_or(Color::Default, |t| t.value);
+                self.painted(color, |this| this.print_value(x))?;
+            }
+            Node::Literal(l) => {
+                let color = self.theme.map_or(Color::Default, |t| t.value);
+                let i = if l.imaginary { "i" } else { "" };
+                self.painted(color, |this| write!(this.writer, "{}{i}", l.text))?;
             }
-            Expr::Neg(x) => {
+            Node::Symbol(x) => {
+                let color = self.theme.map_or(Color::Default, |t| t.symbol);
+                self.painted(color, |this| this.writer.write_str(x))?;
+            }
+            &Node::Neg(e) => {
                 write!(self.writer, "-")?;
-                self.print_with_precedence(x, new_ctxt)?;
+                self.print_with_precedence(x.get(e), new_ctxt)?;
             }
-            Expr::Add(exprs) | Expr::Mul(exprs) | Expr::Div(exprs) | Expr::Sub(exprs) => {
-                let s = match x {
-                    Expr::Add(_) => "+",
-                    Expr::Mul(_) => "*",
-                    Expr::Div(_) => "/",
-                    Expr::Sub(_) => "-",
-                    _ => unreachable!(),
-                };
-
+            Node::Add(items) | Node::Mul(items) => {
+                let s = if let Node::Add(_) = x.node() { "+" } else { "*" };
+                self.maybe_enter_parens(
+                    |this| {
+                        for (n, &item) in items.iter().enumerate() {
+                            if n != 0 {
+                                this.writer.write_str(s)?;
+                            }
+                            this.print_with_precedence(x.get(item), new_ctxt)?;
+                        }
+                        Ok(())
+                    },
+                    new_ctxt < p,
+                )?;
+            }
+            &(Node::Div(a, b) | Node::Sub(a, b)) => {
+                let s = if let Node::Div(..) = x.node() { "/" } else { "-" };
                 self.maybe_enter_parens(
                     |this| {
-                        this.print_with_precedence(&exprs.0, new_ctxt)?;
+                        this.print_with_precedence(x.get(a), new_ctxt)?;
                         this.writer.write_str(s)?;
-                        this.print_with_precedence(&exprs.1, new_ctxt)?;
+                        this.print_with_precedence(x.get(b), new_ctxt)?;
                         Ok(())
                     },
                     new_ctxt < p,
                 )?;
             }
-            /* Expr::Pow(x) => {
-                let (base, exp) = &**x;
-                let base_ctxt = base.precedence_ctxt();
+            &Node::Pow(base, exp) => {
+                let (base, exp) = (x.get(base), x.get(exp));
+                // `-2^2` would be read back as `-(2^2)`
+                let negative = match base.node() {
+                    Node::Value(Value::Exact(r)) => r.is_negative(),
+                    Node::Value(Value::Decimal(d)) => d.is_sign_negative(),
+                    _ => false,
+                };
+                let base_ctxt = base.precedence();
                 self.maybe_enter_parens(
                     |this| this.print_with_precedence(base, PrecedenceContext::NoPrecedence),
-                    base_ctxt != PrecedenceContext::NoPrecedence
-                        && base_ctxt < PrecedenceContext::Pow,
+                    negative || (PrecedenceContext::Sum..=PrecedenceContext::Neg).contains(&base_ctxt),
                 )?;
-                self.writer.write_char('^')?;
-                self.enter_parens(|this| {
-                    this.print_with_precedence(exp, PrecedenceContext::NoPrecedence)
-                })?;
-            } */
-            Expr::Apply(name, params) => {
-                write!(self.writer, "\"{name}\"(")?;
-                for (n, param) in params.iter().enumerate() {
+                // `^` may be xor, `**` is always a power
+                self.writer.write_str(if self.settings.caret == CaretOp::Xor { "**" } else { "^" })?;
+                let exp_ctxt = exp.precedence();
+                self.maybe_enter_parens(
+                    |this| this.print_with_precedence(exp, PrecedenceContext::NoPrecedence),
+                    exp_ctxt != PrecedenceContext::NoPrecedence && exp_ctxt < PrecedenceContext::Pow,
+                )?;
+            }
+            Node::Apply(_, args) if comparison(x).is_some() => {
+                let op = comparison(x).unwrap();
+                self.maybe_enter_parens(
+                    |this| {
+                        this.print_with_precedence(x.get(args[0]), PrecedenceContext::NoPrecedence)?;
+                        write!(this.writer, " {op} ")?;
+                        this.print_with_precedence(x.get(args[1]), PrecedenceContext::NoPrecedence)
+                    },
+                    p != PrecedenceContext::NoPrecedence,
+                )?;
+            }
+            Node::Apply(name, params) => {
+                self.print_with_precedence(x.get(*name), new_ctxt)?;
+                self.writer.write_char('(')?;
+                for (n, &param) in params.iter().enumerate() {
                     if n != 0 {
                         self.writer.write_char(',')?;
                     }
 
-                    self.print_with_precedence(param, PrecedenceContext::NoPrecedence)?;
+                    self.print_with_precedence(x.get(param), PrecedenceContext::NoPrecedence)?;
+                }
+                self.writer.write_char(')')?;
+            }
+            Node::List(items) => {
+                self.print_sequence(items, ('[', ']'), |this, &item| {
+                    this.print_with_precedence(x.get(item), PrecedenceContext::NoPrecedence)
+                })?;
+            }
+            Node::Tuple(items) => {
+                self.print_sequence(items, ('(', ')'), |this, &item| {
+                    this.print_with_precedence(x.get(item), PrecedenceContext::NoPrecedence)
+                })?;
+            }
+            Node::Assign(name, annotation, value) => {
+                match annotation {
+                    Some(annotation) => write!(self.writer, "{name}: {annotation} = ")?,
+                    None => write!(self.writer, "{name} = ")?,
+                }
+                self.print_with_precedence(x.get(*value), PrecedenceContext::NoPrecedence)?;
+            }
+            Node::Unpack(names, value) => {
+                write!(self.writer, "{} = ", names.join(", "))?;
+                self.print_with_precedence(x.get(*value), PrecedenceContext::NoPrecedence)?;
+            }
+            Node::Define(name, params, body, memo) => {
+                let memo = if *memo { "memo " } else { "" };
+                write!(self.writer, "{memo}{name}({}) = ", params.join(", "))?;
+                self.print_with_precedence(x.get(*body), PrecedenceContext::NoPrecedence)?;
+            }
+            &Node::Let(ref name, value, body) => {
+                self.maybe_enter_parens(
+                    |this| {
+                        write!(this.writer, "let {name} = ")?;
+                        this.print_with_precedence(x.get(value), PrecedenceContext::NoPrecedence)?;
+                        this.writer.write_str(" in ")?;
+                        this.print_with_precedence(x.get(body), PrecedenceContext::NoPrecedence)
+                    },
+                    p != PrecedenceContext::NoPrecedence,
+                )?;
+            }
+            &Node::DefineUnit(ref name, value) => {
+                write!(self.writer, "unit {name} = ")?;
+                self.print_with_precedence(x.get(value), PrecedenceContext::NoPrecedence)?;
+            }
+            &Node::Quantity(magnitude, unit) => {
+                self.maybe_enter_parens(
+                    |this| {
+                        // `(1/2) m`, since `1/2 m` is `1/(2 m)`
+                        let magnitude = x.get(magnitude);
+                        this.maybe_enter_parens(
+                            |this| this.print_with_precedence(magnitude, PrecedenceContext::NoPrecedence),
+                            magnitude.precedence() != PrecedenceContext::NoPrecedence,
+                        )?;
+                        this.writer.write_char(' ')?;
+                        this.print_with_precedence(x.get(unit), PrecedenceContext::NoPrecedence)
+                    },
+                    new_ctxt < p,
+                )?;
+            }
+            &Node::Convert(value, unit) => {
+                self.print_with_precedence(x.get(value), PrecedenceContext::NoPrecedence)?;
+                self.writer.write_str(" in ")?;
+                self.print_with_precedence(x.get(unit), PrecedenceContext::NoPrecedence)?;
+            }
+            Node::Block(statements) => {
+                self.writer.write_str("{ ")?;
+                for (n, &statement) in statements.iter().enumerate() {
+                    if n != 0 {
+                        self.writer.write_str("; ")?;
+                    }
+                    self.print_with_precedence(x.get(statement), PrecedenceContext::NoPrecedence)?;
+                }
+                self.writer.write_str(" }")?;
+            }
+            &Node::For(ref var, list, body) => {
+                write!(self.writer, "for {var} in ")?;
+                self.print_with_precedence(x.get(list), PrecedenceContext::NoPrecedence)?;
+                self.writer.write_char(' ')?;
+                self.print_with_precedence(x.get(body), PrecedenceContext::NoPrecedence)?;
+            }
+            &Node::While(condition, body) => {
+                self.writer.write_str("while ")?;
+                self.print_with_precedence(x.get(condition), PrecedenceContext::NoPrecedence)?;
+                self.writer.write_char(' ')?;
+                self.print_with_precedence(x.get(body), PrecedenceContext::NoPrecedence)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn print_value(&mut self, x: &Value) -> fmt::Result {
+        match x {
+            Value::Decimal(dec) => self.print_decimal(dec)?,
+            Value::Exact(e) => match self.settings.money {
+                Some(digits) => money::write(&mut self.writer, e, digits)?,
+                None if self.is_approximated(e) => {
+                    self.writer.write_str("(≈ ")?;
+                    self.print_approx(e)?;
+                    self.writer.write_char(')')?;
+                }
+                None => write!(self.writer, "{e}")?,
+            },
+            Value::Complex(c) => match self.settings.complex_form {
+                ComplexForm::Rectangular => {
+                    let (re, im) = (c.real(), c.imag());
+                    if !re.is_zero() || im.is_zero() {
+                        self.print_decimal(re)?;
+                    }
+                    if !im.is_zero() {
+                        if !re.is_zero() && im.is_sign_positive() {
+                            self.writer.write_char('+')?;
+                        }
+                        self.print_decimal(im)?;
+                        self.writer.write_char('i')?;
+                    }
+                }
+                ComplexForm::Polar => {
+                    let (abs, arg) = (Float::with_val(c.prec().0, c.abs_ref()), Float::with_val(c.prec().0, c.arg_ref()));
+                    self.writer.write_str("polar(")?;
+                    self.print_decimal(&abs)?;
+                    self.writer.write_str(", ")?;
+                    self.print_decimal(&arg)?;
+                    self.writer.write_char(')')?;
+                }
+            },
+            Value::Quaternion(q) => {
+                self.writer.write_str("quat(")?;
+                for (n, part) in [&q.w, &q.x, &q.y, &q.z].into_iter().enumerate() {
+                    if n != 0 {
+                        self.writer.write_str(", ")?;
+                    }
+                    self.print_decimal(part)?;
                 }
                 self.writer.write_char(')')?;
             }
+            Value::Str(s) => write!(self.writer, "{s:?}")?,
+            Value::List(items) => self.print_sequence(items, ('[', ']'), Self::print_value)?,
+            Value::Tuple(items) => self.print_sequence(items, ('(', ')'), Self::print_value)?,
+            Value::Quantity(q) if q.sexagesimal().is_some() => {
+                let s = q.sexagesimal().unwrap();
+                let [degrees, minutes, seconds] = if s.angle { ["°", "'", "\""] } else { ["h", "m", "s"] };
+                let sign = if s.negative { "-" } else { "" };
+                write!(self.writer, "{sign}{}{degrees}{}{minutes}", s.whole, s.minutes)?;
+                self.print_seconds(&s.seconds)?;
+                self.writer.write_str(seconds)?;
+            }
+            Value::Quantity(q) => {
+                let parens = match &q.magnitude {
+                    Value::Exact(r) => *r.denom() != 1 && self.settings.money.is_none(),
+                    Value::Complex(_) | Value::Quaternion(_) => true,
+                    _ => false,
+                };
+                self.maybe_enter_parens(|this| this.print_value(&q.magnitude), parens)?;
+                let printed = q.unit.printed_as(self.settings.base_units);
+                write!(self.writer, " {}", printed.as_ref().unwrap_or(&q.unit))?;
+            }
         }
         Ok(())
     }
 
+    /// Whether an exact value is too big to be displayed exactly.
+    fn is_approximated(&self, r: &Rational) -> bool {
+        self.settings.approximate_after().is_some_and(|max| decimal_digits(r) > max)
+    }
+
+    /// Prints a decimal approximation of an exact value.
+    fn print_approx(&mut self, r: &Rational) -> fmt::Result {
+        // enough bits for the digits we print, plus some guard bits
+        let prec = (self.settings.round_digits as f64 * std::f64::consts::LOG2_10) as u32 + 16;
+        self.print_digits(&Float::with_val(prec, r), self.settings.round_digits)
+    }
+
+    /// Prints the seconds of a sexagesimal angle or time, in decimals unless they are whole.
+    fn print_seconds(&mut self, seconds: &Rational) -> fmt::Result {
+        if seconds.is_integer() {
+            write!(self.writer, "{seconds}")
+        } else {
+            self.print_approx(seconds)
+        }
+    }
+
+    /// Prints a decimal with `digits`, or with the digits its precision holds if it
+    /// wasn't computed with the usual precision, like the result of `N(pi, 50)`.
+    /// With `round-trip` on, with just enough digits to read back the same.
+    fn print_decimal(&mut self, dec: &Float) -> fmt::Result {
+        let digits = match dec.prec() {
+            _ if self.settings.round_trip => round_trip_digits(dec, self.settings.rounding.into()),
+            prec if prec != self.settings.precision => (prec as f64 * std::f64::consts::LOG10_2) as usize,
+            _ => self.settings.round_digits,
+        };
+        self.print_digits(dec, digits)
+    }
+
+    fn print_digits(&mut self, dec: &Float, digits: usize) -> fmt::Result {
+        if dec.is_nan() {
+            return self.writer.write_str("NaN");
+        } else if dec.is_infinite() {
+            return self.writer.write_str(if dec.is_sign_negative() { "-∞" } else { "∞" });
+        }
+        let (negative, mut string, exp) = dec.to_sign_string_exp(10, Some(digits));
+        let sign = if negative {
+            "-"
+        } else {
+            ""
+        };
+        let exp = exp.map(|x| x - 1);
+        // without `sci-high`, positional while all the integer digits fit in the printed digits
+        let high = self.settings.sci_high.unwrap_or(string.len() as i32);
+        // the digits before and after the point, and the exponent
+        let (mut int, mut frac, suffix) = match exp {
+            Some(exp) if exp <= self.settings.sci_low || exp >= high => {
+                let rest = string.split_off(1);
+                (string, rest, format!("e{exp}"))
+            }
+            Some(exp @ ..0) => ("0".into(), format!("{}{string}", "0".repeat((-exp - 1) as usize)), String::new()),
+            Some(exp) if (exp as usize) < string.len() => {
+                let rest = string.split_off(exp as usize + 1);
+                (string, rest, String::new())
+            }
+            // more integer digits than are printed, the rest are zeros
+            Some(exp) => {
+                let zeros = "0".repeat(exp as usize + 1 - string.len());
+                (string + &zeros, String::new(), String::new())
+            }
+            None => (string, String::new(), String::new()),
+        };
+        // `1e-30` rather than `1.e-30` when there is only one digit
+        // and `3` rather than `3.` when reading it back only needs the integer digits
+        let mut point = exp.is_some() && (suffix.is_empty() || !frac.is_empty());
+        if self.settings.round_trip && frac.is_empty() {
+            point = false;
+        }
+        if !self.settings.trailing_zeros {
+            frac.truncate(frac.trim_end_matches('0').len());
+            point = !frac.is_empty();
+        }
+        if frac.len() < self.settings.min_digits {
+            frac.extend(std::iter::repeat_n('0', self.settings.min_digits - frac.len()));
+            point = true;
+        }
+        if !self.settings.leading_zero && int == "0" && point {
+            int.clear();
+        }
+        let point = if point { "." } else { "" };
+        write!(self.writer, "{sign}{int}{point}{frac}{suffix}")
+    }
+
+    /// Prints comma separated items wrapped in the given delimiters.
+    fn print_sequence<T>(
+        &mut self,
+        items: &[T],
+        (open, close): (char, char),
+        mut f: impl FnMut(&mut Self, &T) -> fmt::Result,
+    ) -> fmt::Result {
+        self.writer.write_char(open)?;
+        for (n, item) in items.iter().enumerate() {
+            if n != 0 {
+                self.writer.write_str(", ")?;
+            }
+            f(self, item)?;
+        }
+        self.writer.write_char(close)
+    }
+
     /*pub fn into_inner(self) -> W {
         self.writer
     }*/
@@ -275,3 +699,76 @@ impl Printer<String> {
         }
     }
 }*/
+
+#[test]
+fn test_decimal_style() {
+    let mut e = Evaluator::default();
+    let eval = |e: &mut Evaluator, line: &str| crate::session::eval_text(e, line).unwrap();
+    assert_eq!("-0.50000000", eval(&mut e, "-0.5"));
+    e.settings_mut().set("trailing-zeros", "off").unwrap();
+    e.settings_mut().set("leading-zero", "off").unwrap();
+    assert_eq!("-.5", eval(&mut e, "-0.5"));
+    assert_eq!("[2, 1.5e-5]", eval(&mut e, "[2.0, 1.5e-5]"));
+    // decimals inside expressions are printed the same way
+    assert_eq!("x*.25", eval(&mut e, "x*0.25"));
+    e.settings_mut().set("sci-low", "-6").unwrap();
+    e.settings_mut().set("sci-high", "3").unwrap();
+    assert_eq!("[.000015, 1.5e-6, 250, 2.5e3]", eval(&mut e, "[1.5e-5, 1.5e-6, 250.0, 2500.0]"));
+    e.settings_mut().set("sci-high", "12").unwrap();
+    assert_eq!("[12345679000, 1.2345679e12]", eval(&mut e, "[12345678912.0, 1234567891234.0]"));
+    e.settings_mut().set("min-digits", "2").unwrap();
+    assert_eq!("2.50", eval(&mut e, "2.5"));
+}
+
+#[test]
+fn test_round_trip() {
+    let mut e = Evaluator::default();
+    e.settings_mut().set("round-trip", "on").unwrap();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line).unwrap();
+    assert_eq!("0.1", eval("0.1"));
+    assert_eq!("1e-30", eval("1e-30"));
+    assert_eq!("[3, 1+2i, 1.5-2i]", eval("[3.0, 1+2i, 1.5-2i]"));
+    // reading the printed digits back gives the same number
+    for source in ["1/3.0", "pi", "sqrt(2)", "ln(3)/1e20", "(1+2i)/3"] {
+        let printed = eval(&format!("x = {source}"));
+        eval(&format!("y = {printed}"));
+        assert_eq!("0", eval("abs(x - y)"), "{source} printed as {printed}");
+    }
+    e.settings_mut().set("leading-zero", "off").unwrap();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line).unwrap();
+    let printed = eval("x = 1/3.0");
+    assert!(printed.starts_with(".333"), "{printed}");
+    eval(&format!("y = {printed}"));
+    assert_eq!("0", eval("x - y"));
+}
+
+#[test]
+fn test_to_source() {
+    let mut e = Evaluator::default();
+    e.settings_mut().set("precision", "300").unwrap();
+    // every digit the precision holds is written, not just the ones shown
+    for source in ["sqrt(2)", "pi/3", "1/3", "(1 + 2i)/sqrt(3)", "2^200 + 1/7.0"] {
+        let written = crate::session::eval(&mut e, source).unwrap().to_source(CaretOp::Pow);
+        crate::session::eval(&mut e, &format!("a = {source}; b = {written}")).unwrap();
+        assert_eq!("0", crate::session::eval_text(&mut e, "abs(a - b)").unwrap(), "{source} written as {written}");
+    }
+    assert_eq!("1", crate::session::eval_text(&mut e, "b == 2^200 + 1/7.0").unwrap());
+}
+
+#[test]
+fn test_powers_and_calls() {
+    let mut e = Evaluator::default();
+    let eval = |e: &mut Evaluator, line: &str| crate::session::eval_text(e, line).unwrap();
+    // exponents that bind looser than `^` keep their brackets, fractions included
+    for source in ["x^(1/2)", "x^(2/3)", "2^(x+1)", "x^(a*b)", "(x^a)^b", "2^3^x", "x^-a"] {
+        assert_eq!(source, eval(&mut e, source));
+    }
+    // calls are written the way they are typed, not with the name quoted
+    assert_eq!("f(x) = g(x)^2+g(x^(1/3))", eval(&mut e, "f(x) = g(x)^2 + g(x^(1/3))"));
+    // and both read back the same
+    let written = crate::session::eval(&mut e, "h(x) = x^(1/2) + g(2^(x+1))").unwrap().to_source(CaretOp::Pow);
+    assert_eq!("h(x) = x^(1/2)+g(2^(x+1))", written);
+    eval(&mut e, &written);
+    eval(&mut e, "g(y) = y/4");
+    assert_eq!("[10.000000, 4.5000000]", eval(&mut e, "[h(4), f(8)]"));
+}
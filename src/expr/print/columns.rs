@@ -0,0 +1,126 @@
+//! Lists and matrices shown as results, laid out in aligned columns over
+//! several lines instead of one long line, with the middle of long lists left out.
+
+use crate::expr::{Evaluator, Expr, Value};
+
+/// An item as displayed, plain to measure it and colored to print it.
+struct Cell {
+    plain: String,
+    colored: String,
+}
+
+impl Cell {
+    fn new(e: &Evaluator, v: &Value) -> Cell {
+        let v = Expr::value(v.clone());
+        Cell { plain: e.display(&v).to_string(), colored: e.display(&v).colored().to_string() }
+    }
+
+    fn ellipsis() -> Cell {
+        Cell { plain: "…".into(), colored: "…".into() }
+    }
+
+    fn width(&self) -> usize {
+        self.plain.chars().count()
+    }
+
+    /// The cell right-aligned in `width` columns.
+    fn padded(&self, width: usize) -> String {
+        format!("{}{}", " ".repeat(width.saturating_sub(self.width())), self.colored)
+    }
+}
+
+/// The rows of a list of two or more lists of the same length, none of which contain lists.
+fn matrix(items: &[Value]) -> Option<Vec<&[Value]>> {
+    let rows: Vec<_> = items
+        .iter()
+        .map(|row| match row {
+            Value::List(row) if !row.is_empty() && !row.iter().any(|v| matches!(v, Value::List(_))) => Some(&row[..]),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    (rows.len() >= 2 && rows.iter().all(|r| r.len() == rows[0].len())).then_some(rows)
+}
+
+/// The first `max - 1` items and the last one of a list longer than `max`, with an
+/// ellipsis in between, and whether anything was left out.
+fn elide<T>(items: &[T], max: Option<usize>) -> (Vec<Option<&T>>, bool) {
+    match max.filter(|&max| items.len() > max) {
+        Some(max) => {
+            let head = items[..max.saturating_sub(1)].iter().map(Some);
+            (head.chain([None, items.last()]).collect(), true)
+        }
+        None => (items.iter().map(Some).collect(), false),
+    }
+}
+
+/// Lays out a list result, `None` if it is short enough to be printed as usual.
+pub fn list(e: &Evaluator, items: &[Value]) -> Option<String> {
+    let settings = e.settings();
+    if let Some(rows) = matrix(items) {
+        return Some(grid(e, &rows));
+    }
+    let (shown, elided) = elide(items, settings.max_items);
+    let cells: Vec<_> = shown.iter().map(|v| v.map_or_else(Cell::ellipsis, |v| Cell::new(e, v))).collect();
+    let line_width = cells.iter().map(|c| c.width() + 2).sum::<usize>();
+    let fits = settings.list_width.is_none_or(|max| line_width <= max);
+    if fits && !elided {
+        return None;
+    }
+    let width = cells.iter().map(Cell::width).max().unwrap_or(0);
+    // with room for the brackets and the `, ` after each item
+    let per_line = match settings.list_width {
+        Some(max) if !fits => (max.saturating_sub(1) / (width + 2)).max(1),
+        _ => cells.len(),
+    };
+    let pad = if per_line < cells.len() { width } else { 0 };
+    let mut out = String::from("[");
+    for (n, cell) in cells.iter().enumerate() {
+        if n != 0 {
+            out.push(',');
+            out.push_str(if n % per_line == 0 { "\n " } else { " " });
+        }
+        out.push_str(&cell.padded(pad));
+    }
+    out.push(']');
+    if elided {
+        out.push_str(&format!(" ({} items)", items.len()));
+    }
+    Some(out)
+}
+
+/// A matrix with one row per line and its columns aligned.
+fn grid(e: &Evaluator, rows: &[&[Value]]) -> String {
+    let (shown, elided) = elide(rows, e.settings().max_items);
+    let cells: Vec<Option<Vec<Cell>>> =
+        shown.iter().map(|row| row.map(|row| row.iter().map(|v| Cell::new(e, v)).collect())).collect();
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|col| cells.iter().flatten().map(|row| row[col].width()).max().unwrap_or(0))
+        .collect();
+    let lines: Vec<String> = cells
+        .iter()
+        .map(|row| match row {
+            Some(row) => {
+                let row: Vec<_> = row.iter().zip(&widths).map(|(cell, &width)| cell.padded(width)).collect();
+                format!("[{}]", row.join(", "))
+            }
+            None => "…".into(),
+        })
+        .collect();
+    let mut out = format!("[{}]", lines.join(",\n "));
+    if elided {
+        out.push_str(&format!(" ({} rows)", rows.len()));
+    }
+    out
+}
+
+#[test]
+fn test_list() {
+    let mut e = Evaluator::default();
+    let n = |n: i32| Value::Exact(n.into());
+    assert_eq!(None, list(&e, &[n(1), n(2)]));
+    e.settings_mut().set("max-items", "4").unwrap();
+    let long: Vec<_> = (1..=1000).map(n).collect();
+    assert_eq!(Some("[1, 2, 3, …, 1000] (1000 items)".into()), list(&e, &long));
+    let matrix = [Value::List(vec![n(1), n(-20)]), Value::List(vec![n(300), n(4)])];
+    assert_eq!(Some("[[  1, -20],\n [300,   4]]".into()), list(&e, &matrix));
+}
@@ -0,0 +1,89 @@
+//! The decimal digits of huge integers, generated a piece at a time so that a
+//! number like `2^10^7` starts showing right away instead of after all of its
+//! millions of digits have been converted.
+
+use std::io;
+
+use rug::ops::Pow;
+use rug::{Complete, Integer};
+
+/// Numbers with up to twice this many digits are converted in one go.
+const CHUNK: usize = 10_000;
+
+/// An upper bound for the number of digits of `n`, which is at most one too many.
+fn estimate(n: &Integer) -> usize {
+    (n.significant_bits() as f64 * std::f64::consts::LOG10_2) as usize + 1
+}
+
+/// The number of decimal digits of `n`, without the sign.
+pub fn count(n: &Integer) -> usize {
+    let estimate = estimate(n);
+    if estimate > 1 && *n.as_abs() < Integer::from(10).pow(estimate as u32 - 1) {
+        estimate - 1
+    } else {
+        estimate
+    }
+}
+
+/// The first `len` digits of `n`, which has `total` digits.
+pub fn leading(n: &Integer, len: usize, total: usize) -> String {
+    let shift = Integer::from(10).pow(total.saturating_sub(len) as u32);
+    (Integer::from(n.abs_ref()) / shift).to_string()
+}
+
+/// Writes `n` with `write`, most significant digits first. Stops as soon as
+/// `write` fails, e.g. because the output was closed or the user interrupted.
+pub fn write(n: &Integer, write: &mut impl FnMut(&str) -> io::Result<()>) -> io::Result<()> {
+    if n.is_negative() {
+        write("-")?;
+    }
+    write_padded(&n.as_abs(), 0, &mut vec![], write)
+}
+
+/// Writes `n` padded with zeros to `width` digits. `powers[i]` is `10^(CHUNK << i)`,
+/// filled in as needed and shared between the halves.
+fn write_padded(
+    n: &Integer,
+    width: usize,
+    powers: &mut Vec<Integer>,
+    write: &mut impl FnMut(&str) -> io::Result<()>,
+) -> io::Result<()> {
+    let digits = estimate(n).max(width);
+    if digits <= 2 * CHUNK {
+        let s = n.to_string();
+        write(&"0".repeat(width.saturating_sub(s.len())))?;
+        return write(&s);
+    }
+    // split off at most `digits - 2` low digits, so that the high part isn't
+    // empty even when the estimate was one too many
+    let mut i = 0;
+    while CHUNK << (i + 1) <= digits - 2 {
+        i += 1;
+    }
+    while powers.len() <= i {
+        let next = match powers.last() {
+            Some(p) => p.square_ref().complete(),
+            None => Integer::from(10).pow(CHUNK as u32),
+        };
+        powers.push(next);
+    }
+    let low_digits = CHUNK << i;
+    let (high, low) = n.div_rem_ref(&powers[i]).complete();
+    write_padded(&high, width.saturating_sub(low_digits), powers, write)?;
+    write_padded(&low, low_digits, powers, write)
+}
+
+#[test]
+fn test_write() {
+    let big: Integer = Integer::from(3).pow(100_000) * 7 - Integer::from(10).pow(30_000);
+    for n in [Integer::from(-42), Integer::ZERO, Integer::from(10).pow(50_000) + 1, -big] {
+        let mut out = String::new();
+        write(&n, &mut |s| {
+            out.push_str(s);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(n.to_string(), out);
+        assert_eq!(n.to_string().trim_start_matches('-').len(), count(&n));
+    }
+}
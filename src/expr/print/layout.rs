@@ -0,0 +1,332 @@
+//! The `:set render 2d` backend, which lays expressions out on a grid of
+//! characters so that fractions get horizontal bars and matrices line up.
+
+use std::fmt;
+
+use rug::Rational;
+
+use super::Printer;
+use crate::expr::{Expr, ExprId, ExprRef, Node, PrecedenceContext, Value};
+use crate::settings::Settings;
+
+/// A rectangle of text. All lines have the same width, and `baseline` is the
+/// line that operators next to it should be written on.
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    lines: Vec<String>,
+    baseline: usize,
+}
+
+fn width(s: &str) -> usize {
+    s.chars().count()
+}
+
+impl Block {
+    fn text(s: impl Into<String>) -> Block {
+        Block { lines: vec![s.into()], baseline: 0 }
+    }
+
+    fn width(&self) -> usize {
+        self.lines.first().map_or(0, |l| width(l))
+    }
+
+    fn height(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Puts blocks next to each other with their baselines lined up.
+    fn beside(blocks: Vec<Block>) -> Block {
+        let above = blocks.iter().map(|b| b.baseline).max().unwrap_or(0);
+        let below = blocks.iter().map(|b| b.height() - b.baseline - 1).max().unwrap_or(0);
+        let lines = (0..above + below + 1)
+            .map(|row| {
+                let mut line = String::new();
+                for b in &blocks {
+                    match (row + b.baseline).checked_sub(above).and_then(|r| b.lines.get(r)) {
+                        Some(l) => line.push_str(l),
+                        None => line.push_str(&" ".repeat(b.width())),
+                    }
+                }
+                line
+            })
+            .collect();
+        Block { lines, baseline: above }
+    }
+
+    /// A fraction with `num` centered over `den`.
+    fn over(num: Block, den: Block) -> Block {
+        let w = num.width().max(den.width()) + 2;
+        let baseline = num.height();
+        let mut lines: Vec<_> = num.lines.into_iter().map(|l| center(&l, w)).collect();
+        lines.push("─".repeat(w));
+        lines.extend(den.lines.iter().map(|l| center(l, w)));
+        Block { lines, baseline }
+    }
+
+    /// `base` with `exp` raised so that its last line is just above the first line of `base`.
+    fn superscript(base: Block, exp: Block) -> Block {
+        let blank = " ".repeat(exp.width());
+        let mut lines = exp.lines;
+        lines.extend((0..=base.baseline).map(|_| blank.clone()));
+        let baseline = lines.len() - 1;
+        Block::beside(vec![base, Block { lines, baseline }])
+    }
+
+    /// Wraps the block in delimiters that grow with its height.
+    fn delimited(self, (open, close): (char, char)) -> Block {
+        if self.height() == 1 {
+            return Block::beside(vec![Block::text(open), self, Block::text(close)]);
+        }
+        let ((top_l, mid_l, bot_l), (top_r, mid_r, bot_r)) = match open {
+            '(' => (('⎛', '⎜', '⎝'), ('⎞', '⎟', '⎠')),
+            _ => (('┌', '│', '└'), ('┐', '│', '┘')),
+        };
+        let last = self.height() - 1;
+        let lines = self
+            .lines
+            .into_iter()
+            .enumerate()
+            .map(|(n, l)| {
+                let (l_char, r_char) = match n {
+                    0 => (top_l, top_r),
+                    n if n == last => (bot_l, bot_r),
+                    _ => (mid_l, mid_r),
+                };
+                format!("{l_char}{l}{r_char}")
+            })
+            .collect();
+        Block { lines, baseline: self.baseline }
+    }
+
+    /// Items separated by commas.
+    fn sequence(items: Vec<Block>, delims: (char, char)) -> Block {
+        let mut blocks = vec![];
+        for (n, item) in items.into_iter().enumerate() {
+            if n != 0 {
+                blocks.push(Block::text(", "));
+            }
+            blocks.push(item);
+        }
+        Block::beside(blocks).delimited(delims)
+    }
+
+    /// A grid with right aligned columns.
+    fn matrix(rows: Vec<Vec<Block>>) -> Block {
+        let columns = rows[0].len();
+        let widths: Vec<_> = (0..columns).map(|c| rows.iter().map(|r| r[c].width()).max().unwrap_or(0)).collect();
+        let mut lines = vec![];
+        let mut baselines = vec![];
+        for row in rows {
+            let row: Vec<_> = row
+                .into_iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(c, (b, &w))| {
+                    let pad = w - b.width() + if c == 0 { 0 } else { 2 };
+                    Block::beside(vec![Block::text(" ".repeat(pad)), b])
+                })
+                .collect();
+            let row = Block::beside(row);
+            baselines.push(lines.len() + row.baseline);
+            lines.extend(row.lines);
+        }
+        let baseline = baselines[baselines.len() / 2];
+        let grid = Block { lines, baseline };
+        Block::beside(vec![Block::text(" "), grid, Block::text(" ")]).delimited(('[', ']'))
+    }
+}
+
+fn center(s: &str, w: usize) -> String {
+    let left = (w - width(s)) / 2;
+    format!("{}{s}{}", " ".repeat(left), " ".repeat(w - width(s) - left))
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (n, line) in self.lines.iter().enumerate() {
+            if n != 0 {
+                f.write_str("\n")?;
+            }
+            f.write_str(line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Layout<'a> {
+    settings: &'a Settings,
+}
+
+impl<'a> Layout<'a> {
+    pub fn new(settings: &'a Settings) -> Self {
+        Layout { settings }
+    }
+
+    /// Formats with the inline printer, for the parts that don't need two dimensions.
+    fn inline(&self, f: impl FnOnce(&mut Printer<'_, String>) -> fmt::Result) -> Block {
+        let mut p = Printer::new(String::new(), self.settings);
+        f(&mut p).expect("String format does not have errors");
+        Block::text(p.writer)
+    }
+
+    pub fn expr(&self, x: &Expr) -> Block {
+        self.expr_with_precedence(x.root(), PrecedenceContext::NoPrecedence)
+    }
+
+    fn expr_with_precedence(&self, x: ExprRef, p: PrecedenceContext) -> Block {
+        crate::expr::grow_stack(|| self.expr_with_precedence_node(x, p))
+    }
+
+    fn expr_with_precedence_node(&self, x: ExprRef, p: PrecedenceContext) -> Block {
+        let new_ctxt = x.precedence();
+        // a child that doesn't need parentheses, like a function argument
+        let child = |id: ExprId| self.expr_with_precedence(x.get(id), PrecedenceContext::NoPrecedence);
+        let block = match x.node() {
+            Node::Value(v) => return self.value(v),
+            Node::Literal(l) => return Block::text(format!("{}{}", l.text, if l.imaginary { "i" } else { "" })),
+            Node::Symbol(s) => return Block::text(s.as_str()),
+            &Node::Neg(e) => Block::beside(vec![Block::text("-"), self.expr_with_precedence(x.get(e), new_ctxt)]),
+            // a fraction bar groups by itself
+            &Node::Div(a, b) => return Block::over(child(a), child(b)),
+            Node::Add(items) | Node::Mul(items) => {
+                let op = if let Node::Add(_) = x.node() { " + " } else { " * " };
+                let mut blocks = vec![];
+                for (n, &item) in items.iter().enumerate() {
+                    if n != 0 {
+                        blocks.push(Block::text(op));
+                    }
+                    blocks.push(self.expr_with_precedence(x.get(item), new_ctxt));
+                }
+                Block::beside(blocks)
+            }
+            &Node::Sub(a, b) => Block::beside(vec![
+                self.expr_with_precedence(x.get(a), new_ctxt),
+                Block::text(" - "),
+                self.expr_with_precedence(x.get(b), new_ctxt),
+            ]),
+            &Node::Pow(base, exp) => {
+                // anything but a plain value or name is parenthesized, since `-2²` would read as `-(2²)`
+                let base = match x.get(base).node() {
+                    Node::Symbol(_) | Node::Apply(..) => child(base),
+                    Node::Value(Value::Exact(r)) if !r.is_negative() && *r.denom() == 1 => child(base),
+                    _ => child(base).delimited(('(', ')')),
+                };
+                Block::superscript(base, child(exp))
+            }
+            Node::Apply(name, args) => {
+                let args = args.iter().map(|&a| child(a)).collect();
+                return Block::beside(vec![child(*name), Block::sequence(args, ('(', ')'))]);
+            }
+            Node::List(items) => {
+                let rows: Option<Vec<Vec<Block>>> = items
+                    .iter()
+                    .map(|&item| match x.get(item).node() {
+                        Node::List(row) => Some(row.iter().map(|&x| child(x)).collect()),
+                        Node::Value(Value::List(row)) => Some(row.iter().map(|v| self.value(v)).collect()),
+                        _ => None,
+                    })
+                    .collect();
+                return self.list(rows, || items.iter().map(|&x| child(x)).collect());
+            }
+            Node::Tuple(items) => return Block::sequence(items.iter().map(|&x| child(x)).collect(), ('(', ')')),
+            Node::Assign(name, annotation, value) => {
+                let head = match annotation {
+                    Some(annotation) => format!("{name}: {annotation} = "),
+                    None => format!("{name} = "),
+                };
+                return Block::beside(vec![Block::text(head), child(*value)]);
+            }
+            Node::Unpack(names, value) => {
+                return Block::beside(vec![Block::text(format!("{} = ", names.join(", "))), child(*value)]);
+            }
+            Node::Define(name, params, body, memo) => {
+                let memo = if *memo { "memo " } else { "" };
+                let head = Block::text(format!("{memo}{name}({}) = ", params.join(", ")));
+                return Block::beside(vec![head, child(*body)]);
+            }
+            &Node::Let(ref name, value, body) => {
+                let block = Block::beside(vec![
+                    Block::text(format!("let {name} = ")),
+                    child(value),
+                    Block::text(" in "),
+                    child(body),
+                ]);
+                return if p == PrecedenceContext::NoPrecedence { block } else { block.delimited(('(', ')')) };
+            }
+            Node::Block(_) | Node::For(..) | Node::While(..) => {
+                return self.inline(|p| p.print_with_precedence(x, PrecedenceContext::NoPrecedence));
+            }
+            // units are written like they are typed
+            Node::DefineUnit(..) | Node::Quantity(..) | Node::Convert(..) => {
+                return self.inline(|printer| printer.print_with_precedence(x, p));
+            }
+        };
+        if new_ctxt < p {
+            block.delimited(('(', ')'))
+        } else {
+            block
+        }
+    }
+
+    /// A matrix if every item is a row of the same length, a plain list otherwise.
+    fn list(&self, rows: Option<Vec<Vec<Block>>>, items: impl FnOnce() -> Vec<Block>) -> Block {
+        match rows {
+            Some(rows) if !rows.is_empty() && !rows[0].is_empty() && rows.iter().all(|r| r.len() == rows[0].len()) => {
+                Block::matrix(rows)
+            }
+            _ => Block::sequence(items(), ('[', ']')),
+        }
+    }
+
+    pub fn value(&self, v: &Value) -> Block {
+        match v {
+            Value::Exact(r) if *r.denom() != 1 && self.settings.money.is_none() && self.approx(r).is_some() => {
+                self.fraction(r)
+            }
+            Value::List(items) => {
+                let rows = items
+                    .iter()
+                    .map(|item| match item {
+                        Value::List(row) => Some(row.iter().map(|v| self.value(v)).collect()),
+                        _ => None,
+                    })
+                    .collect();
+                self.list(rows, || items.iter().map(|v| self.value(v)).collect())
+            }
+            Value::Tuple(items) => Block::sequence(items.iter().map(|v| self.value(v)).collect(), ('(', ')')),
+            v => self.inline(|p| p.print_value(v)),
+        }
+    }
+
+    fn fraction(&self, r: &Rational) -> Block {
+        let frac = Block::over(Block::text(r.numer().clone().abs().to_string()), Block::text(r.denom().to_string()));
+        if *r < 0 {
+            Block::beside(vec![Block::text("-"), frac])
+        } else {
+            frac
+        }
+    }
+
+    /// The decimal approximation of `r`, for `show-approx`.
+    pub fn approx(&self, r: &Rational) -> Option<Block> {
+        let mut p = Printer::new(String::new(), self.settings);
+        (!p.is_approximated(r)).then(|| {
+            p.print_approx(r).expect("String format does not have errors");
+            Block::text(p.writer)
+        })
+    }
+
+    pub fn with_approx(&self, block: Block, approx: Block) -> Block {
+        Block::beside(vec![block, Block::text(" ≈ "), approx])
+    }
+}
+
+#[test]
+fn test_fraction_layout() {
+    let settings = Settings::default();
+    let layout = Layout::new(&settings);
+    let half = Value::Exact(Rational::from((-1, 12)));
+    assert_eq!("  1\n-────\n  12", layout.value(&half).to_string());
+    let sum = Expr::sum(vec![Expr::symbol("x"), Expr::value(half)]);
+    assert_eq!("      1\nx + -────\n      12", layout.expr(&sum).to_string());
+}
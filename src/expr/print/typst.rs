@@ -0,0 +1,256 @@
+//! Expressions as Typst math, `1/2 + sqrt(x)` as `frac(1, 2) + sqrt(x)`, for
+//! `:export typst` to put between `$`s.
+
+use rug::Rational;
+
+use super::Printer;
+use crate::expr::{Evaluator, Expr, ExprRef, Node, PrecedenceContext, Value};
+use crate::settings::Settings;
+
+/// Names Typst math writes upright as operators, like `sin`.
+const OPERATORS: &[&str] = &[
+    "arccos", "arcsin", "arctan", "arg", "cos", "cosh", "cot", "coth", "csc", "det", "exp", "gcd", "lg", "ln", "log",
+    "max", "min", "mod", "sec", "sin", "sinh", "tan", "tanh",
+];
+
+/// Functions Typst math draws, like the radical of `sqrt`.
+const FUNCTIONS: &[&str] = &["sqrt", "abs", "floor", "ceil"];
+
+/// Names that are symbols in Typst math.
+const GREEK: &[&str] = &[
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa", "lambda", "mu", "nu", "xi",
+    "pi", "rho", "sigma", "tau", "upsilon", "phi", "chi", "psi", "omega", "Gamma", "Delta", "Theta", "Lambda", "Xi",
+    "Pi", "Sigma", "Phi", "Psi", "Omega",
+];
+
+/// A string in Typst's syntax, which math shows as upright text.
+fn string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A variable or function name. Typst reads a run of letters as one name, so
+/// only single letters and Greek letters are written as they are.
+fn name(s: &str) -> String {
+    let letters = s.trim_end_matches(|c: char| c.is_ascii_digit());
+    if s.chars().count() == 1 || GREEK.contains(&s) {
+        s.into()
+    } else if letters.chars().count() == 1 {
+        // `x1` as `x_1`
+        format!("{letters}_{}", &s[letters.len()..])
+    } else {
+        format!("italic({})", string(s))
+    }
+}
+
+/// `1.5e10` as `1.5 times 10^10`.
+fn number(text: &str) -> String {
+    match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => format!("{mantissa} times 10^({})", exponent.trim_start_matches('+')),
+        None => text.into(),
+    }
+}
+
+struct Typst<'a> {
+    settings: &'a Settings,
+}
+
+impl Typst<'_> {
+    fn rational(&self, r: &Rational) -> String {
+        if *r.denom() == 1 {
+            return r.to_string();
+        }
+        let sign = if r.is_negative() { "-" } else { "" };
+        format!("{sign}frac({}, {})", r.numer().clone().abs(), r.denom())
+    }
+
+    fn value(&self, v: &Value) -> String {
+        match v {
+            Value::Exact(r) if self.settings.money.is_none() => self.rational(r),
+            Value::Str(s) => string(s),
+            Value::List(items) => {
+                let rows = || {
+                    let row = |row: &Value| match row {
+                        Value::List(row) => Some(row.iter().map(|v| self.value(v)).collect()),
+                        _ => None,
+                    };
+                    items.iter().map(row).collect()
+                };
+                self.list(items.iter().map(|v| self.value(v)).collect(), rows)
+            }
+            Value::Tuple(items) => format!("({})", items.iter().map(|v| self.value(v)).collect::<Vec<_>>().join(", ")),
+            Value::Quantity(q) if q.sexagesimal().is_some() => {
+                let s = q.sexagesimal().unwrap();
+                let mut p = Printer::new(String::new(), self.settings);
+                p.print_seconds(&s.seconds).expect("String format does not have errors");
+                let sign = if s.negative { "-" } else { "" };
+                if s.angle {
+                    format!("{sign}{} degree {}' {}''", s.whole, s.minutes, p.writer)
+                } else {
+                    format!("{sign}{} \"h\" thin {} \"m\" thin {} \"s\"", s.whole, s.minutes, p.writer)
+                }
+            }
+            Value::Quantity(q) => {
+                let magnitude = self.value(&q.magnitude);
+                let printed = q.unit.printed_as(self.settings.base_units);
+                let factors = printed.as_ref().unwrap_or(&q.unit).factors().map(|(name, power)| match power {
+                    1 => string(name),
+                    power => format!("{}^({power})", string(name)),
+                });
+                format!("{magnitude} thin {}", factors.collect::<Vec<_>>().join(" dot "))
+            }
+            v => {
+                let mut p = Printer::new(String::new(), self.settings);
+                p.print_value(v).expect("String format does not have errors");
+                number(&p.writer)
+            }
+        }
+    }
+
+    /// A list as `[a, b]`, or a list of rows of the same length as a matrix.
+    fn list(&self, items: Vec<String>, rows: impl FnOnce() -> Option<Vec<Vec<String>>>) -> String {
+        match rows() {
+            Some(rows) if rows.len() >= 2 && rows.iter().all(|row| !row.is_empty() && row.len() == rows[0].len()) => {
+                let rows: Vec<_> = rows.iter().map(|row| row.join(", ")).collect();
+                format!("mat(delim: \"[\", {})", rows.join("; "))
+            }
+            _ => format!("[{}]", items.join(", ")),
+        }
+    }
+
+    /// The unit of a quantity or after `in`, with its names upright.
+    fn unit(&self, x: ExprRef) -> String {
+        match x.node() {
+            Node::Symbol(s) => string(s),
+            Node::Mul(items) => items.iter().map(|&i| self.unit(x.get(i))).collect::<Vec<_>>().join(" dot "),
+            &Node::Div(a, b) => format!("frac({}, {})", self.unit(x.get(a)), self.unit(x.get(b))),
+            &Node::Pow(base, exp) => {
+                format!("{}^({})", self.unit(x.get(base)), self.expr(x.get(exp), PrecedenceContext::NoPrecedence))
+            }
+            _ => self.expr(x, PrecedenceContext::NoPrecedence),
+        }
+    }
+
+    fn call(&self, f: &str, args: Vec<String>) -> String {
+        let args = args.join(", ");
+        if OPERATORS.contains(&f) || FUNCTIONS.contains(&f) || f.chars().count() == 1 {
+            format!("{f}({args})")
+        } else {
+            format!("op({})({args})", string(f))
+        }
+    }
+
+    fn expr(&self, x: ExprRef, p: PrecedenceContext) -> String {
+        crate::expr::grow_stack(|| self.expr_node(x, p))
+    }
+
+    fn expr_node(&self, x: ExprRef, p: PrecedenceContext) -> String {
+        let new_ctxt = x.precedence();
+        let child = |id, p| self.expr(x.get(id), p);
+        // a child that doesn't need parentheses, like a function argument
+        let arg = |id| self.expr(x.get(id), PrecedenceContext::NoPrecedence);
+        let parens = |s: String| if new_ctxt < p { format!("({s})") } else { s };
+        match x.node() {
+            Node::Value(v) => {
+                let s = self.value(v);
+                // a negative number or a fraction as a factor
+                if p > PrecedenceContext::Sum && (s.starts_with('-') || s.contains('+')) {
+                    format!("({s})")
+                } else {
+                    s
+                }
+            }
+            Node::Literal(l) => format!("{}{}", number(&l.text), if l.imaginary { "i" } else { "" }),
+            Node::Symbol(s) => name(s),
+            &Node::Neg(a) => format!("-{}", child(a, new_ctxt)),
+            Node::Add(items) => parens(items.iter().map(|&i| child(i, new_ctxt)).collect::<Vec<_>>().join(" + ")),
+            Node::Mul(items) => parens(items.iter().map(|&i| child(i, new_ctxt)).collect::<Vec<_>>().join(" dot ")),
+            &Node::Sub(a, b) => {
+                // `a - (b + c)`, the right side of a difference needs more than a sum
+                parens(format!("{} - {}", child(a, new_ctxt), child(b, PrecedenceContext::Product)))
+            }
+            // a fraction bar groups by itself
+            &Node::Div(a, b) => {
+                format!("frac({}, {})", arg(a), arg(b))
+            }
+            &Node::Pow(base, exp) => {
+                // `^(...)` only groups, the parentheses aren't shown
+                let base = match x.get(base).node() {
+                    Node::Symbol(_) | Node::Apply(..) => child(base, PrecedenceContext::Pow),
+                    Node::Value(Value::Exact(r)) if !r.is_negative() && *r.denom() == 1 => r.to_string(),
+                    _ => format!("({})", arg(base)),
+                };
+                format!("{base}^({})", arg(exp))
+            }
+            Node::Apply(f, args) => {
+                let args = args.iter().map(|&a| arg(a)).collect();
+                match x.get(*f).node() {
+                    Node::Symbol(f) => self.call(f, args),
+                    _ => format!("({})({})", arg(*f), args.join(", ")),
+                }
+            }
+            Node::List(items) => {
+                let all = items.iter().map(|&i| arg(i)).collect();
+                self.list(all, || {
+                    items
+                        .iter()
+                        .map(|&row| match x.get(row).node() {
+                            Node::List(row) => Some(row.iter().map(|&i| arg(i)).collect()),
+                            Node::Value(Value::List(row)) => Some(row.iter().map(|v| self.value(v)).collect()),
+                            _ => None,
+                        })
+                        .collect()
+                })
+            }
+            Node::Tuple(items) => {
+                let items: Vec<_> = items.iter().map(|&i| arg(i)).collect();
+                format!("({})", items.join(", "))
+            }
+            &Node::Let(ref var, value, body) => parens(format!(
+                "\"let\" {} = {} \"in\" {}",
+                name(var),
+                arg(value),
+                arg(body)
+            )),
+            &Node::Quantity(magnitude, unit) => {
+                parens(format!("{} thin {}", child(magnitude, PrecedenceContext::Pow), self.unit(x.get(unit))))
+            }
+            &Node::Convert(value, unit) => format!("{} quad \"in\" quad {}", arg(value), self.unit(x.get(unit))),
+            // `1 "furlong" = 201.168 "m"`
+            Node::DefineUnit(name, value) => format!("1 thin {} = {}", string(name), arg(*value)),
+            Node::Assign(var, _, value) => format!("{} = {}", name(var), arg(*value)),
+            Node::Unpack(vars, value) => {
+                format!("{} = {}", vars.iter().map(|v| name(v)).collect::<Vec<_>>().join(", "), arg(*value))
+            }
+            Node::Define(f, params, body, _) => {
+                let params = params.iter().map(|p| name(p)).collect();
+                format!("{} = {}", self.call(f, params), arg(*body))
+            }
+            // loops aren't math, they are shown as the code they are
+            Node::Block(_) | Node::For(..) | Node::While(..) => {
+                let mut code = Printer::new(String::new(), self.settings);
+                let result = code.print_with_precedence(x, PrecedenceContext::NoPrecedence);
+                result.expect("String format does not have errors");
+                format!("#raw({})", string(&code.writer))
+            }
+        }
+    }
+}
+
+impl Evaluator {
+    /// The expression as Typst math, with numbers formatted like results are.
+    pub fn typst(&self, e: &Expr) -> String {
+        Typst { settings: self.settings() }.expr(e.root(), PrecedenceContext::NoPrecedence)
+    }
+}
+
+#[test]
+fn test_typst() {
+    let e = Evaluator::default();
+    let typst = |source| e.typst(&crate::expr::parse(&e, source).0.remove(0));
+    assert_eq!("frac(1, 2) + sqrt(x)", typst("1/2 + sqrt(x)"));
+    assert_eq!("(a + b)^(n - 1) dot sin(theta)", typst("(a + b)^(n - 1) * sin(theta)"));
+    assert_eq!("f(x) = x_1 dot italic(\"rate\")", typst("f(x) = x1 * rate"));
+    assert_eq!("op(\"erfc\")(2) dot (-frac(1, 2))", typst("erfc(2) * (-1/2)"));
+    assert_eq!("mat(delim: \"[\", 1, 2; 3, 4)", typst("[[1, 2], [3, 4]]"));
+    assert_eq!("1.5 times 10^(10)", typst("1.5e10"));
+}
@@ -0,0 +1,102 @@
+//! Quaternions, mostly useful for 3D rotations.
+
+use std::ops::{Add, Mul, Sub};
+
+use rug::Float;
+
+#[derive(Clone)]
+pub struct Quaternion {
+    pub w: Float,
+    pub x: Float,
+    pub y: Float,
+    pub z: Float,
+}
+
+impl Quaternion {
+    pub fn conj(self) -> Quaternion {
+        Quaternion { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    pub fn norm_squared(&self) -> Float {
+        let prec = self.w.prec();
+        Float::with_val(prec, self.w.square_ref())
+            + Float::with_val(prec, self.x.square_ref())
+            + Float::with_val(prec, self.y.square_ref())
+            + Float::with_val(prec, self.z.square_ref())
+    }
+
+    pub fn norm(&self) -> Float {
+        self.norm_squared().sqrt()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.w.is_zero() && self.x.is_zero() && self.y.is_zero() && self.z.is_zero()
+    }
+
+    fn scale(self, by: &Float) -> Quaternion {
+        Quaternion { w: self.w * by, x: self.x * by, y: self.y * by, z: self.z * by }
+    }
+
+    /// The multiplicative inverse, `None` for zero.
+    pub fn inverse(self) -> Option<Quaternion> {
+        if self.is_zero() {
+            return None;
+        }
+        let n = self.norm_squared().recip();
+        Some(self.conj().scale(&n))
+    }
+
+    /// Scales to unit length, `None` for zero.
+    pub fn normalize(self) -> Option<Quaternion> {
+        if self.is_zero() {
+            return None;
+        }
+        let n = self.norm().recip();
+        Some(self.scale(&n))
+    }
+}
+
+impl Add for Quaternion {
+    type Output = Quaternion;
+    fn add(self, o: Quaternion) -> Quaternion {
+        Quaternion { w: self.w + o.w, x: self.x + o.x, y: self.y + o.y, z: self.z + o.z }
+    }
+}
+
+impl Sub for Quaternion {
+    type Output = Quaternion;
+    fn sub(self, o: Quaternion) -> Quaternion {
+        Quaternion { w: self.w - o.w, x: self.x - o.x, y: self.y - o.y, z: self.z - o.z }
+    }
+}
+
+/// The Hamilton product, which is not commutative.
+impl Mul for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, o: Quaternion) -> Quaternion {
+        let prec = self.w.prec();
+        let (a, b) = (&self, &o);
+        let p = |x: &Float, y: &Float| Float::with_val(prec, x * y);
+        Quaternion {
+            w: p(&a.w, &b.w) - p(&a.x, &b.x) - p(&a.y, &b.y) - p(&a.z, &b.z),
+            x: p(&a.w, &b.x) + p(&a.x, &b.w) + p(&a.y, &b.z) - p(&a.z, &b.y),
+            y: p(&a.w, &b.y) - p(&a.x, &b.z) + p(&a.y, &b.w) + p(&a.z, &b.x),
+            z: p(&a.w, &b.z) + p(&a.x, &b.y) - p(&a.y, &b.x) + p(&a.z, &b.w),
+        }
+    }
+}
+
+#[test]
+fn test_hamilton_product() {
+    let q = |w: i32, x: i32, y: i32, z: i32| Quaternion {
+        w: Float::with_val(53, w),
+        x: Float::with_val(53, x),
+        y: Float::with_val(53, y),
+        z: Float::with_val(53, z),
+    };
+    // i * j = k, j * i = -k
+    let k = q(0, 1, 0, 0) * q(0, 0, 1, 0);
+    assert!(k.w == 0 && k.x == 0 && k.y == 0 && k.z == 1);
+    let neg_k = q(0, 0, 1, 0) * q(0, 1, 0, 0);
+    assert!(neg_k.z == -1);
+}
@@ -0,0 +1,234 @@
+//! `rearrange(equation, x)`, which solves an equation like `v == u + a*t`, or
+//! `v = u + a*t`, for one of its symbols, giving `t == (v-u)/a`. The symbol is isolated by undoing
+//! what is done to it one operation at a time, sums, products, powers and
+//! functions like `sin` or `tanh` that have an inverse. Where it appears more
+//! than once, the equation has to be linear in it, like `3*x == x + 4`.
+//!
+//! Powers are undone with the principal root, so `x^2 == 4` gives `x == 2`
+//! and not also `x == -2`, and functions with their principal inverse.
+
+use color_eyre::eyre::bail;
+
+use super::{Evaluator, Expr, ExprRef, Node, Value};
+
+pub const NAME: &str = "rearrange";
+
+/// Functions and their inverses, which undo each other both ways on their
+/// principal branches.
+const INVERSES: &[(&str, &str)] = &[
+    ("sin", "asin"),
+    ("cos", "acos"),
+    ("tan", "atan"),
+    ("sec", "asec"),
+    ("csc", "acsc"),
+    ("cot", "acot"),
+    ("sinh", "asinh"),
+    ("cosh", "acosh"),
+    ("tanh", "atanh"),
+];
+
+fn inverse(f: &str) -> Option<&'static str> {
+    INVERSES.iter().find_map(|&(a, b)| if f == a { Some(b) } else if f == b { Some(a) } else { None })
+}
+
+/// Whether the symbol `x` appears in `e`.
+fn mentions(e: ExprRef, x: &str) -> bool {
+    super::grow_stack(|| mentions_node(e, x))
+}
+
+fn mentions_node(e: ExprRef, x: &str) -> bool {
+    match e.node() {
+        Node::Symbol(s) => s == x,
+        // the function called isn't part of the equation
+        Node::Apply(_, args) => args.iter().any(|&a| mentions(e.get(a), x)),
+        node => node.children().into_iter().any(|c| mentions(e.get(c), x)),
+    }
+}
+
+fn int(n: i32) -> Expr {
+    Expr::value(Value::Exact(n.into()))
+}
+
+/// The sum of the items, or the item if there is only one.
+fn sum(mut items: Vec<Expr>) -> Expr {
+    if items.len() == 1 { items.pop().unwrap() } else { Expr::sum(items) }
+}
+
+fn product(mut items: Vec<Expr>) -> Expr {
+    if items.len() == 1 { items.pop().unwrap() } else { Expr::product(items) }
+}
+
+/// The one operand of a sum or product that mentions `x`, and the others.
+fn split(e: ExprRef, items: &[super::ExprId], x: &str) -> Option<(super::ExprId, Vec<Expr>)> {
+    let [with] = items.iter().filter(|&&i| mentions(e.get(i), x)).collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    Some((*with, items.iter().filter(|&i| i != with).map(|&i| e.get(i).to_expr()).collect()))
+}
+
+/// Undoes the outermost operation of `side == other`, where only `side`
+/// mentions `x`, giving the operand of `side` that does and what it equals.
+/// Gives back `other` if the operation can't be undone.
+fn peel<'a>(side: ExprRef<'a>, other: Expr, x: &str) -> Result<(ExprRef<'a>, Expr), Expr> {
+    let has = |id| mentions(side.get(id), x);
+    Ok(match side.node() {
+        Node::Add(items) => match split(side, items, x) {
+            Some((with, rest)) => (side.get(with), Expr::binary(other, sum(rest), Node::Sub)),
+            None => return Err(other),
+        },
+        Node::Mul(items) => match split(side, items, x) {
+            Some((with, rest)) => (side.get(with), Expr::binary(other, product(rest), Node::Div)),
+            None => return Err(other),
+        },
+        &Node::Sub(a, b) if !has(b) => (side.get(a), Expr::sum(vec![other, side.get(b).to_expr()])),
+        &Node::Sub(a, b) if !has(a) => (side.get(b), Expr::binary(side.get(a).to_expr(), other, Node::Sub)),
+        &Node::Div(a, b) if !has(b) => (side.get(a), Expr::product(vec![other, side.get(b).to_expr()])),
+        &Node::Div(a, b) if !has(a) => (side.get(b), Expr::binary(side.get(a).to_expr(), other, Node::Div)),
+        &Node::Neg(a) => (side.get(a), -other),
+        &Node::Pow(a, b) if !has(b) => {
+            if matches!(side.get(b).node(), Node::Value(Value::Exact(r)) if *r == 2) {
+                return Ok((side.get(a), Expr::apply(Expr::symbol("sqrt"), vec![other])));
+            }
+            let root = Expr::binary(int(1), side.get(b).to_expr(), Node::Div);
+            (side.get(a), Expr::binary(other, root, Node::Pow))
+        }
+        &Node::Pow(a, b) if !has(a) => {
+            let ln = |e| Expr::apply(Expr::symbol("ln"), vec![e]);
+            let exponent = match side.get(a).node() {
+                Node::Symbol(e) if e == "e" => ln(other),
+                _ => Expr::binary(ln(other), ln(side.get(a).to_expr()), Node::Div),
+            };
+            (side.get(b), exponent)
+        }
+        Node::Apply(f, args) if args.len() == 1 => {
+            let arg = side.get(args[0]);
+            match side.get(*f).node() {
+                Node::Symbol(f) => match f.as_str() {
+                    "ln" => (arg, Expr::binary(Expr::symbol("e"), other, Node::Pow)),
+                    "sqrt" => (arg, Expr::binary(other, int(2), Node::Pow)),
+                    f => match inverse(f) {
+                        Some(inverse) => (arg, Expr::apply(Expr::symbol(inverse), vec![other])),
+                        None => return Err(other),
+                    },
+                },
+                _ => return Err(other),
+            }
+        }
+        _ => return Err(other),
+    })
+}
+
+/// `e` as `a*x + b`, with neither `a` nor `b` mentioning `x`, if it is linear in `x`.
+fn linear(e: ExprRef, x: &str) -> Option<(Expr, Expr)> {
+    super::grow_stack(|| linear_node(e, x))
+}
+
+fn linear_node(e: ExprRef, x: &str) -> Option<(Expr, Expr)> {
+    if !mentions(e, x) {
+        return Some((int(0), e.to_expr()));
+    }
+    Some(match e.node() {
+        Node::Symbol(_) => (int(1), int(0)),
+        Node::Add(items) => {
+            let parts = items.iter().map(|&i| linear(e.get(i), x)).collect::<Option<Vec<_>>>()?;
+            let (a, b): (Vec<_>, Vec<_>) = parts.into_iter().unzip();
+            (sum(a), sum(b))
+        }
+        &Node::Sub(l, r) => {
+            let ((a, b), (c, d)) = (linear(e.get(l), x)?, linear(e.get(r), x)?);
+            (Expr::binary(a, c, Node::Sub), Expr::binary(b, d, Node::Sub))
+        }
+        &Node::Neg(a) => {
+            let (a, b) = linear(e.get(a), x)?;
+            (-a, -b)
+        }
+        Node::Mul(items) => {
+            let (with, mut rest) = split(e, items, x)?;
+            let (a, b) = linear(e.get(with), x)?;
+            rest.push(a);
+            let a = product(rest.clone());
+            rest.pop();
+            rest.push(b);
+            (a, product(rest))
+        }
+        &Node::Div(l, r) if !mentions(e.get(r), x) => {
+            let (a, b) = linear(e.get(l), x)?;
+            let r = e.get(r).to_expr();
+            (Expr::binary(a, r.clone(), Node::Div), Expr::binary(b, r, Node::Div))
+        }
+        _ => return None,
+    })
+}
+
+impl Evaluator {
+    /// `rearrange(equation, x)`, with neither evaluated yet.
+    pub(super) fn rearrange(&mut self, equation: ExprRef, x: ExprRef) -> color_eyre::Result<Expr> {
+        let Node::Symbol(x) = x.node() else {
+            bail!("`{NAME}` solves for a symbol, not `{}`", x.to_expr());
+        };
+        // the rest of the equation is evaluated, so that variables are filled
+        // in, but with constants left symbolic so that `e^x` is undone by `ln`
+        let symbolic = std::mem::replace(&mut self.settings.symbolic, true);
+        let equation = self.eval_in_terms_of(equation, x);
+        self.settings.symbolic = symbolic;
+        let equation = equation?;
+        let root = equation.root();
+        let (left, right) = match root.node() {
+            Node::Apply(f, args) if args.len() == 2 && matches!(root.get(*f).node(), Node::Symbol(s) if s == "eq") => {
+                (root.get(args[0]), root.get(args[1]).to_expr())
+            }
+            // an expression on its own is equal to zero
+            _ => (root, int(0)),
+        };
+        let solution = match (mentions(left, x), mentions(right.root(), x)) {
+            (true, false) => self.isolate(left, right, x)?,
+            (false, true) => self.isolate(right.root(), left.to_expr(), x)?,
+            (true, true) => self.collect(Expr::binary(left.to_expr(), right, Node::Sub).root(), x)?,
+            (false, false) => bail!("`{x}` doesn't appear in the equation"),
+        };
+        Ok(Expr::apply(Expr::symbol("eq"), vec![Expr::symbol(x), solution]))
+    }
+
+    /// `side == other` solved for `x`, where only `side` mentions it.
+    fn isolate(&mut self, mut side: ExprRef, mut other: Expr, x: &str) -> color_eyre::Result<Expr> {
+        while !matches!(side.node(), Node::Symbol(s) if s == x) {
+            match peel(side, other, x) {
+                Ok(peeled) => (side, other) = peeled,
+                Err(other) => return self.collect(Expr::binary(side.to_expr(), other, Node::Sub).root(), x),
+            }
+        }
+        self.eval(other)
+    }
+
+    /// `e == 0` solved for `x`, if `e` is linear in it.
+    fn collect(&mut self, e: ExprRef, x: &str) -> color_eyre::Result<Expr> {
+        let Some((a, b)) = linear(e, x) else {
+            bail!("can't rearrange for `{x}`, which appears more than once and not only linearly");
+        };
+        let a = self.eval(a)?;
+        if a.as_value().is_some_and(Value::is_zero_or_empty) {
+            bail!("`{x}` cancels out of the equation");
+        }
+        self.eval(Expr::binary(-b, a, Node::Div))
+    }
+}
+
+#[test]
+fn test_rearrange() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("t == (v-u)/a", eval("rearrange(v == u + a*t, t)").unwrap());
+    assert_eq!("x == 2", eval("rearrange(2*x + 3 == 7, x)").unwrap());
+    assert_eq!("x == 2", eval("rearrange(3*x == x + 4, x)").unwrap());
+    assert_eq!("x == 2", eval("rearrange(x^2 == 4, x)").unwrap());
+    assert_eq!("c == sqrt(E/m)", eval("rearrange(E == m*c^2, c)").unwrap());
+    assert_eq!("x == atanh(y)/2", eval("rearrange(tanh(2*x) == y, x)").unwrap());
+    assert_eq!("x == y/5", eval("rearrange(y == 2*x + 3*x, x)").unwrap());
+    assert_eq!("x == 0", eval("rearrange(b*x == 0, x)").unwrap());
+    assert_eq!("t == ln(y)/k", eval("rearrange(y == e^(k*t), t)").unwrap());
+    assert_eq!("u == v-a*t", eval("rearrange(v = u + a*t, u)").unwrap());
+    eval("a = 2").unwrap();
+    assert_eq!("x == 3", eval("rearrange(a*x - 6, x)").unwrap());
+    assert!(eval("rearrange(x*x == x, x)").is_err());
+    assert!(eval("rearrange(y == 2, x)").is_err());
+}
@@ -0,0 +1,256 @@
+//! Rewrite rules for results that are left symbolic, so that `sin(x)^2 +
+//! cos(x)^2` is `1` even while `x` has no value. The rules are in `rules.txt`,
+//! where an identity can be added without touching any code. They are applied
+//! from the leaves of a result up, and the rewritten result is evaluated again,
+//! until no rule applies anymore.
+
+use std::sync::LazyLock;
+
+use super::{Evaluator, Expr, ExprId, ExprRef, Node, Value};
+
+/// How many times a result is rewritten at most, in case rules undo each other.
+const MAX_REWRITES: usize = 32;
+
+struct Rule {
+    pattern: Expr,
+    replacement: Expr,
+}
+
+static RULES: LazyLock<Vec<Rule>> = LazyLock::new(|| {
+    let e = Evaluator::default();
+    let parse = |source: &str| {
+        let (mut statements, errors) = super::parse(&e, source);
+        assert!(errors.is_empty() && statements.len() == 1, "bad rule `{source}`");
+        statements.remove(0)
+    };
+    include_str!("rules.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (pattern, replacement) = line.split_once("->").unwrap_or_else(|| panic!("bad rule `{line}`"));
+            Rule { pattern: parse(pattern), replacement: parse(replacement) }
+        })
+        .collect()
+});
+
+/// What the pattern variables matched.
+type Bindings<'p, 't> = Vec<(&'p str, ExprRef<'t>)>;
+
+fn is_variable(name: &str) -> bool {
+    name.ends_with('_')
+}
+
+/// Whether `t` can be bound to the pattern variable `name`, where those
+/// starting with `n`, like `n_`, only stand for integers.
+fn can_bind(name: &str, t: ExprRef) -> bool {
+    !name.starts_with('n') || matches!(t.node(), Node::Value(Value::Exact(r)) if *r.denom() == 1)
+}
+
+/// Whether two expressions are the same, node for node.
+fn same(a: ExprRef, b: ExprRef) -> bool {
+    super::grow_stack(|| same_node(a, b))
+}
+
+fn same_node(a: ExprRef, b: ExprRef) -> bool {
+    let all = |x: &[ExprId], y: &[ExprId]| x.len() == y.len() && x.iter().zip(y).all(|(&x, &y)| same(a.get(x), b.get(y)));
+    match (a.node(), b.node()) {
+        (Node::Value(x), Node::Value(y)) => same_value(x, y),
+        (Node::Literal(x), Node::Literal(y)) => x.text == y.text && x.imaginary == y.imaginary,
+        (Node::Symbol(x), Node::Symbol(y)) => x == y,
+        (Node::Add(x), Node::Add(y)) | (Node::Mul(x), Node::Mul(y)) => all(x, y),
+        (Node::List(x), Node::List(y)) | (Node::Tuple(x), Node::Tuple(y)) => all(x, y),
+        (&Node::Sub(w, x), &Node::Sub(y, z)) | (&Node::Div(w, x), &Node::Div(y, z)) | (&Node::Pow(w, x), &Node::Pow(y, z)) => {
+            same(a.get(w), b.get(y)) && same(a.get(x), b.get(z))
+        }
+        (&Node::Neg(x), &Node::Neg(y)) => same(a.get(x), b.get(y)),
+        (Node::Apply(f, x), Node::Apply(g, y)) => same(a.get(*f), b.get(*g)) && all(x, y),
+        _ => false,
+    }
+}
+
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Exact(a), Value::Exact(b)) => a == b,
+        (Value::Decimal(a), Value::Decimal(b)) => a == b,
+        (Value::Str(a), Value::Str(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Matches a pattern against an expression, adding to the bindings if it matches.
+fn matches<'p, 't>(p: ExprRef<'p>, t: ExprRef<'t>, bindings: &mut Bindings<'p, 't>) -> bool {
+    let len = bindings.len();
+    let all = |x: &[ExprId], y: &[ExprId], bindings: &mut Bindings<'p, 't>| {
+        x.len() == y.len() && x.iter().zip(y).all(|(&x, &y)| matches(p.get(x), t.get(y), bindings))
+    };
+    let matched = match (p.node(), t.node()) {
+        (Node::Symbol(name), _) if is_variable(name) => match bindings.iter().find(|(n, _)| n == name) {
+            Some(&(_, bound)) => same(bound, t),
+            None if !can_bind(name, t) => false,
+            None => {
+                bindings.push((name, t));
+                true
+            }
+        },
+        (Node::Add(x), Node::Add(y)) | (Node::Mul(x), Node::Mul(y)) => {
+            x.len() == y.len() && match_operands(p, x, t, y, &mut vec![false; y.len()], bindings)
+        }
+        (Node::List(x), Node::List(y)) | (Node::Tuple(x), Node::Tuple(y)) => all(x, y, bindings),
+        (&Node::Sub(w, x), &Node::Sub(y, z)) | (&Node::Div(w, x), &Node::Div(y, z)) | (&Node::Pow(w, x), &Node::Pow(y, z)) => {
+            matches(p.get(w), t.get(y), bindings) && matches(p.get(x), t.get(z), bindings)
+        }
+        (&Node::Neg(x), &Node::Neg(y)) => matches(p.get(x), t.get(y), bindings),
+        (Node::Apply(f, x), Node::Apply(g, y)) => matches(p.get(*f), t.get(*g), bindings) && all(x, y, bindings),
+        _ => same(p, t),
+    };
+    if !matched {
+        bindings.truncate(len);
+    }
+    matched
+}
+
+/// Matches each operand of a sum or product pattern against a different one
+/// of `operands`, in any order, marking the ones used.
+fn match_operands<'p, 't>(
+    p: ExprRef<'p>,
+    patterns: &[ExprId],
+    t: ExprRef<'t>,
+    operands: &[ExprId],
+    used: &mut [bool],
+    bindings: &mut Bindings<'p, 't>,
+) -> bool {
+    let Some((&first, rest)) = patterns.split_first() else {
+        return true;
+    };
+    for (i, &operand) in operands.iter().enumerate() {
+        if used[i] {
+            continue;
+        }
+        let len = bindings.len();
+        if matches(p.get(first), t.get(operand), bindings) {
+            used[i] = true;
+            if match_operands(p, rest, t, operands, used, bindings) {
+                return true;
+            }
+            used[i] = false;
+        }
+        bindings.truncate(len);
+    }
+    false
+}
+
+/// The expression with each child of its root replaced by `f(child)`.
+fn rebuild(e: ExprRef, mut f: impl FnMut(ExprRef) -> Expr) -> Expr {
+    match e.node() {
+        Node::Value(_) | Node::Literal(_) | Node::Symbol(_) | Node::Assign(..) | Node::Define(..) => e.to_expr(),
+        Node::Unpack(..) => e.to_expr(),
+        Node::Block(_) | Node::For(..) | Node::While(..) => e.to_expr(),
+        Node::DefineUnit(..) | Node::Quantity(..) | Node::Convert(..) => e.to_expr(),
+        Node::Add(items) => Expr::sum(items.iter().map(|&i| f(e.get(i))).collect()),
+        Node::Mul(items) => Expr::product(items.iter().map(|&i| f(e.get(i))).collect()),
+        &Node::Sub(a, b) => Expr::binary(f(e.get(a)), f(e.get(b)), Node::Sub),
+        &Node::Div(a, b) => Expr::binary(f(e.get(a)), f(e.get(b)), Node::Div),
+        &Node::Pow(a, b) => Expr::binary(f(e.get(a)), f(e.get(b)), Node::Pow),
+        &Node::Neg(a) => -f(e.get(a)),
+        Node::Apply(callee, args) => {
+            let callee = f(e.get(*callee));
+            Expr::apply(callee, args.iter().map(|&i| f(e.get(i))).collect())
+        }
+        Node::List(items) => Expr::list(items.iter().map(|&i| f(e.get(i))).collect()),
+        Node::Tuple(items) => Expr::tuple(items.iter().map(|&i| f(e.get(i))).collect()),
+        Node::Let(name, value, body) => Expr::let_in(name.clone(), f(e.get(*value)), f(e.get(*body))),
+    }
+}
+
+/// The replacement of a rule with its variables replaced by what they matched.
+fn substitute(r: ExprRef, bindings: &Bindings) -> Expr {
+    match r.node() {
+        Node::Symbol(name) if is_variable(name) => match bindings.iter().find(|(n, _)| n == name) {
+            Some((_, bound)) => bound.to_expr(),
+            None => r.to_expr(),
+        },
+        _ => rebuild(r, |child| substitute(child, bindings)),
+    }
+}
+
+impl Rule {
+    /// The expression rewritten by the rule, if it matches it or, for sums and
+    /// products, some of its operands.
+    fn apply(&self, t: ExprRef) -> Option<Expr> {
+        let p = self.pattern.root();
+        let mut bindings = vec![];
+        if matches(p, t, &mut bindings) {
+            return Some(substitute(self.replacement.root(), &bindings));
+        }
+        let (patterns, operands, node): (_, _, fn(Vec<Expr>) -> Expr) = match (p.node(), t.node()) {
+            (Node::Add(x), Node::Add(y)) => (x, y, Expr::sum),
+            (Node::Mul(x), Node::Mul(y)) => (x, y, Expr::product),
+            _ => return None,
+        };
+        let mut used = vec![false; operands.len()];
+        if patterns.len() >= operands.len() || !match_operands(p, patterns, t, operands, &mut used, &mut bindings) {
+            return None;
+        }
+        // the replacement takes the place of the first operand it replaces
+        let first = used.iter().position(|&u| u).unwrap();
+        let mut replacement = Some(substitute(self.replacement.root(), &bindings));
+        let rest = operands.iter().enumerate().filter_map(|(i, &operand)| match used[i] {
+            true if i == first => replacement.take(),
+            true => None,
+            false => Some(t.get(operand).to_expr()),
+        });
+        Some(node(rest.collect()))
+    }
+}
+
+/// Rewrites the expression from its leaves up, setting `changed` if any rule applied.
+fn rewrite(e: ExprRef, changed: &mut bool) -> Expr {
+    let e = super::grow_stack(|| rebuild(e, |child| rewrite(child, changed)));
+    match RULES.iter().find_map(|rule| rule.apply(e.root())) {
+        Some(rewritten) => {
+            *changed = true;
+            rewritten
+        }
+        None => e,
+    }
+}
+
+impl Evaluator {
+    /// Rewrites a symbolic result with the rules, and evaluates what they give,
+    /// until none of them applies.
+    pub(super) fn simplify(&mut self, mut e: Expr) -> color_eyre::Result<Expr> {
+        for _ in 0..MAX_REWRITES {
+            if e.as_value().is_some() {
+                break;
+            }
+            let mut changed = false;
+            let rewritten = rewrite(e.root(), &mut changed);
+            if !changed {
+                break;
+            }
+            e = self.eval_at(rewritten.root())?;
+        }
+        Ok(e)
+    }
+}
+
+#[test]
+fn test_rewrite() {
+    assert!(!RULES.is_empty());
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("1", eval("sin(x)^2 + cos(x)^2").unwrap());
+    assert_eq!("3", eval("cos(a+b)^2 + 2 + sin(a+b)^2").unwrap());
+    assert_eq!("y+1", eval("y + sin(2*x)^2 + cos(2*x)^2").unwrap());
+    assert_eq!("sin(x)^2+cos(y)^2", eval("sin(x)^2 + cos(y)^2").unwrap());
+    assert_eq!("2*x", eval("x + x").unwrap());
+    eval("z = tan(x)*cos(x)").unwrap();
+    assert_eq!("sin(x)", eval("z").unwrap());
+    assert_eq!("1", eval("x/x").unwrap());
+    e.settings_mut().set("symbolic", "on").unwrap();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    let known = "[pi/pi, 2*pi/pi, ln(e), sin(pi), sin(3*pi), tan(-2*pi), cos(pi), cos(2*pi), cos(-3*pi)]";
+    assert_eq!("[1, 2, 1, 0, 0, 0, -1, 1, -1]", eval(known).unwrap());
+    assert_eq!("sin(x*pi/2)", eval("sin(x*pi/2)").unwrap());
+}
@@ -0,0 +1,258 @@
+//! `isolate_roots(p, x)`, the real roots of a polynomial with exact rational
+//! coefficients, each in an interval `[a, b]` with rational ends that holds no
+//! other root. A root that is rational may be found exactly, as `[r, r]`.
+//!
+//! The intervals come from bisecting a bound on the roots and counting the
+//! roots in each half with the Sturm sequence of the square-free part of `p`,
+//! all in exact arithmetic. `isolate_roots(p, x, width)` keeps bisecting the
+//! intervals until none is wider than `width`.
+
+use color_eyre::eyre::bail;
+use rug::Rational;
+
+use super::{Evaluator, Expr, ExprRef, Node, Value};
+
+pub const NAME: &str = "isolate_roots";
+
+/// Polynomials of higher degree than this are refused rather than taking forever.
+const MAX_DEGREE: usize = 1000;
+
+/// The coefficients of a polynomial, of `x^0` first, without zeros at the end.
+type Poly = Vec<Rational>;
+
+fn trim(mut p: Poly) -> Poly {
+    while p.last().is_some_and(|c| c.is_zero()) {
+        p.pop();
+    }
+    p
+}
+
+fn add(a: &Poly, b: &Poly) -> Poly {
+    let zero = Rational::new();
+    let coefficient = |p: &Poly, i| p.get(i).unwrap_or(&zero).clone();
+    trim((0..a.len().max(b.len())).map(|i| coefficient(a, i) + coefficient(b, i)).collect())
+}
+
+fn scale(p: &Poly, c: &Rational) -> Poly {
+    trim(p.iter().map(|a| Rational::from(a * c)).collect())
+}
+
+fn mul(a: &Poly, b: &Poly) -> Poly {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+    let mut product = vec![Rational::new(); a.len() + b.len() - 1];
+    for (i, a) in a.iter().enumerate() {
+        for (j, b) in b.iter().enumerate() {
+            product[i + j] += Rational::from(a * b);
+        }
+    }
+    trim(product)
+}
+
+/// The quotient and remainder of `a / b`, for a nonzero `b`.
+fn div_rem(a: &Poly, b: &Poly) -> (Poly, Poly) {
+    let lead = b.last().expect("not divided by zero");
+    let mut rem = a.clone();
+    let mut quotient = vec![Rational::new(); a.len().saturating_sub(b.len()) + 1];
+    while rem.len() >= b.len() {
+        let shift = rem.len() - b.len();
+        let c = Rational::from(rem.last().unwrap() / lead);
+        for (i, b) in b.iter().enumerate() {
+            rem[shift + i] -= Rational::from(&c * b);
+        }
+        quotient[shift] = c;
+        // the leading coefficient cancels exactly
+        rem.pop();
+        rem = trim(rem);
+    }
+    (trim(quotient), rem)
+}
+
+fn derivative(p: &Poly) -> Poly {
+    p.iter().enumerate().skip(1).map(|(i, c)| Rational::from(c * i as u32)).collect()
+}
+
+fn gcd(mut a: Poly, mut b: Poly) -> Poly {
+    while !b.is_empty() {
+        (a, b) = (b.clone(), div_rem(&a, &b).1);
+    }
+    a
+}
+
+fn eval(p: &Poly, x: &Rational) -> Rational {
+    p.iter().rev().fold(Rational::new(), |acc, c| acc * x + c)
+}
+
+/// `e` as a polynomial in `x`.
+fn polynomial(e: ExprRef, x: &str) -> color_eyre::Result<Poly> {
+    super::grow_stack(|| polynomial_node(e, x))
+}
+
+fn polynomial_node(e: ExprRef, x: &str) -> color_eyre::Result<Poly> {
+    Ok(match e.node() {
+        Node::Value(Value::Exact(r)) => trim(vec![r.clone()]),
+        Node::Value(v) => bail!("`{NAME}` needs exact coefficients, like 1/2 instead of 0.5, found {}", v.kind()),
+        Node::Symbol(s) if s == x => vec![Rational::new(), Rational::from(1)],
+        Node::Add(items) => {
+            let mut sum = vec![];
+            for &i in items {
+                sum = add(&sum, &polynomial(e.get(i), x)?);
+            }
+            sum
+        }
+        Node::Mul(items) => {
+            let mut product = vec![Rational::from(1)];
+            for &i in items {
+                product = mul(&product, &polynomial(e.get(i), x)?);
+            }
+            product
+        }
+        &Node::Sub(a, b) => add(&polynomial(e.get(a), x)?, &scale(&polynomial(e.get(b), x)?, &Rational::from(-1))),
+        &Node::Neg(a) => scale(&polynomial(e.get(a), x)?, &Rational::from(-1)),
+        &Node::Div(a, b) => match &polynomial(e.get(b), x)?[..] {
+            [c] => scale(&polynomial(e.get(a), x)?, &Rational::from(c.recip_ref())),
+            _ => bail!("`{}` isn't a polynomial in `{x}`", e.to_expr()),
+        },
+        &Node::Pow(a, b) => {
+            let n = match e.get(b).node() {
+                Node::Value(Value::Exact(n)) if n.is_integer() => n.numer().to_usize(),
+                _ => None,
+            };
+            let Some(n) = n.filter(|&n| n <= MAX_DEGREE) else {
+                bail!("`{}` isn't a polynomial in `{x}` of degree up to {MAX_DEGREE}", e.to_expr());
+            };
+            let a = polynomial(e.get(a), x)?;
+            (0..n).fold(vec![Rational::from(1)], |p, _| mul(&p, &a))
+        }
+        _ => bail!("`{}` isn't a polynomial in `{x}` with exact coefficients", e.to_expr()),
+    })
+}
+
+/// The Sturm sequence of a square-free polynomial, whose sign changes at a
+/// point count the roots after it.
+struct Sturm(Vec<Poly>);
+
+impl Sturm {
+    fn new(p: Poly) -> Sturm {
+        let d = derivative(&p);
+        let mut sequence = vec![p, d];
+        while sequence.last().is_some_and(|p| p.len() > 1) {
+            let [.., a, b] = &sequence[..] else { unreachable!("there are at least two") };
+            let rem = scale(&div_rem(a, b).1, &Rational::from(-1));
+            if rem.is_empty() {
+                break;
+            }
+            sequence.push(rem);
+        }
+        Sturm(sequence)
+    }
+
+    fn sign_changes(&self, x: &Rational) -> usize {
+        let signs: Vec<_> = self.0.iter().map(|p| eval(p, x).cmp0()).filter(|s| s.is_ne()).collect();
+        signs.windows(2).filter(|w| w[0] != w[1]).count()
+    }
+
+    /// The number of roots in `(a, b]`.
+    fn count(&self, a: &Rational, b: &Rational) -> usize {
+        self.sign_changes(a) - self.sign_changes(b)
+    }
+
+    fn is_root(&self, x: &Rational) -> bool {
+        eval(&self.0[0], x).is_zero()
+    }
+
+    /// The intervals `(a, b]` that each hold one of the roots in `(a, b]`, in order.
+    fn isolate(&self, a: Rational, b: Rational) -> Vec<(Rational, Rational)> {
+        match self.count(&a, &b) {
+            0 => vec![],
+            // an interval doesn't start at the root before it either
+            1 if !self.is_root(&a) || self.is_root(&b) => vec![self.exact((a, b))],
+            _ => {
+                let mid: Rational = Rational::from(&a + &b) / 2;
+                let mut intervals = self.isolate(a, mid.clone());
+                intervals.extend(self.isolate(mid, b));
+                intervals
+            }
+        }
+    }
+
+    /// The interval, or the root on its right end as an interval of its own.
+    fn exact(&self, (a, b): (Rational, Rational)) -> (Rational, Rational) {
+        match self.is_root(&b) {
+            true => (b.clone(), b),
+            false => (a, b),
+        }
+    }
+
+    /// Halves the interval around the one root in it until it is at most `width` wide.
+    fn refine(&self, (mut a, mut b): (Rational, Rational), width: &Rational) -> (Rational, Rational) {
+        while Rational::from(&b - &a) > *width {
+            let mid: Rational = Rational::from(&a + &b) / 2;
+            match self.count(&a, &mid) {
+                1 => b = mid,
+                _ => a = mid,
+            }
+            (a, b) = self.exact((a, b));
+        }
+        (a, b)
+    }
+}
+
+/// A bound that the absolute values of the roots are below, by Cauchy.
+fn bound(p: &Poly) -> Rational {
+    let lead = p.last().expect("the polynomial isn't zero");
+    let largest = p.iter().map(|c| Rational::from(c / lead).abs()).max().unwrap_or_default();
+    largest + 1
+}
+
+impl Evaluator {
+    /// `isolate_roots(p, x[, width])`, with none of them evaluated yet.
+    pub(super) fn isolate_roots(&mut self, p: ExprRef, x: ExprRef, width: Option<ExprRef>) -> color_eyre::Result<Expr> {
+        let Node::Symbol(x) = x.node() else {
+            bail!("`{NAME}` finds the roots of a polynomial in a symbol, not in `{}`", x.to_expr());
+        };
+        let width = match width {
+            Some(width) => Some(match self.eval_at(width)?.into_value() {
+                Ok(Value::Exact(w)) if w > 0 => w,
+                Ok(Value::Decimal(w)) if w > 0 => w.to_rational().expect("positive decimals are finite"),
+                _ => bail!("the width of the intervals must be a positive number, like 1/1000"),
+            }),
+            None => None,
+        };
+        let p = polynomial(self.eval_in_terms_of(p, x)?.root(), x)?;
+        if p.is_empty() {
+            bail!("every `{x}` is a root of 0");
+        }
+        if p.len() > MAX_DEGREE + 1 {
+            bail!("`{NAME}` finds the roots of polynomials of degree up to {MAX_DEGREE}");
+        }
+        // repeated roots are roots of the derivative too, and would end the sequence early
+        let square_free = div_rem(&p, &gcd(p.clone(), derivative(&p))).0;
+        let sturm = Sturm::new(square_free);
+        let bound = bound(&p);
+        let intervals = sturm.isolate(-bound.clone(), bound);
+        let intervals = intervals.into_iter().map(|i| match &width {
+            Some(width) => sturm.refine(i, width),
+            None => i,
+        });
+        let interval = |(a, b): (Rational, Rational)| Value::List(vec![Value::Exact(a), Value::Exact(b)]);
+        Ok(Expr::value(Value::List(intervals.map(interval).collect())))
+    }
+}
+
+#[test]
+fn test_isolate_roots() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("[[-1, -1], [0, 0], [1, 1]]", eval("isolate_roots(x^3 - x, x)").unwrap());
+    assert_eq!("[[-3, 0], [0, 3]]", eval("isolate_roots(x^2 - 2, x)").unwrap());
+    let sqrt2 = "[[-5793/4096, -2895/2048], [2895/2048, 5793/4096]]";
+    assert_eq!(sqrt2, eval("isolate_roots(x^2 - 2, x, 1/1000)").unwrap());
+    // a double root is found once
+    assert_eq!("[[-3, 3]]", eval("isolate_roots((x - 1)^2*(x^2 + 1), x)").unwrap());
+    assert_eq!("[[0, 0], [3/4, 3/2], [3/2, 9/4], [3, 3]]", eval("isolate_roots(x*(x-1)*(x-2)*(x-3), x)").unwrap());
+    assert_eq!("[]", eval("isolate_roots(x^2 + 1, x)").unwrap());
+    assert!(eval("isolate_roots(x^2 - 0.5, x)").is_err());
+    assert!(eval("isolate_roots(sin(x), x)").is_err());
+}
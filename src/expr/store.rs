@@ -0,0 +1,199 @@
+//! Sharing of the expressions a session keeps, the values of variables and
+//! the bodies of functions. A stored expression has each subexpression that
+//! repeats in it once, as a node that several nodes refer to, and one that is
+//! the same as an expression stored before shares its nodes rather than being
+//! another copy, like the bodies of `f(x) = x^2 + 1` and `g(x) = x^2 + 1`.
+//!
+//! Expressions are told apart by a key made from their structure, which is
+//! hashed to find the ones stored before. `:memstats` shows what it saves.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+use std::sync::Weak;
+
+use super::{Evaluator, Expr, ExprId, ExprRef, Node, Value};
+
+/// The expressions stored so far, by the hash of their key. They are only
+/// referred to weakly, so that the old value of a variable is still freed.
+#[derive(Clone, Default)]
+pub struct Store {
+    stored: HashMap<u64, Vec<Weak<Vec<Node>>>>,
+}
+
+/// How much memory the expressions of a session take, for `:memstats`.
+pub struct MemStats {
+    /// The values of variables and the bodies of functions.
+    pub expressions: usize,
+    /// How many of them share their nodes with one of the others.
+    pub shared: usize,
+    /// The nodes they would have as trees, without any sharing.
+    pub tree_nodes: usize,
+    /// The nodes they actually have.
+    pub nodes: usize,
+    /// The memory those nodes take, not counting what they point to, like long numbers.
+    pub bytes: usize,
+}
+
+/// Writes what tells a value apart from other values, decimals with all of their bits.
+fn value_key(v: &Value, out: &mut String) {
+    let float = |f: &rug::Float, out: &mut String| write!(out, "{}:{}", f.prec(), f.to_string_radix(16, None));
+    let _ = match v {
+        Value::Exact(r) => write!(out, "q{r}"),
+        Value::Decimal(d) => float(d, out),
+        Value::Complex(c) => float(c.real(), out).and_then(|_| float(c.imag(), out)),
+        Value::Quaternion(q) => [&q.w, &q.x, &q.y, &q.z].into_iter().try_for_each(|f| float(f, out)),
+        Value::List(items) | Value::Tuple(items) => {
+            let (open, close) = if matches!(v, Value::List(_)) { ('[', ']') } else { ('(', ')') };
+            out.push(open);
+            for item in items {
+                value_key(item, out);
+                out.push(',');
+            }
+            write!(out, "{close}")
+        }
+        Value::Str(s) => write!(out, "{s:?}"),
+        Value::Quantity(q) => {
+            value_key(&q.magnitude, out);
+            write!(out, " {}{}", q.unit, if q.sexagesimal().is_some() { "°" } else { "" })
+        }
+    };
+}
+
+/// Writes what tells a node apart from others, given that the ids of equal
+/// children are the same.
+fn key(node: &Node, out: &mut String) {
+    let _ = match node {
+        Node::Value(v) => {
+            out.push('v');
+            value_key(v, out);
+            Ok(())
+        }
+        Node::Literal(l) => write!(out, "l{}{}", l.text, if l.imaginary { "i" } else { "" }),
+        Node::Symbol(s) => write!(out, "s{s}"),
+        Node::Add(_) => write!(out, "+"),
+        Node::Sub(..) => write!(out, "-"),
+        Node::Mul(_) => write!(out, "*"),
+        Node::Div(..) => write!(out, "/"),
+        Node::Pow(..) => write!(out, "^"),
+        Node::Neg(_) => write!(out, "n"),
+        Node::Apply(..) => write!(out, "a"),
+        Node::List(_) => write!(out, "["),
+        Node::Tuple(_) => write!(out, "("),
+        Node::Let(name, ..) => write!(out, "let {name}"),
+        Node::Assign(name, annotation, _) => match annotation {
+            Some(annotation) => write!(out, "{name}: {annotation} ="),
+            None => write!(out, "{name} ="),
+        },
+        Node::Unpack(names, _) => write!(out, "{} =", names.join(",")),
+        Node::Define(name, params, _, memo) => {
+            write!(out, "{}{name}({}) =", if *memo { "memo " } else { "" }, params.join(","))
+        }
+        Node::DefineUnit(name, _) => write!(out, "unit {name}"),
+        Node::Quantity(..) => write!(out, "q"),
+        Node::Convert(..) => write!(out, "in"),
+        Node::Block(_) => write!(out, "{{"),
+        Node::For(var, ..) => write!(out, "for {var}"),
+        Node::While(..) => write!(out, "while"),
+    };
+    for child in node.children() {
+        let _ = write!(out, " {child:?}");
+    }
+}
+
+/// The nodes of `e` with each distinct subexpression once, in the order a
+/// walk from the root meets them, and a key that is the same for equal expressions.
+fn canonical(e: &Expr) -> (Vec<Node>, String) {
+    struct Walk {
+        nodes: Vec<Node>,
+        keys: HashMap<String, ExprId>,
+        /// What the nodes of `e` that were walked already became.
+        walked: HashMap<ExprId, ExprId>,
+        key: String,
+    }
+    fn walk(r: ExprRef, w: &mut Walk) -> ExprId {
+        if let Some(&id) = w.walked.get(&r.id()) {
+            return id;
+        }
+        let node = super::grow_stack(|| r.node().clone().map_ids(|child| walk(r.get(child), w)));
+        let mut key = String::new();
+        self::key(&node, &mut key);
+        let id = match w.keys.get(&key) {
+            Some(&id) => id,
+            None => {
+                w.nodes.push(node);
+                let id = ExprId::new(w.nodes.len() - 1);
+                w.key.push_str(&key);
+                w.key.push(';');
+                w.keys.insert(key, id);
+                id
+            }
+        };
+        w.walked.insert(r.id(), id);
+        id
+    }
+    let mut w = Walk { nodes: vec![], keys: HashMap::new(), walked: HashMap::new(), key: String::new() };
+    walk(e.root(), &mut w);
+    (w.nodes, w.key)
+}
+
+impl Store {
+    /// `e` with its repeated subexpressions shared, or an equal expression stored before.
+    pub fn intern(&mut self, e: Expr) -> Expr {
+        let (nodes, key) = canonical(&e);
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let stored = self.stored.entry(hasher.finish()).or_default();
+        stored.retain(|nodes| nodes.strong_count() > 0);
+        if let Some(same) = stored.iter().filter_map(Expr::upgrade).find(|s| canonical(s).1 == key) {
+            return same;
+        }
+        // nothing repeats in `e` if it has as many nodes, so it is kept as it is
+        let e = if nodes.len() < e.nodes().len() { Expr::from_nodes(nodes) } else { e };
+        stored.push(e.downgrade());
+        e
+    }
+}
+
+/// The number of nodes `e` would have as a tree, with shared nodes counted once for each parent.
+fn tree_size(e: &Expr) -> usize {
+    let mut sizes: Vec<usize> = Vec::with_capacity(e.nodes().len());
+    for node in e.nodes() {
+        // children come before their parents
+        let children = node.children().iter().map(|c| sizes[c.index()]).fold(0, usize::saturating_add);
+        sizes.push(children.saturating_add(1));
+    }
+    sizes.last().copied().unwrap_or(0)
+}
+
+impl Evaluator {
+    /// How much memory the values of variables and bodies of functions take.
+    pub fn memory(&self) -> MemStats {
+        let cases = self.functions.values().flat_map(|f| &f.cases).map(|case| &case.body);
+        let expressions: Vec<_> = self.variables.values().chain(cases).collect();
+        let mut seen = HashSet::new();
+        let distinct: Vec<_> = expressions.iter().filter(|e| seen.insert(e.nodes().as_ptr())).collect();
+        let nodes = distinct.iter().map(|e| e.nodes().len()).sum();
+        MemStats {
+            expressions: expressions.len(),
+            shared: expressions.len() - distinct.len(),
+            tree_nodes: expressions.iter().map(|e| tree_size(e)).fold(0, usize::saturating_add),
+            nodes,
+            bytes: nodes * std::mem::size_of::<Node>(),
+        }
+    }
+}
+
+#[test]
+fn test_store() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    eval("f(x) = (x^2 + 1)*(x^2 + 1) + x").unwrap();
+    eval("g(x) = (x^2 + 1)*(x^2 + 1) + x").unwrap();
+    assert_eq!("100", eval("f(3) - g(1) + 2").unwrap());
+    let stats = e.memory();
+    assert_eq!((2, 1), (stats.expressions, stats.shared));
+    // `x^2 + 1` is stored once in the one body both functions share
+    assert_eq!((26, 7), (stats.tree_nodes, stats.nodes));
+}
@@ -0,0 +1,732 @@
+//! Physical units, written after a number like `9.81 m/s^2`, and converted
+//! with `in`, like `3 furlong in m`. A name is only a unit where it follows a
+//! number or a parenthesized expression, after `in`, and in a definition like
+//! `unit furlong = 201.168 m`, and then only if it isn't a variable, so that
+//! `m` and `s` can still be used as symbols anywhere else.
+//!
+//! A quantity keeps the units it was written in, adding `30 cm` to `2 m` gives
+//! `2.3 m`. Units of the same dimension are only converted into each other
+//! when they meet in a product, so that `2 h * 60 km/h` is `120 km`, and a
+//! product without dimension is a plain number again.
+//!
+//! Temperatures in `degC` and `degF` don't start at absolute zero, so they
+//! convert with an offset: `25 degC in degF` is `77 degF`. Such a temperature
+//! is a point on its scale, two of them subtract to a difference in `deltaC`
+//! or `deltaF`, which can be added to one, and they can't be multiplied or
+//! divided at all. `K` is both.
+//!
+//! A product of SI units with the dimensions of a derived unit, or a power of
+//! one, is printed as that unit, `kg*m/s^2` as `N`. With `:set base-units on`
+//! it is the other way around, and `N` is printed as `kg*m/s^2`.
+//!
+//! Data sizes are in `B` and `bit`, which take the binary prefixes as well,
+//! like `KiB` for 1024 bytes.
+//!
+//! Angles in `deg` and times in `h` can also be written in sexagesimal, like
+//! `12°34'56"` and `1h23m45s`, and are printed that way too, including the
+//! results of arithmetic on them. `in dms` and `in hms` write any angle or
+//! time like that.
+
+use std::fmt;
+
+use color_eyre::eyre::{bail, eyre};
+use rug::{Integer, Rational};
+
+use super::{exact, Evaluator, Expr, ExprRef, Node, Value};
+
+/// The powers of the base quantities of a unit: length, mass, time, current,
+/// temperature, amount of substance, luminous intensity, information and angle.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Dims([i8; 9]);
+
+impl Dims {
+    /// The dimensions of a product with `other` raised to `power`.
+    fn times(self, other: Dims, power: i32) -> Dims {
+        Dims(std::array::from_fn(|i| self.0[i] + other.0[i] * power as i8))
+    }
+}
+
+const TEMPERATURE: Dims = Dims([0, 0, 0, 0, 1, 0, 0, 0, 0]);
+
+/// The builtin units, with what one of them is in the SI base units, its
+/// dimensions and whether it takes SI prefixes, like the `k` in `km`.
+const UNITS: &[(&str, &str, [i8; 9], bool)] = &[
+    ("m", "1", [1, 0, 0, 0, 0, 0, 0, 0, 0], true),
+    ("g", "1e-3", [0, 1, 0, 0, 0, 0, 0, 0, 0], true),
+    ("s", "1", [0, 0, 1, 0, 0, 0, 0, 0, 0], true),
+    ("A", "1", [0, 0, 0, 1, 0, 0, 0, 0, 0], true),
+    ("K", "1", [0, 0, 0, 0, 1, 0, 0, 0, 0], true),
+    ("degR", "5/9", [0, 0, 0, 0, 1, 0, 0, 0, 0], false),
+    ("deltaC", "1", [0, 0, 0, 0, 1, 0, 0, 0, 0], false),
+    ("deltaF", "5/9", [0, 0, 0, 0, 1, 0, 0, 0, 0], false),
+    ("mol", "1", [0, 0, 0, 0, 0, 1, 0, 0, 0], true),
+    ("cd", "1", [0, 0, 0, 0, 0, 0, 1, 0, 0], true),
+    ("min", "60", [0, 0, 1, 0, 0, 0, 0, 0, 0], false),
+    ("h", "3600", [0, 0, 1, 0, 0, 0, 0, 0, 0], false),
+    ("d", "86400", [0, 0, 1, 0, 0, 0, 0, 0, 0], false),
+    ("week", "604800", [0, 0, 1, 0, 0, 0, 0, 0, 0], false),
+    // a Julian year, 365.25 days
+    ("yr", "31557600", [0, 0, 1, 0, 0, 0, 0, 0, 0], false),
+    ("inch", "0.0254", [1, 0, 0, 0, 0, 0, 0, 0, 0], false),
+    ("ft", "0.3048", [1, 0, 0, 0, 0, 0, 0, 0, 0], false),
+    ("yd", "0.9144", [1, 0, 0, 0, 0, 0, 0, 0, 0], false),
+    ("mi", "1609.344", [1, 0, 0, 0, 0, 0, 0, 0, 0], false),
+    ("nmi", "1852", [1, 0, 0, 0, 0, 0, 0, 0, 0], false),
+    ("au", "149597870700", [1, 0, 0, 0, 0, 0, 0, 0, 0], false),
+    ("ly", "9460730472580800", [1, 0, 0, 0, 0, 0, 0, 0, 0], false),
+    ("ha", "1e4", [2, 0, 0, 0, 0, 0, 0, 0, 0], false),
+    ("acre", "4046.8564224", [2, 0, 0, 0, 0, 0, 0, 0, 0], false),
+    ("L", "1e-3", [3, 0, 0, 0, 0, 0, 0, 0, 0], true),
+    ("gal", "0.003785411784", [3, 0, 0, 0, 0, 0, 0, 0, 0], false),
+    ("tonne", "1e3", [0, 1, 0, 0, 0, 0, 0, 0, 0], false),
+    ("lb", "0.45359237", [0, 1, 0, 0, 0, 0, 0, 0, 0], false),
+    ("oz", "0.028349523125", [0, 1, 0, 0, 0, 0, 0, 0, 0], false),
+    ("knot", "1852/3600", [1, 0, -1, 0, 0, 0, 0, 0, 0], false),
+    ("Hz", "1", [0, 0, -1, 0, 0, 0, 0, 0, 0], true),
+    ("N", "1", [1, 1, -2, 0, 0, 0, 0, 0, 0], true),
+    ("J", "1", [2, 1, -2, 0, 0, 0, 0, 0, 0], true),
+    ("Wh", "3600", [2, 1, -2, 0, 0, 0, 0, 0, 0], true),
+    ("cal", "4.184", [2, 1, -2, 0, 0, 0, 0, 0, 0], true),
+    ("W", "1", [2, 1, -3, 0, 0, 0, 0, 0, 0], true),
+    ("Pa", "1", [-1, 1, -2, 0, 0, 0, 0, 0, 0], true),
+    ("bar", "1e5", [-1, 1, -2, 0, 0, 0, 0, 0, 0], true),
+    ("atm", "101325", [-1, 1, -2, 0, 0, 0, 0, 0, 0], false),
+    ("C", "1", [0, 0, 1, 1, 0, 0, 0, 0, 0], true),
+    ("V", "1", [2, 1, -3, -1, 0, 0, 0, 0, 0], true),
+    ("ohm", "1", [2, 1, -3, -2, 0, 0, 0, 0, 0], true),
+    ("bit", "1", [0, 0, 0, 0, 0, 0, 0, 1, 0], true),
+    ("B", "8", [0, 0, 0, 0, 0, 0, 0, 1, 0], true),
+    ("deg", "1", [0, 0, 0, 0, 0, 0, 0, 0, 1], false),
+    ("arcmin", "1/60", [0, 0, 0, 0, 0, 0, 0, 0, 1], false),
+    ("arcsec", "1/3600", [0, 0, 0, 0, 0, 0, 0, 0, 1], false),
+    ("turn", "360", [0, 0, 0, 0, 0, 0, 0, 0, 1], false),
+];
+
+/// The units of information, which also take the binary prefixes.
+const DATA: &[&str] = &["bit", "B"];
+
+/// The binary prefixes, `Ki` for 1024 like in `KiB`.
+const BINARY_PREFIXES: &[(&str, u32)] = &[("Ki", 10), ("Mi", 20), ("Gi", 30), ("Ti", 40), ("Pi", 50), ("Ei", 60)];
+
+/// The derived SI units that products of SI units are printed as.
+const DERIVED: &[&str] = &["N", "J", "W", "Pa", "C", "V", "ohm"];
+
+/// The SI base units, in the order of the dimensions they measure.
+const BASE: &[&str] = &["m", "kg", "s", "A", "K", "mol", "cd"];
+
+/// The temperature scales that don't start at absolute zero, with the size of
+/// a degree and where the scale starts in kelvin, and the unit of a difference
+/// between two temperatures on the scale.
+const TEMPERATURES: &[(&str, &str, &str, &str)] =
+    &[("degC", "1", "273.15", "deltaC"), ("degF", "5/9", "45967/180", "deltaF")];
+
+const PREFIXES: &[(&str, &str)] = &[
+    ("E", "1e18"),
+    ("P", "1e15"),
+    ("T", "1e12"),
+    ("G", "1e9"),
+    ("M", "1e6"),
+    ("k", "1e3"),
+    ("c", "1e-2"),
+    ("m", "1e-3"),
+    ("u", "1e-6"),
+    ("µ", "1e-6"),
+    ("n", "1e-9"),
+    ("p", "1e-12"),
+];
+
+/// The scale of a builtin unit or a prefix, a decimal or a fraction like `1852/3600`.
+fn scale(text: &str) -> Rational {
+    let (numer, denom) = text.split_once('/').unwrap_or((text, "1"));
+    let literal = |text| exact::literal(text).expect("the scales of builtin units are valid literals");
+    literal(numer) / literal(denom)
+}
+
+/// A builtin unit, possibly with a prefix, like `km`.
+fn builtin(name: &str) -> Option<Factor> {
+    let unit = |name: &str| UNITS.iter().find(|u| u.0 == name);
+    if let Some(&(_, text, dims, _)) = unit(name) {
+        let scale = Value::Exact(scale(text));
+        return Some(Factor { name: name.into(), power: 1, scale, dims: Dims(dims), zero: None });
+    }
+    if let Some(&(_, size, zero, _)) = TEMPERATURES.iter().find(|t| t.0 == name) {
+        let (scale, zero) = (Value::Exact(scale(size)), Some(scale(zero)));
+        return Some(Factor { name: name.into(), power: 1, scale, dims: TEMPERATURE, zero });
+    }
+    let binary = BINARY_PREFIXES.iter().find_map(|&(prefix, bits)| {
+        let &(_, text, dims, _) = unit(name.strip_prefix(prefix).filter(|base| DATA.contains(base))?)?;
+        Some((scale(text) << bits, dims))
+    });
+    let decimal = || {
+        PREFIXES.iter().find_map(|&(prefix, factor)| {
+            let &(_, text, dims, prefixable) = unit(name.strip_prefix(prefix)?)?;
+            prefixable.then(|| (scale(text) * scale(factor), dims))
+        })
+    };
+    let (scale, dims) = binary.or_else(decimal)?;
+    Some(Factor { name: name.into(), power: 1, scale: Value::Exact(scale), dims: Dims(dims), zero: None })
+}
+
+/// Whether `name` is a builtin unit, which implicit multiplication leaves for
+/// the parser to read as one.
+pub fn is_builtin(name: &str) -> bool {
+    builtin(name).is_some()
+}
+
+/// A named unit raised to a power, like the `s^-2` in `m/s^2`.
+#[derive(Clone)]
+pub struct Factor {
+    name: String,
+    power: i32,
+    /// What one of the unit is in the base units, `1000` for `km`.
+    scale: Value,
+    dims: Dims,
+    /// Where the scale starts in kelvin, for a temperature like `degC`.
+    zero: Option<Rational>,
+}
+
+/// A product of units, like `kg*m/s^2`, each name only once.
+#[derive(Clone, Default)]
+pub struct Unit {
+    factors: Vec<Factor>,
+}
+
+impl Unit {
+    pub fn is_empty(&self) -> bool {
+        self.factors.is_empty()
+    }
+
+    /// The names of the units and their powers.
+    pub fn factors(&self) -> impl Iterator<Item = (&str, i32)> {
+        self.factors.iter().map(|f| (f.name.as_str(), f.power))
+    }
+
+    pub fn dims(&self) -> Dims {
+        self.factors.iter().fold(Dims::default(), |dims, f| dims.times(f.dims, f.power))
+    }
+
+    /// The temperature like `degC` that is the whole unit.
+    fn temperature(&self) -> Option<&Factor> {
+        match &self.factors[..] {
+            [f] if f.zero.is_some() && f.power == 1 => Some(f),
+            _ => None,
+        }
+    }
+
+    /// The derived unit like `J` or `N^2` to print the unit as, if it is a
+    /// product of SI units without a factor, like `kg*m^2/s^2` or `N*m`.
+    pub fn derived(&self) -> Option<Unit> {
+        let coherent = |f: &Factor| f.zero.is_none() && matches!(&f.scale, Value::Exact(r) if *r == 1);
+        if self.factors.len() < 2 || !self.factors.iter().all(coherent) {
+            return None;
+        }
+        let dims = self.dims();
+        // a power of a derived unit divides every exponent
+        let gcd = dims.0.iter().fold(0, |a, &b| gcd(a, b.unsigned_abs())) as i32;
+        DERIVED.iter().find_map(|&name| {
+            let factor = builtin(name).expect("derived units are builtin");
+            let power = (1..=gcd).flat_map(|n| [n, -n]).find(|&n| Dims::default().times(factor.dims, n) == dims)?;
+            Some(Unit { factors: vec![Factor { power, ..factor }] })
+        })
+    }
+
+    /// The unit with the derived SI units in it written in base units, `N*m`
+    /// as `m^2*kg/s^2`, if it has any. Like in [`Unit::derived`], the magnitude
+    /// stays the same, so `kN` isn't one of them.
+    pub fn in_base_units(&self) -> Option<Unit> {
+        let derived = |f: &Factor| DERIVED.contains(&f.name.as_str());
+        if !self.factors.iter().any(derived) {
+            return None;
+        }
+        let mut unit = Unit::default();
+        for f in &self.factors {
+            if !derived(f) {
+                unit.push(f.clone());
+                continue;
+            }
+            for (&name, &power) in BASE.iter().zip(&f.dims.0).filter(|(_, &power)| power != 0) {
+                let base = builtin(name).expect("the SI base units are builtin");
+                unit.push(Factor { power: i32::from(power) * f.power, ..base });
+            }
+        }
+        Some(unit).filter(|unit| !unit.is_empty())
+    }
+
+    /// The unit to print in place of this one, in base units with `:set
+    /// base-units on` and as a derived unit otherwise.
+    pub fn printed_as(&self, base_units: bool) -> Option<Unit> {
+        if base_units { self.in_base_units() } else { self.derived() }
+    }
+
+    /// What one of the unit is in the base units.
+    fn scale(&self, e: &Evaluator) -> color_eyre::Result<Value> {
+        self.factors.iter().try_fold(Value::one(), |scale, f| {
+            scale.mul(f.scale.clone().pow(Value::Exact(f.power.into()), e)?, e)
+        })
+    }
+
+    /// Multiplies in `factor`, adding to the power of a factor with the same name.
+    fn push(&mut self, factor: Factor) {
+        match self.factors.iter_mut().find(|f| f.name == factor.name) {
+            Some(f) => f.power += factor.power,
+            None => self.factors.push(factor),
+        }
+        self.factors.retain(|f| f.power != 0);
+    }
+
+    /// The product with `other` raised to `sign`, 1 or -1, and the number
+    /// the magnitudes are multiplied by for the factors of `other` that were
+    /// converted into factors of the same dimension in `self`.
+    fn combine(mut self, other: Unit, sign: i32, e: &Evaluator) -> color_eyre::Result<(Unit, Value)> {
+        let mut conversion = Value::one();
+        for mut factor in other.factors {
+            factor.power *= sign;
+            let same = |f: &&mut Factor| f.name != factor.name && f.dims == factor.dims;
+            if let Some(into) = self.factors.iter_mut().find(same) {
+                let ratio = factor.scale.clone().div(into.scale.clone(), e)?;
+                conversion = conversion.mul(ratio.pow(Value::Exact(factor.power.into()), e)?, e)?;
+                factor.name.clone_from(&into.name);
+            }
+            self.push(factor);
+        }
+        Ok((self, conversion))
+    }
+
+    fn pow(mut self, power: i32) -> Unit {
+        for f in &mut self.factors {
+            f.power *= power;
+        }
+        self.factors.retain(|f| f.power != 0);
+        self
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let factor = |name: &str, power: i32| if power == 1 { name.to_owned() } else { format!("{name}^{power}") };
+        let (above, below): (Vec<_>, Vec<_>) = self.factors.iter().partition(|f| f.power > 0);
+        // `s^-1` rather than `1/s`, which would take the `1` as the number
+        if above.is_empty() {
+            let factors: Vec<_> = below.iter().map(|f| factor(&f.name, f.power)).collect();
+            return f.write_str(&factors.join("*"));
+        }
+        let above: Vec<_> = above.iter().map(|f| factor(&f.name, f.power)).collect();
+        let below: Vec<_> = below.iter().map(|f| factor(&f.name, -f.power)).collect();
+        f.write_str(&above.join("*"))?;
+        match &below[..] {
+            [] => Ok(()),
+            [one] => write!(f, "/{one}"),
+            many => write!(f, "/({})", many.join("*")),
+        }
+    }
+}
+
+fn gcd(mut a: u8, mut b: u8) -> u8 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// A number with a unit.
+#[derive(Clone)]
+pub struct Quantity {
+    pub magnitude: Value,
+    pub unit: Unit,
+    /// Whether an angle in `deg` or a time in `h` is printed in sexagesimal.
+    sexagesimal: bool,
+}
+
+/// An angle like `12°34'56"` or a time like `1h23m45s`, split into its parts.
+pub struct Sexagesimal {
+    pub angle: bool,
+    pub negative: bool,
+    pub whole: Integer,
+    pub minutes: Integer,
+    pub seconds: Rational,
+}
+
+impl Quantity {
+    /// The parts to print the quantity with, if it is written in sexagesimal.
+    pub fn sexagesimal(&self) -> Option<Sexagesimal> {
+        let angle = sexagesimal_unit(&self.unit).filter(|_| self.sexagesimal)?;
+        let r = match &self.magnitude {
+            Value::Exact(r) => r.clone(),
+            Value::Decimal(d) => d.to_rational()?,
+            _ => return None,
+        };
+        let negative = r < 0;
+        let r = r.abs();
+        let (fraction, whole) = r.fract_trunc(Integer::new());
+        let (fraction, minutes) = (fraction * 60u32).fract_trunc(Integer::new());
+        let seconds = fraction * 60u32;
+        Some(Sexagesimal { angle, negative, whole, minutes, seconds })
+    }
+}
+
+/// Whether the unit is `deg`, true, or `h`, false, the units written in sexagesimal.
+fn sexagesimal_unit(unit: &Unit) -> Option<bool> {
+    match unit.factors().collect::<Vec<_>>()[..] {
+        [("deg", 1)] => Some(true),
+        [("h", 1)] => Some(false),
+        _ => None,
+    }
+}
+
+/// `v` written in sexagesimal, if it is an angle in `deg` or a time in `h`.
+fn in_sexagesimal(v: Value) -> Value {
+    match v {
+        Value::Quantity(mut q) => {
+            q.sexagesimal = sexagesimal_unit(&q.unit).is_some();
+            Value::Quantity(q)
+        }
+        v => v,
+    }
+}
+
+/// The value of a literal like `12°34'56"` or `1h23m45s`.
+pub(super) fn sexagesimal(text: &str) -> color_eyre::Result<Value> {
+    let angle = ['°', '\'', '"'];
+    let (unit, markers) = if text.contains(angle) { ("deg", angle) } else { ("h", ['h', 'm', 's']) };
+    let (mut magnitude, mut rest) = (Rational::new(), text);
+    for (marker, denom) in markers.into_iter().zip([1, 60, 3600]) {
+        let Some((part, after)) = rest.split_once(marker) else {
+            continue;
+        };
+        magnitude += exact::literal(part)? / denom;
+        rest = after;
+    }
+    let unit = Unit { factors: vec![builtin(unit).expect("`deg` and `h` are builtin units")] };
+    Ok(Value::Quantity(Box::new(Quantity { magnitude: Value::Exact(magnitude), unit, sexagesimal: true })))
+}
+
+/// The magnitude and unit of a value, which has no unit if it isn't a quantity.
+fn split(v: Value) -> (Value, Unit) {
+    match v {
+        Value::Quantity(q) => (q.magnitude, q.unit),
+        v => (v, Unit::default()),
+    }
+}
+
+/// A quantity, or just the magnitude if the unit is empty.
+fn quantity(magnitude: Value, unit: Unit) -> Value {
+    if unit.is_empty() {
+        magnitude
+    } else {
+        Value::Quantity(Box::new(Quantity { magnitude, unit, sexagesimal: false }))
+    }
+}
+
+/// `magnitude` of `from` in `to`, which must be of the same dimension.
+fn convert(magnitude: Value, from: &Unit, to: &Unit, e: &Evaluator) -> color_eyre::Result<Value> {
+    if from.dims() != to.dims() {
+        bail!("cannot convert {} to {}, which are of different dimensions", describe(from), describe(to));
+    }
+    if from.temperature().is_none() && to.temperature().is_none() {
+        return magnitude.mul(from.scale(e)?, e)?.div(to.scale(e)?, e);
+    }
+    // a temperature is measured from absolute zero in between
+    if let Some(name) = [from, to].into_iter().find_map(|unit| difference_of(unit)) {
+        bail!("`{name}` is a difference of temperatures, not a temperature on a scale");
+    }
+    let zero = |unit: &Unit| unit.temperature().map_or(Value::zero(), |f| Value::Exact(f.zero.clone().unwrap()));
+    let kelvin = magnitude.mul(from.scale(e)?, e)?.add(zero(from), e)?;
+    kelvin.sub(zero(to), e)?.div(to.scale(e)?, e)
+}
+
+/// The name of the unit if it is a difference of temperatures, like `deltaC`.
+fn difference_of(unit: &Unit) -> Option<&str> {
+    match &unit.factors[..] {
+        [f] if f.power == 1 && TEMPERATURES.iter().any(|t| t.3 == f.name) => Some(&f.name),
+        _ => None,
+    }
+}
+
+/// The unit of a difference between two temperatures in `t`, like `deltaC` for `degC`.
+fn difference(t: &Factor) -> Unit {
+    let &(_, _, _, name) = TEMPERATURES.iter().find(|u| u.0 == t.name).expect("only builtin units are temperatures");
+    Unit { factors: vec![builtin(name).expect("differences of temperatures are builtin units")] }
+}
+
+/// `a + b` or `a - b`, where `a` or `b` is a temperature like `degC`: a
+/// difference can be added to a temperature or subtracted from it, and two
+/// temperatures subtracted from each other to give their difference.
+fn add_temperatures(a: (Value, Unit), op: &str, b: (Value, Unit), e: &Evaluator) -> color_eyre::Result<Value> {
+    let ((x, u), (y, v)) = (a, b);
+    match (u.temperature(), v.temperature()) {
+        (Some(t), Some(_)) if op == "-" => {
+            let y = convert(y, &v, &u, e)?;
+            Ok(quantity(x.sub(y, e)?, difference(t)))
+        }
+        (Some(_), Some(_)) => bail!("cannot add two temperatures, only a difference like `deltaC` to one"),
+        (Some(t), None) => {
+            let y = convert(y, &v, &difference(t), e)?;
+            Ok(quantity(if op == "+" { x.add(y, e)? } else { x.sub(y, e)? }, u))
+        }
+        (None, Some(t)) if op == "+" => {
+            let x = convert(x, &u, &difference(t), e)?;
+            Ok(quantity(x.add(y, e)?, v))
+        }
+        _ => bail!("cannot subtract a temperature from a difference of temperatures"),
+    }
+}
+
+fn describe(unit: &Unit) -> String {
+    if unit.is_empty() { "a number".into() } else { format!("`{unit}`") }
+}
+
+/// `a op b`, where at least one of them is a quantity, in sexagesimal if one of them is.
+pub(super) fn arithmetic(a: Value, op: &str, b: Value, e: &Evaluator) -> color_eyre::Result<Value> {
+    let sexagesimal = [&a, &b].into_iter().any(|v| matches!(v, Value::Quantity(q) if q.sexagesimal));
+    let result = apply(a, op, b, e)?;
+    Ok(if sexagesimal { in_sexagesimal(result) } else { result })
+}
+
+fn apply(a: Value, op: &str, b: Value, e: &Evaluator) -> color_eyre::Result<Value> {
+    let ((x, u), (y, v)) = (split(a), split(b));
+    match op {
+        "+" | "-" => {
+            let verb = if op == "+" { "add" } else { "subtract" };
+            if u.dims() != v.dims() {
+                bail!("cannot {verb} {} and {}, which are of different dimensions", describe(&u), describe(&v));
+            }
+            if u.temperature().is_some() || v.temperature().is_some() {
+                return add_temperatures((x, u), op, (y, v), e);
+            }
+            // the sum is in the unit on the left, unless that is a plain number
+            let (x, y, unit) = if u.is_empty() { (convert(x, &u, &v, e)?, y, v) } else { (x, convert(y, &v, &u, e)?, u) };
+            Ok(quantity(if op == "+" { x.add(y, e)? } else { x.sub(y, e)? }, unit))
+        }
+        "*" | "/" => {
+            // `2 * 10 degC` has no meaning either, since `0 degC` isn't nothing
+            if u.temperature().is_some() || v.temperature().is_some() {
+                bail!("a temperature like `degC` can't be multiplied or divided, use `K` or `deltaC` instead");
+            }
+            let (unit, conversion) = u.combine(v, if op == "*" { 1 } else { -1 }, e)?;
+            let magnitude = if op == "*" { x.mul(y, e)? } else { x.div(y, e)? };
+            Ok(quantity(magnitude.mul(conversion, e)?, unit))
+        }
+        _ => {
+            if !v.is_empty() {
+                bail!("cannot raise to the power of a quantity");
+            }
+            if u.temperature().is_some() {
+                bail!("a temperature like `degC` can't be raised to a power, use `K` or `deltaC` instead");
+            }
+            let power = match &y {
+                Value::Exact(r) if *r.denom() == 1 => r.numer().to_i32(),
+                _ => None,
+            };
+            let Some(power) = power else {
+                bail!("a quantity can only be raised to an integer power");
+            };
+            Ok(quantity(x.pow(y, e)?, u.pow(power)))
+        }
+    }
+}
+
+/// The magnitudes of two values to compare, `b` converted to the unit of `a`.
+pub(super) fn comparable(a: Value, b: Value, e: &Evaluator) -> color_eyre::Result<(Value, Value)> {
+    let ((x, u), (y, v)) = (split(a), split(b));
+    if u.dims() != v.dims() {
+        bail!("cannot compare {} and {}, which are of different dimensions", describe(&u), describe(&v));
+    }
+    Ok((x, convert(y, &v, &u, e)?))
+}
+
+/// A unit the user defined with `unit name = value`.
+#[derive(Clone)]
+pub(super) struct Definition {
+    factor: Factor,
+    /// The value as written, to save the definition with.
+    value: Expr,
+}
+
+impl Evaluator {
+    /// The names of the units the user defined.
+    pub fn unit_names(&self) -> impl Iterator<Item = &str> {
+        self.units.keys().map(String::as_str)
+    }
+
+    /// The unit called `name`, defined by the user or builtin.
+    fn unit(&self, name: &str) -> Option<Factor> {
+        self.units.get(name).map(|d| d.factor.clone()).or_else(|| builtin(name))
+    }
+
+    /// The units defined with `unit name = value`, as those statements.
+    pub(super) fn unit_definitions(&self) -> impl Iterator<Item = Expr> + '_ {
+        self.units.iter().map(|(name, d)| Expr::define_unit(name.clone(), d.value.clone()))
+    }
+
+    /// Whether `name` is a variable, or an argument of the function being called.
+    fn is_bound(&self, name: &str) -> bool {
+        self.scopes.last().is_some_and(|scope| scope.contains_key(name)) || self.variables.contains_key(name)
+    }
+
+    /// The unit after a number, with the names that are units replaced by
+    /// one of them and the others left to evaluate as usual.
+    fn units_in(&self, e: ExprRef) -> Expr {
+        match e.node() {
+            Node::Symbol(name) if !self.is_bound(name) => match self.unit(name) {
+                Some(factor) => Expr::value(quantity(Value::one(), Unit { factors: vec![factor] })),
+                None => e.to_expr(),
+            },
+            Node::Mul(items) => Expr::product(items.iter().map(|&i| self.units_in(e.get(i))).collect()),
+            &Node::Div(a, b) => Expr::binary(self.units_in(e.get(a)), self.units_in(e.get(b)), Node::Div),
+            &Node::Pow(base, exp) => Expr::binary(self.units_in(e.get(base)), e.get(exp).to_expr(), Node::Pow),
+            _ => e.to_expr(),
+        }
+    }
+
+    /// `magnitude unit`, like `9.81 m/s^2`.
+    pub(super) fn eval_quantity(&mut self, magnitude: ExprRef, unit: ExprRef) -> color_eyre::Result<Expr> {
+        // `25 degC` is a point on the scale rather than 25 times `1 degC`
+        let temperature = match unit.node() {
+            Node::Symbol(name) if !self.is_bound(name) => self.unit(name).filter(|f| f.zero.is_some()),
+            _ => None,
+        };
+        if let Some(factor) = temperature {
+            let magnitude = self.eval_at(magnitude)?;
+            if let Some(v @ (Value::Exact(_) | Value::Decimal(_))) = magnitude.as_value() {
+                return Ok(Expr::value(quantity(v.clone(), Unit { factors: vec![factor] })));
+            }
+            bail!("a temperature like `degC` needs a number, use `K` or `deltaC` instead");
+        }
+        let product = Expr::product(vec![magnitude.to_expr(), self.units_in(unit)]);
+        self.eval_at(product.root())
+    }
+
+    /// The unit after `in`, in which every name must be a unit.
+    fn target(&self, e: ExprRef) -> color_eyre::Result<Unit> {
+        Ok(match e.node() {
+            Node::Symbol(name) => match self.unit(name) {
+                Some(factor) => Unit { factors: vec![factor] },
+                None => bail!("unknown unit `{name}`"),
+            },
+            Node::Mul(items) => items.iter().try_fold(Unit::default(), |mut unit, &i| {
+                self.target(e.get(i))?.factors.into_iter().for_each(|f| unit.push(f));
+                Ok::<_, color_eyre::Report>(unit)
+            })?,
+            &Node::Div(a, b) => {
+                let mut unit = self.target(e.get(a))?;
+                self.target(e.get(b))?.pow(-1).factors.into_iter().for_each(|f| unit.push(f));
+                unit
+            }
+            &Node::Pow(base, exp) => match e.get(exp).node() {
+                Node::Value(Value::Exact(r)) if *r.denom() == 1 && r.numer().to_i32().is_some() => {
+                    self.target(e.get(base))?.pow(r.numer().to_i32().unwrap())
+                }
+                _ => bail!("units can only be raised to integer powers"),
+            },
+            _ => bail!("expected a unit after `in`"),
+        })
+    }
+
+    /// `value in unit`, or in sexagesimal with `in dms` and `in hms`.
+    pub(super) fn eval_convert(&mut self, value: ExprRef, unit: ExprRef) -> color_eyre::Result<Expr> {
+        let sexagesimal = match unit.node() {
+            Node::Symbol(name) if !self.units.contains_key(name) => match name.as_str() {
+                "dms" => Some("deg"),
+                "hms" => Some("h"),
+                _ => None,
+            },
+            _ => None,
+        };
+        let target = match sexagesimal {
+            Some(name) => Unit { factors: vec![builtin(name).expect("`deg` and `h` are builtin units")] },
+            None => self.target(unit)?,
+        };
+        if target.factors.iter().any(|f| f.zero.is_some()) && target.temperature().is_none() {
+            bail!("a temperature like `degC` can't be part of a product or a power, use `K` or `deltaC` instead");
+        }
+        Ok(match self.eval_at(value)?.into_value() {
+            Ok(v) => {
+                let (magnitude, from) = split(v);
+                let converted = quantity(convert(magnitude, &from, &target, self)?, target);
+                Expr::value(if sexagesimal.is_some() { in_sexagesimal(converted) } else { converted })
+            }
+            Err(other) => Expr::convert(other, unit.to_expr()),
+        })
+    }
+
+    /// `v` in the unit called `name`, for a variable annotated with it, or
+    /// `None` if there is no such unit. A number without a unit is taken to
+    /// be in it already.
+    pub(super) fn in_unit(&self, v: Value, name: &str) -> Option<color_eyre::Result<Value>> {
+        let target = Unit { factors: vec![self.unit(name)?] };
+        let (magnitude, from) = split(v);
+        if from.is_empty() {
+            return Some(Ok(quantity(magnitude, target)));
+        }
+        Some(convert(magnitude, &from, &target, self).map(|magnitude| quantity(magnitude, target)))
+    }
+
+    /// `unit name = value`, a new unit or a new value for one the user defined.
+    pub(super) fn define_unit(&mut self, name: &str, value: ExprRef) -> color_eyre::Result<()> {
+        if builtin(name).is_some() {
+            bail!("`{name}` is a builtin unit and can't be redefined");
+        }
+        let v = self.eval_at(value)?.into_value().map_err(|e| eyre!("`{e}` isn't a number or a quantity"))?;
+        let (magnitude, unit) = split(v);
+        if !matches!(magnitude, Value::Exact(_) | Value::Decimal(_)) {
+            bail!("a unit must be a real number of some unit, not {}", magnitude.kind());
+        }
+        if unit.temperature().is_some() {
+            bail!("a unit can't be a temperature like `degC`, only a difference like `deltaC`");
+        }
+        let scale = magnitude.mul(unit.scale(self)?, self)?;
+        let factor = Factor { name: name.to_owned(), power: 1, scale, dims: unit.dims(), zero: None };
+        self.units.insert(name.to_owned(), Definition { factor, value: value.to_expr() });
+        self.clear_cache();
+        Ok(())
+    }
+}
+
+#[test]
+fn test_units() {
+    let mut e = Evaluator::default();
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("(23/10) m", eval("2 m + 30 cm").unwrap());
+    assert_eq!("120 km", eval("2 h * 60 km/h").unwrap());
+    assert_eq!("4", eval("(2 m)/(50 cm)").unwrap());
+    assert_eq!("(9/4) m^2", eval("((3/2) m)^2").unwrap());
+    assert!(eval("2 m + 3 s").is_err());
+    eval("unit furlong = 201.168 m").unwrap();
+    assert_eq!("603.50400 m", eval("3 furlong in m").unwrap());
+    assert_eq!("(5/2) km/h", eval("2500 m/(1 h) in km/h").unwrap());
+    assert_eq!("1610.6127 MB", eval("1.5 GiB in MB").unwrap());
+    assert_eq!("(109375/4096) s", eval("700 MB / (25 MiB/s)").unwrap());
+    // temperatures convert with an offset, and their differences without
+    assert_eq!("77 degF", eval("25 degC in degF").unwrap());
+    assert_eq!("(537/20) degC", eval("300 K in degC").unwrap());
+    assert_eq!("10 deltaC", eval("30 degC - 20 degC").unwrap());
+    assert_eq!("25 degC", eval("20 degC + 9 deltaF").unwrap());
+    assert_eq!("1", eval("37 degC > 98 degF").unwrap());
+    assert!(eval("20 degC + 20 degC").is_err());
+    assert!(eval("10 deltaC in degF").is_err());
+    assert_eq!("-5 degC", eval("-5 degC").unwrap());
+    eval("t = 20").unwrap();
+    assert_eq!("20 degC", eval("t degC").unwrap());
+    assert!(eval("2 * 10 degC").unwrap_err().to_string().contains("use `K` or `deltaC`"));
+    assert!(eval("10 degC / 2").is_err());
+    assert!(eval("(2 m) degC").is_err());
+    assert_eq!("20 deltaC", eval("2 * 10 deltaC").unwrap());
+    assert_eq!("13°5'1\"", eval("12°34'56\" + 30'5\"").unwrap());
+    assert_eq!("(1509/1000) h", eval("1h30m32.4s in h").unwrap());
+    assert_eq!("2h47m30s", eval("1h23m45s * 2").unwrap());
+    assert_eq!("0h2m15s", eval("135 s in hms").unwrap());
+    assert_eq!("6 N", eval("3 kg * 2 m/s^2").unwrap());
+    assert_eq!("12 J", eval("6 N * 2 m").unwrap());
+    assert_eq!("4 J^2", eval("(2 kg*m^2/s^2)^2").unwrap());
+    // names are only units after a number, and variables come first
+    assert_eq!("2*m", eval("2*m").unwrap());
+    eval("s = 4").unwrap();
+    assert_eq!("3 m", eval("12 m/s").unwrap());
+    let mut e = Evaluator::default();
+    e.settings_mut().base_units = true;
+    let mut eval = |line: &str| crate::session::eval_text(&mut e, line);
+    assert_eq!("6 kg*m/s^2", eval("3 kg * 2 m/s^2").unwrap());
+    assert_eq!("6 m^2*kg/s^2", eval("2 N * 3 m").unwrap());
+    assert_eq!("5 kg/(m*s^2)", eval("5 Pa").unwrap());
+    assert_eq!("2 kN", eval("2 kN").unwrap());
+}
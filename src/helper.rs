@@ -0,0 +1,379 @@
+//! Line editing help: closing brackets the user hasn't typed yet are shown
+//! as a hint and added when the line is entered, and places where a `*` is
+//! missing between two factors (`2x`, `(a)(b)`) are underlined and filled in.
+//! A unit after a number (`2 km`) is left for the parser to read as one.
+//! With `:set preview on`, the result of the line is shown as it is typed,
+//! unless it takes too long to compute.
+
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::Validator;
+use rustyline::Context;
+
+use crate::expr::{self, builtins, Evaluator};
+use crate::natural;
+use crate::settings::RenderMode;
+
+/// Previews longer than this are cut off.
+const MAX_PREVIEW: usize = 60;
+
+/// How long a preview may take before it is given up on, so that typing isn't held up.
+const PREVIEW_TIME: Duration = Duration::from_millis(50);
+
+/// Whether a preview is being evaluated, perhaps one that was given up on. The
+/// evaluator stops at the deadline, but a single slow builtin call can't be
+/// stopped, and until it is done there are no other previews, so that those
+/// don't pile up while typing.
+static PREVIEWING: AtomicBool = AtomicBool::new(false);
+
+/// Clears [`PREVIEWING`] when the preview is done, even if it panics.
+struct Previewing;
+
+impl Drop for Previewing {
+    fn drop(&mut self) {
+        PREVIEWING.store(false, Ordering::Release);
+    }
+}
+
+pub struct Helper {
+    pub autoclose: bool,
+    pub implicit_mul: bool,
+    /// Whether lines go through [`natural::rewrite`] first.
+    pub natural: bool,
+    pub color: bool,
+    /// The units the user defined, besides the builtin ones.
+    pub units: Vec<String>,
+    /// A copy of the evaluator to preview results with, if previews are on.
+    pub preview: Option<Evaluator>,
+}
+
+/// What came before the current position, ignoring whitespace.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Prev {
+    Number,
+    Ident,
+    /// `)` or `]`
+    Close,
+    Other,
+}
+
+/// The byte offsets in an expression where a `*` is implied, like between `2` and `x` in `2x`.
+/// `units` are the units the user defined, which like the builtin ones don't get a `*`.
+pub fn implicit_mul_positions(line: &str, units: &[String]) -> Vec<usize> {
+    let is_unit = |name: &str| expr::is_unit(name) || units.iter().any(|u| u == name);
+    let mut positions = vec![];
+    let mut chars = line.char_indices().peekable();
+    let mut prev = Prev::Other;
+    while let Some((i, c)) = chars.next() {
+        let factor_follows = matches!(prev, Prev::Number | Prev::Close);
+        prev = match c {
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                Prev::Other
+            }
+            '0'..='9' => {
+                if prev == Prev::Close {
+                    positions.push(i);
+                }
+                // `12°34'56"` is one number, and its `"` doesn't start a string
+                if let Some(len) = expr::sexagesimal_len(&line[i..]) {
+                    while chars.next_if(|(j, _)| *j < i + len).is_some() {}
+                    prev = Prev::Number;
+                    continue;
+                }
+                // `0xff` is left for the parser to reject, rather than made `0*xff`
+                if let Some(len) = expr::radix_literal_len(&line[i..]) {
+                    while chars.next_if(|(j, _)| *j < i + len).is_some() {}
+                    prev = Prev::Other;
+                    continue;
+                }
+                while chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.' || *c == '_').is_some() {}
+                // an exponent or the imaginary unit is part of the number
+                let rest = &line[chars.peek().map_or(line.len(), |(i, _)| *i)..];
+                if let Some(len) = expr::exponent_len(rest) {
+                    chars.nth(len - 1);
+                    while chars.next_if(|(_, c)| c.is_ascii_digit()).is_some() {}
+                }
+                let rest = &line[chars.peek().map_or(line.len(), |(i, _)| *i)..];
+                if rest.starts_with('i') && !rest[1..].starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+                    chars.next();
+                }
+                Prev::Number
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_').is_some() {}
+                let word = &line[i..chars.peek().map_or(line.len(), |(j, _)| *j)];
+                // `of` in `3 of x` is an operator, not a factor
+                if expr::KEYWORDS.contains(&word) || expr::SEPARATORS.contains(&word) {
+                    Prev::Other
+                } else if is_unit(word) && !line[i + word.len()..].trim_start().starts_with('(') {
+                    // `2 km` is a quantity, but `3 min(a, b)` a product
+                    Prev::Ident
+                } else {
+                    if factor_follows {
+                        positions.push(i);
+                    }
+                    Prev::Ident
+                }
+            }
+            '(' => {
+                if factor_follows {
+                    positions.push(i);
+                }
+                Prev::Other
+            }
+            ')' | ']' => Prev::Close,
+            c if c.is_whitespace() => prev,
+            _ => Prev::Other,
+        };
+    }
+    positions
+}
+
+/// Whether the `"` at `i` marks the seconds of an angle like `12°34'56"`.
+fn is_seconds(line: &str, i: usize) -> bool {
+    line[..i].ends_with(|c: char| c.is_ascii_digit())
+}
+
+/// The name of the function whose argument list is the innermost one still open.
+pub fn open_call(line: &str) -> Option<&str> {
+    let mut calls = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' if !is_seconds(line, i) => in_string = true,
+            '(' | '[' => {
+                let before = line[..i].trim_end();
+                let name_start = before.rfind(|c: char| !(c.is_alphanumeric() || c == '_')).map_or(0, |j| j + 1);
+                let name = &before[name_start..];
+                let is_name = c == '(' && name.starts_with(|c: char| c.is_alphabetic() || c == '_');
+                calls.push(is_name.then_some(name));
+            }
+            ')' | ']' => {
+                calls.pop();
+            }
+            _ => {}
+        }
+    }
+    calls.pop().flatten()
+}
+
+/// The closing brackets needed to balance the open ones in `line`.
+pub fn missing_closers(line: &str) -> String {
+    let mut open = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' if !is_seconds(line, i) => in_string = true,
+            '(' => open.push(')'),
+            '[' => open.push(']'),
+            ')' | ']' if open.last() == Some(&c) => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    open.into_iter().rev().collect()
+}
+
+impl Helper {
+    /// Completes an expression the way the hints promised: with the missing
+    /// `*`s inserted and the open brackets closed.
+    pub fn complete<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let positions = if self.implicit_mul { implicit_mul_positions(line, &self.units) } else { vec![] };
+        let closers = if self.autoclose { missing_closers(line) } else { String::new() };
+        if positions.is_empty() && closers.is_empty() {
+            return Cow::Borrowed(line);
+        }
+        let mut completed = String::with_capacity(line.len() + positions.len() + closers.len());
+        let mut last = 0;
+        for i in positions {
+            completed.push_str(&line[last..i]);
+            completed.push('*');
+            last = i;
+        }
+        completed.push_str(&line[last..]);
+        completed.push_str(&closers);
+        Cow::Owned(completed)
+    }
+}
+
+fn is_command(line: &str) -> bool {
+    line.trim_start().starts_with(':')
+}
+
+impl rustyline::Helper for Helper {}
+
+impl Completer for Helper {
+    type Candidate = String;
+}
+
+impl Validator for Helper {}
+
+pub struct LineHint {
+    /// The brackets that will be closed, which can also be accepted with the right arrow.
+    closers: String,
+    display: String,
+}
+
+impl Hint for LineHint {
+    fn display(&self) -> &str {
+        &self.display
+    }
+
+    fn completion(&self) -> Option<&str> {
+        Some(self.closers.as_str()).filter(|c| !c.is_empty())
+    }
+}
+
+impl Helper {
+    /// The result of the line, if it evaluates to a value without errors.
+    fn preview(&self, line: &str) -> Option<String> {
+        let mut evaluator = self.preview.clone()?;
+        evaluator.settings_mut().render = RenderMode::Inline;
+        evaluator.forbid_side_effects();
+        evaluator.set_deadline(Some(Instant::now() + PREVIEW_TIME));
+        let rewritten = natural::rewrite(line).filter(|_| self.natural);
+        let line = rewritten.as_deref().unwrap_or(line);
+        let (statements, errors) = expr::parse(&evaluator, &self.complete(line));
+        if !errors.is_empty() || PREVIEWING.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _previewing = Previewing;
+            let mut result = None;
+            for statement in statements {
+                result = Some(evaluator.eval(statement).ok()?);
+            }
+            let result = result?;
+            result.as_value()?;
+            // the receiver is gone if the preview took too long
+            sender.send(evaluator.display(&result).to_string()).ok()
+        });
+        let mut s = receiver.recv_timeout(PREVIEW_TIME).ok()?;
+        if let Some((i, _)) = s.char_indices().nth(MAX_PREVIEW) {
+            s.truncate(i);
+            s.push('…');
+        }
+        Some(s)
+    }
+}
+
+impl Hinter for Helper {
+    type Hint = LineHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<LineHint> {
+        if pos < line.len() || is_command(line) {
+            return None;
+        }
+        let closers = if self.autoclose { missing_closers(line) } else { String::new() };
+        let mut display = closers.clone();
+        if let Some(builtin) = open_call(line).and_then(builtins::lookup) {
+            display.push_str(&format!("  {}", builtin.signature()));
+        }
+        if let Some(preview) = self.preview(line) {
+            display.push_str(&format!("  = {preview}"));
+        }
+        (!display.is_empty()).then_some(LineHint { closers, display })
+    }
+}
+
+impl Highlighter for Helper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if !self.color || !self.implicit_mul || is_command(line) {
+            return Cow::Borrowed(line);
+        }
+        let positions = implicit_mul_positions(line, &self.units);
+        if positions.is_empty() {
+            return Cow::Borrowed(line);
+        }
+        // underline the start of each factor that gets a `*` in front of it
+        let mut highlighted = String::new();
+        for (i, c) in line.char_indices() {
+            if positions.contains(&i) {
+                highlighted.push_str(&format!("\x1b[4m{c}\x1b[24m"));
+            } else {
+                highlighted.push(c);
+            }
+        }
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        if self.color {
+            Cow::Owned(format!("\x1b[2m{hint}\x1b[22m"))
+        } else {
+            Cow::Borrowed(hint)
+        }
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        self.color && self.implicit_mul
+    }
+}
+
+#[test]
+fn test_completion() {
+    assert_eq!(vec![1, 8, 12, 14], implicit_mul_positions("2x + (1)(2) 3 pi", &[]));
+    assert!(implicit_mul_positions("2i + 1.5e3 + sin(x) + \"2x\"", &[]).is_empty());
+    assert_eq!(vec![1, 15], implicit_mul_positions("2e + 2e-3 + 2E3e", &[]));
+    assert_eq!(vec![17], implicit_mul_positions("15% of 3/4 of (2)(x)", &[]));
+    assert_eq!(vec![9], implicit_mul_positions("2 km + 3 min(1, 2)", &[]));
+    assert_eq!(vec![17], implicit_mul_positions("(1°30'5\" + 1h2m)x", &[]));
+    assert_eq!(vec![15], implicit_mul_positions("0xff 0b1 0o7 10x", &[]));
+    assert_eq!("])", missing_closers("sin([1, (2)"));
+    assert_eq!(")", missing_closers("sin(1°2'3\""));
+    assert_eq!(Some("powmod"), open_call("1 + powmod(2, (3)"));
+    assert_eq!(None, open_call("sin(1) + [2"));
+    let helper =
+        Helper { autoclose: true, implicit_mul: true, natural: false, color: false, units: vec![], preview: None };
+    assert_eq!("2*sin(3*x)", helper.complete("2sin(3x"));
+    let helper = Helper { preview: Some(Evaluator::default()), ..helper };
+    assert_eq!(Some("1/2".to_string()), helper.preview("1/3 + 1/6"));
+    let mut evaluator = Evaluator::default();
+    for line in ["fib(0) = 0", "fib(1) = 1", "fib(n) = fib(n-1) + fib(n-2)"] {
+        calq::session::eval(&mut evaluator, line).unwrap();
+    }
+    let helper = Helper { preview: Some(evaluator), ..helper };
+    // too slow, and it stops at the deadline rather than running on
+    assert_eq!(None, helper.preview("fib(60)"));
+    while PREVIEWING.load(Ordering::Acquire) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(Some("2".to_string()), helper.preview("1 + 1"));
+}
@@ -0,0 +1,17 @@
+//! The calculation engine, used by the `calq` binary, by C programs through
+//! `capi` with the `capi` feature, and by Python with the `python` feature.
+//! Rust programs evaluating from many threads can use a [`session::Session`].
+
+pub mod div;
+pub mod domain;
+pub mod expr;
+pub mod render;
+pub mod session;
+pub mod settings;
+pub mod transcript;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
+#[cfg(feature = "python")]
+mod python;
@@ -1,49 +1,166 @@
-#![feature(lazy_cell)]
-use chumsky::Parser;
-use expr::Evaluator;
-use rustyline::error::ReadlineError;
-use rustyline::DefaultEditor;
+mod alias;
+mod bc;
+mod command;
+mod config;
+mod export;
+mod helper;
+mod natural;
+mod oneshot;
+mod repl;
+mod replay;
+mod server;
+mod signals;
+mod workspace;
 
-mod div;
-mod expr;
+use calq::{expr, render, settings, transcript};
+use clap::{ArgAction, Parser};
 
-fn main() -> color_eyre::Result<()> {
-    color_eyre::install()?;
-    let mut rl = DefaultEditor::new()?;
-    let mut evaluator = Evaluator::default();
-    loop {
-        let readline = rl.readline("calq> ");
-        match readline {
-            Ok(line) => {
-                rl.add_history_entry(line.as_str())?;
-                let exp = match expr::expr_parser(&evaluator).parse(line) {
-                    Ok(exp) => exp,
-                    Err(e) => {
-                        for e in e {
-                            eprintln!("Error: {e}");
-                        }
-                        continue;
-                    }
-                };
+/// A calculator with exact fractions, arbitrary precision decimals and symbolic results.
+///
+/// Without options, calq reads lines from the terminal or from standard input.
+/// The settings given as options replace those in the config file.
+#[derive(Parser)]
+#[command(name = "calq", disable_help_flag = true, disable_version_flag = true)]
+struct Args {
+    /// evaluate one expression, print it and exit
+    #[arg(short, long = "eval", value_name = "expr")]
+    expr: Option<String>,
+    /// with -e, exit with 0 if the result is nonzero, 1 if it is zero or empty
+    /// and 2 if evaluating it failed
+    #[arg(long, requires = "expr")]
+    print_exit_value: bool,
+    /// with -e, print the result as JSON, `{"text": ..., "kind": ...}` like
+    /// `evaluate` answers with --server, or `{"error": ...}`
+    #[arg(long, requires = "expr", conflicts_with_all = ["bc", "oneshot", "snapshot"])]
+    json: bool,
+    /// compute decimals with this many bits, or digits like 50d
+    #[arg(long, value_name = "bits", value_parser = setting("precision"))]
+    precision: Option<String>,
+    /// round decimals to `nearest` (the default), `zero`, `up` or `down`
+    #[arg(long, value_name = "mode", value_parser = setting("rounding"))]
+    round_mode: Option<String>,
+    /// take and return angles in degrees
+    #[arg(long)]
+    degrees: bool,
+    /// never compute with decimals, leaving sqrt(2) and the like as they are
+    #[arg(long)]
+    exact_only: bool,
+    /// accept and print like `bc`
+    #[arg(long)]
+    bc: bool,
+    /// answer JSON-RPC requests on standard input, one per line
+    #[arg(long, conflicts_with_all = ["expr", "bc", "oneshot"])]
+    server: bool,
+    /// evaluate one expression, from -e or standard input, for bots: prints one
+    /// plain line, the result or `error[kind]: message`
+    #[arg(long, conflicts_with = "bc")]
+    oneshot: bool,
+    /// with --oneshot, give up after this long, like 1s or 500ms
+    #[arg(long, value_name = "time", requires = "oneshot", value_parser = oneshot::parse_duration)]
+    max_time: Option<std::time::Duration>,
+    /// cut results off after this many characters
+    #[arg(long, value_name = "n", value_parser = setting("max-output"))]
+    max_output: Option<String>,
+    /// with -e, write what the result depends on to <file> as JSON
+    #[arg(long, value_name = "file", requires = "expr")]
+    snapshot: Option<String>,
+    /// evaluate a snapshot again and tell if the result is the same
+    #[arg(long, value_name = "file", exclusive = true)]
+    replay: Option<String>,
+    /// print this help
+    #[arg(short, long, action = ArgAction::Help)]
+    help: Option<bool>,
+    /// print the version, the number backend and the features
+    #[arg(short = 'V', long)]
+    version: bool,
+}
 
-                let value = evaluator.eval(exp);
+/// Checks the value of an option that sets `name`, so that a bad one is
+/// reported like any other bad argument.
+fn setting(name: &'static str) -> impl Fn(&str) -> color_eyre::Result<String> + Clone {
+    move |value| {
+        settings::Settings::default().set(name, value)?;
+        Ok(value.to_string())
+    }
+}
 
-                match value {
-                    Ok(value) => {
-                        println!("{value}");
-                    }
-                    Err(e) => {
-                        eprintln!("Error: {e}");
-                    }
-                }
-            }
-            Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
-                break;
+impl Args {
+    /// The settings given as options, applied after the config file so that
+    /// scripts don't depend on it.
+    fn apply(&self, s: &mut settings::Settings) -> color_eyre::Result<()> {
+        let values = [("precision", &self.precision), ("rounding", &self.round_mode), ("max-output", &self.max_output)];
+        for (name, value) in values {
+            if let Some(value) = value {
+                s.set(name, value)?;
             }
-            Err(e) => {
-                return Err(e.into());
+        }
+        if self.degrees {
+            s.set("angle", "degrees")?;
+        }
+        if self.exact_only {
+            s.set("strict-exact", "on")?;
+        }
+        Ok(())
+    }
+
+    fn settings(&self) -> color_eyre::Result<settings::Settings> {
+        let mut settings = settings::Settings::default();
+        self.apply(&mut settings)?;
+        Ok(settings)
+    }
+}
+
+/// The version, and what calq was built with.
+fn version() -> String {
+    let bits = expr::Evaluator::default().precision();
+    let features: Vec<&str> = [("capi", cfg!(feature = "capi")), ("python", cfg!(feature = "python"))]
+        .into_iter()
+        .filter_map(|(name, on)| on.then_some(name))
+        .collect();
+    format!(
+        "calq {}\nnumbers: exact rationals with GMP, decimals with MPFR through rug, {bits} bits (about {} digits)\nfeatures: {}",
+        env!("CARGO_PKG_VERSION"),
+        (f64::from(bits) * std::f64::consts::LOG10_2) as u32,
+        if features.is_empty() { "none".to_string() } else { features.join(", ") },
+    )
+}
+
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+    let args = Args::parse();
+    if args.version {
+        println!("{}", version());
+        return Ok(());
+    }
+    if let Some(path) = &args.replay {
+        std::process::exit(replay::replay(path));
+    }
+    if args.server {
+        return server::run(args.settings()?);
+    }
+    if args.oneshot {
+        let line = oneshot::input(args.expr.clone())?;
+        std::process::exit(oneshot::run(args.settings()?, &line, args.max_time));
+    }
+    if args.json {
+        let expr = args.expr.as_deref().expect("`--json` requires `-e`");
+        std::process::exit(server::run_once(args.settings()?, expr, args.print_exit_value));
+    }
+    let mut repl = repl::Repl::new()?;
+    args.apply(repl.evaluator.settings_mut())?;
+    if args.bc {
+        repl.bc = Some(bc::Bc::default());
+    }
+    match &args.expr {
+        Some(expr) => {
+            if let Some(path) = &args.snapshot {
+                replay::snapshot(path, &repl.evaluator, expr)?;
             }
+            std::process::exit(repl.run_once(expr, args.print_exit_value))
+        }
+        None => {
+            signals::install()?;
+            repl.run()
         }
     }
-    Ok(())
 }
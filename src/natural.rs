@@ -0,0 +1,187 @@
+//! A best-effort layer that rewrites common English phrases into expressions
+//! before they are parsed, so that `5 plus 3 times 2`, `sqrt of 2` and
+//! `20% of 150` work. The REPL shows the rewritten line, since a phrase can
+//! easily mean something other than what was intended.
+
+use crate::expr::builtins;
+
+/// Phrases and what they are replaced with, longer phrases first.
+const PHRASES: &[(&[&str], &str)] = &[
+    (&["to", "the", "power", "of"], "^"),
+    (&["multiplied", "by"], "*"),
+    (&["divided", "by"], "/"),
+    (&["plus"], "+"),
+    (&["minus"], "-"),
+    (&["times"], "*"),
+    (&["over"], "/"),
+    (&["squared"], "^2"),
+    (&["cubed"], "^3"),
+];
+
+/// Words at the start of a line that are dropped.
+const QUESTIONS: &[&[&str]] = &[&["what", "is"], &["what's"], &["calculate"]];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Word,
+    Number,
+    Other,
+}
+
+/// A token and whether there was whitespace before it, so that the parts
+/// that aren't rewritten are kept as they were typed.
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    kind: Kind,
+    space: bool,
+}
+
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut chars = line.char_indices().peekable();
+    let mut space = false;
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        let kind = if c.is_alphabetic() || c == '_' {
+            Kind::Word
+        } else if c.is_ascii_digit() || c == '.' {
+            Kind::Number
+        } else {
+            Kind::Other
+        };
+        let mut end = start + c.len_utf8();
+        // strings are left alone
+        if c == '"' {
+            let mut escaped = false;
+            for (i, c) in chars.by_ref() {
+                end = i + c.len_utf8();
+                match c {
+                    '"' if !escaped => break,
+                    '\\' => escaped = !escaped,
+                    _ => escaped = false,
+                }
+            }
+            tokens.push(Token { text: &line[start..end], kind, space });
+            space = false;
+            continue;
+        }
+        // `what's` is a single word
+        let continues = |c: char| match kind {
+            Kind::Word => c.is_alphanumeric() || c == '_' || c == '\'',
+            Kind::Number => c.is_ascii_digit() || c == '.' || c == '_',
+            Kind::Other => false,
+        };
+        while let Some(&(i, c)) = chars.peek().filter(|&&(_, c)| continues(c)) {
+            end = i + c.len_utf8();
+            chars.next();
+        }
+        tokens.push(Token { text: &line[start..end], kind, space });
+        space = false;
+    }
+    tokens
+}
+
+fn is_word(t: Option<&Token>, word: &str) -> bool {
+    t.is_some_and(|t| t.kind == Kind::Word && t.text.eq_ignore_ascii_case(word))
+}
+
+fn starts_with_words(tokens: &[Token], words: &[&str]) -> bool {
+    words.len() <= tokens.len() && words.iter().zip(tokens).all(|(w, t)| is_word(Some(t), w))
+}
+
+/// Rewrites the phrases in a line, `None` if there weren't any.
+pub fn rewrite(line: &str) -> Option<String> {
+    let tokens = tokenize(line);
+    let mut tokens = &tokens[..];
+    let mut changed = false;
+    if let Some(question) = QUESTIONS.iter().find(|q| starts_with_words(tokens, q)) {
+        tokens = &tokens[question.len()..];
+        changed = true;
+    }
+    if let [rest @ .., last] = tokens {
+        if last.text == "?" {
+            tokens = rest;
+            changed = true;
+        }
+    }
+
+    let mut out = String::with_capacity(line.len());
+    let mut i = 0;
+    // no space after a `^` from a phrase either, for `2^10` rather than `2^ 10`
+    let mut glue = false;
+    while i < tokens.len() {
+        let t = tokens[i];
+        if t.space && !out.is_empty() && !glue {
+            out.push(' ');
+        }
+        glue = false;
+        if let Some((words, replacement)) = PHRASES.iter().find(|(words, _)| starts_with_words(&tokens[i..], words)) {
+            // `4 squared` is `4^2`
+            if replacement.starts_with('^') && out.ends_with(' ') {
+                out.pop();
+            }
+            out.push_str(replacement);
+            glue = *replacement == "^";
+            i += words.len();
+            changed = true;
+        } else if let Some((operand, next)) = function_of(tokens, i) {
+            out.push_str(&operand);
+            i = next;
+            changed = true;
+        } else if t.kind == Kind::Number && tokens.get(i + 1).is_some_and(|t| t.text == "%") {
+            // `2^50%` would otherwise be `2^50/100`
+            if out.trim_end().ends_with(['^', '/']) {
+                out.push_str(&format!("({}/100)", t.text));
+            } else {
+                out.push_str(&format!("{}/100", t.text));
+            }
+            i += 2;
+            changed = true;
+            if is_word(tokens.get(i), "of") {
+                out.push_str(" *");
+                i += 1;
+            }
+        } else {
+            out.push_str(t.text);
+            i += 1;
+        }
+    }
+    changed.then_some(out)
+}
+
+/// `sqrt of 2` as `sqrt(2)`, and the index of the token after it.
+fn function_of(tokens: &[Token], i: usize) -> Option<(String, usize)> {
+    let name = tokens.get(i).filter(|t| t.kind == Kind::Word && builtins::lookup(t.text).is_some())?;
+    if !is_word(tokens.get(i + 1), "of") {
+        return None;
+    }
+    let (operand, next) = operand(tokens, i + 2)?;
+    Some((format!("{}({operand})", name.text), next))
+}
+
+/// The single value an `of` applies to, a number, a name, or another `f of x`.
+fn operand(tokens: &[Token], i: usize) -> Option<(String, usize)> {
+    let t = tokens.get(i)?;
+    match t.kind {
+        Kind::Other if t.text == "-" => operand(tokens, i + 1).map(|(o, next)| (format!("-{o}"), next)),
+        Kind::Word => function_of(tokens, i).or(Some((t.text.to_string(), i + 1))),
+        Kind::Number => Some((t.text.to_string(), i + 1)),
+        Kind::Other => None,
+    }
+}
+
+#[test]
+fn test_rewrite() {
+    assert_eq!(Some("20/100 * 150".into()), rewrite("20% of 150"));
+    assert_eq!(Some("sqrt(2)".into()), rewrite("sqrt of 2"));
+    assert_eq!(Some("5 + 3 * 2".into()), rewrite("5 plus 3 times 2"));
+    assert_eq!(Some("abs(ln(-2))".into()), rewrite("what is abs of ln of -2?"));
+    assert_eq!(Some("2^10".into()), rewrite("2 to the power of 10"));
+    assert_eq!(Some("2^(50/100) / 4^2".into()), rewrite("2^50% divided by 4 squared"));
+    assert_eq!(None, rewrite("sqrt(2) + [1, 2]"));
+    assert_eq!(None, rewrite(r#"write("one plus \" two", "x")"#));
+}
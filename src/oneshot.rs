@@ -0,0 +1,134 @@
+//! `calq --oneshot`, for chat bots and other programs that pass on what
+//! users type. It evaluates one expression with the default settings, so that
+//! no config file changes the answer, and prints exactly one line on stdout:
+//! the result, or `error[kind]: message` with `kind` one of
+//!
+//! - `syntax`, the expression couldn't be parsed
+//! - `too-complex`, it is over the `max-input` or `max-depth` limits
+//! - `eval`, it couldn't be evaluated
+//! - `timeout`, it took longer than `--max-time`
+//!
+//! The line has no colors or other control characters, and is cut off after
+//! `max-output` characters. Functions with side effects aren't allowed.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use color_eyre::eyre::{bail, eyre};
+
+use crate::expr::{self, Evaluator, ParseErrorKind};
+use crate::settings::Settings;
+
+struct Error {
+    kind: &'static str,
+    message: String,
+}
+
+impl Error {
+    fn new(kind: &'static str, message: impl ToString) -> Error {
+        Error { kind, message: message.to_string() }
+    }
+}
+
+/// Parses a duration like `1s`, `250ms` or `2`, which is in seconds.
+pub fn parse_duration(s: &str) -> color_eyre::Result<Duration> {
+    let (number, scale) = match s.strip_suffix("ms") {
+        Some(ms) => (ms, 1e-3),
+        None => (s.strip_suffix('s').unwrap_or(s), 1.0),
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(Duration::from_secs_f64(n * scale)),
+        _ => bail!("expected a duration like `1s` or `500ms`, found `{s}`"),
+    }
+}
+
+/// Prints the answer to `line`, returning the exit status: 0 for a result
+/// and 1 for an error.
+pub fn run(settings: Settings, line: &str, max_time: Option<Duration>) -> i32 {
+    // whoever gets here first prints, either the answer or that there is none in time
+    let done = Arc::new(Mutex::new(false));
+    if let Some(max_time) = max_time {
+        let done = Arc::clone(&done);
+        std::thread::spawn(move || {
+            std::thread::sleep(max_time);
+            let done = done.lock().unwrap_or_else(|e| e.into_inner());
+            // the answer was printed, and calq is about to exit with its status
+            if *done {
+                return;
+            }
+            let error = Error::new("timeout", format!("no result within {}ms", max_time.as_millis()));
+            print(None, Err(error));
+            std::process::exit(1);
+        });
+    }
+    let mut evaluator = Evaluator::default();
+    *evaluator.settings_mut() = settings;
+    evaluator.forbid_side_effects();
+    let answer = answer(&mut evaluator, line);
+    let mut done = done.lock().unwrap_or_else(|e| e.into_inner());
+    *done = true;
+    print(evaluator.settings().max_output, answer)
+}
+
+fn answer(evaluator: &mut Evaluator, line: &str) -> Result<String, Error> {
+    let (mut statements, errors) = expr::parse(evaluator, line);
+    if let Some(e) = errors.first() {
+        let kind = if e.kind == ParseErrorKind::TooComplex { "too-complex" } else { "syntax" };
+        return Err(Error::new(kind, &e.message));
+    }
+    let statement = match statements.pop() {
+        Some(statement) if statements.is_empty() => statement,
+        _ => return Err(Error::new("syntax", "expected a single expression")),
+    };
+    let result = evaluator.eval(statement).map_err(|e| Error::new("eval", e))?;
+    Ok(evaluator.display(&result).to_string())
+}
+
+fn print(max_output: Option<usize>, answer: Result<String, Error>) -> i32 {
+    let (line, status) = match answer {
+        Ok(result) => (result, 0),
+        Err(e) => (format!("error[{}]: {}", e.kind, e.message), 1),
+    };
+    println!("{}", one_line(&line, max_output));
+    status
+}
+
+/// `text` on one line without control characters, cut off after `max` characters.
+fn one_line(text: &str, max: Option<usize>) -> String {
+    let mut line = text.split('\n').map(str::trim).collect::<Vec<_>>().join(" ");
+    line = line.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    match max {
+        Some(max) if line.chars().count() > max => {
+            let more = line.chars().count() - max;
+            format!("{}... [{more} more characters]", line.chars().take(max).collect::<String>())
+        }
+        _ => line,
+    }
+}
+
+/// The expression to evaluate, from `-e` or else the first line of stdin.
+pub fn input(expr: Option<String>) -> color_eyre::Result<String> {
+    if let Some(expr) = expr {
+        return Ok(expr);
+    }
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line).map_err(|e| eyre!("couldn't read the expression: {e}"))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[test]
+fn test_oneshot() {
+    let mut e = Evaluator::default();
+    e.forbid_side_effects();
+    assert_eq!("3/4", answer(&mut e, "1/2 + 1/4").ok().unwrap());
+    assert_eq!("eval", answer(&mut e, "1/0").err().unwrap().kind);
+    assert_eq!("syntax", answer(&mut e, "1 +").err().unwrap().kind);
+    assert_eq!("syntax", answer(&mut e, "1; 2").err().unwrap().kind);
+    e.settings_mut().set("max-input", "10").unwrap();
+    assert_eq!("too-complex", answer(&mut e, "1+1+1+1+1+1").err().unwrap().kind);
+    assert_eq!("a b  c", one_line("a\n  b\n\x1bc", None));
+    assert_eq!("12345... [2 more characters]", one_line("1234567", Some(5)));
+    assert_eq!(Duration::from_millis(250), parse_duration("250ms").unwrap());
+    assert_eq!(Duration::from_secs(2), parse_duration("2").unwrap());
+    assert!(parse_duration("-1s").is_err());
+}
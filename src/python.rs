@@ -0,0 +1,97 @@
+//! The `calq` Python module, enabled with the `python` feature and built with
+//! `cargo rustc --release --lib --features python --crate-type cdylib`.
+//!
+//! ```python
+//! import calq
+//! s = calq.Session()
+//! s.eval("x = 1/3")
+//! s.eval("x + 1/6")  # Fraction(1, 2)
+//! ```
+//!
+//! Integers are returned as `int`, rationals as `fractions.Fraction`,
+//! decimals as `decimal.Decimal` with all of their digits, and complex numbers
+//! as `complex` if both parts fit in a `float`, and otherwise as text with all
+//! of their digits. Results with unknowns left in them are returned as text.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyComplex, PyList, PyString, PyTuple};
+
+use crate::expr::{self, Evaluator, Expr, Value};
+use crate::settings::CaretOp;
+
+/// An evaluator with its variables and functions.
+#[pyclass]
+struct Session {
+    evaluator: Evaluator,
+}
+
+#[pymethods]
+impl Session {
+    #[new]
+    fn new() -> Self {
+        Session { evaluator: Evaluator::default() }
+    }
+
+    /// Evaluates `;` separated statements, returning the result of the last one.
+    fn eval<'py>(&mut self, py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyAny>> {
+        let (statements, errors) = expr::parse(&self.evaluator, source);
+        if !errors.is_empty() {
+            let errors: Vec<_> = errors.iter().map(|e| e.render(source)).collect();
+            return Err(PyValueError::new_err(errors.join("\n")));
+        }
+        let mut result = None;
+        for statement in statements {
+            result = Some(self.evaluator.eval(statement).map_err(|e| PyValueError::new_err(e.to_string()))?);
+        }
+        match result.map(|e| e.into_value()) {
+            Some(Ok(v)) => to_python(py, &v),
+            Some(Err(e)) => Ok(PyString::new(py, &self.evaluator.display(&e).to_string()).into_any()),
+            None => Ok(py.None().into_bound(py)),
+        }
+    }
+}
+
+fn to_python<'py>(py: Python<'py>, v: &Value) -> PyResult<Bound<'py, PyAny>> {
+    let int = |i: &rug::Integer| py.import("builtins")?.getattr("int")?.call1((i.to_string(),));
+    Ok(match v {
+        Value::Exact(r) if *r.denom() == 1 => int(r.numer())?,
+        Value::Exact(r) => py.import("fractions")?.getattr("Fraction")?.call1((int(r.numer())?, int(r.denom())?))?,
+        Value::Decimal(d) => py.import("decimal")?.getattr("Decimal")?.call1((d.to_string_radix(10, None),))?,
+        Value::Complex(c) => {
+            let (re, im) = (c.real().to_f64(), c.imag().to_f64());
+            // a `float` would lose digits of a more precise result
+            match *c.real() == re && *c.imag() == im {
+                true => PyComplex::from_doubles(py, re, im).into_any(),
+                false => PyString::new(py, &Expr::value(v.clone()).to_source(CaretOp::Pow)).into_any(),
+            }
+        }
+        // Python has no quaternions
+        Value::Quaternion(_) | Value::Quantity(_) => PyString::new(py, &v.to_string()).into_any(),
+        Value::Str(s) => PyString::new(py, s).into_any(),
+        Value::List(items) => PyList::new(py, items.iter().map(|v| to_python(py, v)).collect::<PyResult<Vec<_>>>()?)?.into_any(),
+        Value::Tuple(items) => PyTuple::new(py, items.iter().map(|v| to_python(py, v)).collect::<PyResult<Vec<_>>>()?)?.into_any(),
+    })
+}
+
+#[pymodule]
+fn calq(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Session>()
+}
+
+#[test]
+fn test_complex() {
+    Python::initialize();
+    Python::attach(|py| {
+        let mut session = Session::new();
+        let z = session.eval(py, "1/2 + 2i").unwrap();
+        let part = |name: &str| z.getattr(name).unwrap().extract::<f64>().unwrap();
+        assert_eq!((0.5, 2.0), (part("real"), part("imag")));
+        // not every digit fits in a `float`
+        session.evaluator.settings_mut().set("precision", "200").unwrap();
+        let z = session.eval(py, "z = sqrt(2) + 1/3*1i").unwrap();
+        let text: String = z.extract().unwrap();
+        assert!(text.len() > 100, "{text}");
+        assert_eq!(1, session.eval(py, &format!("abs(({text}) - z) == 0")).unwrap().extract::<i64>().unwrap());
+    });
+}
@@ -0,0 +1,255 @@
+//! Everything the REPL prints goes through here, so that colors are applied
+//! consistently and can be turned off.
+
+use std::fmt::{self, Write as _};
+
+use color_eyre::eyre::{bail, eyre};
+use rug::Integer;
+
+use crate::expr::digits;
+use crate::settings::Settings;
+use crate::transcript;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Default,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Bright(u8),
+}
+
+const COLOR_NAMES: &[&str] = &["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+impl Color {
+    fn parse(s: &str) -> color_eyre::Result<Color> {
+        if let Some(base) = s.strip_prefix("bright-") {
+            let Some(n) = COLOR_NAMES.iter().position(|c| *c == base) else {
+                bail!("unknown color `{s}`");
+            };
+            return Ok(Color::Bright(n as u8));
+        }
+        Ok(match s {
+            "default" | "none" => Color::Default,
+            "black" => Color::Black,
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "yellow" => Color::Yellow,
+            "blue" => Color::Blue,
+            "magenta" => Color::Magenta,
+            "cyan" => Color::Cyan,
+            "white" => Color::White,
+            _ => bail!("unknown color `{s}`"),
+        })
+    }
+
+    fn ansi_code(self) -> Option<u8> {
+        match self {
+            Color::Default => None,
+            Color::Black => Some(30),
+            Color::Red => Some(31),
+            Color::Green => Some(32),
+            Color::Yellow => Some(33),
+            Color::Blue => Some(34),
+            Color::Magenta => Some(35),
+            Color::Cyan => Some(36),
+            Color::White => Some(37),
+            Color::Bright(n) => Some(90 + n),
+        }
+    }
+
+    fn name(self) -> String {
+        match self {
+            Color::Default => "default".into(),
+            Color::Bright(n) => format!("bright-{}", COLOR_NAMES[n as usize]),
+            other => COLOR_NAMES[(other.ansi_code().unwrap() - 30) as usize].into(),
+        }
+    }
+
+    /// Writes `f` in this color.
+    pub fn paint<W: fmt::Write>(self, w: &mut W, f: impl FnOnce(&mut W) -> fmt::Result) -> fmt::Result {
+        match self.ansi_code() {
+            Some(code) => {
+                write!(w, "\x1b[{code}m")?;
+                f(w)?;
+                w.write_str("\x1b[0m")
+            }
+            None => f(w),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub value: Color,
+    pub symbol: Color,
+    pub error: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme { value: Color::Green, symbol: Color::Cyan, error: Color::Red }
+    }
+}
+
+impl Theme {
+    /// Builds a theme from `key=color` pairs, starting from the default theme.
+    pub fn parse(spec: &str) -> color_eyre::Result<Theme> {
+        let mut theme = Theme::default();
+        for pair in spec.split_whitespace() {
+            let (key, color) = pair.split_once('=').ok_or_else(|| eyre!("expected `key=color`, found `{pair}`"))?;
+            let color = Color::parse(color)?;
+            match key {
+                "value" => theme.value = color,
+                "symbol" => theme.symbol = color,
+                "error" => theme.error = color,
+                _ => bail!("unknown theme key `{key}`, expected `value`, `symbol` or `error`"),
+            }
+        }
+        Ok(theme)
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "value={} symbol={} error={}", self.value.name(), self.symbol.name(), self.error.name())
+    }
+}
+
+/// Whether colors should be on when starting up, following <https://no-color.org>.
+pub fn colors_by_default() -> bool {
+    use std::io::IsTerminal;
+    std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && std::io::stdout().is_terminal() && escape_codes()
+}
+
+/// Turns on escape codes in the Windows console, false for legacy consoles
+/// before Windows 10 that would print them as text.
+#[cfg(windows)]
+fn escape_codes() -> bool {
+    use windows_sys::Win32::System::Console::{
+        GetConsoleMode, GetStdHandle, SetConsoleMode, ENABLE_VIRTUAL_TERMINAL_PROCESSING, STD_OUTPUT_HANDLE,
+    };
+
+    // SAFETY: the handle comes from `GetStdHandle`, and `mode` outlives the call writing to it
+    unsafe {
+        let stdout = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0;
+        GetConsoleMode(stdout, &mut mode) != 0
+            && (mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+                || SetConsoleMode(stdout, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0)
+    }
+}
+
+#[cfg(not(windows))]
+fn escape_codes() -> bool {
+    true
+}
+
+/// Prints an already formatted result.
+pub fn result(result: &str) {
+    transcript::output(result);
+    println!("{result}");
+}
+
+/// Prints the start of a result that is too long to be shown in full.
+pub fn truncated(settings: &Settings, plain: &str, max: usize) {
+    let (shown, rest) = plain.split_at(plain.char_indices().nth(max).map_or(plain.len(), |(i, _)| i));
+    let what = if rest.chars().all(|c| c.is_ascii_digit()) { "digits" } else { "characters" };
+    cut_off(settings, shown, rest.chars().count(), what);
+}
+
+/// Prints the first digits of an integer that has `more` digits after them.
+pub fn truncated_digits(settings: &Settings, shown: &str, more: usize) {
+    cut_off(settings, shown, more, "digits");
+}
+
+fn cut_off(settings: &Settings, shown: &str, more: usize, what: &str) {
+    let color = settings.theme().map_or(Color::Default, |t| t.value);
+    let mut out = String::new();
+    let _ = color.paint(&mut out, |w| w.write_str(shown));
+    let out = format!("{out}... [{more} more {what}], use :full or :page to show");
+    transcript::output(&out);
+    println!("{out}");
+}
+
+/// Prints a huge integer while its digits are still being generated. Stops
+/// quietly when stdout is closed, e.g. when piped into `head`, and with a
+/// note once `interrupted` is true, which is checked between pieces.
+pub fn digits(settings: &Settings, n: &Integer, interrupted: impl Fn() -> bool) {
+    use std::io::Write;
+
+    let code = settings.theme().and_then(|t| t.value.ansi_code());
+    let mut stdout = std::io::stdout().lock();
+    transcript::output_start();
+    let mut print = || {
+        if let Some(code) = code {
+            write!(stdout, "\x1b[{code}m")?;
+        }
+        let written = digits::write(n, &mut |s| {
+            if interrupted() {
+                return Err(std::io::ErrorKind::Interrupted.into());
+            }
+            transcript::output_part(s);
+            stdout.write_all(s.as_bytes())
+        });
+        if code.is_some() {
+            stdout.write_all(b"\x1b[0m")?;
+        }
+        writeln!(stdout)?;
+        written
+    };
+    let result = print();
+    transcript::output_end();
+    drop(stdout);
+    if result.is_err_and(|e| e.kind() == std::io::ErrorKind::Interrupted) {
+        info("[interrupted]");
+    }
+}
+
+/// Shows text in `$PAGER` (or `less`), printing it directly if that fails.
+pub fn page(text: &str) {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    transcript::output(text);
+    let pager = std::env::var("PAGER").ok().filter(|p| !p.is_empty()).unwrap_or_else(|| "less".into());
+    let child = Command::new("sh").arg("-c").arg(&pager).stdin(Stdio::piped()).spawn();
+    match child {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                // the pager quitting early closes the pipe, which is fine
+                let _ = writeln!(stdin, "{text}");
+            }
+            let _ = child.wait();
+        }
+        Err(_) => println!("{text}"),
+    }
+}
+
+/// Prints informational output from commands.
+pub fn info(text: impl fmt::Display) {
+    let text = text.to_string();
+    transcript::output(&text);
+    println!("{text}");
+}
+
+/// Prints something that didn't stop a statement but may not be what was meant.
+pub fn warning(text: impl fmt::Display) {
+    let text = format!("Warning: {text}");
+    transcript::output(&text);
+    eprintln!("{text}");
+}
+
+pub fn error(settings: &Settings, e: impl fmt::Display) {
+    let mut out = String::new();
+    let color = settings.theme().map_or(Color::Default, |t| t.error);
+    // writing to a String can't fail
+    let _ = color.paint(&mut out, |w| write!(w, "Error: {e}"));
+    transcript::output(&out);
+    eprintln!("{out}");
+}
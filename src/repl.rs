@@ -0,0 +1,377 @@
+//! The interactive read-eval-print loop.
+
+use std::borrow::Cow;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use calq::session::guarded;
+use color_eyre::eyre::{bail, eyre};
+use rug::Integer;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+
+use crate::expr::{self, columns, digits, Evaluator, Expr, Node, Value};
+use crate::alias::Aliases;
+use crate::bc::Bc;
+use crate::export::Entry;
+use crate::helper::Helper;
+use crate::settings::RenderMode;
+use crate::workspace::{self, Workspaces};
+use crate::{command, config, natural, render, signals, transcript};
+
+pub struct Repl {
+    pub editor: Editor<Helper, DefaultHistory>,
+    pub evaluator: Evaluator,
+    /// The last result, used by `:copy`, `:full` and the like.
+    pub last_result: Option<LastResult>,
+    /// Kept around for the whole session, since on some platforms the copied
+    /// text is only available for as long as the clipboard is alive.
+    pub clipboard: Option<arboard::Clipboard>,
+    pub aliases: Aliases,
+    /// Set by `--bc` to accept and print like `bc`.
+    pub bc: Option<Bc>,
+    pub workspaces: Workspaces,
+    /// Every statement evaluated so far with its result, and comments, for `:export`.
+    pub session: Vec<Entry>,
+    /// The file history is kept in, only for interactive sessions.
+    history: Option<PathBuf>,
+}
+
+/// Integers with at least this many digits are printed while their digits are generated.
+const STREAMED_DIGITS: usize = 100_000;
+
+pub enum LastResult {
+    /// The printed form of the result.
+    Text(String),
+    /// A result, with the approximation that followed it and the whole of
+    /// its printed form, which may be approximated or have items left out.
+    Result { result: Expr, approx: String, text: String },
+    /// An integer with too many digits to turn into text before it's needed.
+    Digits(Integer),
+}
+
+impl LastResult {
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            LastResult::Text(text) | LastResult::Result { text, .. } => Cow::Borrowed(text),
+            LastResult::Digits(n) => Cow::Owned(n.to_string()),
+        }
+    }
+}
+
+impl Repl {
+    pub fn new() -> color_eyre::Result<Repl> {
+        let mut repl = Repl {
+            editor: Editor::new()?,
+            evaluator: Evaluator::default(),
+            last_result: None,
+            clipboard: None,
+            aliases: Aliases::default(),
+            bc: None,
+            workspaces: Workspaces::default(),
+            session: Vec::new(),
+            history: None,
+        };
+        repl.evaluator.settings_mut().color = render::colors_by_default();
+        config::load(&mut repl);
+        if std::io::stdin().is_terminal() {
+            repl.history = workspace::data_dir().map(|d| d.join("history"));
+        }
+        if let Some(path) = &repl.history {
+            // there is no history yet the first time
+            let _ = repl.editor.load_history(path);
+        }
+        let helper = repl.helper();
+        repl.editor.set_helper(Some(helper));
+        Ok(repl)
+    }
+
+    pub fn run(&mut self) -> color_eyre::Result<()> {
+        loop {
+            // settings may have changed since the last line
+            let helper = self.helper();
+            self.editor.set_helper(Some(helper));
+            let prompt = match self.workspaces.current.as_str() {
+                workspace::DEFAULT => "calq> ".to_string(),
+                name => format!("calq[{name}]> "),
+            };
+            match self.editor.readline(&prompt) {
+                Ok(line) => {
+                    let line = match self.expand_history(&line) {
+                        Ok(Some(expanded)) => {
+                            render::info(&expanded);
+                            expanded
+                        }
+                        Ok(None) => line,
+                        Err(e) => {
+                            render::error(self.evaluator.settings(), e);
+                            continue;
+                        }
+                    };
+                    self.editor.add_history_entry(line.as_str())?;
+                    self.save_history();
+                    transcript::input(&line);
+                    let line = match self.bc.as_mut().map(|bc| bc.translate(&line)) {
+                        Some(Ok(Some(translated))) => translated,
+                        Some(Ok(None)) => break,
+                        Some(Err(e)) => {
+                            render::error(self.evaluator.settings(), e);
+                            continue;
+                        }
+                        None => line,
+                    };
+                    self.handle_line(&line);
+                    signals::line_done();
+                    if let Err(e) = workspace::save(&self.workspaces.current, &self.evaluator) {
+                        render::error(self.evaluator.settings(), e);
+                    }
+                }
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
+                    break;
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            }
+        }
+        workspace::save(&self.workspaces.current, &self.evaluator)
+    }
+
+    /// Appends the lines entered since the last call to the history file, as
+    /// they are entered so that they survive calq being killed.
+    fn save_history(&mut self) {
+        let Some(path) = &self.history else { return };
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        // losing history isn't worth an error after every line
+        let _ = self.editor.append_history(path);
+    }
+
+    /// Evaluates a single expression from the command line and prints it,
+    /// returning the exit status. With `exit_value`, the status is the one
+    /// `expr` uses: 0 if the result is nonzero, 1 if it is zero or empty, and
+    /// 2 for errors.
+    pub fn run_once(&mut self, line: &str, exit_value: bool) -> i32 {
+        // read like a line at the prompt, without the `interpreted as` note
+        // that would end up in the output of scripts
+        let line = self.aliases.expand(line).map(|line| {
+            let natural = self.natural(&line).unwrap_or_else(|| line.into_owned());
+            self.helper().complete(&natural).into_owned()
+        });
+        match line.and_then(|line| self.eval(&line)) {
+            Ok(result) => {
+                self.show(&result);
+                match result.as_value() {
+                    Some(v) if exit_value && v.is_zero_or_empty() => 1,
+                    _ => 0,
+                }
+            }
+            Err(e) => {
+                render::error(self.evaluator.settings(), e);
+                if exit_value { 2 } else { 1 }
+            }
+        }
+    }
+
+    /// Expands `!!` (the previous line), `!n` (history entry `n`) and `!-n`
+    /// (the `n`th previous line) at the start of a line, returning `None` if
+    /// the line doesn't start with one of them.
+    fn expand_history(&self, line: &str) -> color_eyre::Result<Option<String>> {
+        let Some(rest) = line.trim_start().strip_prefix('!') else {
+            return Ok(None);
+        };
+        let history: Vec<&String> = self.editor.history().iter().collect();
+        let (entry, rest) = if let Some(rest) = rest.strip_prefix('!') {
+            (history.last().copied(), rest)
+        } else {
+            let (negative, digits) = match rest.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, rest),
+            };
+            let len = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+            let Ok(n) = digits[..len].parse::<usize>() else {
+                bail!("expected `!!` or `!n` with `n` a history number");
+            };
+            let index = if negative { history.len().checked_sub(n) } else { n.checked_sub(1) };
+            (index.and_then(|i| history.get(i).copied()), &digits[len..])
+        };
+        match entry {
+            Some(entry) => Ok(Some(format!("{entry}{rest}"))),
+            None => bail!("no such history entry"),
+        }
+    }
+
+    fn helper(&self) -> Helper {
+        let settings = self.evaluator.settings();
+        Helper {
+            autoclose: settings.autoclose,
+            implicit_mul: settings.implicit_mul,
+            natural: settings.natural,
+            color: settings.color,
+            units: self.evaluator.unit_names().map(String::from).collect(),
+            preview: settings.preview.then(|| self.evaluator.clone()),
+        }
+    }
+
+    fn handle_line(&mut self, line: &str) {
+        let result = match guarded(|| self.run_line(line)) {
+            Ok(result) => result,
+            Err(panic) => {
+                self.evaluator.abandon();
+                Err(eyre!("internal error, please report: {panic}"))
+            }
+        };
+        if let Err(e) = result {
+            render::error(self.evaluator.settings(), e);
+        }
+    }
+
+    /// Runs a command or evaluates an expression, printing the result.
+    pub fn run_line(&mut self, line: &str) -> color_eyre::Result<()> {
+        let expanded = self.aliases.expand(line)?.into_owned();
+        let line = expanded.as_str();
+        if let Some(command) = line.trim_start().strip_prefix(':') {
+            return command::run(self, command);
+        }
+        if let Some(comment) = line.trim_start().strip_prefix('#') {
+            self.session.push(Entry::Comment { text: comment.trim().into(), time: SystemTime::now() });
+            return Ok(());
+        }
+
+        let natural = match self.natural(line) {
+            Some(rewritten) => {
+                render::info(format_args!("interpreted as `{rewritten}`"));
+                rewritten
+            }
+            None => line.to_string(),
+        };
+        let line = self.helper().complete(&natural).into_owned();
+        let (statements, errors) = expr::parse(&self.evaluator, &line);
+        for statement in statements {
+            // like `bc`, don't echo assignments and definitions
+            let quiet = self.bc.is_some()
+                && matches!(
+                    statement.root().node(),
+                    Node::Assign(..) | Node::Unpack(..) | Node::Define(..) | Node::DefineUnit(..)
+                );
+            let result = self.evaluator.eval(statement.clone());
+            self.evaluator.warnings().iter().for_each(render::warning);
+            match result {
+                Ok(result) => {
+                    // nor loops that ran nothing
+                    let looped = matches!(statement.root().node(), Node::For(..) | Node::While(..));
+                    let nothing = looped && matches!(result.as_value(), Some(Value::List(items)) if items.is_empty());
+                    if !quiet && !nothing {
+                        self.show(&result);
+                        self.show_error_bar(&statement, &result);
+                    }
+                    self.session.push(Entry::Step { input: statement, result, time: SystemTime::now() });
+                }
+                Err(e) => render::error(self.evaluator.settings(), e),
+            }
+        }
+        if !errors.is_empty() {
+            bail!(errors.iter().map(|e| e.render(&line)).collect::<Vec<_>>().join("\n"));
+        }
+        Ok(())
+    }
+
+    /// Rewrites natural language like `sqrt of 16` into an expression, if
+    /// `:set natural` is on and the line reads as such.
+    fn natural(&self, line: &str) -> Option<String> {
+        natural::rewrite(line).filter(|_| self.evaluator.settings().natural)
+    }
+
+    /// Prints a result and remembers it for `:copy`.
+    fn show(&mut self, result: &Expr) {
+        if let (Some(bc), Some(v)) = (&self.bc, result.as_value()) {
+            if let Some(text) = bc.format(v) {
+                render::result(&text);
+                self.last_result = Some(LastResult::Text(text));
+                return;
+            }
+        }
+        if let Some(Value::Exact(r)) = result.as_value() {
+            if *r.denom() == 1 && self.show_digits(r.numer()) {
+                return;
+            }
+        }
+        // with `:set symbolic on`, `2*pi` is followed by its value
+        let approx = self.approximation(result).map(|v| format!(" ≈ {}", self.evaluator.display(&v)));
+        let approx = approx.unwrap_or_default();
+        let plain = format!("{}{approx}", self.evaluator.display(result));
+        if let (Some(Value::List(items)), RenderMode::Inline) = (result.as_value(), self.evaluator.settings().render) {
+            // `:full` and `:copy` still get the whole list
+            if let Some(text) = columns::list(&self.evaluator, items) {
+                render::result(&text);
+                self.last_result = Some(LastResult::Result { result: result.clone(), approx, text: plain });
+                return;
+            }
+        }
+        match self.evaluator.settings().max_output {
+            Some(max) if plain.chars().count() > max => render::truncated(self.evaluator.settings(), &plain, max),
+            _ => render::result(&format!("{}{approx}", self.evaluator.display(result).colored())),
+        }
+        self.last_result = Some(LastResult::Result { result: result.clone(), approx, text: plain });
+    }
+
+    /// With `:set error-bars on`, how far apart rounding down and up put a decimal result.
+    fn show_error_bar(&self, statement: &Expr, result: &Expr) {
+        if !self.evaluator.settings().error_bars || !matches!(result.as_value(), Some(Value::Decimal(_))) {
+            return;
+        }
+        if let Some(bar) = self.evaluator.error_bar(statement) {
+            let width = bar.width();
+            let width = if width.is_zero() { "0".to_string() } else { width.to_string_radix(10, Some(2)) };
+            render::info(format_args!("  ± {width} rounding down and up, about {} digits agree", bar.digits()));
+        }
+    }
+
+    /// The value of a result that `symbolic` mode left symbolic, if it has one.
+    fn approximation(&self, result: &Expr) -> Option<Expr> {
+        if !self.evaluator.settings().symbolic || result.as_value().is_some() {
+            return None;
+        }
+        let mut evaluator = self.evaluator.clone();
+        evaluator.forbid_side_effects();
+        evaluator.numeric(result.clone()).ok().filter(|v| v.as_value().is_some())
+    }
+
+    /// Shows an integer with so many digits that they are better printed as
+    /// they are generated, returning `false` for any other integer.
+    fn show_digits(&mut self, n: &Integer) -> bool {
+        let settings = self.evaluator.settings();
+        // a cheap check first, counting the digits exactly takes a while for huge numbers
+        if (n.significant_bits() as f64 * std::f64::consts::LOG10_2) < STREAMED_DIGITS as f64 {
+            return false;
+        }
+        let total = digits::count(n);
+        if total < STREAMED_DIGITS || settings.approximate_after().is_some_and(|max| total > max) {
+            return false;
+        }
+        match settings.max_output {
+            Some(max) if total > max => {
+                let sign = if n.is_negative() { "-" } else { "" };
+                render::truncated_digits(settings, &format!("{sign}{}", digits::leading(n, max, total)), total - max);
+            }
+            _ => render::digits(settings, n, signals::interrupted),
+        }
+        self.last_result = Some(LastResult::Digits(n.clone()));
+        true
+    }
+
+    /// Parses and evaluates a line holding a single expression.
+    pub fn eval(&mut self, line: &str) -> color_eyre::Result<Expr> {
+        let (mut statements, errors) = expr::parse(&self.evaluator, line);
+        if !errors.is_empty() {
+            bail!(errors.iter().map(|e| e.render(line)).collect::<Vec<_>>().join("\n"));
+        }
+        match statements.pop() {
+            Some(exp) if statements.is_empty() => self.evaluator.eval(exp),
+            _ => bail!("expected a single expression"),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Snapshots of an evaluation, to reproduce "it gave me a different answer"
+//! reports. `calq --snapshot bundle.json -e expr` writes what the answer
+//! depends on to `bundle.json`: the settings, the variables and functions
+//! defined before, like those from the config file, the expression and what
+//! it gave. calq has no randomness, so that is all there is.
+//!
+//! `calq --replay bundle.json` evaluates the expression again the same way,
+//! without the config file, and tells whether it gives the same answer.
+
+use color_eyre::eyre::{bail, eyre};
+use serde_json::{json, Map, Value as Json};
+
+use calq::expr::Evaluator;
+use calq::render;
+use calq::session;
+use calq::settings::Settings;
+
+/// The settings that have to do with the terminal rather than with the answer.
+const TERMINAL_SETTINGS: [&str; 2] = ["color", "theme"];
+
+/// The answer to `input`, as it would be printed, or the error.
+fn answer(e: &mut Evaluator, input: &str) -> Json {
+    match session::eval(e, input) {
+        Ok(result) => json!({ "result": e.display(&result).to_string() }),
+        Err(err) => json!({ "error": err.to_string() }),
+    }
+}
+
+/// Writes a bundle for evaluating `input` with `e`, which is left as it was.
+pub fn snapshot(path: &str, e: &Evaluator, input: &str) -> color_eyre::Result<()> {
+    let settings: Map<_, _> = e
+        .settings()
+        .list()
+        .into_iter()
+        .filter(|(name, _)| !TERMINAL_SETTINGS.contains(name))
+        .map(|(name, value)| (name.to_string(), Json::from(value)))
+        .collect();
+    let definitions: Vec<_> = e.definitions().map(|d| d.to_source(e.settings().caret)).collect();
+    let mut bundle = json!({
+        "calq": env!("CARGO_PKG_VERSION"),
+        "settings": settings,
+        "definitions": definitions,
+        "input": input,
+    });
+    let mut e = e.clone();
+    e.forbid_side_effects();
+    bundle.as_object_mut().unwrap().extend(answer(&mut e, input).as_object().unwrap().clone());
+    std::fs::write(path, format!("{bundle:#}\n")).map_err(|err| eyre!("could not write {path}: {err}"))
+}
+
+/// Evaluates the bundle at `path` again, returning the exit status: 0 if
+/// the answer is the same, 1 if it isn't and 2 if the bundle can't be read.
+pub fn replay(path: &str) -> i32 {
+    match check(path) {
+        Ok(same) => i32::from(!same),
+        Err(err) => {
+            render::error(&Settings::default(), err);
+            2
+        }
+    }
+}
+
+/// Whether the bundle at `path` gives the same answer again.
+fn check(path: &str) -> color_eyre::Result<bool> {
+    let contents = std::fs::read_to_string(path).map_err(|err| eyre!("could not read {path}: {err}"))?;
+    let bundle: Json = serde_json::from_str(&contents).map_err(|err| eyre!("{path}: {err}"))?;
+    let (mut e, input) = restore(&bundle).map_err(|err| eyre!("{path}: {err}"))?;
+    let version = bundle["calq"].as_str().unwrap_or("unknown");
+    if version != env!("CARGO_PKG_VERSION") {
+        render::info(format_args!("the snapshot was taken with calq {version}"));
+    }
+    let replayed = answer(&mut e, input);
+    let text = |answer: &Json| match (answer["result"].as_str(), answer["error"].as_str()) {
+        (Some(result), _) => result.to_string(),
+        (_, Some(error)) => format!("Error: {error}"),
+        _ => "nothing".to_string(),
+    };
+    println!("{}", text(&replayed));
+    if replayed["result"] != bundle["result"] || replayed["error"] != bundle["error"] {
+        render::error(e.settings(), format_args!("the snapshot gave `{}` instead", text(&bundle)));
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// The evaluator and the input of a bundle.
+fn restore(bundle: &Json) -> color_eyre::Result<(Evaluator, &str)> {
+    let mut e = Evaluator::default();
+    let Some(settings) = bundle["settings"].as_object() else {
+        bail!("expected the `settings` of the snapshot");
+    };
+    for (name, value) in settings {
+        let value = value.as_str().ok_or_else(|| eyre!("expected the value of `{name}` to be a string"))?;
+        e.settings_mut().set(name, value)?;
+    }
+    for definition in bundle["definitions"].as_array().into_iter().flatten() {
+        let definition = definition.as_str().ok_or_else(|| eyre!("expected definitions to be strings"))?;
+        session::eval(&mut e, definition).map_err(|err| eyre!("`{definition}`: {err}"))?;
+    }
+    let input = bundle["input"].as_str().ok_or_else(|| eyre!("expected the `input` of the snapshot"))?;
+    Ok((e, input))
+}
+
+#[test]
+fn test_restore() {
+    let mut e = Evaluator::default();
+    e.settings_mut().set("precision", "300").unwrap();
+    e.settings_mut().set("digits", "20").unwrap();
+    session::eval(&mut e, "x = 1/3; f(y) = x*y + 0.1; z = sqrt(2)").unwrap();
+    let path = std::env::temp_dir().join(format!("calq-snapshot-{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    snapshot(path, &e, "f(2)").unwrap();
+    let bundle: Json = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!("0.76666666666666666667", bundle["result"]);
+    let (mut restored, input) = restore(&bundle).unwrap();
+    assert_eq!(bundle["result"], answer(&mut restored, input)["result"]);
+    // decimals keep every digit of the precision, not just the printed ones
+    assert_eq!("1", answer(&mut restored, "z == sqrt(2)")["result"]);
+}
@@ -0,0 +1,219 @@
+//! `calq --server`, which answers JSON-RPC 2.0 requests read from stdin, one
+//! per line, with one line of JSON each on stdout, so that editors and other
+//! programs can use calq as a calculation engine.
+//!
+//! The methods are:
+//! - `evaluate` with `{"expr": "1/3 + x"}`, returning the result as `text` and
+//!   what `kind` of value it is, or `"expression"` if it has unknowns left
+//! - `define` with `{"name": "x", "value": "2"}` for variables and
+//!   `{"name": "f", "params": ["x"], "value": "x^2"}` for functions
+//! - `reset`, forgetting all definitions
+//! - `get-vars`, returning the `variables` and `functions` defined so far
+//!
+//! Expressions that can't be parsed are error `-32000`, or `-32001` if they
+//! are over the `max-input` or `max-depth` limits. A panic is error `-32603`
+//! for that request, and the server goes on.
+//!
+//! `calq --json -e <expr>` answers a single `evaluate` the same way, without
+//! the JSON-RPC envelope.
+
+use std::io::{BufRead, Write};
+
+use calq::session::guarded;
+use serde_json::{json, Map, Value as Json};
+
+use crate::expr::{self, Evaluator, Expr, Node, ParseErrorKind};
+use crate::settings::Settings;
+
+// https://www.jsonrpc.org/specification#error_object
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// A bug in calq, which ends the request rather than the server.
+const INTERNAL_ERROR: i64 = -32603;
+/// An expression that couldn't be parsed or evaluated.
+const EVAL_ERROR: i64 = -32000;
+/// An expression over the `max-input` or `max-depth` limits, which wasn't parsed at all.
+const TOO_COMPLEX: i64 = -32001;
+
+struct Error {
+    code: i64,
+    message: String,
+}
+
+impl Error {
+    fn new(code: i64, message: impl Into<String>) -> Error {
+        Error { code, message: message.into() }
+    }
+}
+
+#[derive(Default)]
+pub struct Server {
+    evaluator: Evaluator,
+}
+
+/// Answers requests until stdin is closed, with the settings given on the command line.
+pub fn run(settings: Settings) -> color_eyre::Result<()> {
+    let mut server = Server::default();
+    *server.evaluator.settings_mut() = settings;
+    let mut stdout = std::io::stdout().lock();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle(&line) {
+            writeln!(stdout, "{response}")?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// `calq --json -e <expr>`: prints what `evaluate` would answer, or the error
+/// as `{"error": {"code": ..., "message": ...}}`, and returns the exit status
+/// like [`Repl::run_once`](crate::repl::Repl::run_once).
+pub fn run_once(settings: Settings, source: &str, exit_value: bool) -> i32 {
+    let mut server = Server::default();
+    *server.evaluator.settings_mut() = settings;
+    let (answer, status) = match server.guard(|server| server.evaluate(source)) {
+        Ok((result, zero)) => (result, i32::from(exit_value && zero)),
+        Err(e) => (json!({ "error": { "code": e.code, "message": e.message } }), if exit_value { 2 } else { 1 }),
+    };
+    println!("{answer}");
+    status
+}
+
+impl Server {
+    /// Answers a request, `None` for notifications, which have no `id`.
+    pub fn handle(&mut self, line: &str) -> Option<String> {
+        let request: Json = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => return Some(response(Json::Null, Err(Error::new(PARSE_ERROR, e.to_string())))),
+        };
+        let id = request.get("id").cloned();
+        let result = match (request.get("method").and_then(Json::as_str), request.get("params")) {
+            (Some(method), params) => self.guard(|server| server.call(method, params.unwrap_or(&Json::Null))),
+            (None, _) => Err(Error::new(INVALID_REQUEST, "expected a `method`")),
+        };
+        id.map(|id| response(id, result))
+    }
+
+    /// Runs `f`, answering a panic in it with an internal error.
+    fn guard<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        guarded(|| f(self)).unwrap_or_else(|panic| {
+            self.evaluator.abandon();
+            Err(Error::new(INTERNAL_ERROR, format!("internal error, please report: {panic}")))
+        })
+    }
+
+    fn call(&mut self, method: &str, params: &Json) -> Result<Json, Error> {
+        match method {
+            "evaluate" => self.evaluate(string_param(params, "expr")?).map(|(result, _)| result),
+            "define" => {
+                let name = string_param(params, "name")?;
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(Error::new(INVALID_PARAMS, format!("`{name}` is not a valid name")));
+                }
+                let value = self.parse(string_param(params, "value")?)?;
+                let definition = match params.get("params") {
+                    None => Expr::assign(name, None, value),
+                    Some(Json::Array(names)) => {
+                        let names = names.iter().map(|p| p.as_str().map(String::from)).collect::<Option<_>>();
+                        let names = names.ok_or_else(|| Error::new(INVALID_PARAMS, "`params` must be strings"))?;
+                        Expr::define(name, names, value, false)
+                    }
+                    Some(_) => return Err(Error::new(INVALID_PARAMS, "`params` must be an array")),
+                };
+                self.evaluator.eval(definition).map_err(|e| Error::new(EVAL_ERROR, e.to_string()))?;
+                Ok(Json::Null)
+            }
+            "reset" => {
+                let settings = self.evaluator.settings().clone();
+                self.evaluator = Evaluator::default();
+                *self.evaluator.settings_mut() = settings;
+                Ok(Json::Null)
+            }
+            "get-vars" => {
+                let (mut variables, mut functions) = (Map::new(), Map::new());
+                for definition in self.evaluator.definitions() {
+                    match definition.root().node() {
+                        &Node::Assign(ref name, _, value) => {
+                            let value = definition.root().get(value).to_expr();
+                            variables.insert(name.clone(), self.evaluator.display(&value).to_string().into())
+                        }
+                        Node::Define(name, ..) => {
+                            let source = definition.to_source(self.evaluator.settings().caret);
+                            functions.insert(name.clone(), source.into())
+                        }
+                        // units are neither, and are used by writing them after numbers
+                        Node::DefineUnit(..) => continue,
+                        _ => unreachable!("definitions are assignments, functions or units"),
+                    };
+                }
+                Ok(json!({ "variables": variables, "functions": functions }))
+            }
+            _ => Err(Error::new(METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+        }
+    }
+
+    /// The answer to `evaluate`, and whether the result is zero or empty.
+    fn evaluate(&mut self, source: &str) -> Result<(Json, bool), Error> {
+        let result = self.parse(source)?;
+        let result = self.evaluator.eval(result).map_err(|e| Error::new(EVAL_ERROR, e.to_string()))?;
+        let (kind, zero) = match result.as_value() {
+            // `a rational` is for error messages
+            Some(v) => (v.kind().trim_start_matches("an ").trim_start_matches("a "), v.is_zero_or_empty()),
+            None => ("expression", false),
+        };
+        Ok((json!({ "text": self.evaluator.display(&result).to_string(), "kind": kind }), zero))
+    }
+
+    /// Parses a single expression.
+    fn parse(&self, source: &str) -> Result<Expr, Error> {
+        let (mut statements, errors) = expr::parse(&self.evaluator, source);
+        if let Some(e) = errors.first() {
+            let code = if e.kind == ParseErrorKind::TooComplex { TOO_COMPLEX } else { EVAL_ERROR };
+            return Err(Error::new(code, e.render(source)));
+        }
+        match statements.pop() {
+            Some(e) if statements.is_empty() => Ok(e),
+            _ => Err(Error::new(INVALID_PARAMS, "expected a single expression")),
+        }
+    }
+}
+
+fn string_param<'a>(params: &'a Json, name: &str) -> Result<&'a str, Error> {
+    params
+        .get(name)
+        .and_then(Json::as_str)
+        .ok_or_else(|| Error::new(INVALID_PARAMS, format!("expected a string `{name}` parameter")))
+}
+
+fn response(id: Json, result: Result<Json, Error>) -> String {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": e.code, "message": e.message } }),
+    }
+    .to_string()
+}
+
+#[test]
+fn test_handle() {
+    let mut server = Server::default();
+    let define = r#"{"jsonrpc": "2.0", "id": 1, "method": "define", "params": {"name": "f", "params": ["x"], "value": "x^2"}}"#;
+    assert_eq!(r#"{"id":1,"jsonrpc":"2.0","result":null}"#, server.handle(define).unwrap());
+    let evaluate = r#"{"jsonrpc": "2.0", "id": 2, "method": "evaluate", "params": {"expr": "f(3)/2"}}"#;
+    let response = server.handle(evaluate).unwrap();
+    assert_eq!(r#"{"id":2,"jsonrpc":"2.0","result":{"kind":"rational","text":"9/2"}}"#, response);
+    assert_eq!(None, server.handle(r#"{"jsonrpc": "2.0", "method": "reset"}"#));
+    let response = server.handle(r#"{"jsonrpc": "2.0", "id": 3, "method": "get-vars"}"#).unwrap();
+    assert_eq!(r#"{"id":3,"jsonrpc":"2.0","result":{"functions":{},"variables":{}}}"#, response);
+    // a bug is an error for that request, and the server goes on
+    let bug = server.guard(|_| -> Result<(), Error> { panic!("bug") }).err().unwrap();
+    assert_eq!(INTERNAL_ERROR, bug.code);
+    assert!(bug.message.starts_with("internal error, please report: panicked at src/server.rs"), "{}", bug.message);
+    server.handle(define).unwrap();
+    assert!(server.handle(evaluate).unwrap().contains("9/2"));
+}
@@ -0,0 +1,133 @@
+//! A [`Session`] that can be shared between threads, for servers that
+//! evaluate requests from many threads with one session per user.
+//!
+//! ```
+//! let session = std::sync::Arc::new(calq::session::Session::default());
+//! let other = std::sync::Arc::clone(&session);
+//! std::thread::spawn(move || other.eval("x = 1/3")).join().unwrap().unwrap();
+//! assert_eq!("1/2", session.eval("x + 1/6").unwrap());
+//! ```
+//!
+//! Each call on a session happens as a whole or not at all: calls from
+//! different threads take turns, so `x = x + 1` from two threads at once adds
+//! 2, and a call never sees the variables of another one halfway through.
+//! Different sessions share nothing and evaluate in parallel.
+//!
+//! A call that panics leaves the session as it was before the statement that
+//! panicked, and later calls can keep using it.
+
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard, Once};
+
+use color_eyre::eyre::bail;
+
+use crate::expr::{self, Evaluator, Expr};
+use crate::settings::Settings;
+
+/// An evaluator with its variables and functions, behind a lock.
+#[derive(Default)]
+pub struct Session {
+    evaluator: Mutex<Evaluator>,
+}
+
+impl Session {
+    pub fn new(settings: Settings) -> Session {
+        let mut evaluator = Evaluator::default();
+        *evaluator.settings_mut() = settings;
+        Session { evaluator: Mutex::new(evaluator) }
+    }
+
+    /// Evaluates the `;` separated statements in `source`, returning the
+    /// result of the last one as text.
+    pub fn eval(&self, source: &str) -> color_eyre::Result<String> {
+        let mut evaluator = self.lock();
+        let result = eval(&mut evaluator, source)?;
+        Ok(evaluator.display(&result).to_string())
+    }
+
+    /// The evaluator, for anything more than [`Session::eval`]. Other calls
+    /// on the session wait until the guard is dropped.
+    pub fn lock(&self) -> MutexGuard<'_, Evaluator> {
+        self.evaluator.lock().unwrap_or_else(|poisoned| {
+            let mut evaluator = poisoned.into_inner();
+            evaluator.abandon();
+            self.evaluator.clear_poison();
+            evaluator
+        })
+    }
+}
+
+/// Evaluates the `;` separated statements in `source`, returning the result of the last one.
+pub fn eval(evaluator: &mut Evaluator, source: &str) -> color_eyre::Result<Expr> {
+    let (statements, errors) = expr::parse(evaluator, source);
+    if !errors.is_empty() {
+        bail!(errors.iter().map(|e| e.render(source)).collect::<Vec<_>>().join("\n"));
+    }
+    let mut result = None;
+    for statement in statements {
+        result = Some(evaluator.eval(statement)?);
+    }
+    match result {
+        Some(result) => Ok(result),
+        None => bail!("there is nothing to evaluate"),
+    }
+}
+
+/// [`eval`] with the result as it is displayed, for tests.
+#[cfg(test)]
+pub(crate) fn eval_text(evaluator: &mut Evaluator, source: &str) -> color_eyre::Result<String> {
+    let result = eval(evaluator, source)?;
+    Ok(evaluator.display(&result).to_string())
+}
+
+thread_local! {
+    /// Whether a panic on this thread is caught by [`guarded`].
+    static GUARDED: Cell<bool> = const { Cell::new(false) };
+    /// The message and location of the last panic [`guarded`] caught.
+    static PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, returning the message of a panic in it instead of unwinding
+/// further, so that a bug ends the line rather than the session and everything
+/// defined in it. Panics elsewhere are still reported by the usual hook.
+pub fn guarded<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if GUARDED.get() {
+                PANIC.set(Some(info.to_string()));
+            } else {
+                previous(info);
+            }
+        }));
+    });
+    GUARDED.set(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    GUARDED.set(false);
+    result.map_err(|_| PANIC.take().unwrap_or_else(|| "unknown panic".into()))
+}
+
+#[test]
+fn test_threads() {
+    fn shared<T: Send + Sync>() {}
+    shared::<Session>();
+    let session = Session::default();
+    session.eval("n = 0").unwrap();
+    std::thread::scope(|s| {
+        for _ in 0..8 {
+            s.spawn(|| (0..10).for_each(|_| drop(session.eval("n = n + 1").unwrap())));
+        }
+    });
+    assert_eq!("80", session.eval("n").unwrap());
+    assert!(session.eval("").is_err());
+}
+
+#[test]
+fn test_guarded() {
+    assert_eq!(Ok(2), guarded(|| 1 + 1));
+    let panic = guarded(|| -> i32 { panic!("oops") }).unwrap_err();
+    assert!(panic.starts_with("panicked at src/session.rs") && panic.ends_with("oops"), "{panic}");
+    assert_eq!(Ok(3), guarded(|| 3));
+}
@@ -0,0 +1,484 @@
+//! Session settings, changed from the REPL with `:set <name> <value>`.
+
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::{bail, eyre};
+
+use crate::div::DivisionPolicy;
+use crate::expr::MAX_DEPTH;
+use crate::render::Theme;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ComplexForm {
+    /// `1+2i`
+    Rectangular,
+    /// `polar(2.236068, 1.1071487)`
+    Polar,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AngleMode {
+    Radians,
+    Degrees,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Everything on one line, `1/2`
+    Inline,
+    /// Fractions with bars and matrices in grids, over multiple lines
+    TwoD,
+}
+
+/// What happens when a computation on finite numbers produces ±∞ or NaN.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NonFinite {
+    /// Report an error as soon as it happens.
+    Error,
+    /// Keep going with the special value, printed as `∞` or `NaN`.
+    Propagate,
+}
+
+/// Which way decimals are rounded to the precision, as in IEEE 754.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// To the nearest, ties to even.
+    Nearest,
+    Zero,
+    Up,
+    Down,
+}
+
+impl Rounding {
+    pub fn parse(name: &str) -> Option<Rounding> {
+        Some(match name {
+            "nearest" => Rounding::Nearest,
+            "zero" | "toward-zero" => Rounding::Zero,
+            "up" => Rounding::Up,
+            "down" => Rounding::Down,
+            _ => return None,
+        })
+    }
+}
+
+/// What `^` means, `**` is always a power.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CaretOp {
+    Pow,
+    /// Bitwise exclusive or of integers, binding looser than sums like in C and Python.
+    Xor,
+}
+
+#[derive(Clone)]
+pub struct Settings {
+    /// Bits of precision decimals are computed with.
+    pub precision: u32,
+    pub rounding: Rounding,
+    /// Never compute with decimals, leaving what would be inexact symbolic.
+    pub strict_exact: bool,
+    /// Leave constants like `pi` and inexact results like `sqrt(2)` symbolic, see `N`.
+    pub symbolic: bool,
+    /// Compute with exact amounts of money with this many fraction digits, see `expr/money.rs`.
+    pub money: Option<u32>,
+    /// Number of significant digits decimals are printed with.
+    pub round_digits: usize,
+    /// Decimals with a decimal exponent of this or lower are printed in scientific notation.
+    pub sci_low: i32,
+    /// Decimals with a decimal exponent of this or higher are printed in
+    /// scientific notation, `None` for those with more integer digits than `round_digits`.
+    pub sci_high: Option<i32>,
+    /// Print the zeros at the end of decimals, `2.5000000` rather than `2.5`.
+    pub trailing_zeros: bool,
+    /// Print the zero before the point of decimals below 1, `0.5` rather than `.5`.
+    pub leading_zero: bool,
+    /// Decimals are printed with at least this many digits after the point.
+    pub min_digits: usize,
+    /// Print decimals with the fewest digits that read back as the same number,
+    /// rather than with `round_digits`.
+    pub round_trip: bool,
+    pub complex_form: ComplexForm,
+    /// Exact values with more digits than this are displayed as a decimal
+    /// approximation instead, `None` to always display them exactly.
+    pub exact_digits: Option<usize>,
+    /// Follow non-integer exact results with their decimal approximation.
+    pub show_approx: bool,
+    /// Follow decimal results with how far apart rounding down and up puts them.
+    pub error_bars: bool,
+    /// Whether output is colored at all.
+    pub color: bool,
+    /// The name of the current theme in `themes`.
+    pub theme: String,
+    pub themes: BTreeMap<String, Theme>,
+    /// Results longer than this many characters are truncated, see `:full`.
+    pub max_output: Option<usize>,
+    /// Lines longer than this many bytes are refused before they are parsed.
+    pub max_input: Option<usize>,
+    /// How deep brackets and chains of powers can be nested in a line, at
+    /// most [`MAX_DEPTH`].
+    pub max_depth: usize,
+    /// List results with more items than this only show the first and last ones.
+    pub max_items: Option<usize>,
+    /// List results wider than this are wrapped into aligned columns.
+    pub list_width: Option<usize>,
+    pub division: DivisionPolicy,
+    pub nonfinite: NonFinite,
+    /// The unit trigonometric functions take and return angles in.
+    pub angle: AngleMode,
+    pub render: RenderMode,
+    /// Close brackets left open when a line is entered.
+    pub autoclose: bool,
+    /// Insert the `*` in `2x` and `(a)(b)` instead of reporting a syntax error.
+    pub implicit_mul: bool,
+    /// Show the result of the line as a hint while typing.
+    pub preview: bool,
+    /// Rewrite phrases like `sqrt of 2` and `5 plus 3` into expressions.
+    pub natural: bool,
+    /// Reduce constant parts like the `2*3` in `2*3*x` once when parsing.
+    pub fold: bool,
+    /// Print units like `kg*m/s^2` as they are, rather than as the derived
+    /// unit `N`, and derived units like `N` in base units.
+    pub base_units: bool,
+    /// Let variables have the names of builtin functions and of constants
+    /// like `pi`, which is otherwise an error or a warning.
+    pub allow_shadowing: bool,
+    pub caret: CaretOp,
+}
+
+fn parse_bool(name: &str, value: &str) -> color_eyre::Result<bool> {
+    match value {
+        "on" | "true" | "yes" => Ok(true),
+        "off" | "false" | "no" => Ok(false),
+        _ => bail!("`{name}` must be `on` or `off`, found `{value}`"),
+    }
+}
+
+/// The most fraction digits `money` can be set to.
+const MAX_MONEY_DIGITS: u32 = 12;
+
+/// `1e1000` and `1e-1000` are as far as writing decimals out in full makes sense.
+const MAX_SCI_EXPONENT: i32 = 1000;
+
+/// The most digits after the point `min-digits` can ask for.
+const MAX_MIN_DIGITS: usize = 100;
+
+/// Decimals can't be computed with less than this, or more than `MAX_PRECISION`.
+const MIN_PRECISION: u32 = 8;
+pub const MAX_PRECISION: u32 = 1 << 20;
+
+/// A precision in bits, or in decimal digits like `50d`.
+fn parse_precision(value: &str) -> Option<u32> {
+    let bits = match value.strip_suffix('d') {
+        Some(digits) => (digits.parse::<f64>().ok()? / std::f64::consts::LOG10_2).ceil() as u32,
+        None => value.parse().ok()?,
+    };
+    (MIN_PRECISION..=MAX_PRECISION).contains(&bits).then_some(bits)
+}
+
+fn fmt_bool(b: bool) -> String {
+    if b { "on" } else { "off" }.to_string()
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            precision: 100,
+            rounding: Rounding::Nearest,
+            strict_exact: false,
+            symbolic: false,
+            money: None,
+            round_digits: 8,
+            sci_low: -4,
+            sci_high: None,
+            trailing_zeros: true,
+            leading_zero: true,
+            min_digits: 0,
+            round_trip: false,
+            complex_form: ComplexForm::Rectangular,
+            exact_digits: Some(100),
+            show_approx: false,
+            error_bars: false,
+            color: false,
+            theme: "default".into(),
+            themes: BTreeMap::from([("default".into(), Theme::default())]),
+            max_output: Some(2000),
+            max_input: Some(100_000),
+            max_depth: MAX_DEPTH,
+            max_items: Some(100),
+            list_width: Some(80),
+            division: DivisionPolicy::Error,
+            nonfinite: NonFinite::Error,
+            angle: AngleMode::Radians,
+            render: RenderMode::Inline,
+            autoclose: true,
+            implicit_mul: true,
+            preview: false,
+            natural: true,
+            fold: true,
+            base_units: false,
+            allow_shadowing: false,
+            caret: CaretOp::Pow,
+        }
+    }
+}
+
+impl Settings {
+    pub fn set(&mut self, name: &str, value: &str) -> color_eyre::Result<()> {
+        match name {
+            "precision" => {
+                self.precision = parse_precision(value).ok_or_else(|| {
+                    eyre!(
+                        "`precision` must be a number of bits from {MIN_PRECISION} to {MAX_PRECISION}, \
+                         or of digits like `50d`, found `{value}`"
+                    )
+                })?;
+            }
+            "rounding" => {
+                self.rounding = Rounding::parse(value).ok_or_else(|| {
+                    eyre!("`rounding` must be `nearest`, `zero` (or `toward-zero`), `up` or `down`, found `{value}`")
+                })?;
+            }
+            "strict-exact" => self.strict_exact = parse_bool(name, value)?,
+            "symbolic" => self.symbolic = parse_bool(name, value)?,
+            "money" => {
+                self.money = match value {
+                    "off" => None,
+                    _ => Some(value.parse().ok().filter(|&d| d <= MAX_MONEY_DIGITS).ok_or_else(|| {
+                        eyre!("`money` must be a number of fraction digits up to {MAX_MONEY_DIGITS} or `off`, found `{value}`")
+                    })?),
+                };
+            }
+            "digits" => {
+                self.round_digits = value
+                    .parse()
+                    .ok()
+                    .filter(|&d| d > 0)
+                    .ok_or_else(|| eyre!("`digits` must be a positive integer, found `{value}`"))?;
+            }
+            "sci-low" => {
+                self.sci_low = value.parse().ok().filter(|e| (-MAX_SCI_EXPONENT..0).contains(e)).ok_or_else(|| {
+                    eyre!("`sci-low` must be an exponent from -{MAX_SCI_EXPONENT} to -1, found `{value}`")
+                })?;
+            }
+            "sci-high" => {
+                self.sci_high = match value {
+                    "auto" => None,
+                    _ => Some(value.parse().ok().filter(|e| (1..=MAX_SCI_EXPONENT).contains(e)).ok_or_else(|| {
+                        eyre!("`sci-high` must be an exponent from 1 to {MAX_SCI_EXPONENT} or `auto`, found `{value}`")
+                    })?),
+                };
+            }
+            "trailing-zeros" => self.trailing_zeros = parse_bool(name, value)?,
+            "leading-zero" => self.leading_zero = parse_bool(name, value)?,
+            "min-digits" => {
+                self.min_digits = value.parse().ok().filter(|&d| d <= MAX_MIN_DIGITS).ok_or_else(|| {
+                    eyre!("`min-digits` must be a number of digits up to {MAX_MIN_DIGITS}, found `{value}`")
+                })?;
+            }
+            "round-trip" => self.round_trip = parse_bool(name, value)?,
+            "complex-form" => {
+                self.complex_form = match value {
+                    "rectangular" | "rect" => ComplexForm::Rectangular,
+                    "polar" => ComplexForm::Polar,
+                    _ => bail!("`complex-form` must be `rectangular` or `polar`, found `{value}`"),
+                };
+            }
+            "exact-digits" => {
+                self.exact_digits = match value {
+                    "off" => None,
+                    _ => Some(value.parse().map_err(|_| {
+                        eyre!("`exact-digits` must be a number of digits or `off`, found `{value}`")
+                    })?),
+                };
+            }
+            "show-approx" => self.show_approx = parse_bool(name, value)?,
+            "error-bars" => self.error_bars = parse_bool(name, value)?,
+            "color" => self.color = parse_bool(name, value)?,
+            "theme" => {
+                if !self.themes.contains_key(value) {
+                    bail!("unknown theme `{value}`, define it with `:theme {value} value=... symbol=... error=...`");
+                }
+                self.theme = value.into();
+            }
+            "max-output" => {
+                self.max_output = match value {
+                    "off" => None,
+                    _ => Some(value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        eyre!("`max-output` must be a positive number of characters or `off`, found `{value}`")
+                    })?),
+                };
+            }
+            "max-input" => {
+                self.max_input = match value {
+                    "off" => None,
+                    _ => Some(value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        eyre!("`max-input` must be a positive number of bytes or `off`, found `{value}`")
+                    })?),
+                };
+            }
+            "max-depth" => {
+                self.max_depth = value.parse().ok().filter(|d| (1..=MAX_DEPTH).contains(d)).ok_or_else(|| {
+                    eyre!("`max-depth` must be a number from 1 to {MAX_DEPTH}, found `{value}`")
+                })?;
+            }
+            "max-items" | "list-width" => {
+                let limit = match value {
+                    "off" => None,
+                    _ => Some(value.parse().ok().filter(|&n| n > 1).ok_or_else(|| {
+                        eyre!("`{name}` must be a number greater than 1 or `off`, found `{value}`")
+                    })?),
+                };
+                if name == "max-items" {
+                    self.max_items = limit;
+                } else {
+                    self.list_width = limit;
+                }
+            }
+            "division" => {
+                self.division = match value {
+                    "error" => DivisionPolicy::Error,
+                    "ieee" => DivisionPolicy::Ieee,
+                    _ => bail!("`division` must be `error` or `ieee`, found `{value}`"),
+                };
+            }
+            "nonfinite" => {
+                self.nonfinite = match value {
+                    "error" => NonFinite::Error,
+                    "propagate" => NonFinite::Propagate,
+                    _ => bail!("`nonfinite` must be `error` or `propagate`, found `{value}`"),
+                };
+            }
+            "angle" => {
+                self.angle = match value {
+                    "radians" | "rad" => AngleMode::Radians,
+                    "degrees" | "deg" => AngleMode::Degrees,
+                    _ => bail!("`angle` must be `radians` or `degrees`, found `{value}`"),
+                };
+            }
+            "autoclose" => self.autoclose = parse_bool(name, value)?,
+            "implicit-mul" => self.implicit_mul = parse_bool(name, value)?,
+            "preview" => self.preview = parse_bool(name, value)?,
+            "natural" => self.natural = parse_bool(name, value)?,
+            "fold" => self.fold = parse_bool(name, value)?,
+            "base-units" => self.base_units = parse_bool(name, value)?,
+            "allow-shadowing" => self.allow_shadowing = parse_bool(name, value)?,
+            "caret" => {
+                self.caret = match value {
+                    "pow" => CaretOp::Pow,
+                    "xor" => CaretOp::Xor,
+                    _ => bail!("`caret` must be `pow` or `xor`, found `{value}`"),
+                };
+            }
+            "render" => {
+                self.render = match value {
+                    "inline" => RenderMode::Inline,
+                    "2d" => RenderMode::TwoD,
+                    _ => bail!("`render` must be `inline` or `2d`, found `{value}`"),
+                };
+            }
+            _ => bail!("unknown setting `{name}`"),
+        }
+        Ok(())
+    }
+
+    /// The name and current value of every setting.
+    pub fn list(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("precision", self.precision.to_string()),
+            (
+                "rounding",
+                match self.rounding {
+                    Rounding::Nearest => "nearest",
+                    Rounding::Zero => "zero",
+                    Rounding::Up => "up",
+                    Rounding::Down => "down",
+                }
+                .to_string(),
+            ),
+            ("strict-exact", fmt_bool(self.strict_exact)),
+            ("symbolic", fmt_bool(self.symbolic)),
+            ("money", self.money.map_or("off".to_string(), |d| d.to_string())),
+            ("digits", self.round_digits.to_string()),
+            ("sci-low", self.sci_low.to_string()),
+            ("sci-high", self.sci_high.map_or("auto".to_string(), |e| e.to_string())),
+            ("trailing-zeros", fmt_bool(self.trailing_zeros)),
+            ("leading-zero", fmt_bool(self.leading_zero)),
+            ("min-digits", self.min_digits.to_string()),
+            ("round-trip", fmt_bool(self.round_trip)),
+            (
+                "complex-form",
+                match self.complex_form {
+                    ComplexForm::Rectangular => "rectangular",
+                    ComplexForm::Polar => "polar",
+                }
+                .to_string(),
+            ),
+            ("exact-digits", self.exact_digits.map_or("off".to_string(), |d| d.to_string())),
+            ("show-approx", fmt_bool(self.show_approx)),
+            ("error-bars", fmt_bool(self.error_bars)),
+            ("color", fmt_bool(self.color)),
+            ("theme", self.theme.clone()),
+            ("max-output", self.max_output.map_or("off".to_string(), |n| n.to_string())),
+            ("max-input", self.max_input.map_or("off".to_string(), |n| n.to_string())),
+            ("max-depth", self.max_depth.to_string()),
+            ("max-items", self.max_items.map_or("off".to_string(), |n| n.to_string())),
+            ("list-width", self.list_width.map_or("off".to_string(), |n| n.to_string())),
+            (
+                "division",
+                match self.division {
+                    DivisionPolicy::Error => "error",
+                    DivisionPolicy::Ieee => "ieee",
+                }
+                .to_string(),
+            ),
+            (
+                "nonfinite",
+                match self.nonfinite {
+                    NonFinite::Error => "error",
+                    NonFinite::Propagate => "propagate",
+                }
+                .to_string(),
+            ),
+            (
+                "angle",
+                match self.angle {
+                    AngleMode::Radians => "radians",
+                    AngleMode::Degrees => "degrees",
+                }
+                .to_string(),
+            ),
+            (
+                "render",
+                match self.render {
+                    RenderMode::Inline => "inline",
+                    RenderMode::TwoD => "2d",
+                }
+                .to_string(),
+            ),
+            ("autoclose", fmt_bool(self.autoclose)),
+            ("implicit-mul", fmt_bool(self.implicit_mul)),
+            ("preview", fmt_bool(self.preview)),
+            ("natural", fmt_bool(self.natural)),
+            ("fold", fmt_bool(self.fold)),
+            ("base-units", fmt_bool(self.base_units)),
+            ("allow-shadowing", fmt_bool(self.allow_shadowing)),
+            (
+                "caret",
+                match self.caret {
+                    CaretOp::Pow => "pow",
+                    CaretOp::Xor => "xor",
+                }
+                .to_string(),
+            ),
+        ]
+    }
+
+    /// `exact_digits`, except that `strict-exact` never approximates exact values.
+    pub fn approximate_after(&self) -> Option<usize> {
+        self.exact_digits.filter(|_| !self.strict_exact)
+    }
+
+    /// The theme output should be colored with, if colors are on.
+    pub fn theme(&self) -> Option<&Theme> {
+        self.themes.get(&self.theme).filter(|_| self.color)
+    }
+}
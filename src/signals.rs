@@ -0,0 +1,70 @@
+//! Signals the REPL handles itself.
+//!
+//! Ctrl-C while a line is being evaluated or its result printed no longer
+//! kills calq in the middle of writing a colored result, the line is finished
+//! instead, and pressing it a second time quits. The digits of a huge number
+//! stop being printed at the first one. At the prompt the line editor
+//! reads Ctrl-C as a key, so this doesn't change what it does there.
+//!
+//! SIGTERM and SIGHUP put the terminal back the way it was before quitting.
+//! History and workspaces are saved after every line, so there is nothing
+//! else left to save by then.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+
+static INTERRUPTED: LazyLock<Arc<AtomicBool>> = LazyLock::new(Arc::default);
+
+#[cfg(unix)]
+pub fn install() -> std::io::Result<()> {
+    use std::io::Write;
+
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    // a second Ctrl-C before the line is done quits, with the status of dying by it
+    signal_hook::flag::register_conditional_shutdown(SIGINT, 128 + SIGINT, Arc::clone(&INTERRUPTED))?;
+    signal_hook::flag::register(SIGINT, Arc::clone(&INTERRUPTED))?;
+    let terminal = terminal_mode();
+    let mut signals = Signals::new([SIGTERM, SIGHUP])?;
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            if let Some(mode) = terminal {
+                // SAFETY: `mode` was filled in by `tcgetattr` on the same descriptor
+                unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &mode) };
+                // the line editor turns on bracketed paste while it reads a line
+                print!("\x1b[?2004l");
+                let _ = std::io::stdout().flush();
+            }
+            std::process::exit(128 + signal);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn install() -> std::io::Result<()> {
+    Ok(())
+}
+
+/// The mode of the terminal on stdin before the line editor changes it, if there is one.
+#[cfg(unix)]
+fn terminal_mode() -> Option<libc::termios> {
+    let mut mode = std::mem::MaybeUninit::uninit();
+    // SAFETY: `tcgetattr` only writes to `mode`, which is only read if it succeeded
+    let ok = unsafe { libc::tcgetattr(libc::STDIN_FILENO, mode.as_mut_ptr()) } == 0;
+    // SAFETY: see above
+    ok.then(|| unsafe { mode.assume_init() })
+}
+
+/// Whether Ctrl-C was pressed while the current line is handled, for work
+/// that can stop halfway, like printing the digits of a huge number.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Called once a line is handled, so that Ctrl-C only quits if it's pressed
+/// twice while the same line is.
+pub fn line_done() {
+    INTERRUPTED.store(false, Ordering::Relaxed);
+}
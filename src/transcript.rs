@@ -0,0 +1,124 @@
+//! `:log file` appends every line typed and everything printed to a file, each
+//! with a timestamp, until `:log off`.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use color_eyre::eyre::eyre;
+
+struct Transcript {
+    file: File,
+    path: String,
+}
+
+thread_local! {
+    static TRANSCRIPT: RefCell<Option<Transcript>> = const { RefCell::new(None) };
+}
+
+/// Starts appending to `path`, replacing the transcript that was being written.
+pub fn start(path: &str) -> color_eyre::Result<()> {
+    let file = File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| eyre!("could not open `{path}`: {e}"))?;
+    TRANSCRIPT.with_borrow_mut(|t| *t = Some(Transcript { file, path: path.into() }));
+    Ok(())
+}
+
+/// Stops writing the transcript, returning the file it was written to.
+pub fn stop() -> Option<String> {
+    TRANSCRIPT.with_borrow_mut(|t| t.take().map(|t| t.path))
+}
+
+/// Whether a transcript is being written.
+pub fn active() -> bool {
+    TRANSCRIPT.with_borrow(|t| t.is_some())
+}
+
+/// Records a line the user entered.
+pub fn input(line: &str) {
+    record("> ", line);
+}
+
+/// Records printed output, without its colors.
+pub fn output(text: &str) {
+    record("  ", &strip_colors(text));
+}
+
+/// Starts recording a line of output that is printed a piece at a time, like
+/// the digits of a huge number, so that it doesn't have to be put together first.
+pub fn output_start() {
+    TRANSCRIPT.with_borrow_mut(|t| {
+        if let Some(t) = t {
+            let _ = write!(t.file, "[{}]   ", timestamp(SystemTime::now()));
+        }
+    });
+}
+
+/// Records the next piece of the line started with [`output_start`], which has no colors.
+pub fn output_part(text: &str) {
+    TRANSCRIPT.with_borrow_mut(|t| {
+        if let Some(t) = t {
+            let _ = t.file.write_all(text.as_bytes());
+        }
+    });
+}
+
+/// Ends the line started with [`output_start`].
+pub fn output_end() {
+    output_part("\n");
+}
+
+fn record(prefix: &str, text: &str) {
+    TRANSCRIPT.with_borrow_mut(|t| {
+        let Some(t) = t else { return };
+        let time = timestamp(SystemTime::now());
+        for line in text.lines() {
+            // a transcript that can't be written shouldn't get in the way of calculating
+            let _ = writeln!(t.file, "[{time}] {prefix}{line}");
+        }
+    });
+}
+
+fn strip_colors(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // `ESC [ ... m`
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// `2024-01-31 13:45:00Z`
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+    // civil date from days since the epoch, from Howard Hinnant's `civil_from_days`
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year}-{month:02}-{day:02} {:02}:{:02}:{:02}Z", rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+#[test]
+fn test_timestamp() {
+    use std::time::Duration;
+
+    assert_eq!("1970-01-01 00:00:00Z", timestamp(UNIX_EPOCH));
+    assert_eq!("2024-02-29 13:45:07Z", timestamp(UNIX_EPOCH + Duration::from_secs(1709214307)));
+    assert_eq!("\"a\" = 1", strip_colors("\x1b[32m\"a\"\x1b[0m = 1"));
+}
@@ -0,0 +1,204 @@
+//! Named workspaces, each with its own variables, functions and settings.
+//!
+//! `:workspace name` creates one and `:workspace switch name` goes back to it.
+//! Every workspace but `default` is saved to its own file when switching away
+//! from it and after every line, as lines of `:set` commands and definitions
+//! like the config file, and loaded again the next time it is switched to.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use color_eyre::eyre::{bail, eyre};
+
+use crate::expr::{self, Evaluator};
+use crate::settings::Settings;
+
+/// The workspace calq starts in, which isn't saved.
+pub const DEFAULT: &str = "default";
+
+pub struct Workspaces {
+    pub current: String,
+    /// The workspaces switched away from in this session.
+    inactive: BTreeMap<String, Evaluator>,
+}
+
+impl Default for Workspaces {
+    fn default() -> Self {
+        Workspaces { current: DEFAULT.into(), inactive: BTreeMap::new() }
+    }
+}
+
+impl Workspaces {
+    /// The names of all workspaces, including the saved ones not used yet in this session.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.inactive.keys().cloned().collect();
+        names.push(self.current.clone());
+        if let Some(entries) = dir().and_then(|d| std::fs::read_dir(d).ok()) {
+            names.extend(entries.filter_map(|e| e.ok()?.file_name().into_string().ok()));
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        name == self.current || self.inactive.contains_key(name) || path(name).is_some_and(|p| p.exists())
+    }
+
+    /// Makes `name` the current workspace, replacing `current` by its evaluator
+    /// and saving the one switched away from. With `create`, the workspace must
+    /// not exist yet and starts out empty.
+    pub fn switch(&mut self, current: &mut Evaluator, name: &str, create: bool) -> color_eyre::Result<()> {
+        check_name(name)?;
+        if name == self.current {
+            bail!("already in workspace `{name}`");
+        }
+        let exists = self.exists(name);
+        if create && exists {
+            bail!("workspace `{name}` already exists, use `:workspace switch {name}` to go to it");
+        } else if !create && !exists {
+            bail!("there is no workspace named `{name}`, use `:workspace {name}` to create it");
+        }
+        let next = match self.inactive.remove(name) {
+            Some(next) => next,
+            None => load(name, fresh(current))?,
+        };
+        let previous = std::mem::replace(current, next);
+        let previous_name = std::mem::replace(&mut self.current, name.into());
+        let saved = save(&previous_name, &previous);
+        self.inactive.insert(previous_name, previous);
+        saved
+    }
+
+    /// Forgets a workspace and deletes its file.
+    pub fn delete(&mut self, name: &str) -> color_eyre::Result<()> {
+        check_name(name)?;
+        if name == self.current {
+            bail!("can't delete the current workspace");
+        }
+        if name == DEFAULT {
+            bail!("the `{DEFAULT}` workspace can't be deleted");
+        }
+        let in_memory = self.inactive.remove(name).is_some();
+        match path(name) {
+            Some(path) if path.exists() => {
+                std::fs::remove_file(&path).map_err(|e| eyre!("could not delete {}: {e}", path.display()))?;
+            }
+            _ if in_memory => {}
+            _ => bail!("there is no workspace named `{name}`"),
+        }
+        Ok(())
+    }
+}
+
+fn check_name(name: &str) -> color_eyre::Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+        bail!("workspace names may only contain letters, digits, `_` and `-`, found `{name}`");
+    }
+    Ok(())
+}
+
+/// An empty evaluator for a new workspace, keeping how output is displayed.
+fn fresh(current: &Evaluator) -> Evaluator {
+    let mut e = Evaluator::default();
+    let settings = e.settings_mut();
+    settings.color = current.settings().color;
+    settings.themes = current.settings().themes.clone();
+    e
+}
+
+/// `$XDG_DATA_HOME/calq`, falling back to `~/.local/share/calq`, or
+/// `%LOCALAPPDATA%\calq` on Windows.
+pub fn data_dir() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_DATA_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(dir.join("calq"))
+}
+
+fn dir() -> Option<PathBuf> {
+    Some(data_dir()?.join("workspaces"))
+}
+
+fn path(name: &str) -> Option<PathBuf> {
+    Some(dir()?.join(name))
+}
+
+/// Saves a workspace to its file, doing nothing for the default one.
+pub fn save(name: &str, e: &Evaluator) -> color_eyre::Result<()> {
+    if name == DEFAULT {
+        return Ok(());
+    }
+    let path = path(name).ok_or_else(|| eyre!("could not find a directory to save workspaces in"))?;
+    let write = || {
+        std::fs::create_dir_all(path.parent().expect("workspaces are in a directory"))?;
+        std::fs::write(&path, serialize(e))
+    };
+    write().map_err(|err| eyre!("could not save workspace `{name}` to {}: {err}", path.display()))
+}
+
+/// Loads a saved workspace on top of `e`, which is returned as is if it was never saved.
+fn load(name: &str, mut e: Evaluator) -> color_eyre::Result<Evaluator> {
+    let Some(path) = path(name).filter(|p| p.exists()) else {
+        return Ok(e);
+    };
+    let contents =
+        std::fs::read_to_string(&path).map_err(|err| eyre!("could not read {}: {err}", path.display()))?;
+    deserialize(&contents, &mut e).map_err(|err| eyre!("{}: {err}", path.display()))?;
+    Ok(e)
+}
+
+/// The settings that differ from the defaults and every definition, one per line.
+fn serialize(e: &Evaluator) -> String {
+    let defaults = Settings::default().list();
+    let mut out = String::from("# calq workspace\n");
+    for ((name, value), (_, default)) in e.settings().list().into_iter().zip(defaults) {
+        // colors are a property of the terminal rather than of the workspace
+        if value != default && name != "color" {
+            out.push_str(&format!(":set {name} {value}\n"));
+        }
+    }
+    for definition in e.definitions() {
+        out.push_str(&definition.to_source(e.settings().caret));
+        out.push('\n');
+    }
+    out
+}
+
+fn deserialize(contents: &str, e: &mut Evaluator) -> color_eyre::Result<()> {
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let result = match line.strip_prefix(":set ") {
+            Some(setting) => {
+                let (name, value) = setting.trim().split_once(' ').unwrap_or((setting, ""));
+                e.settings_mut().set(name, value.trim())
+            }
+            None => {
+                let (statements, errors) = expr::parse(e, line);
+                match errors.first() {
+                    Some(err) => Err(eyre!("{err}")),
+                    None => statements.into_iter().try_for_each(|s| e.eval(s).map(drop)),
+                }
+            }
+        };
+        result.map_err(|err| eyre!("line {}: {err}", n + 1))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_serialize() {
+    let mut e = Evaluator::default();
+    e.settings_mut().set("digits", "12").unwrap();
+    deserialize("x = 1/3\ny = 1/2 + z\nf(a, b) = sqrt(a) * b^2", &mut e).unwrap();
+    let saved = serialize(&e);
+    assert_eq!("# calq workspace\n:set digits 12\nx = 1/3\ny = 1/2+z\nf(a, b) = sqrt(a)*b^2\n", saved);
+    let mut loaded = Evaluator::default();
+    deserialize(&saved, &mut loaded).unwrap();
+    assert_eq!(saved, serialize(&loaded));
+}
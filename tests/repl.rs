@@ -0,0 +1,163 @@
+//! End to end tests that run the `calq` binary, feeding it lines on stdin like
+//! a pipe would. Each test gets its own config and data directories, so that
+//! neither a config file nor saved workspaces on the machine change the output.
+
+use std::path::PathBuf;
+
+use assert_cmd::Command;
+
+fn calq(test: &str) -> Command {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(test);
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut cmd = Command::cargo_bin("calq").unwrap();
+    cmd.env("HOME", &dir).env("XDG_CONFIG_HOME", dir.join("config")).env("XDG_DATA_HOME", dir.join("data"));
+    cmd.env("NO_COLOR", "1").env_remove("RUST_BACKTRACE");
+    cmd
+}
+
+fn stdout(cmd: &mut Command, input: &str) -> String {
+    let output = cmd.write_stdin(input).assert().success().get_output().clone();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_piped_lines() {
+    let out = stdout(&mut calq("piped"), "1/3 + 1/6\nx = 2\nf(y) = x*y\nf(21)\n");
+    assert_eq!("1/2\n2\nf(y) = x*y\n42\n", out);
+}
+
+#[test]
+fn test_loops() {
+    // a loop that ran nothing prints nothing, rather than its empty result
+    let input = "t = 0\nfor k in 1..0 { k }\nfor k in 1..4 { t = t + k }\nwhile t < 0 { t }\n";
+    let out = stdout(&mut calq("loops"), input);
+    assert_eq!("0\n10\n", out);
+}
+
+#[test]
+fn test_crlf() {
+    let mut cmd = calq("crlf");
+    let config = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("crlf").join("config").join("calq");
+    std::fs::create_dir_all(&config).unwrap();
+    std::fs::write(config.join("config"), "# written on Windows\r\n:set digits 3\r\n").unwrap();
+    let out = stdout(&mut cmd, "s = \"ab\"\r\n1/7 + 0.0\r\n!!\r\n:set digits\r\n");
+    assert_eq!("\"ab\"\n0.143\n1/7 + 0.0\n0.143\ndigits = 3\n", out);
+}
+
+#[test]
+fn test_errors_go_to_stderr() {
+    let assert = calq("errors").write_stdin("foo(\n:nope\n1 + 1\n").assert().success();
+    let output = assert.get_output();
+    assert_eq!("2\n", String::from_utf8_lossy(&output.stdout));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error: unknown command `:nope`"), "{stderr}");
+}
+
+#[test]
+fn test_commands_and_history() {
+    let out = stdout(&mut calq("commands"), ":set digits 3\n1/7 + 0.0\n!!*7\n");
+    assert!(out.starts_with("0.143\n1/7 + 0.0*7\n"), "{out}");
+    let out = stdout(&mut calq("table"), "sqr(x) = x^2\n:table sqr 1 3\n:set caret\n");
+    assert_eq!("sqr(x) = x^2\nx  sqr(x)\n1  1\n2  4\n3  9\ncaret = pow\n", out);
+    // the rows are what calling the function gives, also past the digits of an `f64`
+    let input = "g(x) = sqrt(x)*1.0\nh(x) = x/3\n:set digits 30\n:table g 2 3\ng(2)\ng(3)\n:table h 1/2 1 1/2\n";
+    let out = stdout(&mut calq("table"), input);
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(format!("2  {}", lines[5]), lines[3]);
+    assert_eq!(format!("3  {}", lines[6]), lines[4]);
+    assert_eq!(["1/2  1/6", "1    1/3"], lines[8..]);
+    // `:full` prints the digits of an integer shown approximated
+    let out = stdout(&mut calq("full"), "2^5000\n:full\n");
+    let (shown, full) = out.split_once('\n').unwrap();
+    assert_eq!("(≈ 1.4124670e1505)", shown);
+    assert_eq!(format!("{}\n", rug::Integer::from(rug::Integer::u_pow_u(2, 5000))), full);
+}
+
+#[test]
+fn test_write_results() {
+    let mut cmd = calq("write");
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("write");
+    std::fs::create_dir_all(&dir).unwrap();
+    cmd.current_dir(&dir);
+    let input = "1/4 + 1/4\n:write out.txt\n[1, 2]\n:append out.txt\nwrite(7, \"out.txt\", \"a\")\n:write\n";
+    let output = cmd.write_stdin(input).assert().success().get_output().clone();
+    assert_eq!("1/2\n[1, 2]\n7\n", String::from_utf8_lossy(&output.stdout));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("expected a file name after `:write`"));
+    assert_eq!("1/2\n[1, 2]\n7\n", std::fs::read_to_string(dir.join("out.txt")).unwrap());
+    // `:write` replaces what was there
+    stdout(calq("write-again").current_dir(&dir), "3\n:write out.txt\n");
+    assert_eq!("3\n", std::fs::read_to_string(dir.join("out.txt")).unwrap());
+}
+
+#[test]
+fn test_exit_codes() {
+    calq("exit").args(["-e", "2 - 2"]).assert().success().stdout("0\n");
+    calq("exit").args(["-e", "2 - 2", "--print-exit-value"]).assert().code(1);
+    calq("exit").args(["-e", "3", "--print-exit-value"]).assert().code(0);
+    calq("exit").args(["-e", "1/0", "--print-exit-value"]).assert().code(2);
+    calq("exit").arg("--bogus").assert().failure();
+}
+
+#[test]
+fn test_expr_flag_reads_like_the_prompt() {
+    calq("expr").args(["-e", "sqrt of 16"]).assert().success().stdout("4\n");
+    calq("expr").args(["-e", "(1 + 2"]).assert().success().stdout("3\n");
+}
+
+#[test]
+fn test_oneshot() {
+    calq("oneshot").args(["--oneshot", "--max-output", "5", "-e", "1/7 + 0.0"]).assert().success().stdout(
+        "0.142... [5 more characters]\n",
+    );
+    calq("oneshot").arg("--oneshot").write_stdin("1/0\n").assert().code(1).stdout("error[eval]: division by zero\n");
+    let slow = calq("oneshot").args(["--oneshot", "--max-time", "10ms", "-e", "primorial(10^8)"]).assert().code(1);
+    assert_eq!("error[timeout]: no result within 10ms\n", String::from_utf8_lossy(&slow.get_output().stdout));
+    calq("oneshot").args(["--max-time", "1s", "-e", "1"]).assert().code(2);
+}
+
+#[test]
+fn test_help_and_version() {
+    let help = stdout(calq("flags").arg("--help"), "");
+    assert!(help.contains("--print-exit-value") && help.contains("--json"), "{help}");
+    let version = stdout(calq("flags").arg("-V"), "");
+    assert!(version.starts_with(concat!("calq ", env!("CARGO_PKG_VERSION"), "\n")), "{version}");
+    assert!(version.contains("features: "), "{version}");
+}
+
+#[test]
+fn test_setting_flags() {
+    let mut cmd = calq("settings");
+    cmd.args(["--precision", "20", "--round-mode", "down", "--degrees"]);
+    let out = stdout(&mut cmd, ":set digits 10\n2/3 + 0.0\nsin(30)\n:set precision\n");
+    assert_eq!("0.6666660309\n1/2\nprecision = 20\n", out);
+    calq("settings").args(["--precision", "50d", "-e", "1"]).assert().success();
+    let bad = calq("settings").args(["--round-mode", "sideways", "-e", "1"]).assert().code(2);
+    let stderr = String::from_utf8(bad.get_output().stderr.clone()).unwrap();
+    assert!(stderr.starts_with("error: invalid value 'sideways' for '--round-mode <mode>'"), "{stderr}");
+    assert!(!stderr.contains("Location"), "{stderr}");
+    calq("settings").arg("--bogus").assert().code(2);
+}
+
+#[test]
+fn test_replay_errors() {
+    let missing = calq("replay").args(["--replay", "missing.json"]).assert().code(2);
+    let stderr = String::from_utf8(missing.get_output().stderr.clone()).unwrap();
+    assert!(stderr.starts_with("Error: could not read missing.json: ") && stderr.lines().count() == 1, "{stderr}");
+}
+
+#[test]
+fn test_json() {
+    let out = stdout(calq("json").args(["--json", "--precision", "20", "-e", "1/3 + 0.0"]), "");
+    assert_eq!("{\"kind\":\"decimal\",\"text\":\"0.33333349\"}\n", out);
+    calq("json").args(["--json", "--print-exit-value", "-e", "1 - 1"]).assert().code(1);
+    let error = calq("json").args(["--json", "-e", "1/0"]).assert().code(1);
+    let out = String::from_utf8(error.get_output().stdout.clone()).unwrap();
+    assert_eq!("{\"error\":{\"code\":-32000,\"message\":\"division by zero\"}}\n", out);
+    calq("json").arg("--json").assert().code(2);
+}
+
+#[test]
+fn test_symbolic() {
+    let out = stdout(&mut calq("symbolic"), ":set symbolic on\n2*pi*3\nx*pi\nN(sqrt(2))\n");
+    assert_eq!("6*pi ≈ 18.849556\nx*pi\n1.4142136\n", out);
+}